@@ -1,16 +1,20 @@
 use crate::storage::{
-    get_config, is_initialized, save_config, set_initialized, Config, DataKey, LiquidityPoolInfo,
-    PairTupleKey,
+    get_config, get_pool_type, is_initialized, save_config, save_pool_type, set_initialized,
+    Config, DataKey, LiquidityPoolInfo, OptionAmplifierParameters, PairTupleKey, PoolResponse,
+    StablePoolInfo, XykPoolInfo,
 };
 use crate::utils::deploy_multihop_contract;
 use crate::{
-    storage::{get_lp_vec, save_lp_vec, save_lp_vec_with_tuple_as_key},
+    storage::{get_lp_vec, has_pool_for_pair, save_lp_vec, save_lp_vec_with_tuple_as_key},
     utils::deploy_lp_contract,
 };
-use phoenix::utils::{LiquidityPoolInitInfo, StakeInitInfo, TokenInitInfo};
 use phoenix::validate_bps;
+use phoenix::{
+    error::FactoryError,
+    utils::{LiquidityPoolInitInfo, PoolType, PoolTypeInitInfo, StablePoolInitInfo, StakeInitInfo},
+};
 use soroban_sdk::{
-    contract, contractimpl, contractmeta, log, Address, BytesN, Env, IntoVal, Symbol, Val, Vec,
+    contract, contractimpl, contractmeta, log, vec, Address, BytesN, Env, IntoVal, Symbol, Val, Vec,
 };
 
 // Metadata that is added on to the WASM custom section
@@ -20,28 +24,37 @@ contractmeta!(key = "Description", val = "Phoenix Protocol Factory");
 pub struct Factory;
 
 pub trait FactoryTrait {
+    #[allow(clippy::too_many_arguments)]
     fn initialize(
         env: Env,
         admin: Address,
         multihop_wasm_hash: BytesN<32>,
         lp_wasm_hash: BytesN<32>,
+        stable_wasm_hash: BytesN<32>,
         stake_wasm_hash: BytesN<32>,
         token_wasm_hash: BytesN<32>,
         whitelisted_accounts: Vec<Address>,
-    );
+    ) -> Result<(), FactoryError>;
 
+    // Stable pools take their tokens, amplification coefficient and rate providers from the
+    // `Stable` variant of `pool_type`, the pair of `lp_init_info.token_init_info` is not used
     fn create_liquidity_pool(
         env: Env,
         lp_init_info: LiquidityPoolInitInfo,
         caller: Address,
-    ) -> Address;
+        pool_type: PoolTypeInitInfo,
+    ) -> Result<Address, FactoryError>;
 
     fn update_whitelisted_accounts(
         env: Env,
         sender: Address,
         to_add: Vec<Address>,
         to_remove: Vec<Address>,
-    );
+    ) -> Result<(), FactoryError>;
+
+    // Pauses or resumes the swaps and deposits of every pool deployed by the factory.
+    // Withdrawals are left as they are, so that liquidity providers can leave the pools.
+    fn pause_all(env: Env, sender: Address, paused: bool) -> Result<(), FactoryError>;
 
     fn query_pools(env: Env) -> Vec<Address>;
 
@@ -49,7 +62,13 @@ pub trait FactoryTrait {
 
     fn query_all_pools_details(env: Env) -> Vec<LiquidityPoolInfo>;
 
-    fn query_for_pool_by_token_pair(env: Env, token_a: Address, token_b: Address) -> Address;
+    fn query_pool_type(env: Env, pool_address: Address) -> PoolType;
+
+    fn query_for_pool_by_token_pair(
+        env: Env,
+        token_a: Address,
+        token_b: Address,
+    ) -> Result<Address, FactoryError>;
 
     fn get_admin(env: Env) -> Address;
 
@@ -58,21 +77,28 @@ pub trait FactoryTrait {
 
 #[contractimpl]
 impl FactoryTrait for Factory {
+    #[allow(clippy::too_many_arguments)]
     fn initialize(
         env: Env,
         admin: Address,
         multihop_wasm_hash: BytesN<32>,
         lp_wasm_hash: BytesN<32>,
+        stable_wasm_hash: BytesN<32>,
         stake_wasm_hash: BytesN<32>,
         token_wasm_hash: BytesN<32>,
         whitelisted_accounts: Vec<Address>,
-    ) {
+    ) -> Result<(), FactoryError> {
         if is_initialized(&env) {
-            panic!("Factory: Initialize: initializing contract twice is not allowed");
+            log!(
+                &env,
+                "Factory: Initialize: initializing contract twice is not allowed"
+            );
+            return Err(FactoryError::AlreadyInitialized);
         }
 
         if whitelisted_accounts.is_empty() {
-            panic!("Factory: Initialize: there must be at least one whitelisted account able to create liquidity pools.")
+            log!(&env, "Factory: Initialize: there must be at least one whitelisted account able to create liquidity pools.");
+            return Err(FactoryError::WhitelistEmpty);
         }
 
         set_initialized(&env);
@@ -86,6 +112,7 @@ impl FactoryTrait for Factory {
                 admin: admin.clone(),
                 multihop_address,
                 lp_wasm_hash,
+                stable_wasm_hash,
                 stake_wasm_hash,
                 token_wasm_hash,
                 whitelisted_accounts,
@@ -96,48 +123,91 @@ impl FactoryTrait for Factory {
 
         env.events()
             .publish(("initialize", "LP factory contract"), admin);
+
+        Ok(())
     }
 
     fn create_liquidity_pool(
         env: Env,
         lp_init_info: LiquidityPoolInitInfo,
         caller: Address,
-    ) -> Address {
+        pool_type: PoolTypeInitInfo,
+    ) -> Result<Address, FactoryError> {
         caller.require_auth();
         if !get_config(&env).whitelisted_accounts.contains(caller) {
-            panic!(
+            log!(
+                &env,
                 "Factory: Create Liquidity Pool: You are not authorized to create liquidity pool!"
-            )
+            );
+            return Err(FactoryError::Unauthorized);
         };
 
-        validate_token_info(
-            &env,
-            &lp_init_info.token_init_info,
-            &lp_init_info.stake_init_info,
-        );
+        let tokens = match &pool_type {
+            PoolTypeInitInfo::Xyk => vec![
+                &env,
+                lp_init_info.token_init_info.token_a.clone(),
+                lp_init_info.token_init_info.token_b.clone(),
+            ],
+            PoolTypeInitInfo::Stable(stable_init_info) => stable_init_info.tokens.clone(),
+        };
+        validate_token_info(&env, &tokens, &lp_init_info.stake_init_info)?;
 
         let config = get_config(&env);
-        let lp_wasm_hash = config.lp_wasm_hash;
+        let lp_wasm_hash = match pool_type {
+            PoolTypeInitInfo::Xyk => config.lp_wasm_hash,
+            PoolTypeInitInfo::Stable(_) => config.stable_wasm_hash,
+        };
         let stake_wasm_hash = config.stake_wasm_hash;
         let token_wasm_hash = config.token_wasm_hash;
 
-        let lp_contract_address = deploy_lp_contract(
-            &env,
-            lp_wasm_hash,
-            &lp_init_info.token_init_info.token_a,
-            &lp_init_info.token_init_info.token_b,
-        );
+        if let PoolTypeInitInfo::Stable(stable_init_info) = &pool_type {
+            if stable_init_info.amp == 0 {
+                log!(
+                    &env,
+                    "Factory: Create Liquidity Pool: Stable pools require an amplification coefficient bigger then 0"
+                );
+                return Err(FactoryError::InvalidAmp);
+            }
+        }
+
+        let lp_contract_address = deploy_lp_contract(&env, lp_wasm_hash, &tokens);
 
         validate_bps!(
             lp_init_info.swap_fee_bps,
             lp_init_info.max_allowed_slippage_bps,
             lp_init_info.max_allowed_spread_bps,
             lp_init_info.max_referral_bps
-        );
+        )?;
 
         let init_fn: Symbol = Symbol::new(&env, "initialize");
-        let init_fn_args: Vec<Val> =
-            (stake_wasm_hash, token_wasm_hash, lp_init_info.clone()).into_val(&env);
+        let init_fn_args: Vec<Val> = match pool_type.clone() {
+            PoolTypeInitInfo::Xyk => (
+                stake_wasm_hash,
+                token_wasm_hash,
+                lp_init_info.clone(),
+                env.current_contract_address(),
+            )
+                .into_val(&env),
+            PoolTypeInitInfo::Stable(stable_init_info) => (
+                stake_wasm_hash,
+                token_wasm_hash,
+                stable_init_info.amp,
+                StablePoolInitInfo {
+                    admin: lp_init_info.admin.clone(),
+                    share_token_decimals: lp_init_info.share_token_decimals,
+                    swap_fee_bps: lp_init_info.swap_fee_bps,
+                    fee_recipient: lp_init_info.fee_recipient.clone(),
+                    max_allowed_slippage_bps: lp_init_info.max_allowed_slippage_bps,
+                    max_allowed_spread_bps: lp_init_info.max_allowed_spread_bps,
+                    tokens: stable_init_info.tokens,
+                    rate_providers: stable_init_info.rate_providers,
+                    max_rate_staleness: stable_init_info.max_rate_staleness,
+                    stake_init_info: lp_init_info.stake_init_info.clone(),
+                },
+                env.current_contract_address(),
+            )
+                .into_val(&env),
+        };
 
         env.invoke_contract::<Val>(&lp_contract_address, &init_fn, init_fn_args);
 
@@ -146,14 +216,23 @@ impl FactoryTrait for Factory {
         lp_vec.push_back(lp_contract_address.clone());
 
         save_lp_vec(&env, lp_vec);
-        let token_a = &lp_init_info.token_init_info.token_a;
-        let token_b = &lp_init_info.token_init_info.token_b;
-        save_lp_vec_with_tuple_as_key(&env, (token_a, token_b), &lp_contract_address);
+        save_pool_type(&env, &lp_contract_address, pool_type.pool_type());
+        // the pools holding more than two tokens can be found by any pair of them; a pair that
+        // already leads to a pool keeps doing so, the new pool is still listed in the pool details
+        for (index, token_a) in tokens.iter().enumerate() {
+            for token_b in tokens.iter().skip(index + 1) {
+                if !has_pool_for_pair(&env, (&token_a, &token_b)) {
+                    save_lp_vec_with_tuple_as_key(&env, (&token_a, &token_b), &lp_contract_address);
+                }
+            }
+        }
 
         env.events()
             .publish(("create", "liquidity_pool"), &lp_contract_address);
+        env.events()
+            .publish(("create", "pool_type"), pool_type.pool_type());
 
-        lp_contract_address
+        Ok(lp_contract_address)
     }
 
     fn update_whitelisted_accounts(
@@ -161,15 +240,17 @@ impl FactoryTrait for Factory {
         sender: Address,
         to_add: Vec<Address>,
         to_remove: Vec<Address>,
-    ) {
+    ) -> Result<(), FactoryError> {
         sender.require_auth();
 
         let config = get_config(&env);
 
         if config.admin != sender {
-            panic!(
-                "Factory: Create Liquidity Pool: You are not authorized to create liquidity pool!"
-            )
+            log!(
+                &env,
+                "Factory: Update whitelisted accounts: You are not authorized!"
+            );
+            return Err(FactoryError::Unauthorized);
         };
 
         let mut whitelisted_accounts = config.whitelisted_accounts;
@@ -192,7 +273,34 @@ impl FactoryTrait for Factory {
                 whitelisted_accounts,
                 ..config
             },
+        );
+
+        Ok(())
+    }
+
+    fn pause_all(env: Env, sender: Address, paused: bool) -> Result<(), FactoryError> {
+        sender.require_auth();
+
+        if get_config(&env).admin != sender {
+            log!(&env, "Factory: Pause all: You are not authorized!");
+            return Err(FactoryError::Unauthorized);
+        }
+
+        let pause_fn = Symbol::new(&env, "update_pause_status");
+        let pause_fn_args: Vec<Val> = (
+            env.current_contract_address(),
+            Some(paused),
+            Some(paused),
+            None::<bool>,
         )
+            .into_val(&env);
+        for pool_address in get_lp_vec(&env) {
+            env.invoke_contract::<Val>(&pool_address, &pause_fn, pause_fn_args.clone());
+        }
+
+        env.events().publish(("pause_all", "paused"), paused);
+
+        Ok(())
     }
 
     fn query_pools(env: Env) -> Vec<Address> {
@@ -200,38 +308,35 @@ impl FactoryTrait for Factory {
     }
 
     fn query_pool_details(env: Env, pool_address: Address) -> LiquidityPoolInfo {
-        let pool_response: LiquidityPoolInfo = env.invoke_contract(
-            &pool_address,
-            &Symbol::new(&env, "query_pool_info_for_factory"),
-            Vec::new(&env),
-        );
-        pool_response
+        query_pool_info(&env, &pool_address)
     }
 
     fn query_all_pools_details(env: Env) -> Vec<LiquidityPoolInfo> {
         let all_lp_vec_addresses = get_lp_vec(&env);
         let mut result = Vec::new(&env);
         for address in all_lp_vec_addresses {
-            let pool_response: LiquidityPoolInfo = env.invoke_contract(
-                &address,
-                &Symbol::new(&env, "query_pool_info_for_factory"),
-                Vec::new(&env),
-            );
-
-            result.push_back(pool_response);
+            result.push_back(query_pool_info(&env, &address));
         }
 
         result
     }
 
-    fn query_for_pool_by_token_pair(env: Env, token_a: Address, token_b: Address) -> Address {
+    fn query_pool_type(env: Env, pool_address: Address) -> PoolType {
+        get_pool_type(&env, &pool_address)
+    }
+
+    fn query_for_pool_by_token_pair(
+        env: Env,
+        token_a: Address,
+        token_b: Address,
+    ) -> Result<Address, FactoryError> {
         let pool_result: Option<Address> = env.storage().persistent().get(&PairTupleKey {
             token_a: token_a.clone(),
             token_b: token_b.clone(),
         });
 
         if let Some(addr) = pool_result {
-            return addr;
+            return Ok(addr);
         }
 
         let reverted_pool_resul: Option<Address> = env.storage().persistent().get(&PairTupleKey {
@@ -240,10 +345,14 @@ impl FactoryTrait for Factory {
         });
 
         if let Some(addr) = reverted_pool_resul {
-            return addr;
+            return Ok(addr);
         }
 
-        panic!("Factory: query_for_pool_by_token_pair failed: No liquidity pool found");
+        log!(
+            &env,
+            "Factory: query_for_pool_by_token_pair failed: No liquidity pool found"
+        );
+        Err(FactoryError::PoolNotFound)
     }
 
     fn get_admin(env: Env) -> Address {
@@ -258,14 +367,58 @@ impl FactoryTrait for Factory {
     }
 }
 
+/// Queries the pool for its details and tags them with the type of the pool,
+/// as the XYK and stable pools respond with differently shaped structs.
+fn query_pool_info(env: &Env, pool_address: &Address) -> LiquidityPoolInfo {
+    let query_fn = Symbol::new(env, "query_pool_info_for_factory");
+
+    match get_pool_type(env, pool_address) {
+        PoolType::Xyk => {
+            let pool_info: XykPoolInfo =
+                env.invoke_contract(pool_address, &query_fn, Vec::new(env));
+            LiquidityPoolInfo {
+                pool_address: pool_info.pool_address,
+                assets: vec![
+                    env,
+                    pool_info.pool_response.asset_a.clone(),
+                    pool_info.pool_response.asset_b.clone(),
+                ],
+                pool_response: pool_info.pool_response,
+                total_fee_bps: pool_info.total_fee_bps,
+                pool_type: PoolType::Xyk,
+                amp_parameters: OptionAmplifierParameters::None,
+            }
+        }
+        PoolType::Stable => {
+            let pool_info: StablePoolInfo =
+                env.invoke_contract(pool_address, &query_fn, Vec::new(env));
+            let assets = pool_info.pool_response.assets;
+            LiquidityPoolInfo {
+                pool_address: pool_info.pool_address,
+                pool_response: PoolResponse {
+                    asset_a: assets.get(0).unwrap(),
+                    asset_b: assets.get(1).unwrap(),
+                    asset_lp_share: pool_info.pool_response.asset_lp_share,
+                },
+                assets,
+                total_fee_bps: pool_info.total_fee_bps,
+                pool_type: PoolType::Stable,
+                amp_parameters: OptionAmplifierParameters::Some(pool_info.amp_parameters),
+            }
+        }
+    }
+}
+
 fn validate_token_info(
     env: &Env,
-    token_init_info: &TokenInitInfo,
+    tokens: &Vec<Address>,
     stake_init_info: &StakeInitInfo,
-) {
-    if token_init_info.token_a >= token_init_info.token_b {
-        log!(env, "token_a must be less than token_b");
-        panic!("Factory: validate_token_info failed: First token must be smaller then second");
+) -> Result<(), FactoryError> {
+    for (previous, next) in tokens.iter().zip(tokens.iter().skip(1)) {
+        if previous >= next {
+            log!(env, "tokens must be sorted alphabetically and unique");
+            return Err(FactoryError::TokensNotSorted);
+        }
     }
 
     if stake_init_info.min_bond <= 0 {
@@ -273,13 +426,15 @@ fn validate_token_info(
             env,
             "Minimum amount of lp share tokens to bond can not be smaller or equal to 0"
         );
-        panic!("Factory: validate_token_info failed: min stake is less or equal to zero");
+        return Err(FactoryError::InvalidMinBond);
     }
 
     if stake_init_info.min_reward <= 0 {
         log!(env, "min_reward must be bigger then 0!");
-        panic!("Factory: validate_token_info failed: min reward too small");
+        return Err(FactoryError::InvalidMinReward);
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -288,9 +443,6 @@ mod tests {
     use soroban_sdk::{testutils::Address as _, Address, String};
 
     #[test]
-    #[should_panic(
-        expected = "Factory: validate_token_info failed: First token must be smaller then second"
-    )]
     fn validate_token_info_should_fail_on_token_a_less_than_token_b() {
         let env = Env::default();
 
@@ -303,27 +455,27 @@ mod tests {
             "CAOUDQCLN3BYHH4L7GSH3OSQJFVELHKOEVKOPBENVIGZ6WZ5ZRHFC5LN",
         ));
 
-        let token_init_info = TokenInitInfo { token_a, token_b };
+        let tokens = vec![&env, token_a, token_b];
 
         let stake_init_info = StakeInitInfo {
             max_distributions: 10,
             min_bond: 10,
             min_reward: 10,
         };
-        validate_token_info(&env, &token_init_info, &stake_init_info);
+        assert_eq!(
+            validate_token_info(&env, &tokens, &stake_init_info),
+            Err(FactoryError::TokensNotSorted)
+        );
     }
 
     #[test]
-    #[should_panic(
-        expected = "Factory: validate_token_info failed: min stake is less or equal to zero"
-    )]
     fn validate_token_info_should_fail_on_min_bond_less_than_zero() {
         let env = Env::default();
 
         let token_a = Address::generate(&env);
         let token_b = Address::generate(&env);
 
-        let token_init_info = TokenInitInfo { token_a, token_b };
+        let tokens = vec![&env, token_a, token_b];
 
         let stake_init_info = StakeInitInfo {
             max_distributions: 10,
@@ -331,24 +483,29 @@ mod tests {
             min_reward: 10,
         };
 
-        validate_token_info(&env, &token_init_info, &stake_init_info);
+        assert_eq!(
+            validate_token_info(&env, &tokens, &stake_init_info),
+            Err(FactoryError::InvalidMinBond)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Factory: validate_token_info failed: min reward too small")]
     fn validate_token_info_should_fail_on_min_reward_less_than_zero() {
         let env = Env::default();
 
         let token_a = Address::generate(&env);
         let token_b = Address::generate(&env);
 
-        let token_init_info = TokenInitInfo { token_a, token_b };
+        let tokens = vec![&env, token_a, token_b];
 
         let stake_init_info = StakeInitInfo {
             max_distributions: 10,
             min_bond: 10,
             min_reward: 0,
         };
-        validate_token_info(&env, &token_init_info, &stake_init_info);
+        assert_eq!(
+            validate_token_info(&env, &tokens, &stake_init_info),
+            Err(FactoryError::InvalidMinReward)
+        );
     }
 }
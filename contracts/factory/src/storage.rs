@@ -1,3 +1,4 @@
+use phoenix::utils::PoolType;
 use soroban_sdk::{contracttype, Address, BytesN, ConversionError, Env, TryFromVal, Val, Vec};
 
 #[derive(Clone, Copy)]
@@ -15,6 +16,12 @@ pub struct PairTupleKey {
     pub(crate) token_b: Address,
 }
 
+#[derive(Clone)]
+#[contracttype]
+pub struct PoolTypeKey {
+    pub(crate) pool_address: Address,
+}
+
 impl TryFromVal<Env, DataKey> for Val {
     type Error = ConversionError;
 
@@ -29,6 +36,7 @@ pub struct Config {
     pub admin: Address,
     pub multihop_address: Address,
     pub lp_wasm_hash: BytesN<32>,
+    pub stable_wasm_hash: BytesN<32>,
     pub stake_wasm_hash: BytesN<32>,
     pub token_wasm_hash: BytesN<32>,
     pub whitelisted_accounts: Vec<Address>,
@@ -55,12 +63,65 @@ pub struct PoolResponse {
     pub asset_lp_share: Asset,
 }
 
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AmplifierParameters {
+    pub init_amp: u64,
+    pub init_amp_time: u64,
+    pub next_amp: u64,
+    pub next_amp_time: u64,
+}
+
+// This type has been created because of lack of conversion of Option<T> into ScVal
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptionAmplifierParameters {
+    Some(AmplifierParameters),
+    None,
+}
+
+/// Pool details as returned by `query_pool_info_for_factory` of the XYK pool.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XykPoolInfo {
+    pub pool_address: Address,
+    pub pool_response: PoolResponse,
+    pub total_fee_bps: i64,
+}
+
+/// Pool assets as returned by the stable pool, which can hold more than two tokens.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StablePoolResponse {
+    /// Assets of the pool, sorted alphabetically
+    pub assets: Vec<Asset>,
+    /// The total amount of LP tokens currently issued
+    pub asset_lp_share: Asset,
+}
+
+/// Pool details as returned by `query_pool_info_for_factory` of the stable pool.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StablePoolInfo {
+    pub pool_address: Address,
+    pub pool_response: StablePoolResponse,
+    pub total_fee_bps: i64,
+    pub amp_parameters: AmplifierParameters,
+}
+
 #[contracttype]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct LiquidityPoolInfo {
     pub pool_address: Address,
+    /// For stable pools of more than two tokens, `asset_a` and `asset_b` are the first two of
+    /// `assets`
     pub pool_response: PoolResponse,
+    /// Every asset of the pool, sorted alphabetically
+    pub assets: Vec<Asset>,
     pub total_fee_bps: i64,
+    pub pool_type: PoolType,
+    /// Amplification parameters of the pool; `None` for XYK pools
+    pub amp_parameters: OptionAmplifierParameters,
 }
 
 pub fn save_config(env: &Env, config: Config) {
@@ -99,6 +160,31 @@ pub fn save_lp_vec_with_tuple_as_key(
     )
 }
 
+pub fn has_pool_for_pair(env: &Env, tuple_pool: (&Address, &Address)) -> bool {
+    env.storage().persistent().has(&PairTupleKey {
+        token_a: tuple_pool.0.clone(),
+        token_b: tuple_pool.1.clone(),
+    })
+}
+
+pub fn save_pool_type(env: &Env, lp_address: &Address, pool_type: PoolType) {
+    env.storage().persistent().set(
+        &PoolTypeKey {
+            pool_address: lp_address.clone(),
+        },
+        &pool_type,
+    )
+}
+
+pub fn get_pool_type(env: &Env, lp_address: &Address) -> PoolType {
+    env.storage()
+        .persistent()
+        .get(&PoolTypeKey {
+            pool_address: lp_address.clone(),
+        })
+        .unwrap_or(PoolType::Xyk)
+}
+
 pub fn is_initialized(e: &Env) -> bool {
     e.storage()
         .persistent()
@@ -1,8 +1,9 @@
+use phoenix::error::FactoryError;
 use soroban_sdk::{testutils::Address as _, vec, Address, Env};
 
 use self::setup::{
-    deploy_factory_contract, install_lp_contract, install_multihop_wasm, install_stake_wasm,
-    install_token_wasm,
+    deploy_factory_contract, install_lp_contract, install_multihop_wasm, install_stable_lp,
+    install_stake_wasm, install_token_wasm,
 };
 
 mod config;
@@ -10,7 +11,6 @@ mod setup;
 
 mod queries;
 #[test]
-#[should_panic(expected = "Factory: Initialize: initializing contract twice is not allowed")]
 fn test_deploy_factory_twice_should_fail() {
     let env = Env::default();
     env.mock_all_auths();
@@ -21,25 +21,22 @@ fn test_deploy_factory_twice_should_fail() {
     let auth_user = Address::generate(&env);
     let multihop_wasm_hash = install_multihop_wasm(&env);
     let lp_wasm_hash = install_lp_contract(&env);
+    let stable_wasm_hash = install_stable_lp(&env);
     let stake_wasm_hash = install_stake_wasm(&env);
     let token_wasm_hash = install_token_wasm(&env);
 
     let factory = deploy_factory_contract(&env, admin.clone());
 
-    factory.initialize(
-        &admin,
-        &multihop_wasm_hash,
-        &lp_wasm_hash,
-        &stake_wasm_hash,
-        &token_wasm_hash,
-        &vec![&env, auth_user.clone()],
-    );
-    factory.initialize(
-        &admin,
-        &multihop_wasm_hash,
-        &lp_wasm_hash,
-        &stake_wasm_hash,
-        &token_wasm_hash,
-        &vec![&env, auth_user.clone()],
+    assert_eq!(
+        factory.try_initialize(
+            &admin,
+            &multihop_wasm_hash,
+            &lp_wasm_hash,
+            &stable_wasm_hash,
+            &stake_wasm_hash,
+            &token_wasm_hash,
+            &vec![&env, auth_user.clone()],
+        ),
+        Err(Ok(FactoryError::AlreadyInitialized))
     );
 }
@@ -1,5 +1,10 @@
 use super::setup::{deploy_factory_contract, lp_contract};
-use phoenix::utils::{LiquidityPoolInitInfo, StakeInitInfo, TokenInitInfo};
+use phoenix::{
+    error::FactoryError,
+    utils::{
+        LiquidityPoolInitInfo, PoolTypeInitInfo, StableInitInfo, StakeInitInfo, TokenInitInfo,
+    },
+};
 
 use soroban_sdk::{
     testutils::{arbitrary::std, Address as _},
@@ -20,6 +25,7 @@ fn factory_successfully_inits_itself() {
 fn factory_successfully_inits_multihop() {
     let env = Env::default();
     env.mock_all_auths();
+    env.budget().reset_unlimited();
 
     let admin = Address::generate(&env);
 
@@ -77,7 +83,7 @@ fn factory_successfully_inits_lp() {
         stake_init_info,
     };
 
-    factory.create_liquidity_pool(&lp_init_info, &admin);
+    factory.create_liquidity_pool(&lp_init_info, &admin, &PoolTypeInitInfo::Xyk);
     let lp_contract_addr = factory.query_pools().get(0).unwrap();
 
     let first_lp_contract = lp_contract::Client::new(&env, &lp_contract_addr);
@@ -87,11 +93,14 @@ fn factory_successfully_inits_lp() {
     assert_eq!(
         first_lp_contract.query_config(),
         lp_contract::Config {
+            distribute_lp_fees_to_stakers: false,
+            flash_fee_bps: 0,
             fee_recipient: user,
             max_allowed_slippage_bps: 5_000,
             max_allowed_spread_bps: 500,
             max_referral_bps: 5_000,
             pool_type: lp_contract::PairType::Xyk,
+            protocol_fee_share_bps: 10_000,
             share_token: share_token_address,
             stake_contract: stake_token_address,
             token_a: token_init_info.token_a,
@@ -102,9 +111,6 @@ fn factory_successfully_inits_lp() {
 }
 
 #[test]
-#[should_panic(
-    expected = "Factory: Create Liquidity Pool: You are not authorized to create liquidity pool!"
-)]
 fn factory_fails_to_init_lp_when_authorized_address_not_present() {
     let env = Env::default();
     let admin = Address::generate(&env);
@@ -150,7 +156,14 @@ fn factory_fails_to_init_lp_when_authorized_address_not_present() {
 
     let unauthorized_addr = Address::generate(&env);
 
-    factory.create_liquidity_pool(&lp_init_info, &unauthorized_addr);
+    assert_eq!(
+        factory.try_create_liquidity_pool(
+            &lp_init_info,
+            &unauthorized_addr,
+            &PoolTypeInitInfo::Xyk
+        ),
+        Err(Ok(FactoryError::Unauthorized))
+    );
 }
 
 #[test]
@@ -238,3 +251,90 @@ fn test_add_vec_with_duplicates_should_be_handled_correctly() {
     assert!(config.whitelisted_accounts.contains(second_wl_addr));
     assert!(config.whitelisted_accounts.len() == 2);
 }
+
+#[test]
+fn pause_all_pauses_swaps_and_deposits_of_every_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let pool_admin = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let mut tokens = std::vec![
+        env.register_stellar_asset_contract(admin.clone()),
+        env.register_stellar_asset_contract(admin.clone()),
+        env.register_stellar_asset_contract(admin.clone()),
+        env.register_stellar_asset_contract(admin.clone()),
+    ];
+    tokens.sort();
+
+    let factory = deploy_factory_contract(&env, Some(admin.clone()));
+
+    let stake_init_info = StakeInitInfo {
+        min_bond: 10i128,
+        max_distributions: 10u32,
+        min_reward: 5i128,
+    };
+    let lp_init_info = |token_a: &Address, token_b: &Address| LiquidityPoolInitInfo {
+        admin: pool_admin.clone(),
+        fee_recipient: user.clone(),
+        max_allowed_slippage_bps: 5_000,
+        max_allowed_spread_bps: 500,
+        share_token_decimals: 7,
+        swap_fee_bps: 0,
+        max_referral_bps: 5_000,
+        token_init_info: TokenInitInfo {
+            token_a: token_a.clone(),
+            token_b: token_b.clone(),
+        },
+        stake_init_info: stake_init_info.clone(),
+    };
+
+    factory.create_liquidity_pool(
+        &lp_init_info(&tokens[0], &tokens[1]),
+        &admin,
+        &PoolTypeInitInfo::Xyk,
+    );
+    factory.create_liquidity_pool(
+        &lp_init_info(&tokens[2], &tokens[3]),
+        &admin,
+        &PoolTypeInitInfo::Stable(StableInitInfo {
+            amp: 6,
+            tokens: vec![&env, tokens[2].clone(), tokens[3].clone()],
+            rate_providers: vec![&env],
+            max_rate_staleness: 0,
+        }),
+    );
+
+    assert_eq!(
+        factory.try_pause_all(&pool_admin, &true),
+        Err(Ok(FactoryError::Unauthorized))
+    );
+
+    factory.pause_all(&admin, &true);
+    for pool in factory.query_pools() {
+        // both pool types respond with the same status struct
+        assert_eq!(
+            lp_contract::Client::new(&env, &pool).query_status(),
+            lp_contract::PoolStatus {
+                swaps_paused: true,
+                deposits_paused: true,
+                withdrawals_paused: false,
+            }
+        );
+    }
+
+    factory.pause_all(&admin, &false);
+    for pool in factory.query_pools() {
+        assert_eq!(
+            lp_contract::Client::new(&env, &pool).query_status(),
+            lp_contract::PoolStatus {
+                swaps_paused: false,
+                deposits_paused: false,
+                withdrawals_paused: false,
+            }
+        );
+    }
+}
@@ -1,10 +1,17 @@
 use super::setup::deploy_factory_contract;
-use phoenix::utils::{LiquidityPoolInitInfo, StakeInitInfo, TokenInitInfo};
+use crate::storage::OptionAmplifierParameters;
+use phoenix::{
+    error::FactoryError,
+    utils::{
+        LiquidityPoolInitInfo, PoolType, PoolTypeInitInfo, StableInitInfo, StakeInitInfo,
+        TokenInitInfo,
+    },
+};
 
 use soroban_sdk::{
     contracttype,
     testutils::{arbitrary::std, Address as _},
-    Address, Env, Symbol, Vec,
+    vec, Address, Env, Symbol, Vec,
 };
 
 #[contracttype]
@@ -30,6 +37,9 @@ pub struct LiquidityPoolConfig {
     /// The maximum amount of spread (in bps) that is tolerated during swap
     pub max_allowed_spread_bps: i64,
     pub max_referral_bps: i64,
+    pub protocol_fee_share_bps: i64,
+    pub distribute_lp_fees_to_stakers: bool,
+    pub flash_fee_bps: i64,
 }
 
 #[test]
@@ -128,10 +138,12 @@ fn test_deploy_multiple_liquidity_pools() {
         stake_init_info: third_stake_init_info,
     };
 
-    let lp_contract_addr = factory.create_liquidity_pool(&first_lp_init_info, &admin.clone());
+    let lp_contract_addr =
+        factory.create_liquidity_pool(&first_lp_init_info, &admin.clone(), &PoolTypeInitInfo::Xyk);
     let second_lp_contract_addr =
-        factory.create_liquidity_pool(&second_lp_init_info, &admin.clone());
-    let third_lp_contract_addr = factory.create_liquidity_pool(&third_lp_init_info, &admin.clone());
+        factory.create_liquidity_pool(&second_lp_init_info, &admin.clone(), &PoolTypeInitInfo::Xyk);
+    let third_lp_contract_addr =
+        factory.create_liquidity_pool(&third_lp_init_info, &admin.clone(), &PoolTypeInitInfo::Xyk);
 
     let first_result = factory.query_pool_details(&lp_contract_addr);
     let share_token_addr: Address = env.invoke_contract(
@@ -314,10 +326,12 @@ fn test_queries_by_tuple() {
         stake_init_info: third_stake_init_info,
     };
 
-    let lp_contract_addr = factory.create_liquidity_pool(&first_lp_init_info, &admin.clone());
+    let lp_contract_addr =
+        factory.create_liquidity_pool(&first_lp_init_info, &admin.clone(), &PoolTypeInitInfo::Xyk);
     let second_lp_contract_addr =
-        factory.create_liquidity_pool(&second_lp_init_info, &admin.clone());
-    let third_lp_contract_addr = factory.create_liquidity_pool(&third_lp_init_info, &admin.clone());
+        factory.create_liquidity_pool(&second_lp_init_info, &admin.clone(), &PoolTypeInitInfo::Xyk);
+    let third_lp_contract_addr =
+        factory.create_liquidity_pool(&third_lp_init_info, &admin.clone(), &PoolTypeInitInfo::Xyk);
 
     let first_result = factory.query_pool_details(&lp_contract_addr);
 
@@ -360,11 +374,344 @@ fn test_queries_by_tuple() {
 }
 
 #[test]
-#[should_panic(expected = "Factory: query_for_pool_by_token_pair failed: No liquidity pool found")]
 fn test_queries_by_tuple_errors() {
     let env = Env::default();
     let admin = Address::generate(&env);
     let factory = deploy_factory_contract(&env, Some(admin.clone()));
 
-    factory.query_for_pool_by_token_pair(&Address::generate(&env), &Address::generate(&env));
+    assert_eq!(
+        factory
+            .try_query_for_pool_by_token_pair(&Address::generate(&env), &Address::generate(&env)),
+        Err(Ok(FactoryError::PoolNotFound))
+    );
+}
+
+#[test]
+fn test_deploy_xyk_and_stable_pools() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let mut token1 = env.register_stellar_asset_contract(admin.clone());
+    let mut token2 = env.register_stellar_asset_contract(admin.clone());
+    let mut token3 = env.register_stellar_asset_contract(admin.clone());
+    let mut token4 = env.register_stellar_asset_contract(admin.clone());
+
+    if token2 < token1 {
+        std::mem::swap(&mut token1, &mut token2);
+    }
+
+    if token4 < token3 {
+        std::mem::swap(&mut token3, &mut token4);
+    }
+
+    let factory = deploy_factory_contract(&env, Some(admin.clone()));
+
+    let stake_init_info = StakeInitInfo {
+        min_bond: 10i128,
+        max_distributions: 10u32,
+        min_reward: 5i128,
+    };
+
+    let xyk_lp_init_info = LiquidityPoolInitInfo {
+        admin: admin.clone(),
+        fee_recipient: user.clone(),
+        max_allowed_slippage_bps: 5_000,
+        max_allowed_spread_bps: 500,
+        share_token_decimals: 7,
+        swap_fee_bps: 0,
+        max_referral_bps: 5_000,
+        token_init_info: TokenInitInfo {
+            token_a: token1.clone(),
+            token_b: token2.clone(),
+        },
+        stake_init_info: stake_init_info.clone(),
+    };
+
+    let stable_lp_init_info = LiquidityPoolInitInfo {
+        admin: admin.clone(),
+        fee_recipient: user.clone(),
+        max_allowed_slippage_bps: 5_000,
+        max_allowed_spread_bps: 500,
+        share_token_decimals: 7,
+        swap_fee_bps: 0,
+        max_referral_bps: 5_000,
+        token_init_info: TokenInitInfo {
+            token_a: token3.clone(),
+            token_b: token4.clone(),
+        },
+        stake_init_info,
+    };
+
+    let xyk_lp_addr =
+        factory.create_liquidity_pool(&xyk_lp_init_info, &admin, &PoolTypeInitInfo::Xyk);
+    let stable_lp_addr = factory.create_liquidity_pool(
+        &stable_lp_init_info,
+        &admin,
+        &PoolTypeInitInfo::Stable(StableInitInfo {
+            amp: 6,
+            tokens: vec![&env, token3.clone(), token4.clone()],
+            rate_providers: vec![&env],
+            max_rate_staleness: 0,
+        }),
+    );
+
+    let xyk_result = factory.query_pool_details(&xyk_lp_addr);
+    assert_eq!(xyk_result.pool_type, PoolType::Xyk);
+    assert_eq!(xyk_result.amp_parameters, OptionAmplifierParameters::None);
+    assert_eq!(xyk_result.pool_response.asset_a.address, token1);
+    assert_eq!(xyk_result.pool_response.asset_b.address, token2);
+    assert_eq!(
+        xyk_result.assets,
+        vec![
+            &env,
+            xyk_result.pool_response.asset_a.clone(),
+            xyk_result.pool_response.asset_b.clone()
+        ]
+    );
+
+    let stable_result = factory.query_pool_details(&stable_lp_addr);
+    assert_eq!(stable_result.pool_type, PoolType::Stable);
+    assert_eq!(factory.query_pool_type(&xyk_lp_addr), PoolType::Xyk);
+    assert_eq!(factory.query_pool_type(&stable_lp_addr), PoolType::Stable);
+    assert_eq!(stable_result.pool_address, stable_lp_addr);
+    assert_eq!(stable_result.pool_response.asset_a.address, token3);
+    assert_eq!(stable_result.pool_response.asset_b.address, token4);
+    let OptionAmplifierParameters::Some(amp_parameters) = stable_result.amp_parameters.clone()
+    else {
+        panic!("Stable pool should report its amp parameters");
+    };
+    // amp is stored with a precision of 100
+    assert_eq!(amp_parameters.init_amp, 600);
+    assert_eq!(amp_parameters.next_amp, 600);
+
+    let all_pools = factory.query_all_pools_details();
+    assert_eq!(all_pools.len(), 2);
+    assert_eq!(all_pools.get(0).unwrap(), xyk_result);
+    assert_eq!(all_pools.get(1).unwrap(), stable_result);
+
+    assert_eq!(
+        factory.query_for_pool_by_token_pair(&token4, &token3),
+        stable_lp_addr
+    );
+}
+
+#[test]
+fn test_deploy_stable_pool_without_amp_should_fail() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+
+    let mut token1 = env.register_stellar_asset_contract(admin.clone());
+    let mut token2 = env.register_stellar_asset_contract(admin.clone());
+    if token2 < token1 {
+        std::mem::swap(&mut token1, &mut token2);
+    }
+
+    let factory = deploy_factory_contract(&env, Some(admin.clone()));
+
+    let lp_init_info = LiquidityPoolInitInfo {
+        admin: admin.clone(),
+        fee_recipient: admin.clone(),
+        max_allowed_slippage_bps: 5_000,
+        max_allowed_spread_bps: 500,
+        share_token_decimals: 7,
+        swap_fee_bps: 0,
+        max_referral_bps: 5_000,
+        token_init_info: TokenInitInfo {
+            token_a: token1.clone(),
+            token_b: token2.clone(),
+        },
+        stake_init_info: StakeInitInfo {
+            min_bond: 10i128,
+            max_distributions: 10u32,
+            min_reward: 5i128,
+        },
+    };
+
+    assert_eq!(
+        factory.try_create_liquidity_pool(
+            &lp_init_info,
+            &admin,
+            &PoolTypeInitInfo::Stable(StableInitInfo {
+                amp: 0,
+                tokens: vec![&env, token1, token2],
+                rate_providers: vec![&env],
+                max_rate_staleness: 0,
+            }),
+        ),
+        Err(Ok(FactoryError::InvalidAmp))
+    );
+}
+
+#[test]
+fn test_deploy_stable_pool_of_three_tokens() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+
+    let mut tokens = std::vec![
+        env.register_stellar_asset_contract(admin.clone()),
+        env.register_stellar_asset_contract(admin.clone()),
+        env.register_stellar_asset_contract(admin.clone()),
+    ];
+    tokens.sort();
+
+    let factory = deploy_factory_contract(&env, Some(admin.clone()));
+
+    let lp_init_info = LiquidityPoolInitInfo {
+        admin: admin.clone(),
+        fee_recipient: admin.clone(),
+        max_allowed_slippage_bps: 5_000,
+        max_allowed_spread_bps: 500,
+        share_token_decimals: 7,
+        swap_fee_bps: 0,
+        max_referral_bps: 5_000,
+        token_init_info: TokenInitInfo {
+            token_a: tokens[0].clone(),
+            token_b: tokens[1].clone(),
+        },
+        stake_init_info: StakeInitInfo {
+            min_bond: 10i128,
+            max_distributions: 10u32,
+            min_reward: 5i128,
+        },
+    };
+    let stable_init_info = |tokens: Vec<Address>| {
+        PoolTypeInitInfo::Stable(StableInitInfo {
+            amp: 6,
+            tokens,
+            rate_providers: vec![&env],
+            max_rate_staleness: 0,
+        })
+    };
+
+    assert_eq!(
+        factory.try_create_liquidity_pool(
+            &lp_init_info,
+            &admin,
+            &stable_init_info(vec![
+                &env,
+                tokens[1].clone(),
+                tokens[0].clone(),
+                tokens[2].clone()
+            ]),
+        ),
+        Err(Ok(FactoryError::TokensNotSorted))
+    );
+
+    let stable_lp_addr = factory.create_liquidity_pool(
+        &lp_init_info,
+        &admin,
+        &stable_init_info(vec![
+            &env,
+            tokens[0].clone(),
+            tokens[1].clone(),
+            tokens[2].clone(),
+        ]),
+    );
+
+    let result = factory.query_pool_details(&stable_lp_addr);
+    assert_eq!(result.assets.len(), 3);
+    for (asset, token) in result.assets.iter().zip(tokens.iter()) {
+        assert_eq!(&asset.address, token);
+    }
+    assert_eq!(result.pool_response.asset_a.address, tokens[0]);
+    assert_eq!(result.pool_response.asset_b.address, tokens[1]);
+
+    // every pair of the tokens leads to the pool
+    assert_eq!(
+        factory.query_for_pool_by_token_pair(&tokens[0], &tokens[2]),
+        stable_lp_addr
+    );
+    assert_eq!(
+        factory.query_for_pool_by_token_pair(&tokens[2], &tokens[1]),
+        stable_lp_addr
+    );
+}
+
+#[test]
+fn test_stable_pool_does_not_replace_the_pool_of_a_pair() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+
+    let mut tokens = std::vec![
+        env.register_stellar_asset_contract(admin.clone()),
+        env.register_stellar_asset_contract(admin.clone()),
+        env.register_stellar_asset_contract(admin.clone()),
+    ];
+    tokens.sort();
+
+    let factory = deploy_factory_contract(&env, Some(admin.clone()));
+
+    let lp_init_info = LiquidityPoolInitInfo {
+        admin: admin.clone(),
+        fee_recipient: admin.clone(),
+        max_allowed_slippage_bps: 5_000,
+        max_allowed_spread_bps: 500,
+        share_token_decimals: 7,
+        swap_fee_bps: 0,
+        max_referral_bps: 5_000,
+        token_init_info: TokenInitInfo {
+            token_a: tokens[0].clone(),
+            token_b: tokens[1].clone(),
+        },
+        stake_init_info: StakeInitInfo {
+            min_bond: 10i128,
+            max_distributions: 10u32,
+            min_reward: 5i128,
+        },
+    };
+
+    let xyk_lp_addr = factory.create_liquidity_pool(&lp_init_info, &admin, &PoolTypeInitInfo::Xyk);
+    let stable_lp_addr = factory.create_liquidity_pool(
+        &lp_init_info,
+        &admin,
+        &PoolTypeInitInfo::Stable(StableInitInfo {
+            amp: 6,
+            tokens: vec![
+                &env,
+                tokens[0].clone(),
+                tokens[1].clone(),
+                tokens[2].clone(),
+            ],
+            rate_providers: vec![&env],
+            max_rate_staleness: 0,
+        }),
+    );
+    assert_ne!(xyk_lp_addr, stable_lp_addr);
+
+    // the pair of the XYK pool keeps leading to it
+    assert_eq!(
+        factory.query_for_pool_by_token_pair(&tokens[0], &tokens[1]),
+        xyk_lp_addr
+    );
+    assert_eq!(
+        factory.query_for_pool_by_token_pair(&tokens[1], &tokens[0]),
+        xyk_lp_addr
+    );
+    // the pairs without a pool lead to the stable pool
+    assert_eq!(
+        factory.query_for_pool_by_token_pair(&tokens[0], &tokens[2]),
+        stable_lp_addr
+    );
+    assert_eq!(
+        factory.query_for_pool_by_token_pair(&tokens[1], &tokens[2]),
+        stable_lp_addr
+    );
+
+    assert_eq!(
+        factory.query_pools(),
+        vec![&env, xyk_lp_addr, stable_lp_addr]
+    );
 }
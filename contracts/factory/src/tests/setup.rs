@@ -7,7 +7,10 @@ pub mod lp_contract {
     );
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn install_multihop_wasm(env: &Env) -> BytesN<32> {
+    // the multihop spec references the pool `Referral` type without exporting it
+    use lp_contract::Referral;
     soroban_sdk::contractimport!(
         file = "../../target/wasm32-unknown-unknown/release/phoenix_multihop.wasm"
     );
@@ -18,6 +21,14 @@ pub fn install_lp_contract(env: &Env) -> BytesN<32> {
     env.deployer().upload_contract_wasm(lp_contract::WASM)
 }
 
+#[allow(clippy::too_many_arguments)]
+pub fn install_stable_lp(env: &Env) -> BytesN<32> {
+    soroban_sdk::contractimport!(
+        file = "../../target/wasm32-unknown-unknown/release/phoenix_pool_stable.wasm"
+    );
+    env.deployer().upload_contract_wasm(WASM)
+}
+
 pub fn install_token_wasm(env: &Env) -> BytesN<32> {
     soroban_sdk::contractimport!(
         file = "../../target/wasm32-unknown-unknown/release/soroban_token_contract.wasm"
@@ -37,11 +48,14 @@ pub fn deploy_factory_contract<'a>(
     admin: impl Into<Option<Address>>,
 ) -> FactoryClient<'a> {
     let admin = admin.into().unwrap_or(Address::generate(env));
+    // deploying the multihop contract alone does not fit in the default budget
+    env.budget().reset_unlimited();
     let factory = FactoryClient::new(env, &env.register_contract(None, Factory {}));
     let multihop_wasm_hash = install_multihop_wasm(env);
     let whitelisted_accounts = vec![env, admin.clone()];
 
     let lp_wasm_hash = install_lp_contract(env);
+    let stable_wasm_hash = install_stable_lp(env);
     let stake_wasm_hash = install_stake_wasm(env);
     let token_wasm_hash = install_token_wasm(env);
 
@@ -49,6 +63,7 @@ pub fn deploy_factory_contract<'a>(
         &admin,
         &multihop_wasm_hash,
         &lp_wasm_hash,
+        &stable_wasm_hash,
         &stake_wasm_hash,
         &token_wasm_hash,
         &whitelisted_accounts,
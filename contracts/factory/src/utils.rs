@@ -1,11 +1,6 @@
 use soroban_sdk::{xdr::ToXdr, Address, Bytes, BytesN, Env, IntoVal, Symbol, Val, Vec};
 
-pub fn deploy_lp_contract(
-    env: &Env,
-    lp_wasm_hash: BytesN<32>,
-    token_a: &Address,
-    token_b: &Address,
-) -> Address {
+pub fn deploy_lp_contract(env: &Env, lp_wasm_hash: BytesN<32>, tokens: &Vec<Address>) -> Address {
     let deployer = env.current_contract_address();
 
     if deployer != env.current_contract_address() {
@@ -13,8 +8,9 @@ pub fn deploy_lp_contract(
     }
 
     let mut salt = Bytes::new(env);
-    salt.append(&token_a.to_xdr(env));
-    salt.append(&token_b.to_xdr(env));
+    for token in tokens.iter() {
+        salt.append(&token.to_xdr(env));
+    }
     let salt = env.crypto().sha256(&salt);
 
     env.deployer()
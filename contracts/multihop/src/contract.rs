@@ -1,13 +1,19 @@
-use soroban_sdk::{contract, contractimpl, contractmeta, vec, Address, Env, Vec};
+use phoenix::{error::MultihopError, utils::PoolType, validate_int_parameters};
+use soroban_sdk::{contract, contractimpl, contractmeta, log, vec, Address, Env, Vec};
 
-// FIXM: Disable Referral struct
-// use crate::lp_contract::Referral;
+use crate::factory_contract;
+use crate::lp_contract::Referral;
+use crate::pool::PoolClient;
+use crate::router::find_best_route;
 use crate::storage::{
-    get_factory, is_initialized, save_factory, set_initialized, DataKey,
-    SimulateReverseSwapResponse, SimulateSwapResponse, Swap,
+    get_factory, is_initialized, save_factory, set_initialized, BestRouteResponse, DataKey,
+    SimulateHopResponse, SimulateReverseSwapResponse, SimulateSplitRouteResponse,
+    SimulateSplitSwapResponse, SimulateSwapResponse, SplitRoute, Swap,
+};
+use crate::utils::{
+    split_amount, verify_deadline, verify_min_amount_out, verify_reverse_swap, verify_split_pools,
+    verify_split_routes, verify_swap,
 };
-use crate::utils::{verify_reverse_swap, verify_swap};
-use crate::{factory_contract, lp_contract};
 
 // Metadata that is added on to the WASM custom section
 contractmeta!(
@@ -19,35 +25,112 @@ contractmeta!(
 pub struct Multihop;
 
 pub trait MultihopTrait {
-    fn initialize(env: Env, admin: Address, factory: Address);
-
+    fn initialize(env: Env, admin: Address, factory: Address) -> Result<(), MultihopError>;
+
+    // Swaps `amount` through all the operations, one after another.
+    // `recipient` pays for the swap and the output of the last operation is sent to `to`, or back
+    // to `recipient` if it is not set.
+    // Fails if the amount received from the last one is below `min_amount_out`, or if the ledger
+    // timestamp is past the optional `deadline`.
+    // Returns the amount of the ask asset of the last operation received.
+    #[allow(clippy::too_many_arguments)]
     fn swap(
         env: Env,
         recipient: Address,
-        // FIXM: Disable Referral struct
-        // referral: Option<Referral>,
+        referral: Option<Referral>,
         operations: Vec<Swap>,
-        max_belief_price: Option<i64>,
-        max_spread_bps: Option<i64>,
         amount: i128,
-    );
+        min_amount_out: i128,
+        deadline: Option<u64>,
+        to: Option<Address>,
+    ) -> Result<i128, MultihopError>;
+
+    // Buys exactly `ask_amount` of the ask asset of the last operation, spending at most
+    // `max_offer_amount` of the offer asset of the first operation. `recipient` pays for the swap
+    // and the bought tokens are sent to `to`, or back to `recipient` if it is not set.
+    // Fails like `swap` if the ledger timestamp is past the optional `deadline`.
+    // Returns the amount of the offer asset spent.
+    fn swap_exact_out(
+        env: Env,
+        recipient: Address,
+        operations: Vec<Swap>,
+        ask_amount: i128,
+        max_offer_amount: i128,
+        deadline: Option<u64>,
+        to: Option<Address>,
+    ) -> Result<i128, MultihopError>;
+
+    // Swaps `amount` of `offer_asset` for `ask_asset` through the route found by `find_best_route`.
+    // Fails like `swap` if less than `min_amount_out` is received or if the ledger timestamp is
+    // past the optional `deadline`.
+    // Returns the amount of `ask_asset` received.
+    #[allow(clippy::too_many_arguments)]
+    fn swap_best_route(
+        env: Env,
+        recipient: Address,
+        offer_asset: Address,
+        ask_asset: Address,
+        max_hops: u32,
+        amount: i128,
+        min_amount_out: i128,
+        deadline: Option<u64>,
+    ) -> Result<i128, MultihopError>;
+
+    // Splits `amount` between several routes from the same offer asset to the same ask asset,
+    // according to their `allocation_bps`, and swaps through all of them.
+    // Fails like `swap` if the total amount received is below `min_amount_out` or if the ledger
+    // timestamp is past the optional `deadline`.
+    // Returns the total amount of the ask asset received.
+    fn swap_split(
+        env: Env,
+        recipient: Address,
+        referral: Option<Referral>,
+        routes: Vec<SplitRoute>,
+        amount: i128,
+        min_amount_out: i128,
+        deadline: Option<u64>,
+    ) -> Result<i128, MultihopError>;
 
-    fn simulate_swap(env: Env, operations: Vec<Swap>, amount: i128) -> SimulateSwapResponse;
+    // Searches the pools registered in the factory for the route of at most `max_hops` swaps
+    // returning the most of `ask_asset` for `amount` of `offer_asset`
+    fn find_best_route(
+        env: Env,
+        offer_asset: Address,
+        ask_asset: Address,
+        amount: i128,
+        max_hops: u32,
+    ) -> Result<BestRouteResponse, MultihopError>;
+
+    fn simulate_swap(
+        env: Env,
+        operations: Vec<Swap>,
+        amount: i128,
+    ) -> Result<SimulateSwapResponse, MultihopError>;
 
     fn simulate_reverse_swap(
         env: Env,
         operations: Vec<Swap>,
         amount: i128,
-    ) -> SimulateReverseSwapResponse;
+    ) -> Result<SimulateReverseSwapResponse, MultihopError>;
+
+    fn simulate_split_swap(
+        env: Env,
+        routes: Vec<SplitRoute>,
+        amount: i128,
+    ) -> Result<SimulateSplitSwapResponse, MultihopError>;
 
     fn get_admin(env: Env) -> Address;
 }
 
 #[contractimpl]
 impl MultihopTrait for Multihop {
-    fn initialize(env: Env, admin: Address, factory: Address) {
+    fn initialize(env: Env, admin: Address, factory: Address) -> Result<(), MultihopError> {
         if is_initialized(&env) {
-            panic!("Multihop: Initialize: initializing contract twice is not allowed");
+            log!(
+                &env,
+                "Multihop: Initialize: initializing contract twice is not allowed"
+            );
+            return Err(MultihopError::AlreadyInitialized);
         }
 
         set_initialized(&env);
@@ -60,135 +143,289 @@ impl MultihopTrait for Multihop {
 
         env.events()
             .publish(("initialize", "Multihop factory with admin: "), admin);
+
+        Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn swap(
         env: Env,
         recipient: Address,
-        // FIXM: Disable Referral struct
-        // referral: Option<Referral>,
+        referral: Option<Referral>,
         operations: Vec<Swap>,
-        max_belief_price: Option<i64>,
-        max_spread_bps: Option<i64>,
         amount: i128,
-    ) {
+        min_amount_out: i128,
+        deadline: Option<u64>,
+        to: Option<Address>,
+    ) -> Result<i128, MultihopError> {
+        verify_deadline(&env, deadline)?;
         if operations.is_empty() {
-            panic!("Multihop: Swap: operations is empty!");
+            log!(&env, "Multihop: Swap: operations is empty!");
+            return Err(MultihopError::OperationsEmpty);
         }
-        verify_swap(&operations);
+        verify_swap(&env, &operations)?;
 
         recipient.require_auth();
 
-        // first offer amount is an input from the user,
-        // subsequent are the results of the previous swap
-        let mut next_offer_amount: i128 = amount;
+        // the pools keep applying their own spread limits, the only bound that makes sense
+        // across assets of different prices is the amount received at the end of the route
+        let to = to.unwrap_or_else(|| recipient.clone());
+        let amount_out = execute_swaps(&env, &recipient, &to, &referral, &operations, amount);
+
+        verify_min_amount_out(&env, amount_out, min_amount_out)?;
+
+        Ok(amount_out)
+    }
+
+    fn swap_exact_out(
+        env: Env,
+        recipient: Address,
+        operations: Vec<Swap>,
+        ask_amount: i128,
+        max_offer_amount: i128,
+        deadline: Option<u64>,
+        to: Option<Address>,
+    ) -> Result<i128, MultihopError> {
+        verify_deadline(&env, deadline)?;
+        if operations.is_empty() {
+            log!(&env, "Multihop: Swap exact out: operations is empty!");
+            return Err(MultihopError::OperationsEmpty);
+        }
+        verify_swap(&env, &operations)?;
+
+        recipient.require_auth();
 
         let factory_client = factory_contract::Client::new(&env, &get_factory(&env));
 
-        operations.iter().for_each(|op| {
-            let liquidity_pool_addr: Address = factory_client
-                .query_for_pool_by_token_pair(&op.clone().offer_asset, &op.ask_asset.clone());
-
-            let lp_client = lp_contract::Client::new(&env, &liquidity_pool_addr);
-            // FIXM: Disable Referral struct
-            // if let Some(referral) = referral.clone() {
-            //     next_offer_amount = lp_client.swap(
-            //         &recipient,
-            //         &Some(referral),
-            //         &op.offer_asset,
-            //         &next_offer_amount,
-            //         &max_belief_price,
-            //         &max_spread_bps,
-            //     );
-            // } else {
-            next_offer_amount = lp_client.swap(
+        // walk the operations backwards to find out how much every hop has to offer,
+        // the ask amount of a hop being the offer amount of the following one
+        let mut pools: Vec<(Address, PoolType)> = Vec::new(&env);
+        let mut offer_amounts: Vec<i128> = Vec::new(&env);
+        let mut next_ask_amount: i128 = ask_amount;
+        for op in operations.iter().rev() {
+            let pool = PoolClient::for_swap(&env, &factory_client, &op);
+            next_ask_amount = pool
+                .simulate_reverse_swap(&op.offer_asset, &op.ask_asset, next_ask_amount)
+                .offer_amount;
+
+            pools.push_front((pool.address, pool.pool_type));
+            offer_amounts.push_front(next_ask_amount);
+        }
+
+        let offer_amount = offer_amounts.first().unwrap();
+        if offer_amount > max_offer_amount {
+            log!(
+                &env,
+                "Multihop: Swap exact out: Offer amount {} exceeds the maximum of {}",
+                offer_amount,
+                max_offer_amount
+            );
+            return Err(MultihopError::OfferAmountExceedsMax);
+        }
+
+        let last_index = operations.len() - 1;
+        for (index, op) in operations.iter().enumerate() {
+            let index = index as u32;
+            let hop_ask_amount = offer_amounts.get(index + 1).unwrap_or(ask_amount);
+            // like in `swap`, only the last hop sends its output past the recipient
+            let receiver = if index == last_index {
+                to.clone()
+            } else {
+                None
+            };
+
+            let (pool_address, pool_type) = pools.get(index).unwrap();
+            PoolClient::new(&env, pool_address, pool_type).swap_exact_out(
                 &recipient,
                 &op.offer_asset,
-                &next_offer_amount,
-                &max_belief_price,
-                &max_spread_bps,
+                &op.ask_asset,
+                hop_ask_amount,
+                offer_amounts.get(index).unwrap(),
+                &receiver,
             );
-            // }
-        });
+        }
+
+        Ok(offer_amount)
     }
 
-    fn simulate_swap(env: Env, operations: Vec<Swap>, amount: i128) -> SimulateSwapResponse {
-        if operations.is_empty() {
-            panic!("Multihop: Simulate swap: operations empty");
-        }
+    #[allow(clippy::too_many_arguments)]
+    fn swap_best_route(
+        env: Env,
+        recipient: Address,
+        offer_asset: Address,
+        ask_asset: Address,
+        max_hops: u32,
+        amount: i128,
+        min_amount_out: i128,
+        deadline: Option<u64>,
+    ) -> Result<i128, MultihopError> {
+        validate_int_parameters!(amount)?;
+        verify_deadline(&env, deadline)?;
 
-        verify_swap(&operations);
+        let route = find_best_route(&env, offer_asset, ask_asset, amount, max_hops)?;
+        verify_swap(&env, &route.operations)?;
 
-        let mut next_offer_amount: i128 = amount;
+        recipient.require_auth();
 
-        let mut simulate_swap_response = SimulateSwapResponse {
-            ask_amount: 0,
-            total_commission_amount: 0,
-            spread_amount: vec![&env],
-        };
+        let amount_out = execute_swaps(
+            &env,
+            &recipient,
+            &recipient,
+            &None,
+            &route.operations,
+            amount,
+        );
 
-        let factory_client = factory_contract::Client::new(&env, &get_factory(&env));
+        verify_min_amount_out(&env, amount_out, min_amount_out)?;
 
-        operations.iter().for_each(|op| {
-            let liquidity_pool_addr: Address = factory_client
-                .query_for_pool_by_token_pair(&op.clone().offer_asset, &op.ask_asset.clone());
+        Ok(amount_out)
+    }
 
-            let lp_client = lp_contract::Client::new(&env, &liquidity_pool_addr);
-            let simulate_swap = lp_client.simulate_swap(&op.offer_asset, &next_offer_amount);
+    fn swap_split(
+        env: Env,
+        recipient: Address,
+        referral: Option<Referral>,
+        routes: Vec<SplitRoute>,
+        amount: i128,
+        min_amount_out: i128,
+        deadline: Option<u64>,
+    ) -> Result<i128, MultihopError> {
+        validate_int_parameters!(amount)?;
+        verify_deadline(&env, deadline)?;
+        verify_split_routes(&env, &routes)?;
+        verify_split_pools(&env, &routes)?;
 
-            simulate_swap_response.total_commission_amount += simulate_swap.commission_amount;
-            simulate_swap_response.ask_amount = simulate_swap.ask_amount;
-            simulate_swap_response
-                .spread_amount
-                .push_back(simulate_swap.spread_amount);
+        recipient.require_auth();
 
-            next_offer_amount = simulate_swap.ask_amount;
-        });
+        let mut ask_amount = 0;
+        for (route, route_amount) in routes.iter().zip(split_amount(&env, &routes, amount)) {
+            // a tiny allocation of a small amount can round down to nothing
+            if route_amount == 0 {
+                continue;
+            }
+            // like in `swap`, the pools keep applying their own spread limits and the total
+            // amount received is bound by `min_amount_out`
+            ask_amount += execute_swaps(
+                &env,
+                &recipient,
+                &recipient,
+                &referral,
+                &route.operations,
+                route_amount,
+            );
+        }
+
+        verify_min_amount_out(&env, ask_amount, min_amount_out)?;
+
+        Ok(ask_amount)
+    }
+
+    fn find_best_route(
+        env: Env,
+        offer_asset: Address,
+        ask_asset: Address,
+        amount: i128,
+        max_hops: u32,
+    ) -> Result<BestRouteResponse, MultihopError> {
+        validate_int_parameters!(amount)?;
 
-        simulate_swap_response
+        find_best_route(&env, offer_asset, ask_asset, amount, max_hops)
+    }
+
+    fn simulate_swap(
+        env: Env,
+        operations: Vec<Swap>,
+        amount: i128,
+    ) -> Result<SimulateSwapResponse, MultihopError> {
+        if operations.is_empty() {
+            log!(&env, "Multihop: Simulate swap: operations empty");
+            return Err(MultihopError::OperationsEmpty);
+        }
+
+        verify_swap(&env, &operations)?;
+
+        Ok(simulate_swaps(&env, &operations, amount))
     }
 
     fn simulate_reverse_swap(
         env: Env,
         operations: Vec<Swap>,
         amount: i128,
-    ) -> SimulateReverseSwapResponse {
+    ) -> Result<SimulateReverseSwapResponse, MultihopError> {
         if operations.is_empty() {
-            panic!("Multihop: Simulate reverse swap: operations empty");
+            log!(&env, "Multihop: Simulate reverse swap: operations empty");
+            return Err(MultihopError::OperationsEmpty);
         }
 
-        verify_reverse_swap(&operations);
+        verify_reverse_swap(&env, &operations)?;
 
         let mut next_ask_amount: i128 = amount;
 
         let mut simulate_swap_response = SimulateReverseSwapResponse {
             offer_amount: 0,
-            total_commission_amount: 0,
-            spread_amount: vec![&env],
+            hops: vec![&env],
         };
 
         let factory_client = factory_contract::Client::new(&env, &get_factory(&env));
 
         operations.iter().for_each(|op| {
-            let liquidity_pool_addr: Address = factory_client
-                .query_for_pool_by_token_pair(&op.clone().offer_asset, &op.ask_asset.clone());
-
-            let lp_client = lp_contract::Client::new(&env, &liquidity_pool_addr);
+            let pool = PoolClient::for_swap(&env, &factory_client, &op);
             let simulate_reverse_swap =
-                lp_client.simulate_reverse_swap(&op.ask_asset, &next_ask_amount);
+                pool.simulate_reverse_swap(&op.offer_asset, &op.ask_asset, next_ask_amount);
 
-            simulate_swap_response.total_commission_amount +=
-                simulate_reverse_swap.commission_amount;
             simulate_swap_response.offer_amount = simulate_reverse_swap.offer_amount;
-
-            simulate_swap_response
-                .spread_amount
-                .push_back(simulate_reverse_swap.spread_amount);
+            simulate_swap_response.hops.push_back(hop_response(
+                pool,
+                op,
+                simulate_reverse_swap.offer_amount,
+                next_ask_amount,
+                simulate_reverse_swap.commission_amount,
+                simulate_reverse_swap.spread_amount,
+            ));
 
             next_ask_amount = simulate_reverse_swap.offer_amount;
         });
 
-        simulate_swap_response
+        Ok(simulate_swap_response)
+    }
+
+    fn simulate_split_swap(
+        env: Env,
+        routes: Vec<SplitRoute>,
+        amount: i128,
+    ) -> Result<SimulateSplitSwapResponse, MultihopError> {
+        validate_int_parameters!(amount)?;
+        verify_split_routes(&env, &routes)?;
+        verify_split_pools(&env, &routes)?;
+
+        let mut simulate_split_swap_response = SimulateSplitSwapResponse {
+            ask_amount: 0,
+            routes: vec![&env],
+        };
+
+        for (route, route_amount) in routes.iter().zip(split_amount(&env, &routes, amount)) {
+            let route_response = if route_amount == 0 {
+                SimulateSplitRouteResponse {
+                    offer_amount: 0,
+                    ask_amount: 0,
+                    hops: vec![&env],
+                }
+            } else {
+                let simulate_swap = simulate_swaps(&env, &route.operations, route_amount);
+                SimulateSplitRouteResponse {
+                    offer_amount: route_amount,
+                    ask_amount: simulate_swap.ask_amount,
+                    hops: simulate_swap.hops,
+                }
+            };
+
+            simulate_split_swap_response.ask_amount += route_response.ask_amount;
+            simulate_split_swap_response
+                .routes
+                .push_back(route_response);
+        }
+
+        Ok(simulate_split_swap_response)
     }
 
     fn get_admin(env: Env) -> Address {
@@ -198,3 +435,104 @@ impl MultihopTrait for Multihop {
             .expect("Multihop: No admin found")
     }
 }
+
+/// Swaps through the pools of the given operations, one after another. The `recipient` pays for
+/// every hop and the last one sends its output directly to `to`.
+/// Returns the amount of the ask asset of the last operation received.
+fn execute_swaps(
+    env: &Env,
+    recipient: &Address,
+    to: &Address,
+    referral: &Option<Referral>,
+    operations: &Vec<Swap>,
+    amount: i128,
+) -> i128 {
+    // first offer amount is an input from the user,
+    // subsequent are the results of the previous swap
+    let mut next_offer_amount: i128 = amount;
+
+    let factory_client = factory_contract::Client::new(env, &get_factory(env));
+
+    let last_index = operations.len() - 1;
+    for (index, op) in operations.iter().enumerate() {
+        // intermediate outputs go back to the recipient, which offers them to the next hop
+        let receiver = if index as u32 == last_index {
+            Some(to.clone())
+        } else {
+            None
+        };
+        next_offer_amount = PoolClient::for_swap(env, &factory_client, &op).swap(
+            recipient,
+            referral,
+            &op.offer_asset,
+            &op.ask_asset,
+            next_offer_amount,
+            None,
+            None,
+            &receiver,
+        );
+    }
+
+    next_offer_amount
+}
+
+/// Simulates the swaps of the given operations, one after another.
+fn simulate_swaps(env: &Env, operations: &Vec<Swap>, amount: i128) -> SimulateSwapResponse {
+    let mut next_offer_amount: i128 = amount;
+
+    let mut simulate_swap_response = SimulateSwapResponse {
+        ask_amount: 0,
+        hops: vec![env],
+    };
+
+    let factory_client = factory_contract::Client::new(env, &get_factory(env));
+
+    operations.iter().for_each(|op| {
+        let pool = PoolClient::for_swap(env, &factory_client, &op);
+        let simulate_swap = pool.simulate_swap(&op.offer_asset, &op.ask_asset, next_offer_amount);
+
+        simulate_swap_response.ask_amount = simulate_swap.ask_amount;
+        simulate_swap_response.hops.push_back(hop_response(
+            pool,
+            op,
+            next_offer_amount,
+            simulate_swap.ask_amount,
+            simulate_swap.commission_amount,
+            simulate_swap.spread_amount,
+        ));
+
+        next_offer_amount = simulate_swap.ask_amount;
+    });
+
+    simulate_swap_response
+}
+
+/// Describes a simulated hop. The price impact is the spread relative to the amount that
+/// would have been bought at the pool price before the swap.
+fn hop_response(
+    pool: PoolClient,
+    op: Swap,
+    offer_amount: i128,
+    ask_amount: i128,
+    commission_amount: i128,
+    spread_amount: i128,
+) -> SimulateHopResponse {
+    let amount_at_pool_price = ask_amount + commission_amount + spread_amount;
+    let price_impact_bps = if amount_at_pool_price > 0 {
+        (spread_amount * 10_000 / amount_at_pool_price) as i64
+    } else {
+        0
+    };
+
+    SimulateHopResponse {
+        pool_address: pool.address,
+        pool_type: pool.pool_type,
+        offer_asset: op.offer_asset,
+        ask_asset: op.ask_asset,
+        offer_amount,
+        ask_amount,
+        commission_amount,
+        spread_amount,
+        price_impact_bps,
+    }
+}
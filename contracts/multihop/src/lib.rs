@@ -1,6 +1,10 @@
 #![no_std]
 mod contract;
 
+mod pool;
+
+mod router;
+
 mod storage;
 
 mod utils;
@@ -15,6 +19,14 @@ pub mod lp_contract {
     );
 }
 
+#[allow(clippy::too_many_arguments)]
+pub mod stable_lp_contract {
+    soroban_sdk::contractimport!(
+        file = "../../target/wasm32-unknown-unknown/release/phoenix_pool_stable.wasm"
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
 pub mod factory_contract {
     soroban_sdk::contractimport!(
         file = "../../target/wasm32-unknown-unknown/release/phoenix_factory.wasm"
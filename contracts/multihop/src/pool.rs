@@ -0,0 +1,162 @@
+use phoenix::utils::PoolType;
+use soroban_sdk::{Address, Env};
+
+use crate::factory_contract;
+use crate::lp_contract::{self, Referral, SimulateReverseSwapResponse, SimulateSwapResponse};
+use crate::stable_lp_contract;
+use crate::storage::{Swap, SwapPool};
+
+impl From<factory_contract::PoolType> for PoolType {
+    fn from(pool_type: factory_contract::PoolType) -> Self {
+        match pool_type {
+            factory_contract::PoolType::Xyk => PoolType::Xyk,
+            factory_contract::PoolType::Stable => PoolType::Stable,
+        }
+    }
+}
+
+/// Client of a pool that a route goes through. The XYK and the stable pools have different
+/// interfaces, so every call is dispatched to the client of the type the pool was created with.
+pub struct PoolClient<'a> {
+    env: &'a Env,
+    pub address: Address,
+    pub pool_type: PoolType,
+}
+
+impl<'a> PoolClient<'a> {
+    pub fn new(env: &'a Env, address: Address, pool_type: PoolType) -> Self {
+        PoolClient {
+            env,
+            address,
+            pool_type,
+        }
+    }
+
+    /// Looks up the pool of the given pair and its type in the factory.
+    pub fn for_pair(
+        env: &'a Env,
+        factory_client: &factory_contract::Client,
+        offer_asset: &Address,
+        ask_asset: &Address,
+    ) -> Self {
+        let address = factory_client.query_for_pool_by_token_pair(offer_asset, ask_asset);
+        let pool_type = factory_client.query_pool_type(&address).into();
+
+        Self::new(env, address, pool_type)
+    }
+
+    /// The pool that the given swap goes through.
+    pub fn for_swap(env: &'a Env, factory_client: &factory_contract::Client, op: &Swap) -> Self {
+        match &op.pool {
+            SwapPool::Pair => Self::for_pair(env, factory_client, &op.offer_asset, &op.ask_asset),
+            SwapPool::Pool(address, pool_type) => Self::new(env, address.clone(), *pool_type),
+        }
+    }
+
+    /// Stable pools do not pay referral commissions, so `referral` only applies to XYK pools.
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap(
+        &self,
+        sender: &Address,
+        referral: &Option<Referral>,
+        offer_asset: &Address,
+        ask_asset: &Address,
+        offer_amount: i128,
+        belief_price: Option<i64>,
+        max_spread_bps: Option<i64>,
+        to: &Option<Address>,
+    ) -> i128 {
+        match self.pool_type {
+            PoolType::Xyk => lp_contract::Client::new(self.env, &self.address).swap(
+                sender,
+                referral,
+                offer_asset,
+                &offer_amount,
+                &belief_price,
+                &max_spread_bps,
+                to,
+            ),
+            PoolType::Stable => stable_lp_contract::Client::new(self.env, &self.address).swap(
+                sender,
+                offer_asset,
+                ask_asset,
+                &offer_amount,
+                &belief_price,
+                &max_spread_bps,
+                to,
+            ),
+        }
+    }
+
+    pub fn swap_exact_out(
+        &self,
+        sender: &Address,
+        offer_asset: &Address,
+        ask_asset: &Address,
+        ask_amount: i128,
+        max_offer_amount: i128,
+        to: &Option<Address>,
+    ) -> i128 {
+        match self.pool_type {
+            PoolType::Xyk => lp_contract::Client::new(self.env, &self.address).swap_exact_out(
+                sender,
+                ask_asset,
+                &ask_amount,
+                &max_offer_amount,
+                to,
+            ),
+            PoolType::Stable => stable_lp_contract::Client::new(self.env, &self.address)
+                .swap_exact_out(
+                    sender,
+                    offer_asset,
+                    ask_asset,
+                    &ask_amount,
+                    &max_offer_amount,
+                    to,
+                ),
+        }
+    }
+
+    pub fn simulate_swap(
+        &self,
+        offer_asset: &Address,
+        ask_asset: &Address,
+        offer_amount: i128,
+    ) -> SimulateSwapResponse {
+        match self.pool_type {
+            PoolType::Xyk => lp_contract::Client::new(self.env, &self.address)
+                .simulate_swap(offer_asset, &offer_amount),
+            PoolType::Stable => {
+                let response = stable_lp_contract::Client::new(self.env, &self.address)
+                    .simulate_swap(offer_asset, ask_asset, &offer_amount);
+                SimulateSwapResponse {
+                    ask_amount: response.ask_amount,
+                    commission_amount: response.commission_amount,
+                    spread_amount: response.spread_amount,
+                    total_return: response.total_return,
+                }
+            }
+        }
+    }
+
+    pub fn simulate_reverse_swap(
+        &self,
+        offer_asset: &Address,
+        ask_asset: &Address,
+        ask_amount: i128,
+    ) -> SimulateReverseSwapResponse {
+        match self.pool_type {
+            PoolType::Xyk => lp_contract::Client::new(self.env, &self.address)
+                .simulate_reverse_swap(ask_asset, &ask_amount),
+            PoolType::Stable => {
+                let response = stable_lp_contract::Client::new(self.env, &self.address)
+                    .simulate_reverse_swap(offer_asset, ask_asset, &ask_amount);
+                SimulateReverseSwapResponse {
+                    offer_amount: response.offer_amount,
+                    commission_amount: response.commission_amount,
+                    spread_amount: response.spread_amount,
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use phoenix::error::MultihopError;
+use phoenix::utils::PoolType;
+use soroban_sdk::{log, vec, Address, Env, Map, Vec};
+
+use crate::factory_contract;
+use crate::pool::PoolClient;
+use crate::storage::{get_factory, BestRouteResponse, Swap, SwapPool};
+
+/// Upper limit of `max_hops`, as every additional hop multiplies the number of simulated paths
+pub const MAX_HOPS: u32 = 4;
+
+/// Upper limit of the swaps simulated by one search, which bounds its cost however many pools
+/// the factory holds; the best route among the paths simulated so far is returned
+pub const MAX_SIMULATIONS: u32 = 64;
+
+/// Finds the path of at most `max_hops` swaps between the pools registered in the factory that
+/// returns the most of `ask_asset` for `amount` of `offer_asset`.
+pub fn find_best_route(
+    env: &Env,
+    offer_asset: Address,
+    ask_asset: Address,
+    amount: i128,
+    max_hops: u32,
+) -> Result<BestRouteResponse, MultihopError> {
+    if max_hops == 0 || max_hops > MAX_HOPS {
+        log!(
+            env,
+            "Multihop: Find best route: max_hops must be between 1 and {}",
+            MAX_HOPS
+        );
+        return Err(MultihopError::InvalidMaxHops);
+    }
+
+    let factory_client = factory_contract::Client::new(env, &get_factory(env));
+    // every asset of a pool holding some liquidity can be swapped for any other of its assets
+    let mut edges: Map<Address, Vec<Edge>> = Map::new(env);
+    for pool in factory_client.query_all_pools_details() {
+        if pool.assets.iter().any(|asset| asset.amount <= 0) {
+            continue;
+        }
+        let pool_type: PoolType = pool.pool_type.into();
+        for offer in pool.assets.iter() {
+            let mut asset_edges = edges.get(offer.address.clone()).unwrap_or(Vec::new(env));
+            for ask in pool.assets.iter() {
+                if ask.address != offer.address {
+                    asset_edges.push_back((pool.pool_address.clone(), pool_type, ask.address));
+                }
+            }
+            edges.set(offer.address, asset_edges);
+        }
+    }
+
+    let mut search = RouteSearch {
+        env,
+        edges,
+        ask_asset: ask_asset.clone(),
+        path: Vec::new(env),
+        visited: vec![env, offer_asset.clone()],
+        simulations_left: MAX_SIMULATIONS,
+        best: None,
+    };
+    search.visit(offer_asset, amount, max_hops);
+
+    search.best.ok_or_else(|| {
+        log!(
+            env,
+            "Multihop: Find best route: No route found to {}",
+            ask_asset
+        );
+        MultihopError::NoRouteFound
+    })
+}
+
+/// Pool that swaps the asset it is listed under for `ask_asset`: (pool address, pool type,
+/// ask asset)
+type Edge = (Address, PoolType, Address);
+
+/// Depth first search over the pool graph, simulating every path on the way.
+struct RouteSearch<'a> {
+    env: &'a Env,
+    /// Pools leading out of every asset
+    edges: Map<Address, Vec<Edge>>,
+    ask_asset: Address,
+    /// Swaps leading to the asset currently visited
+    path: Vec<Swap>,
+    /// Assets on the current path, so that no path goes through the same asset twice
+    visited: Vec<Address>,
+    /// Number of swaps that can still be simulated before the search stops
+    simulations_left: u32,
+    best: Option<BestRouteResponse>,
+}
+
+impl RouteSearch<'_> {
+    fn visit(&mut self, offer_asset: Address, amount: i128, hops_left: u32) {
+        let Some(asset_edges) = self.edges.get(offer_asset.clone()) else {
+            return;
+        };
+        for (pool_address, pool_type, next_asset) in asset_edges.iter() {
+            if self.visited.contains(&next_asset) {
+                continue;
+            }
+            // a path that can not reach the ask asset in the hops left is not worth simulating
+            if hops_left == 1 && next_asset != self.ask_asset {
+                continue;
+            }
+            if self.simulations_left == 0 {
+                return;
+            }
+            self.simulations_left -= 1;
+
+            let ask_amount = PoolClient::new(self.env, pool_address.clone(), pool_type)
+                .simulate_swap(&offer_asset, &next_asset, amount)
+                .ask_amount;
+            if ask_amount <= 0 {
+                continue;
+            }
+
+            self.path.push_back(Swap {
+                offer_asset: offer_asset.clone(),
+                ask_asset: next_asset.clone(),
+                pool: SwapPool::Pool(pool_address, pool_type),
+            });
+            if next_asset == self.ask_asset {
+                if self
+                    .best
+                    .as_ref()
+                    .map_or(true, |best| ask_amount > best.ask_amount)
+                {
+                    self.best = Some(BestRouteResponse {
+                        operations: self.path.clone(),
+                        ask_amount,
+                    });
+                }
+            } else {
+                self.visited.push_back(next_asset.clone());
+                self.visit(next_asset, ask_amount, hops_left - 1);
+                self.visited.pop_back();
+            }
+            self.path.pop_back();
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use phoenix::utils::PoolType;
 use soroban_sdk::{contracttype, Address, Env, Vec};
 
 #[contracttype]
@@ -5,6 +6,18 @@ use soroban_sdk::{contracttype, Address, Env, Vec};
 pub struct Swap {
     pub ask_asset: Address,
     pub offer_asset: Address,
+    /// The pool to swap in
+    pub pool: SwapPool,
+}
+
+/// The pool that a swap goes through
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SwapPool {
+    /// The pool registered in the factory for the pair of the swap
+    Pair,
+    /// The pool at the given address, of the given type
+    Pool(Address, PoolType),
 }
 
 #[derive(Clone)]
@@ -63,18 +76,77 @@ pub fn set_initialized(e: &Env) {
     e.storage().persistent().set(&DataKey::Initialized, &true);
 }
 
+/// Simulated swap in one of the pools of a route
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulateHopResponse {
+    pub pool_address: Address,
+    pub pool_type: PoolType,
+    pub offer_asset: Address,
+    pub ask_asset: Address,
+    /// The amount of the offer asset sold in the pool
+    pub offer_amount: i128,
+    /// The amount of the ask asset bought, after the commission
+    pub ask_amount: i128,
+    /// The commission charged by the pool, in the ask asset
+    pub commission_amount: i128,
+    /// The difference between the amount bought at the pool price before the swap and the
+    /// amount actually bought, in the ask asset
+    pub spread_amount: i128,
+    /// The share of the amount at the pool price before the swap lost to the spread, in bps
+    pub price_impact_bps: i64,
+}
+
 #[contracttype]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SimulateSwapResponse {
     pub ask_amount: i128,
-    pub total_commission_amount: i128,
-    pub spread_amount: Vec<i128>,
+    /// Every hop of the route, in the order of the operations
+    pub hops: Vec<SimulateHopResponse>,
 }
 
 #[contracttype]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SimulateReverseSwapResponse {
     pub offer_amount: i128,
-    pub total_commission_amount: i128,
-    pub spread_amount: Vec<i128>,
+    /// Every hop of the route, in the order of the operations, thus starting with the last swap
+    pub hops: Vec<SimulateHopResponse>,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BestRouteResponse {
+    /// The swaps of the route, in the order they are executed
+    pub operations: Vec<Swap>,
+    /// The amount of the ask asset returned by the route
+    pub ask_amount: i128,
+}
+
+/// One of the routes of a split swap
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitRoute {
+    /// The swaps of the route, in the order they are executed
+    pub operations: Vec<Swap>,
+    /// The share of the offered amount that goes through this route, in bps
+    pub allocation_bps: i64,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulateSplitRouteResponse {
+    /// The amount of the offer asset that goes through the route
+    pub offer_amount: i128,
+    pub ask_amount: i128,
+    /// Every hop of the route, in the order of the operations
+    pub hops: Vec<SimulateHopResponse>,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulateSplitSwapResponse {
+    /// The total amount of the ask asset returned by all the routes
+    pub ask_amount: i128,
+    /// The simulation of every route, in the order of the given routes
+    pub routes: Vec<SimulateSplitRouteResponse>,
 }
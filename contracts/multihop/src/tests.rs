@@ -1,5 +1,6 @@
 use crate::contract::{Multihop, MultihopClient};
 use crate::tests::setup::deploy_factory_contract;
+use phoenix::error::MultihopError;
 use soroban_sdk::{testutils::Address as _, Address, Env};
 
 mod query;
@@ -7,7 +8,6 @@ mod setup;
 mod swap;
 
 #[test]
-#[should_panic(expected = "Multihop: Initialize: initializing contract twice is not allowed")]
 fn test_deploy_multihop_twice_should_fail() {
     let env = Env::default();
     env.mock_all_auths();
@@ -18,5 +18,8 @@ fn test_deploy_multihop_twice_should_fail() {
     let multihop = MultihopClient::new(&env, &env.register_contract(None, Multihop {}));
     let factory = deploy_factory_contract(&env, admin.clone());
     multihop.initialize(&admin, &factory);
-    multihop.initialize(&admin, &factory);
+    assert_eq!(
+        multihop.try_initialize(&admin, &factory),
+        Err(Ok(MultihopError::AlreadyInitialized))
+    );
 }
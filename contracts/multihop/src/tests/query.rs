@@ -1,10 +1,27 @@
-use crate::storage::Swap;
+use crate::storage::{SimulateHopResponse, SimulateSplitRouteResponse, SplitRoute, Swap, SwapPool};
 use crate::tests::setup::{
-    deploy_and_initialize_factory, deploy_and_initialize_lp, deploy_and_mint_tokens,
-    deploy_multihop_contract, deploy_token_contract,
+    deploy_and_initialize_factory, deploy_and_initialize_lp, deploy_and_initialize_stable_lp,
+    deploy_and_mint_tokens, deploy_multihop_contract, deploy_token_contract,
 };
 
-use soroban_sdk::{testutils::Address as _, vec, Address, Env};
+use phoenix::{error::MultihopError, utils::PoolType};
+use soroban_sdk::{testutils::Address as _, vec, Address, Env, Vec};
+
+fn commission_amounts(env: &Env, hops: &Vec<SimulateHopResponse>) -> Vec<i128> {
+    let mut amounts = vec![env];
+    for hop in hops.iter() {
+        amounts.push_back(hop.commission_amount);
+    }
+    amounts
+}
+
+fn spread_amounts(env: &Env, hops: &Vec<SimulateHopResponse>) -> Vec<i128> {
+    let mut amounts = vec![env];
+    for hop in hops.iter() {
+        amounts.push_back(hop.spread_amount);
+    }
+    amounts
+}
 
 #[test]
 fn simulate_swap_single_pool_no_fees() {
@@ -38,6 +55,7 @@ fn simulate_swap_single_pool_no_fees() {
         Swap {
             offer_asset: token1.address.clone(),
             ask_asset: token2.address.clone(),
+            pool: SwapPool::Pair,
         },
     ];
 
@@ -45,15 +63,22 @@ fn simulate_swap_single_pool_no_fees() {
     let result = multihop.simulate_swap(&operation, &1_000);
 
     assert_eq!(result.ask_amount, 2_000i128);
-    assert_eq!(result.total_commission_amount, 0i128);
-    assert_eq!(result.spread_amount, vec![&env, 0i128]);
+    assert_eq!(commission_amounts(&env, &result.hops), vec![&env, 0]);
+    assert_eq!(spread_amounts(&env, &result.hops), vec![&env, 0i128]);
 
     // simulate reverse swap for exact results
     let reverse_simulated_swap = multihop.simulate_reverse_swap(&operation, &2_000i128);
 
-    assert_eq!(reverse_simulated_swap.offer_amount, 1_000i128);
-    assert_eq!(reverse_simulated_swap.total_commission_amount, 0i128);
-    assert_eq!(reverse_simulated_swap.spread_amount, vec![&env, 0i128]);
+    // the pools round the reverse swap up
+    assert_eq!(reverse_simulated_swap.offer_amount, 1_001i128);
+    assert_eq!(
+        commission_amounts(&env, &reverse_simulated_swap.hops),
+        vec![&env, 0]
+    );
+    assert_eq!(
+        spread_amounts(&env, &reverse_simulated_swap.hops),
+        vec![&env, 2i128]
+    );
 }
 
 #[test]
@@ -111,23 +136,29 @@ fn simulate_swap_three_equal_pools_no_fees() {
             Swap {
                 offer_asset: token1.address.clone(),
                 ask_asset: token2.address.clone(),
+                pool: SwapPool::Pair,
             },
             Swap {
                 offer_asset: token2.address.clone(),
                 ask_asset: token3.address.clone(),
+                pool: SwapPool::Pair,
             },
             Swap {
                 offer_asset: token3.address.clone(),
                 ask_asset: token4.address.clone(),
+                pool: SwapPool::Pair,
             },
         ],
         &50i128,
     );
 
     assert_eq!(simulated_swap.ask_amount, 50i128);
-    assert_eq!(simulated_swap.total_commission_amount, 0i128);
     assert_eq!(
-        simulated_swap.spread_amount,
+        commission_amounts(&env, &simulated_swap.hops),
+        vec![&env, 0, 0, 0]
+    );
+    assert_eq!(
+        spread_amounts(&env, &simulated_swap.hops),
         vec![&env, 0i128, 0i128, 0i128]
     );
 
@@ -138,24 +169,31 @@ fn simulate_swap_three_equal_pools_no_fees() {
             Swap {
                 offer_asset: token3.address.clone(),
                 ask_asset: token4.address.clone(),
+                pool: SwapPool::Pair,
             },
             Swap {
                 offer_asset: token2.address.clone(),
                 ask_asset: token3.address.clone(),
+                pool: SwapPool::Pair,
             },
             Swap {
                 offer_asset: token1.address.clone(),
                 ask_asset: token2.address.clone(),
+                pool: SwapPool::Pair,
             },
         ],
         &50i128,
     );
 
-    assert_eq!(reverse_simulated_swap.offer_amount, 50i128);
-    assert_eq!(reverse_simulated_swap.total_commission_amount, 0i128);
+    // the pools round the reverse swap up
+    assert_eq!(reverse_simulated_swap.offer_amount, 53i128);
     assert_eq!(
-        reverse_simulated_swap.spread_amount,
-        vec![&env, 0i128, 0i128, 0i128]
+        commission_amounts(&env, &reverse_simulated_swap.hops),
+        vec![&env, 0, 0, 0]
+    );
+    assert_eq!(
+        spread_amounts(&env, &reverse_simulated_swap.hops),
+        vec![&env, 1i128, 1i128, 1i128]
     );
 }
 
@@ -191,6 +229,7 @@ fn simulate_swap_single_pool_with_fees() {
         Swap {
             offer_asset: token1.address.clone(),
             ask_asset: token2.address.clone(),
+            pool: SwapPool::Pair,
         },
     ];
 
@@ -200,15 +239,28 @@ fn simulate_swap_single_pool_with_fees() {
     // swap 300 from token1 to token2 with 2000 bps (20%)
     // tokens2 will be 240
     assert_eq!(simulated_swap.ask_amount, 240i128);
-    assert_eq!(simulated_swap.total_commission_amount, 60i128);
-    assert_eq!(simulated_swap.spread_amount, vec![&env, 0i128]);
+    assert_eq!(
+        commission_amounts(&env, &simulated_swap.hops),
+        vec![&env, 60]
+    );
+    assert_eq!(
+        spread_amounts(&env, &simulated_swap.hops),
+        vec![&env, 0i128]
+    );
 
     // simulate reverse swap returns same result
     let reverse_simulated_swap = multihop.simulate_reverse_swap(&operation, &240i128);
 
-    assert_eq!(reverse_simulated_swap.offer_amount, 300i128);
-    assert_eq!(reverse_simulated_swap.total_commission_amount, 60i128);
-    assert_eq!(reverse_simulated_swap.spread_amount, vec![&env, 0i128]);
+    // the pools round the reverse swap up
+    assert_eq!(reverse_simulated_swap.offer_amount, 301i128);
+    assert_eq!(
+        commission_amounts(&env, &reverse_simulated_swap.hops),
+        vec![&env, 60]
+    );
+    assert_eq!(
+        spread_amounts(&env, &reverse_simulated_swap.hops),
+        vec![&env, 1i128]
+    );
 }
 
 #[test]
@@ -265,14 +317,17 @@ fn simulate_swap_three_different_pools_no_fees() {
             Swap {
                 offer_asset: token1.address.clone(),
                 ask_asset: token2.address.clone(),
+                pool: SwapPool::Pair,
             },
             Swap {
                 offer_asset: token2.address.clone(),
                 ask_asset: token3.address.clone(),
+                pool: SwapPool::Pair,
             },
             Swap {
                 offer_asset: token3.address.clone(),
                 ask_asset: token4.address.clone(),
+                pool: SwapPool::Pair,
             },
         ],
         &5_000i128,
@@ -280,9 +335,12 @@ fn simulate_swap_three_different_pools_no_fees() {
 
     // constant product formula starts to with which amoutns such as 5k
     assert_eq!(simulated_swap.ask_amount, 4_956i128);
-    assert_eq!(simulated_swap.total_commission_amount, 0i128);
     assert_eq!(
-        simulated_swap.spread_amount,
+        commission_amounts(&env, &simulated_swap.hops),
+        vec![&env, 0, 0, 0]
+    );
+    assert_eq!(
+        spread_amounts(&env, &simulated_swap.hops),
         vec![&env, 24i128, 12i128, 8i128]
     );
 
@@ -293,24 +351,31 @@ fn simulate_swap_three_different_pools_no_fees() {
             Swap {
                 offer_asset: token3.address.clone(),
                 ask_asset: token4.address.clone(),
+                pool: SwapPool::Pair,
             },
             Swap {
                 offer_asset: token2.address.clone(),
                 ask_asset: token3.address.clone(),
+                pool: SwapPool::Pair,
             },
             Swap {
                 offer_asset: token1.address.clone(),
                 ask_asset: token2.address.clone(),
+                pool: SwapPool::Pair,
             },
         ],
         &4_956i128,
     );
 
-    assert_eq!(reverse_simulated_swap.offer_amount, 5_000i128);
-    assert_eq!(reverse_simulated_swap.total_commission_amount, 0i128);
+    // the pools round the reverse swap up
+    assert_eq!(reverse_simulated_swap.offer_amount, 5_003i128);
     assert_eq!(
-        reverse_simulated_swap.spread_amount,
-        vec![&env, 8i128, 12i128, 24i128]
+        commission_amounts(&env, &reverse_simulated_swap.hops),
+        vec![&env, 0, 0, 0]
+    );
+    assert_eq!(
+        spread_amounts(&env, &reverse_simulated_swap.hops),
+        vec![&env, 9i128, 13i128, 25i128]
     );
 }
 
@@ -369,14 +434,17 @@ fn simulate_swap_three_different_pools_with_fees() {
             Swap {
                 offer_asset: token1.address.clone(),
                 ask_asset: token2.address.clone(),
+                pool: SwapPool::Pair,
             },
             Swap {
                 offer_asset: token2.address.clone(),
                 ask_asset: token3.address.clone(),
+                pool: SwapPool::Pair,
             },
             Swap {
                 offer_asset: token3.address.clone(),
                 ask_asset: token4.address.clone(),
+                pool: SwapPool::Pair,
             },
         ],
         &10_000i128,
@@ -406,10 +474,12 @@ fn simulate_swap_three_different_pools_with_fees() {
     // commission_amount = 22_571.3
     // ask_amount = 225_714 - 22_571 = 203_143
     assert_eq!(simulated_swap.ask_amount, 203_143i128);
-    // total_commission_amount = 1_980 + 5_253 + 22_571 = 29_804
-    assert_eq!(simulated_swap.total_commission_amount, 29_804i128);
     assert_eq!(
-        simulated_swap.spread_amount,
+        commission_amounts(&env, &simulated_swap.hops),
+        vec![&env, 1_980, 5_253, 22_571]
+    );
+    assert_eq!(
+        spread_amounts(&env, &simulated_swap.hops),
         vec![&env, 198i128, 936i128, 10671i128]
     );
 
@@ -420,31 +490,36 @@ fn simulate_swap_three_different_pools_with_fees() {
             Swap {
                 offer_asset: token3.address.clone(),
                 ask_asset: token4.address.clone(),
+                pool: SwapPool::Pair,
             },
             Swap {
                 offer_asset: token2.address.clone(),
                 ask_asset: token3.address.clone(),
+                pool: SwapPool::Pair,
             },
             Swap {
                 offer_asset: token1.address.clone(),
                 ask_asset: token2.address.clone(),
+                pool: SwapPool::Pair,
             },
         ],
         &203_143i128,
     );
 
-    // one difference due to rounding
-    assert_eq!(reverse_simulated_swap.offer_amount, 9_999i128);
-    assert_eq!(reverse_simulated_swap.total_commission_amount, 29_803i128);
+    // the pools round the reverse swap up
+    assert_eq!(reverse_simulated_swap.offer_amount, 10_002i128);
+    assert_eq!(
+        commission_amounts(&env, &reverse_simulated_swap.hops),
+        vec![&env, 22_571, 5_253, 1_980]
+    );
     assert_eq!(
-        reverse_simulated_swap.spread_amount,
-        vec![&env, 10671i128, 934i128, 197i128]
+        spread_amounts(&env, &reverse_simulated_swap.hops),
+        vec![&env, 10675i128, 937i128, 200i128]
     );
 }
 
 #[test]
-#[should_panic(expected = "Multihop: Simulate swap: operations empty")]
-fn query_simulate_swap_panics_with_no_operations() {
+fn query_simulate_swap_fails_with_no_operations() {
     let env = Env::default();
     env.mock_all_auths();
     let admin = Address::generate(&env);
@@ -458,12 +533,14 @@ fn query_simulate_swap_panics_with_no_operations() {
 
     let swap_vec = vec![&env];
 
-    multihop.simulate_swap(&swap_vec, &50i128);
+    assert_eq!(
+        multihop.try_simulate_swap(&swap_vec, &50i128),
+        Err(Ok(MultihopError::OperationsEmpty))
+    );
 }
 
 #[test]
-#[should_panic(expected = "Multihop: Simulate reverse swap: operations empty")]
-fn query_simulate_reverse_swap_panics_with_no_operations() {
+fn query_simulate_reverse_swap_fails_with_no_operations() {
     let env = Env::default();
     env.mock_all_auths();
     let admin = Address::generate(&env);
@@ -477,5 +554,554 @@ fn query_simulate_reverse_swap_panics_with_no_operations() {
 
     let swap_vec = vec![&env];
 
-    multihop.simulate_reverse_swap(&swap_vec, &50i128);
+    assert_eq!(
+        multihop.try_simulate_reverse_swap(&swap_vec, &50i128),
+        Err(Ok(MultihopError::OperationsEmpty))
+    );
+}
+
+#[test]
+fn find_best_route_prefers_deeper_pools() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token4 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+
+    // the direct pool is shallow, while the route through token2 goes through deep pools
+    for (token_a, token_b, liquidity) in [
+        (&token1, &token4, 10_000),
+        (&token1, &token2, 1_000_000),
+        (&token2, &token4, 1_000_000),
+        (&token2, &token3, 1_000_000),
+        (&token3, &token4, 1_000_000),
+    ] {
+        deploy_and_initialize_lp(
+            &env,
+            &factory_client,
+            admin.clone(),
+            token_a.address.clone(),
+            liquidity,
+            token_b.address.clone(),
+            liquidity,
+            None,
+        );
+    }
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+
+    let best_route = multihop.find_best_route(&token1.address, &token4.address, &1_000, &3);
+    assert_eq!(
+        best_route.operations,
+        vec![
+            &env,
+            Swap {
+                offer_asset: token1.address.clone(),
+                ask_asset: token2.address.clone(),
+                pool: SwapPool::Pool(
+                    factory_client.query_for_pool_by_token_pair(&token1.address, &token2.address),
+                    PoolType::Xyk
+                ),
+            },
+            Swap {
+                offer_asset: token2.address.clone(),
+                ask_asset: token4.address.clone(),
+                pool: SwapPool::Pool(
+                    factory_client.query_for_pool_by_token_pair(&token2.address, &token4.address),
+                    PoolType::Xyk
+                ),
+            },
+        ]
+    );
+    assert_eq!(best_route.ask_amount, 1000);
+    assert_eq!(
+        multihop
+            .simulate_swap(&best_route.operations, &1_000)
+            .ask_amount,
+        best_route.ask_amount
+    );
+
+    // with a single hop only the shallow direct pool is left
+    let best_route = multihop.find_best_route(&token1.address, &token4.address, &1_000, &1);
+    assert_eq!(
+        best_route.operations,
+        vec![
+            &env,
+            Swap {
+                offer_asset: token1.address.clone(),
+                ask_asset: token4.address.clone(),
+                pool: SwapPool::Pool(
+                    factory_client.query_for_pool_by_token_pair(&token1.address, &token4.address),
+                    PoolType::Xyk
+                ),
+            },
+        ]
+    );
+    assert_eq!(best_route.ask_amount, 910);
+}
+
+#[test]
+fn find_best_route_fails_without_route() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+    deploy_and_initialize_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token1.address.clone(),
+        1_000_000,
+        token2.address.clone(),
+        1_000_000,
+        None,
+    );
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+
+    assert_eq!(
+        multihop.try_find_best_route(&token1.address, &token3.address, &1_000, &3),
+        Err(Ok(MultihopError::NoRouteFound))
+    );
+    assert_eq!(
+        multihop.try_find_best_route(&token1.address, &token1.address, &1_000, &3),
+        Err(Ok(MultihopError::NoRouteFound))
+    );
+    assert_eq!(
+        multihop.try_find_best_route(&token1.address, &token2.address, &1_000, &0),
+        Err(Ok(MultihopError::InvalidMaxHops))
+    );
+    assert_eq!(
+        multihop.try_find_best_route(&token1.address, &token2.address, &1_000, &5),
+        Err(Ok(MultihopError::InvalidMaxHops))
+    );
+}
+
+#[test]
+fn simulate_split_swap_returns_every_route() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+    for (token_a, token_b) in [(&token1, &token3), (&token1, &token2), (&token2, &token3)] {
+        deploy_and_initialize_lp(
+            &env,
+            &factory_client,
+            admin.clone(),
+            token_a.address.clone(),
+            1_000_000,
+            token_b.address.clone(),
+            1_000_000,
+            None,
+        );
+    }
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+
+    let direct = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token3.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+    let through_token2 = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token2.address.clone(),
+            pool: SwapPool::Pair,
+        },
+        Swap {
+            offer_asset: token2.address.clone(),
+            ask_asset: token3.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+    let routes = vec![
+        &env,
+        SplitRoute {
+            operations: direct.clone(),
+            allocation_bps: 6_000,
+        },
+        SplitRoute {
+            operations: through_token2.clone(),
+            allocation_bps: 4_000,
+        },
+    ];
+
+    let response = multihop.simulate_split_swap(&routes, &20_000);
+
+    let direct_simulation = multihop.simulate_swap(&direct, &12_000);
+    let through_token2_simulation = multihop.simulate_swap(&through_token2, &8_000);
+    assert_eq!(
+        response.routes,
+        vec![
+            &env,
+            SimulateSplitRouteResponse {
+                offer_amount: 12_000,
+                ask_amount: direct_simulation.ask_amount,
+                hops: direct_simulation.hops,
+            },
+            SimulateSplitRouteResponse {
+                offer_amount: 8_000,
+                ask_amount: through_token2_simulation.ask_amount,
+                hops: through_token2_simulation.hops,
+            },
+        ]
+    );
+    assert_eq!(
+        response.ask_amount,
+        direct_simulation.ask_amount + through_token2_simulation.ask_amount
+    );
+    // splitting the order lowers the price impact of the single direct pool
+    assert!(response.ask_amount > multihop.simulate_swap(&direct, &20_000).ask_amount);
+}
+
+#[test]
+fn simulate_split_swap_fails_when_routes_share_a_pool() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+    for (token_a, token_b) in [(&token1, &token3), (&token1, &token2), (&token2, &token3)] {
+        deploy_and_initialize_lp(
+            &env,
+            &factory_client,
+            admin.clone(),
+            token_a.address.clone(),
+            1_000_000,
+            token_b.address.clone(),
+            1_000_000,
+            None,
+        );
+    }
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+
+    let direct = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token3.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+    // the same pool as the one of the pair, given by its address
+    let direct_pool = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token3.address.clone(),
+            pool: SwapPool::Pool(
+                factory_client.query_for_pool_by_token_pair(&token1.address, &token3.address),
+                PoolType::Xyk,
+            ),
+        },
+    ];
+    let routes = vec![
+        &env,
+        SplitRoute {
+            operations: direct,
+            allocation_bps: 5_000,
+        },
+        SplitRoute {
+            operations: direct_pool,
+            allocation_bps: 5_000,
+        },
+    ];
+
+    assert_eq!(
+        multihop.try_simulate_split_swap(&routes, &20_000),
+        Err(Ok(MultihopError::SplitRoutesSharePool))
+    );
+}
+
+#[test]
+fn simulate_split_swap_fails_with_invalid_allocation() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+    for (token_a, token_b) in [(&token1, &token3), (&token1, &token2), (&token2, &token3)] {
+        deploy_and_initialize_lp(
+            &env,
+            &factory_client,
+            admin.clone(),
+            token_a.address.clone(),
+            1_000_000,
+            token_b.address.clone(),
+            1_000_000,
+            None,
+        );
+    }
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+
+    let direct = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token3.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+    let through_token2 = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token2.address.clone(),
+            pool: SwapPool::Pair,
+        },
+        Swap {
+            offer_asset: token2.address.clone(),
+            ask_asset: token3.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+    let routes = vec![
+        &env,
+        SplitRoute {
+            operations: direct.clone(),
+            allocation_bps: 6_000,
+        },
+        SplitRoute {
+            operations: through_token2.clone(),
+            allocation_bps: 4_000,
+        },
+    ];
+
+    let mut routes = routes;
+    routes.push_back(SplitRoute {
+        operations: direct,
+        allocation_bps: 1_000,
+    });
+
+    assert_eq!(
+        multihop.try_simulate_split_swap(&routes, &20_000),
+        Err(Ok(MultihopError::InvalidSplitAllocation))
+    );
+}
+
+#[test]
+fn find_best_route_through_stable_pool() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+    deploy_and_initialize_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token1.address.clone(),
+        1_000_000,
+        token2.address.clone(),
+        1_000_000,
+        None,
+    );
+    deploy_and_initialize_stable_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token2.address.clone(),
+        1_000_000,
+        token3.address.clone(),
+        1_000_000,
+        None,
+    );
+
+    // shallow direct pool
+    deploy_and_initialize_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token1.address.clone(),
+        10_000,
+        token3.address.clone(),
+        10_000,
+        None,
+    );
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+
+    let operations = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token2.address.clone(),
+            pool: SwapPool::Pool(
+                factory_client.query_for_pool_by_token_pair(&token1.address, &token2.address),
+                PoolType::Xyk,
+            ),
+        },
+        Swap {
+            offer_asset: token2.address.clone(),
+            ask_asset: token3.address.clone(),
+            pool: SwapPool::Pool(
+                factory_client.query_for_pool_by_token_pair(&token2.address, &token3.address),
+                PoolType::Stable,
+            ),
+        },
+    ];
+
+    let best_route = multihop.find_best_route(&token1.address, &token3.address, &1_000, &2);
+
+    assert_eq!(best_route.operations, operations);
+    assert_eq!(
+        best_route.ask_amount,
+        multihop.simulate_swap(&operations, &1_000).ask_amount
+    );
+}
+
+#[test]
+fn simulate_swap_returns_every_hop() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+    deploy_and_initialize_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token1.address.clone(),
+        1_000_000,
+        token2.address.clone(),
+        1_000_000,
+        None,
+    );
+    deploy_and_initialize_stable_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token2.address.clone(),
+        1_000_000,
+        token3.address.clone(),
+        1_000_000,
+        None,
+    );
+    let xyk_pool = factory_client.query_for_pool_by_token_pair(&token1.address, &token2.address);
+    let stable_pool = factory_client.query_for_pool_by_token_pair(&token2.address, &token3.address);
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+
+    let simulated_swap = multihop.simulate_swap(
+        &vec![
+            &env,
+            Swap {
+                offer_asset: token1.address.clone(),
+                ask_asset: token2.address.clone(),
+                pool: SwapPool::Pair,
+            },
+            Swap {
+                offer_asset: token2.address.clone(),
+                ask_asset: token3.address.clone(),
+                pool: SwapPool::Pair,
+            },
+        ],
+        &100_000,
+    );
+
+    let xyk_hop = simulated_swap.hops.get(0).unwrap();
+    // return_amount = 1_000_000 - (10^12 / (1_000_000 + 100_000)) = 90_910
+    // spread_amount = 100_000 - 90_910 = 9_090, which is 909 bps of the 100_000 at the pool price
+    assert_eq!(
+        xyk_hop,
+        SimulateHopResponse {
+            pool_address: xyk_pool,
+            pool_type: PoolType::Xyk,
+            offer_asset: token1.address.clone(),
+            ask_asset: token2.address.clone(),
+            offer_amount: 100_000,
+            ask_amount: 90_910,
+            commission_amount: 0,
+            spread_amount: 9_090,
+            price_impact_bps: 909,
+        }
+    );
+
+    let stable_hop = simulated_swap.hops.get(1).unwrap();
+    assert_eq!(stable_hop.pool_address, stable_pool);
+    assert_eq!(stable_hop.pool_type, PoolType::Stable);
+    assert_eq!(stable_hop.offer_asset, token2.address);
+    assert_eq!(stable_hop.ask_asset, token3.address);
+    assert_eq!(stable_hop.offer_amount, xyk_hop.ask_amount);
+    assert_eq!(stable_hop.ask_amount, simulated_swap.ask_amount);
+    // the stable pool keeps close to the peg
+    assert!(stable_hop.price_impact_bps < xyk_hop.price_impact_bps);
+
+    let reverse_simulated_swap = multihop.simulate_reverse_swap(
+        &vec![
+            &env,
+            Swap {
+                offer_asset: token2.address.clone(),
+                ask_asset: token3.address.clone(),
+                pool: SwapPool::Pair,
+            },
+            Swap {
+                offer_asset: token1.address.clone(),
+                ask_asset: token2.address.clone(),
+                pool: SwapPool::Pair,
+            },
+        ],
+        &1_000,
+    );
+
+    // the hops follow the operations, starting with the last swap
+    let stable_hop = reverse_simulated_swap.hops.get(0).unwrap();
+    let xyk_hop = reverse_simulated_swap.hops.get(1).unwrap();
+    assert_eq!(stable_hop.pool_type, PoolType::Stable);
+    assert_eq!(stable_hop.ask_amount, 1_000);
+    assert_eq!(xyk_hop.pool_type, PoolType::Xyk);
+    assert_eq!(xyk_hop.ask_amount, stable_hop.offer_amount);
+    assert_eq!(xyk_hop.offer_amount, reverse_simulated_swap.offer_amount);
 }
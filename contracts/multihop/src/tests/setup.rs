@@ -1,12 +1,15 @@
 use crate::contract::{Multihop, MultihopClient};
-use crate::tests::setup::factory::{LiquidityPoolInitInfo, StakeInitInfo, TokenInitInfo};
+use crate::tests::setup::factory::{
+    LiquidityPoolInitInfo, PoolType, PoolTypeInitInfo, StableInitInfo, StakeInitInfo, TokenInitInfo,
+};
 
 use soroban_sdk::vec;
 use soroban_sdk::{
     testutils::{arbitrary::std, Address as _},
-    Address, Bytes, BytesN, Env,
+    Address, Bytes, BytesN, Env, Vec,
 };
 
+#[allow(clippy::too_many_arguments)]
 pub mod factory {
     soroban_sdk::contractimport!(
         file = "../../target/wasm32-unknown-unknown/release/phoenix_factory.wasm"
@@ -26,10 +29,22 @@ pub mod lp_contract {
     );
 }
 
+#[allow(clippy::too_many_arguments)]
+pub mod stable_lp_contract {
+    soroban_sdk::contractimport!(
+        file = "../../target/wasm32-unknown-unknown/release/phoenix_pool_stable.wasm"
+    );
+}
+
 pub fn install_lp_contract(env: &Env) -> BytesN<32> {
     env.deployer().upload_contract_wasm(lp_contract::WASM)
 }
 
+pub fn install_stable_lp(env: &Env) -> BytesN<32> {
+    env.deployer()
+        .upload_contract_wasm(stable_lp_contract::WASM)
+}
+
 pub fn install_token_wasm(env: &Env) -> BytesN<32> {
     soroban_sdk::contractimport!(
         file = "../../target/wasm32-unknown-unknown/release/soroban_token_contract.wasm"
@@ -48,7 +63,10 @@ pub fn install_stake_wasm(env: &Env) -> BytesN<32> {
     env.deployer().upload_contract_wasm(WASM)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn install_multihop_wasm(env: &Env) -> BytesN<32> {
+    // `Referral` comes from the pool contract and is not exported in the multihop spec
+    use crate::lp_contract::Referral;
     soroban_sdk::contractimport!(
         file = "../../target/wasm32-unknown-unknown/release/phoenix_multihop.wasm"
     );
@@ -93,6 +111,7 @@ pub fn deploy_and_initialize_factory(env: &Env, admin: Address) -> factory::Clie
     let whitelisted_accounts = vec![env, admin.clone()];
 
     let lp_wasm_hash = install_lp_contract(env);
+    let stable_wasm_hash = install_stable_lp(env);
     let stake_wasm_hash = install_stake_wasm(env);
     let token_wasm_hash = install_token_wasm(env);
 
@@ -100,6 +119,7 @@ pub fn deploy_and_initialize_factory(env: &Env, admin: Address) -> factory::Clie
         &admin.clone(),
         &multihop_wasm_hash,
         &lp_wasm_hash,
+        &stable_wasm_hash,
         &stake_wasm_hash,
         &token_wasm_hash,
         &whitelisted_accounts,
@@ -112,12 +132,81 @@ pub fn deploy_and_initialize_lp(
     env: &Env,
     factory: &factory::Client,
     admin: Address,
+    token_a: Address,
+    token_a_amount: i128,
+    token_b: Address,
+    token_b_amount: i128,
+    fees: Option<i64>,
+) {
+    let (lp, token_a_amount, token_b_amount) = create_liquidity_pool(
+        factory,
+        &admin,
+        token_a,
+        token_a_amount,
+        token_b,
+        token_b_amount,
+        fees,
+        PoolType::Xyk,
+    );
+
+    let lp_client = lp_contract::Client::new(env, &lp);
+    lp_client.provide_liquidity(
+        &admin.clone(),
+        &Some(token_a_amount),
+        &None,
+        &Some(token_b_amount),
+        &None,
+        &None::<i64>,
+        &false,
+        &None,
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn deploy_and_initialize_stable_lp(
+    env: &Env,
+    factory: &factory::Client,
+    admin: Address,
+    token_a: Address,
+    token_a_amount: i128,
+    token_b: Address,
+    token_b_amount: i128,
+    fees: Option<i64>,
+) {
+    let (lp, token_a_amount, token_b_amount) = create_liquidity_pool(
+        factory,
+        &admin,
+        token_a,
+        token_a_amount,
+        token_b,
+        token_b_amount,
+        fees,
+        PoolType::Stable,
+    );
+
+    let lp_client = stable_lp_contract::Client::new(env, &lp);
+    lp_client.provide_liquidity(
+        &admin,
+        &vec![env, token_a_amount, token_b_amount],
+        &None::<i64>,
+        &false,
+        &None,
+    );
+}
+
+/// Creates a pool of the given type in the factory and returns its address together with the
+/// amounts, swapped if needed to match the sorted tokens.
+#[allow(clippy::too_many_arguments)]
+fn create_liquidity_pool(
+    factory: &factory::Client,
+    admin: &Address,
     mut token_a: Address,
     mut token_a_amount: i128,
     mut token_b: Address,
     mut token_b_amount: i128,
     fees: Option<i64>,
-) {
+    pool_type: PoolType,
+) -> (Address, i128, i128) {
     // 2. create liquidity pool from factory
 
     if token_b < token_a {
@@ -125,17 +214,64 @@ pub fn deploy_and_initialize_lp(
         std::mem::swap(&mut token_a_amount, &mut token_b_amount);
     }
 
-    let token_init_info = TokenInitInfo {
-        token_a: token_a.clone(),
-        token_b: token_b.clone(),
-    };
-    let stake_init_info = StakeInitInfo {
-        min_bond: 10i128,
-        max_distributions: 10u32,
-        min_reward: 5i128,
+    let lp_init_info = lp_init_info(admin, token_a.clone(), token_b.clone(), fees);
+
+    let pool_type = match pool_type {
+        PoolType::Xyk => PoolTypeInitInfo::Xyk,
+        PoolType::Stable => PoolTypeInitInfo::Stable(StableInitInfo {
+            amp: 100,
+            tokens: vec![&factory.env, token_a, token_b],
+            rate_providers: vec![&factory.env],
+            max_rate_staleness: 0,
+        }),
     };
+    let lp = factory.create_liquidity_pool(&lp_init_info, admin, &pool_type);
+
+    (lp, token_a_amount, token_b_amount)
+}
 
-    let lp_init_info = LiquidityPoolInitInfo {
+/// Creates a stable pool of all the given tokens in the factory and provides `amount` of every
+/// one of them. Returns the address of the pool.
+pub fn deploy_and_initialize_stable_basket(
+    env: &Env,
+    factory: &factory::Client,
+    admin: Address,
+    mut tokens: std::vec::Vec<Address>,
+    amount: i128,
+) -> Address {
+    tokens.sort();
+
+    let lp_init_info = lp_init_info(&admin, tokens[0].clone(), tokens[1].clone(), None);
+    let pool_type = PoolTypeInitInfo::Stable(StableInitInfo {
+        amp: 100,
+        tokens: Vec::from_slice(env, &tokens),
+        rate_providers: vec![env],
+        max_rate_staleness: 0,
+    });
+    let lp = factory.create_liquidity_pool(&lp_init_info, &admin, &pool_type);
+
+    let mut amounts = vec![env];
+    for _ in tokens.iter() {
+        amounts.push_back(amount);
+    }
+    stable_lp_contract::Client::new(env, &lp).provide_liquidity(
+        &admin,
+        &amounts,
+        &None::<i64>,
+        &false,
+        &None,
+    );
+
+    lp
+}
+
+fn lp_init_info(
+    admin: &Address,
+    token_a: Address,
+    token_b: Address,
+    fees: Option<i64>,
+) -> LiquidityPoolInitInfo {
+    LiquidityPoolInitInfo {
         admin: admin.clone(),
         fee_recipient: admin.clone(),
         max_allowed_slippage_bps: 5000,
@@ -143,19 +279,11 @@ pub fn deploy_and_initialize_lp(
         share_token_decimals: 7,
         swap_fee_bps: fees.unwrap_or(0i64),
         max_referral_bps: 5_000,
-        token_init_info,
-        stake_init_info,
-    };
-
-    let lp = factory.create_liquidity_pool(&lp_init_info, &admin.clone());
-
-    let lp_client = lp_contract::Client::new(env, &lp);
-    lp_client.provide_liquidity(
-        &admin.clone(),
-        &Some(token_a_amount),
-        &None,
-        &Some(token_b_amount),
-        &None,
-        &None::<i64>,
-    );
+        token_init_info: TokenInitInfo { token_a, token_b },
+        stake_init_info: StakeInitInfo {
+            min_bond: 10i128,
+            max_distributions: 10u32,
+            min_reward: 5i128,
+        },
+    }
 }
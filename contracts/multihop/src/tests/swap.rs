@@ -1,20 +1,17 @@
-// FIXM: Disable Referral struct
-// use crate::lp_contract::Referral;
-use crate::storage::Swap;
+use crate::lp_contract::Referral;
+use crate::storage::{SplitRoute, Swap, SwapPool};
 use crate::tests::setup::{
-    deploy_and_initialize_factory, deploy_and_initialize_lp, deploy_and_mint_tokens,
-    deploy_multihop_contract, deploy_token_contract,
+    deploy_and_initialize_factory, deploy_and_initialize_lp, deploy_and_initialize_stable_basket,
+    deploy_and_initialize_stable_lp, deploy_and_mint_tokens, deploy_multihop_contract,
+    deploy_token_contract,
 };
 
-use soroban_sdk::contracterror;
-use soroban_sdk::{testutils::Address as _, vec, Address, Env};
-
-#[contracterror]
-#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
-#[repr(u32)]
-pub enum ContractError {
-    SpreadExceedsLimit = 1,
-}
+use phoenix::error::{MultihopError, PoolError};
+use phoenix::utils::PoolType;
+use soroban_sdk::{
+    testutils::{arbitrary::std, Address as _, Ledger},
+    vec, Address, Env, InvokeError,
+};
 
 #[test]
 fn swap_three_equal_pools_no_fees() {
@@ -74,29 +71,28 @@ fn swap_three_equal_pools_no_fees() {
     let swap1 = Swap {
         offer_asset: token1.address.clone(),
         ask_asset: token2.address.clone(),
+        pool: SwapPool::Pair,
     };
     let swap2 = Swap {
         offer_asset: token2.address.clone(),
         ask_asset: token3.address.clone(),
+        pool: SwapPool::Pair,
     };
     let swap3 = Swap {
         offer_asset: token3.address.clone(),
         ask_asset: token4.address.clone(),
+        pool: SwapPool::Pair,
     };
 
     let operations = vec![&env, swap1, swap2, swap3];
 
-    // FIXM: Disable Referral struct
-    // multihop.swap(&recipient, &None, &operations, &None, &None, &50i128);
-    multihop.swap(&recipient, &operations, &None, &None, &50i128);
+    multihop.swap(&recipient, &None, &operations, &50i128, &0, &None, &None);
 
     // 5. check if it goes according to plan
     assert_eq!(token1.balance(&recipient), 0i128);
     assert_eq!(token4.balance(&recipient), 50i128);
 }
 
-// FIXM: Disable Referral struct
-#[ignore]
 #[test]
 fn swap_three_equal_pools_no_fees_referral_fee() {
     let env = Env::default();
@@ -155,33 +151,35 @@ fn swap_three_equal_pools_no_fees_referral_fee() {
     let swap1 = Swap {
         offer_asset: token1.address.clone(),
         ask_asset: token2.address.clone(),
+        pool: SwapPool::Pair,
     };
     let swap2 = Swap {
         offer_asset: token2.address.clone(),
         ask_asset: token3.address.clone(),
+        pool: SwapPool::Pair,
     };
     let swap3 = Swap {
         offer_asset: token3.address.clone(),
         ask_asset: token4.address.clone(),
+        pool: SwapPool::Pair,
     };
 
     let operations = vec![&env, swap1, swap2, swap3];
     let referral_addr = Address::generate(&env);
-    // FIXM: Disable Referral struct
-    // let referral = Referral {
-    //     address: referral_addr.clone(),
-    //     fee: 1_000,
-    // };
-
-    // multihop.swap(
-    //     &recipient,
-    //     &Some(referral),
-    //     &operations,
-    //     &None,
-    //     &None,
-    //     &50i128,
-    // );
-    multihop.swap(&recipient, &operations, &None, &None, &50i128);
+    let referral = Referral {
+        address: referral_addr.clone(),
+        fee: 1_000,
+    };
+
+    multihop.swap(
+        &recipient,
+        &Some(referral),
+        &operations,
+        &50i128,
+        &0,
+        &None,
+        &None,
+    );
 
     // 5. check if it goes according to plan
     assert_eq!(token1.balance(&recipient), 0i128);
@@ -229,13 +227,12 @@ fn swap_single_pool_no_fees() {
     let swap1 = Swap {
         offer_asset: token1.address.clone(),
         ask_asset: token2.address.clone(),
+        pool: SwapPool::Pair,
     };
 
     let operations = vec![&env, swap1];
 
-    // FIXM: Disable Referral struct
-    // multihop.swap(&recipient, /*&None,*/ &operations, &None, &None, &50i128);
-    multihop.swap(&recipient, &operations, &None, &None, &1_000);
+    multihop.swap(&recipient, &None, &operations, &1_000, &0, &None, &None);
 
     // 5. check if it goes according to plan
     assert_eq!(token1.balance(&recipient), 4_000i128); // -1_000 token0
@@ -243,8 +240,6 @@ fn swap_single_pool_no_fees() {
 }
 
 #[test]
-/// Asserting HostError, because of panic messages are not propagated and IIUC are normally compiled out
-#[should_panic(expected = "HostError: Error(Contract, #1)")]
 fn swap_should_fail_when_spread_exceeds_the_limit() {
     let env = Env::default();
     let admin = Address::generate(&env);
@@ -275,13 +270,21 @@ fn swap_should_fail_when_spread_exceeds_the_limit() {
     let swap1 = Swap {
         offer_asset: token1.address.clone(),
         ask_asset: token2.address.clone(),
+        pool: SwapPool::Pair,
     };
 
     let operations = vec![&env, swap1];
 
-    // FIXM: Disable Referral struct
-    // multihop.swap(&recipient, &None, &operations, &None, &Some(50), &50);
-    multihop.swap(&recipient, &operations, &None, &Some(50), &50);
+    // the pool keeps applying its own spread limit of 5% to the hop, its error being returned
+    // from the nested call
+    assert_eq!(
+        multihop.try_swap(&recipient, &None, &operations, &500, &0, &None, &None),
+        Err(Err(InvokeError::Contract(
+            PoolError::SpreadExceedsLimit as u32
+        )))
+    );
+    assert_eq!(token1.balance(&recipient), 5_000);
+    assert_eq!(token2.balance(&recipient), 0);
 }
 
 #[test]
@@ -319,13 +322,12 @@ fn swap_single_pool_with_fees() {
     let swap1 = Swap {
         offer_asset: token1.address.clone(),
         ask_asset: token2.address.clone(),
+        pool: SwapPool::Pair,
     };
 
     let operations = vec![&env, swap1];
 
-    // FIXM: Disable Referral struct
-    // multihop.swap(&recipient, &None, &operations, &None, &None, &300i128);
-    multihop.swap(&recipient, &operations, &None, &None, &300i128);
+    multihop.swap(&recipient, &None, &operations, &300i128, &0, &None, &None);
 
     // 5. check if it goes according to plan
     // 1000 tokens initially
@@ -394,21 +396,22 @@ fn swap_three_different_pools_no_fees() {
     let swap1 = Swap {
         offer_asset: token1.address.clone(),
         ask_asset: token2.address.clone(),
+        pool: SwapPool::Pair,
     };
     let swap2 = Swap {
         offer_asset: token2.address.clone(),
         ask_asset: token3.address.clone(),
+        pool: SwapPool::Pair,
     };
     let swap3 = Swap {
         offer_asset: token3.address.clone(),
         ask_asset: token4.address.clone(),
+        pool: SwapPool::Pair,
     };
 
     let operations = vec![&env, swap1, swap2, swap3];
 
-    // FIXM: Disable Referral struct
-    // multihop.swap(&recipient, &None, &operations, &None, &None, &5_000i128);
-    multihop.swap(&recipient, &operations, &None, &None, &5_000i128);
+    multihop.swap(&recipient, &None, &operations, &5_000i128, &0, &None, &None);
 
     // 5. check if it goes according to plan
     assert_eq!(token1.balance(&recipient), 0i128);
@@ -476,21 +479,30 @@ fn swap_three_different_pools_with_fees() {
     let swap1 = Swap {
         offer_asset: token1.address.clone(),
         ask_asset: token2.address.clone(),
+        pool: SwapPool::Pair,
     };
     let swap2 = Swap {
         offer_asset: token2.address.clone(),
         ask_asset: token3.address.clone(),
+        pool: SwapPool::Pair,
     };
     let swap3 = Swap {
         offer_asset: token3.address.clone(),
         ask_asset: token4.address.clone(),
+        pool: SwapPool::Pair,
     };
 
     let operations = vec![&env, swap1, swap2, swap3];
 
-    // FIXM: Disable Referral struct
-    // multihop.swap(&recipient, &None, &operations, &None, &None, &10_000i128);
-    multihop.swap(&recipient, &operations, &None, &None, &10_000i128);
+    multihop.swap(
+        &recipient,
+        &None,
+        &operations,
+        &10_000i128,
+        &0,
+        &None,
+        &None,
+    );
 
     // we start swapping 10_000 tokens
 
@@ -518,8 +530,7 @@ fn swap_three_different_pools_with_fees() {
 }
 
 #[test]
-#[should_panic(expected = "Multihop: Swap: operations is empty!")]
-fn swap_panics_with_no_operations() {
+fn swap_fails_with_no_operations() {
     let env = Env::default();
     env.mock_all_auths();
     let admin = Address::generate(&env);
@@ -533,7 +544,861 @@ fn swap_panics_with_no_operations() {
 
     let swap_vec = vec![&env];
 
-    // FIXM: Disable Referral struct
-    // multihop.swap(&recipient, &None, &swap_vec, &None, &None, &50i128);
-    multihop.swap(&recipient, &swap_vec, &None, &None, &50i128);
+    assert_eq!(
+        multihop.try_swap(&recipient, &None, &swap_vec, &50i128, &0, &None, &None),
+        Err(Ok(MultihopError::OperationsEmpty))
+    );
+}
+
+#[test]
+fn swap_exact_out_three_different_pools_with_fees() {
+    let env = Env::default();
+
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token4 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+
+    let fees = Some(1_000); // 10% bps
+    deploy_and_initialize_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token1.address.clone(),
+        1_000_000,
+        token2.address.clone(),
+        1_000_000,
+        fees,
+    );
+    deploy_and_initialize_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token2.address.clone(),
+        2_000_000,
+        token3.address.clone(),
+        2_000_000,
+        fees,
+    );
+    deploy_and_initialize_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token3.address.clone(),
+        3_000_000,
+        token4.address.clone(),
+        3_000_000,
+        fees,
+    );
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+    let recipient = Address::generate(&env);
+    token1.mint(&recipient, &10_000i128);
+
+    let swap1 = Swap {
+        offer_asset: token1.address.clone(),
+        ask_asset: token2.address.clone(),
+        pool: SwapPool::Pair,
+    };
+    let swap2 = Swap {
+        offer_asset: token2.address.clone(),
+        ask_asset: token3.address.clone(),
+        pool: SwapPool::Pair,
+    };
+    let swap3 = Swap {
+        offer_asset: token3.address.clone(),
+        ask_asset: token4.address.clone(),
+        pool: SwapPool::Pair,
+    };
+
+    let operations = vec![&env, swap1, swap2, swap3];
+
+    let simulated = multihop.simulate_reverse_swap(
+        &vec![
+            &env,
+            operations.get(2).unwrap(),
+            operations.get(1).unwrap(),
+            operations.get(0).unwrap(),
+        ],
+        &5_000i128,
+    );
+
+    env.ledger().with_mut(|li| li.timestamp = 100);
+    assert_eq!(
+        multihop.try_swap_exact_out(
+            &recipient,
+            &operations,
+            &5_000i128,
+            &10_000i128,
+            &Some(99),
+            &None
+        ),
+        Err(Ok(MultihopError::DeadlineExceeded))
+    );
+
+    let offer_amount = multihop.swap_exact_out(
+        &recipient,
+        &operations,
+        &5_000i128,
+        &10_000i128,
+        &Some(100),
+        &None,
+    );
+
+    // the recipient receives exactly the ask amount and pays what the reverse simulation predicted
+    assert_eq!(offer_amount, simulated.offer_amount);
+    assert_eq!(token1.balance(&recipient), 10_000i128 - offer_amount);
+    assert_eq!(token2.balance(&recipient), 0i128);
+    assert_eq!(token3.balance(&recipient), 0i128);
+    assert_eq!(token4.balance(&recipient), 5_000i128);
+}
+
+#[test]
+fn swap_exact_out_should_fail_when_offer_exceeds_max() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+
+    deploy_and_initialize_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token1.address.clone(),
+        1_000_000,
+        token2.address.clone(),
+        1_000_000,
+        None,
+    );
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+    let recipient = Address::generate(&env);
+    token1.mint(&recipient, &20_000i128);
+
+    let operations = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token2.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+
+    // buying 10_000 of token2 costs 10_101 of token1
+    assert_eq!(
+        multihop.try_swap_exact_out(
+            &recipient,
+            &operations,
+            &10_000i128,
+            &10_100i128,
+            &None,
+            &None
+        ),
+        Err(Ok(MultihopError::OfferAmountExceedsMax))
+    );
+    assert_eq!(token1.balance(&recipient), 20_000i128);
+    assert_eq!(token2.balance(&recipient), 0i128);
+
+    assert_eq!(
+        multihop.try_swap_exact_out(
+            &recipient,
+            &vec![&env],
+            &10_000i128,
+            &10_100i128,
+            &None,
+            &None
+        ),
+        Err(Ok(MultihopError::OperationsEmpty))
+    );
+}
+
+#[test]
+fn swap_best_route() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+
+    // the direct pool is shallow, while the route through token2 goes through deep pools
+    for (token_a, token_b, liquidity) in [
+        (&token1, &token3, 10_000),
+        (&token1, &token2, 1_000_000),
+        (&token2, &token3, 1_000_000),
+    ] {
+        deploy_and_initialize_lp(
+            &env,
+            &factory_client,
+            admin.clone(),
+            token_a.address.clone(),
+            liquidity,
+            token_b.address.clone(),
+            liquidity,
+            None,
+        );
+    }
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+    let recipient = Address::generate(&env);
+    token1.mint(&recipient, &1_000i128);
+
+    assert_eq!(
+        multihop.try_swap_best_route(
+            &recipient,
+            &token1.address,
+            &token3.address,
+            &2,
+            &1_000,
+            &1_001,
+            &None,
+        ),
+        Err(Ok(MultihopError::AskAmountBelowMinimum))
+    );
+
+    env.ledger().with_mut(|li| li.timestamp = 100);
+    assert_eq!(
+        multihop.try_swap_best_route(
+            &recipient,
+            &token1.address,
+            &token3.address,
+            &2,
+            &1_000,
+            &0,
+            &Some(99),
+        ),
+        Err(Ok(MultihopError::DeadlineExceeded))
+    );
+
+    let received = multihop.swap_best_route(
+        &recipient,
+        &token1.address,
+        &token3.address,
+        &2,
+        &1_000,
+        &1_000,
+        &Some(100),
+    );
+
+    assert_eq!(received, 1000);
+    assert_eq!(token1.balance(&recipient), 0);
+    assert_eq!(token2.balance(&recipient), 0);
+    assert_eq!(token3.balance(&recipient), 1000);
+
+    assert_eq!(
+        multihop.try_swap_best_route(
+            &recipient,
+            &token3.address,
+            &Address::generate(&env),
+            &2,
+            &100,
+            &0,
+            &None,
+        ),
+        Err(Ok(MultihopError::NoRouteFound))
+    );
+}
+
+#[test]
+fn swap_best_route_swaps_in_the_pools_of_the_route() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+
+    // the pair keeps leading to the shallow XYK pool, while the stable basket holding the same
+    // pair is deeper
+    deploy_and_initialize_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token1.address.clone(),
+        10_000,
+        token2.address.clone(),
+        10_000,
+        None,
+    );
+    let xyk_pool = factory_client.query_for_pool_by_token_pair(&token1.address, &token2.address);
+    let stable_pool = deploy_and_initialize_stable_basket(
+        &env,
+        &factory_client,
+        admin.clone(),
+        std::vec![
+            token1.address.clone(),
+            token2.address.clone(),
+            token3.address.clone(),
+        ],
+        1_000_000,
+    );
+    assert_eq!(
+        factory_client.query_for_pool_by_token_pair(&token1.address, &token2.address),
+        xyk_pool
+    );
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+
+    let best_route = multihop.find_best_route(&token1.address, &token2.address, &1_000, &1);
+    assert_eq!(
+        best_route.operations,
+        vec![
+            &env,
+            Swap {
+                offer_asset: token1.address.clone(),
+                ask_asset: token2.address.clone(),
+                pool: SwapPool::Pool(stable_pool.clone(), PoolType::Stable),
+            },
+        ]
+    );
+
+    let recipient = Address::generate(&env);
+    token1.mint(&recipient, &1_000i128);
+    let received = multihop.swap_best_route(
+        &recipient,
+        &token1.address,
+        &token2.address,
+        &1,
+        &1_000,
+        &0,
+        &None,
+    );
+
+    // the swap goes through the stable pool that the route was found in
+    assert_eq!(received, best_route.ask_amount);
+    assert_eq!(token2.balance(&recipient), received);
+    assert_eq!(token1.balance(&xyk_pool), 10_000);
+    assert_eq!(token2.balance(&xyk_pool), 10_000);
+    assert_eq!(token1.balance(&stable_pool), 1_001_000);
+    assert_eq!(token2.balance(&stable_pool), 1_000_000 - received);
+}
+
+#[test]
+fn swap_split() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+    for (token_a, token_b) in [(&token1, &token3), (&token1, &token2), (&token2, &token3)] {
+        deploy_and_initialize_lp(
+            &env,
+            &factory_client,
+            admin.clone(),
+            token_a.address.clone(),
+            1_000_000,
+            token_b.address.clone(),
+            1_000_000,
+            None,
+        );
+    }
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+
+    let direct = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token3.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+    let through_token2 = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token2.address.clone(),
+            pool: SwapPool::Pair,
+        },
+        Swap {
+            offer_asset: token2.address.clone(),
+            ask_asset: token3.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+    let routes = vec![
+        &env,
+        SplitRoute {
+            operations: direct.clone(),
+            allocation_bps: 6_000,
+        },
+        SplitRoute {
+            operations: through_token2.clone(),
+            allocation_bps: 4_000,
+        },
+    ];
+
+    let recipient = Address::generate(&env);
+    token1.mint(&recipient, &20_000i128);
+
+    let expected = multihop.simulate_split_swap(&routes, &20_000).ask_amount;
+
+    env.ledger().with_mut(|li| li.timestamp = 100);
+    assert_eq!(
+        multihop.try_swap_split(&recipient, &None, &routes, &20_000, &expected, &Some(99)),
+        Err(Ok(MultihopError::DeadlineExceeded))
+    );
+
+    let received = multihop.swap_split(&recipient, &None, &routes, &20_000, &expected, &Some(100));
+
+    assert_eq!(received, expected);
+    assert_eq!(token1.balance(&recipient), 0);
+    assert_eq!(token2.balance(&recipient), 0);
+    assert_eq!(token3.balance(&recipient), received);
+
+    // routes that go through the same pool are rejected
+    token1.mint(&recipient, &20_000i128);
+    let shared_pool = vec![
+        &env,
+        SplitRoute {
+            operations: direct.clone(),
+            allocation_bps: 5_000,
+        },
+        SplitRoute {
+            operations: direct,
+            allocation_bps: 5_000,
+        },
+    ];
+    assert_eq!(
+        multihop.try_swap_split(&recipient, &None, &shared_pool, &20_000, &0, &None),
+        Err(Ok(MultihopError::SplitRoutesSharePool))
+    );
+}
+
+#[test]
+fn swap_split_should_fail_below_min_ask_amount() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+    for (token_a, token_b) in [(&token1, &token3), (&token1, &token2), (&token2, &token3)] {
+        deploy_and_initialize_lp(
+            &env,
+            &factory_client,
+            admin.clone(),
+            token_a.address.clone(),
+            1_000_000,
+            token_b.address.clone(),
+            1_000_000,
+            None,
+        );
+    }
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+
+    let direct = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token3.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+    let through_token2 = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token2.address.clone(),
+            pool: SwapPool::Pair,
+        },
+        Swap {
+            offer_asset: token2.address.clone(),
+            ask_asset: token3.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+    let routes = vec![
+        &env,
+        SplitRoute {
+            operations: direct.clone(),
+            allocation_bps: 6_000,
+        },
+        SplitRoute {
+            operations: through_token2.clone(),
+            allocation_bps: 4_000,
+        },
+    ];
+
+    let recipient = Address::generate(&env);
+    token1.mint(&recipient, &20_000i128);
+
+    let expected = multihop.simulate_split_swap(&routes, &20_000).ask_amount;
+
+    assert_eq!(
+        multihop.try_swap_split(&recipient, &None, &routes, &20_000, &(expected + 1), &None),
+        Err(Ok(MultihopError::AskAmountBelowMinimum))
+    );
+    // the whole split swap is reverted
+    assert_eq!(token1.balance(&recipient), 20_000);
+    assert_eq!(token3.balance(&recipient), 0);
+}
+
+#[test]
+fn swap_through_xyk_and_stable_pools() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+    deploy_and_initialize_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token1.address.clone(),
+        1_000_000,
+        token2.address.clone(),
+        1_000_000,
+        None,
+    );
+    deploy_and_initialize_stable_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token2.address.clone(),
+        1_000_000,
+        token3.address.clone(),
+        1_000_000,
+        None,
+    );
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+
+    let operations = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token2.address.clone(),
+            pool: SwapPool::Pair,
+        },
+        Swap {
+            offer_asset: token2.address.clone(),
+            ask_asset: token3.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+
+    let recipient = Address::generate(&env);
+    token1.mint(&recipient, &1_000i128);
+
+    let simulated = multihop.simulate_swap(&operations, &1_000);
+    multihop.swap(&recipient, &None, &operations, &1_000, &0, &None, &None);
+
+    assert_eq!(token1.balance(&recipient), 0);
+    assert_eq!(token2.balance(&recipient), 0);
+    assert_eq!(token3.balance(&recipient), simulated.ask_amount);
+    // the XYK hop loses a unit to its price impact, while the stable hop keeps the peg
+    assert_eq!(simulated.ask_amount, 999);
+}
+
+#[test]
+fn swap_exact_out_through_xyk_and_stable_pools() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+    deploy_and_initialize_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token1.address.clone(),
+        1_000_000,
+        token2.address.clone(),
+        1_000_000,
+        None,
+    );
+    deploy_and_initialize_stable_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token2.address.clone(),
+        1_000_000,
+        token3.address.clone(),
+        1_000_000,
+        None,
+    );
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+
+    let operations = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token2.address.clone(),
+            pool: SwapPool::Pair,
+        },
+        Swap {
+            offer_asset: token2.address.clone(),
+            ask_asset: token3.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+
+    let recipient = Address::generate(&env);
+    token1.mint(&recipient, &2_000i128);
+
+    let offer_amount = multihop.simulate_reverse_swap(
+        &vec![
+            &env,
+            Swap {
+                offer_asset: token2.address.clone(),
+                ask_asset: token3.address.clone(),
+                pool: SwapPool::Pair,
+            },
+            Swap {
+                offer_asset: token1.address.clone(),
+                ask_asset: token2.address.clone(),
+                pool: SwapPool::Pair,
+            },
+        ],
+        &1_000,
+    );
+    let spent = multihop.swap_exact_out(&recipient, &operations, &1_000, &2_000, &None, &None);
+
+    assert_eq!(spent, offer_amount.offer_amount);
+    assert_eq!(token1.balance(&recipient), 2_000 - spent);
+    assert_eq!(token3.balance(&recipient), 1_000);
+}
+
+#[test]
+fn swap_returns_amount_and_checks_min_amount_out() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+    deploy_and_initialize_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token1.address.clone(),
+        1_000_000,
+        token2.address.clone(),
+        1_000_000,
+        None,
+    );
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+    let recipient = Address::generate(&env);
+    token1.mint(&recipient, &1_000i128);
+
+    let operations = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token2.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+
+    assert_eq!(
+        multihop.try_swap(&recipient, &None, &operations, &1_000, &1_001, &None, &None),
+        Err(Ok(MultihopError::AskAmountBelowMinimum))
+    );
+    assert_eq!(token1.balance(&recipient), 1_000);
+
+    let received = multihop.swap(&recipient, &None, &operations, &1_000, &1_000, &None, &None);
+    assert_eq!(received, 1_000);
+    assert_eq!(token1.balance(&recipient), 0);
+    assert_eq!(token2.balance(&recipient), 1_000);
+}
+
+#[test]
+fn swap_fails_after_deadline() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+    deploy_and_initialize_lp(
+        &env,
+        &factory_client,
+        admin.clone(),
+        token1.address.clone(),
+        1_000_000,
+        token2.address.clone(),
+        1_000_000,
+        None,
+    );
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+    let recipient = Address::generate(&env);
+    token1.mint(&recipient, &1_000i128);
+
+    let operations = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token2.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+
+    env.ledger().with_mut(|li| {
+        li.timestamp = 1_000;
+    });
+
+    assert_eq!(
+        multihop.try_swap(
+            &recipient,
+            &None,
+            &operations,
+            &1_000,
+            &0,
+            &Some(999),
+            &None
+        ),
+        Err(Ok(MultihopError::DeadlineExceeded))
+    );
+
+    // the deadline itself is still valid
+    assert_eq!(
+        multihop.swap(
+            &recipient,
+            &None,
+            &operations,
+            &1_000,
+            &0,
+            &Some(1_000),
+            &None
+        ),
+        1_000
+    );
+}
+
+#[test]
+fn swap_sends_the_output_of_the_last_hop_to_another_receiver() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token2 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+    let token3 = deploy_and_mint_tokens(&env, &admin, 10_000_000i128);
+
+    let factory_client = deploy_and_initialize_factory(&env, admin.clone());
+    for (token_a, token_b) in [(&token1, &token2), (&token2, &token3)] {
+        deploy_and_initialize_lp(
+            &env,
+            &factory_client,
+            admin.clone(),
+            token_a.address.clone(),
+            1_000_000,
+            token_b.address.clone(),
+            1_000_000,
+            None,
+        );
+    }
+
+    let multihop = deploy_multihop_contract(&env, admin.clone(), &factory_client.address);
+    let recipient = Address::generate(&env);
+    let receiver = Address::generate(&env);
+    token1.mint(&recipient, &1_000i128);
+
+    let operations = vec![
+        &env,
+        Swap {
+            offer_asset: token1.address.clone(),
+            ask_asset: token2.address.clone(),
+            pool: SwapPool::Pair,
+        },
+        Swap {
+            offer_asset: token2.address.clone(),
+            ask_asset: token3.address.clone(),
+            pool: SwapPool::Pair,
+        },
+    ];
+
+    let received = multihop.swap(
+        &recipient,
+        &None,
+        &operations,
+        &1_000,
+        &0,
+        &None,
+        &Some(receiver.clone()),
+    );
+
+    // the recipient pays and keeps nothing of the intermediate asset
+    assert_eq!(token1.balance(&recipient), 0);
+    assert_eq!(token2.balance(&recipient), 0);
+    assert_eq!(token3.balance(&recipient), 0);
+    assert_eq!(token2.balance(&receiver), 0);
+    assert_eq!(token3.balance(&receiver), received);
+
+    // the same goes for buying an exact amount
+    token1.mint(&recipient, &2_000i128);
+    let spent = multihop.swap_exact_out(
+        &recipient,
+        &operations,
+        &500,
+        &2_000,
+        &None,
+        &Some(receiver.clone()),
+    );
+    assert_eq!(token1.balance(&recipient), 2_000 - spent);
+    assert_eq!(token2.balance(&recipient), 0);
+    assert_eq!(token3.balance(&recipient), 0);
+    assert_eq!(token2.balance(&receiver), 0);
+    assert_eq!(token3.balance(&receiver), received + 500);
 }
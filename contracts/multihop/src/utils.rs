@@ -1,27 +1,166 @@
-use soroban_sdk::Vec;
+use phoenix::error::MultihopError;
+use soroban_sdk::{log, Address, Env, Vec};
 
-use crate::storage::Swap;
+use crate::factory_contract;
+use crate::pool::PoolClient;
+use crate::storage::{get_factory, SplitRoute, Swap};
 
-pub fn verify_swap(operations: &Vec<Swap>) {
+pub fn verify_swap(env: &Env, operations: &Vec<Swap>) -> Result<(), MultihopError> {
     for (current, next) in operations.iter().zip(operations.iter().skip(1)) {
         if current.ask_asset != next.offer_asset {
-            panic!("Multihop: Swap: Provided bad swap order");
+            log!(env, "Multihop: Swap: Provided bad swap order");
+            return Err(MultihopError::IncorrectSwapOrder);
         }
     }
+
+    Ok(())
 }
 
-pub fn verify_reverse_swap(operations: &Vec<Swap>) {
+/// Checks that the ledger timestamp has not passed the optional `deadline` of a swap.
+pub fn verify_deadline(env: &Env, deadline: Option<u64>) -> Result<(), MultihopError> {
+    if let Some(deadline) = deadline {
+        if env.ledger().timestamp() > deadline {
+            log!(env, "Multihop: Swap: deadline of {} has passed", deadline);
+            return Err(MultihopError::DeadlineExceeded);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the amount received at the end of a swap is at least `min_amount_out`.
+pub fn verify_min_amount_out(
+    env: &Env,
+    amount_out: i128,
+    min_amount_out: i128,
+) -> Result<(), MultihopError> {
+    if amount_out < min_amount_out {
+        log!(
+            env,
+            "Multihop: Swap: Received {} which is below the minimum of {}",
+            amount_out,
+            min_amount_out
+        );
+        return Err(MultihopError::AskAmountBelowMinimum);
+    }
+
+    Ok(())
+}
+
+pub fn verify_reverse_swap(env: &Env, operations: &Vec<Swap>) -> Result<(), MultihopError> {
     for (current, next) in operations.iter().zip(operations.iter().skip(1)) {
         if current.offer_asset != next.ask_asset {
-            panic!("Multihop: Reverse swap: Provided bad swap order");
+            log!(env, "Multihop: Reverse swap: Provided bad swap order");
+            return Err(MultihopError::IncorrectSwapOrder);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that every route of a split swap is a valid swap between the same offer and ask asset
+/// and that the allocations add up to the whole amount.
+pub fn verify_split_routes(env: &Env, routes: &Vec<SplitRoute>) -> Result<(), MultihopError> {
+    let Some(first_route) = routes.first() else {
+        log!(env, "Multihop: Split swap: routes are empty!");
+        return Err(MultihopError::OperationsEmpty);
+    };
+    let (Some(first_swap), Some(last_swap)) = (
+        first_route.operations.first(),
+        first_route.operations.last(),
+    ) else {
+        log!(env, "Multihop: Split swap: operations are empty!");
+        return Err(MultihopError::OperationsEmpty);
+    };
+
+    let mut total_allocation_bps = 0;
+    for route in routes.iter() {
+        if route.operations.is_empty() {
+            log!(env, "Multihop: Split swap: operations are empty!");
+            return Err(MultihopError::OperationsEmpty);
+        }
+        verify_swap(env, &route.operations)?;
+
+        let route_offer_asset = route.operations.first().unwrap().offer_asset;
+        let route_ask_asset = route.operations.last().unwrap().ask_asset;
+        if route_offer_asset != first_swap.offer_asset || route_ask_asset != last_swap.ask_asset {
+            log!(
+                env,
+                "Multihop: Split swap: all routes have to swap the same offer asset for the same ask asset"
+            );
+            return Err(MultihopError::SplitRoutesMismatch);
+        }
+
+        if route.allocation_bps <= 0 {
+            log!(env, "Multihop: Split swap: allocation has to be positive");
+            return Err(MultihopError::InvalidSplitAllocation);
+        }
+        total_allocation_bps += route.allocation_bps;
+    }
+
+    if total_allocation_bps != 10_000 {
+        log!(
+            env,
+            "Multihop: Split swap: allocations add up to {} bps instead of 10_000",
+            total_allocation_bps
+        );
+        return Err(MultihopError::InvalidSplitAllocation);
+    }
+
+    Ok(())
+}
+
+/// Checks that no two routes of a split swap go through the same pool, as every route is
+/// simulated against the reserves the pools hold before the split swap.
+pub fn verify_split_pools(env: &Env, routes: &Vec<SplitRoute>) -> Result<(), MultihopError> {
+    let factory_client = factory_contract::Client::new(env, &get_factory(env));
+
+    let mut pools: Vec<Address> = Vec::new(env);
+    for route in routes.iter() {
+        let mut route_pools: Vec<Address> = Vec::new(env);
+        for op in route.operations.iter() {
+            let pool = PoolClient::for_swap(env, &factory_client, &op).address;
+            if pools.contains(&pool) {
+                log!(
+                    env,
+                    "Multihop: Split swap: pool {} is part of more than one route",
+                    pool
+                );
+                return Err(MultihopError::SplitRoutesSharePool);
+            }
+            route_pools.push_back(pool);
         }
+        pools.append(&route_pools);
     }
+
+    Ok(())
+}
+
+/// Splits `amount` between the routes according to their allocation. The last route gets
+/// whatever is left after rounding down the others, so that the whole amount is used.
+pub fn split_amount(env: &Env, routes: &Vec<SplitRoute>, amount: i128) -> Vec<i128> {
+    let mut amounts = Vec::new(env);
+    let mut remaining = amount;
+    for (index, route) in routes.iter().enumerate() {
+        let route_amount = if index as u32 == routes.len() - 1 {
+            remaining
+        } else {
+            amount * route.allocation_bps as i128 / 10_000
+        };
+        remaining -= route_amount;
+        amounts.push_back(route_amount);
+    }
+
+    amounts
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{storage::Swap, utils::verify_swap};
+    use crate::{
+        storage::{Swap, SwapPool},
+        utils::verify_swap,
+    };
 
     use soroban_sdk::{testutils::Address as _, vec, Address, Env};
 
@@ -37,19 +176,22 @@ mod tests {
         let swap1 = Swap {
             offer_asset: token1.clone(),
             ask_asset: token2.clone(),
+            pool: SwapPool::Pair,
         };
         let swap2 = Swap {
             offer_asset: token2.clone(),
             ask_asset: token3.clone(),
+            pool: SwapPool::Pair,
         };
         let swap3 = Swap {
             offer_asset: token3.clone(),
             ask_asset: token4.clone(),
+            pool: SwapPool::Pair,
         };
 
         let operations = vec![&env, swap1, swap2, swap3];
 
-        verify_swap(&operations);
+        assert_eq!(verify_swap(&env, &operations), Ok(()));
     }
 
     #[test]
@@ -64,23 +206,25 @@ mod tests {
         let swap1 = Swap {
             offer_asset: token3.clone(),
             ask_asset: token4.clone(),
+            pool: SwapPool::Pair,
         };
         let swap2 = Swap {
             offer_asset: token2.clone(),
             ask_asset: token3.clone(),
+            pool: SwapPool::Pair,
         };
         let swap3 = Swap {
             offer_asset: token1.clone(),
             ask_asset: token2.clone(),
+            pool: SwapPool::Pair,
         };
 
         let operations = vec![&env, swap1, swap2, swap3];
 
-        verify_reverse_swap(&operations);
+        assert_eq!(verify_reverse_swap(&env, &operations), Ok(()));
     }
 
     #[test]
-    #[should_panic(expected = "Multihop: Swap: Provided bad swap order")]
     fn verify_operations_should_fail_when_bad_order_provided() {
         let env = Env::default();
 
@@ -92,19 +236,23 @@ mod tests {
         let swap1 = Swap {
             offer_asset: token1.clone(),
             ask_asset: token2.clone(),
+            pool: SwapPool::Pair,
         };
         let swap2 = Swap {
             offer_asset: token3.clone(),
             ask_asset: token4.clone(),
+            pool: SwapPool::Pair,
         };
 
         let operations = vec![&env, swap1, swap2];
 
-        verify_swap(&operations);
+        assert_eq!(
+            verify_swap(&env, &operations),
+            Err(MultihopError::IncorrectSwapOrder)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Multihop: Reverse swap: Provided bad swap order")]
     fn verify_operations_reverse_swap_should_fail_when_bad_order_provided() {
         let env = Env::default();
 
@@ -116,14 +264,138 @@ mod tests {
         let swap1 = Swap {
             offer_asset: token1.clone(),
             ask_asset: token2.clone(),
+            pool: SwapPool::Pair,
         };
         let swap2 = Swap {
             offer_asset: token3.clone(),
             ask_asset: token4.clone(),
+            pool: SwapPool::Pair,
         };
 
         let operations = vec![&env, swap1, swap2];
 
-        verify_reverse_swap(&operations);
+        assert_eq!(
+            verify_reverse_swap(&env, &operations),
+            Err(MultihopError::IncorrectSwapOrder)
+        );
+    }
+
+    #[test]
+    fn verify_split_routes_should_work() {
+        let env = Env::default();
+
+        let token1 = Address::generate(&env);
+        let token2 = Address::generate(&env);
+        let token3 = Address::generate(&env);
+
+        let direct = vec![
+            &env,
+            Swap {
+                offer_asset: token1.clone(),
+                ask_asset: token3.clone(),
+                pool: SwapPool::Pair,
+            },
+        ];
+        let through_token2 = vec![
+            &env,
+            Swap {
+                offer_asset: token1.clone(),
+                ask_asset: token2.clone(),
+                pool: SwapPool::Pair,
+            },
+            Swap {
+                offer_asset: token2.clone(),
+                ask_asset: token3.clone(),
+                pool: SwapPool::Pair,
+            },
+        ];
+        let split_routes = |first_bps, second_bps| {
+            vec![
+                &env,
+                SplitRoute {
+                    operations: direct.clone(),
+                    allocation_bps: first_bps,
+                },
+                SplitRoute {
+                    operations: through_token2.clone(),
+                    allocation_bps: second_bps,
+                },
+            ]
+        };
+
+        assert_eq!(
+            verify_split_routes(&env, &split_routes(3_000, 7_000)),
+            Ok(())
+        );
+        assert_eq!(
+            verify_split_routes(&env, &split_routes(3_000, 6_000)),
+            Err(MultihopError::InvalidSplitAllocation)
+        );
+        assert_eq!(
+            verify_split_routes(&env, &split_routes(0, 10_000)),
+            Err(MultihopError::InvalidSplitAllocation)
+        );
+        assert_eq!(
+            verify_split_routes(&env, &vec![&env]),
+            Err(MultihopError::OperationsEmpty)
+        );
+
+        let other_ask_asset = vec![
+            &env,
+            SplitRoute {
+                operations: direct,
+                allocation_bps: 5_000,
+            },
+            SplitRoute {
+                operations: vec![
+                    &env,
+                    Swap {
+                        offer_asset: token1,
+                        ask_asset: token2,
+                        pool: SwapPool::Pair,
+                    },
+                ],
+                allocation_bps: 5_000,
+            },
+        ];
+        assert_eq!(
+            verify_split_routes(&env, &other_ask_asset),
+            Err(MultihopError::SplitRoutesMismatch)
+        );
+    }
+
+    #[test]
+    fn split_amount_gives_the_remainder_to_the_last_route() {
+        let env = Env::default();
+
+        let operations = vec![
+            &env,
+            Swap {
+                offer_asset: Address::generate(&env),
+                ask_asset: Address::generate(&env),
+                pool: SwapPool::Pair,
+            },
+        ];
+        let routes = vec![
+            &env,
+            SplitRoute {
+                operations: operations.clone(),
+                allocation_bps: 3_333,
+            },
+            SplitRoute {
+                operations: operations.clone(),
+                allocation_bps: 3_333,
+            },
+            SplitRoute {
+                operations,
+                allocation_bps: 3_334,
+            },
+        ];
+
+        assert_eq!(
+            split_amount(&env, &routes, 1_000),
+            vec![&env, 333, 333, 334]
+        );
+        assert_eq!(split_amount(&env, &routes, 1), vec![&env, 0, 0, 1]);
     }
 }
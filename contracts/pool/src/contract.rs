@@ -1,23 +1,27 @@
 use phoenix::utils::LiquidityPoolInitInfo;
 use soroban_sdk::{
-    contract, contractimpl, contractmeta, log, panic_with_error, Address, BytesN, Env, IntoVal,
+    contract, contractimpl, contractmeta, log, Address, Bytes, BytesN, Env, IntoVal, Symbol, Val,
+    Vec,
 };
 
 use num_integer::Roots;
 
-use crate::contracterror::ContractError;
 use crate::storage::utils::{is_initialized, set_initialized};
-use crate::storage::{ComputeSwap, LiquidityPoolInfo};
+use crate::storage::{ComputeSwap, FlashLoan, LiquidityPoolInfo, Referral, TwapResponse};
 use crate::{
-    stake_contract,
+    oracle, stake_contract,
     storage::{
         get_config, save_config, utils, validate_fee_bps, Asset, Config, PairType, PoolResponse,
-        SimulateReverseSwapResponse, SimulateSwapResponse,
+        PoolStatus, ProvideLiquidityResponse, SimulateReverseSwapResponse, SimulateSwapResponse,
+        SimulateWithdrawSingleResponse,
     },
     token_contract,
 };
 use decimal::Decimal;
-use phoenix::{utils::is_approx_ratio, validate_bps, validate_int_parameters};
+use phoenix::{error::PoolError, utils::is_approx_ratio, validate_bps, validate_int_parameters};
+
+// Minimum amount of initial LP shares, locked in the pool forever on the first deposit
+const MINIMUM_LIQUIDITY_AMOUNT: i128 = 1000;
 
 // Metadata that is added on to the WASM custom section
 contractmeta!(
@@ -31,17 +35,23 @@ pub struct LiquidityPool;
 pub trait LiquidityPoolTrait {
     // Sets the token contract addresses for this pool
     // token_wasm_hash is the WASM hash of the deployed token contract for the pool share token
+    // factory_addr is the factory that deployed the pool, which can pause it next to the admin
     #[allow(clippy::too_many_arguments)]
     fn initialize(
         env: Env,
         stake_wasm_hash: BytesN<32>,
         token_wasm_hash: BytesN<32>,
         lp_init_info: LiquidityPoolInitInfo,
-    );
+        factory_addr: Address,
+    ) -> Result<(), PoolError>;
 
     // Deposits token_a and token_b. Also mints pool shares for the "to" Identifier. The amount minted
     // is determined based on the difference between the reserves stored by this contract, and
     // the actual balance of token_a and token_b for this contract.
+    // If `auto_stake` is set, the minted shares are bonded in the stake contract on behalf of the depositor.
+    // Fails if fewer than `min_shares_out` shares would be minted.
+    // Returns the amount of shares minted and the amounts of token_a and token_b taken from the depositor.
+    #[allow(clippy::too_many_arguments)]
     fn provide_liquidity(
         env: Env,
         depositor: Address,
@@ -50,22 +60,40 @@ pub trait LiquidityPoolTrait {
         desired_b: Option<i128>,
         min_b: Option<i128>,
         custom_slippage_bps: Option<i64>,
-    );
+        auto_stake: bool,
+        min_shares_out: Option<i128>,
+    ) -> Result<ProvideLiquidityResponse, PoolError>;
 
     // `offer_asset` is the asset that the user would like to swap for the other token in the pool.
     // `offer_amount` is the amount being sold, with `max_spread_bps` being a safety to make sure you receive at least that amount.
-    // swap will transfer the selling token "to" to this contract, and then the contract will transfer the buying token to `sender`.
+    // `referral` is an optional address that receives `fee` bps of the bought amount, capped by `max_referral_bps`.
+    // swap will transfer the selling token "to" to this contract, and then the contract will transfer the buying token to `to`,
+    // or back to `sender` if it is not set.
     // Returns the amount of the token being bought.
+    #[allow(clippy::too_many_arguments)]
     fn swap(
         env: Env,
         sender: Address,
-        // FIXM: Disable Referral struct
-        // referral: Option<Referral>,
+        referral: Option<Referral>,
         offer_asset: Address,
         offer_amount: i128,
         belief_price: Option<i64>,
         max_spread_bps: Option<i64>,
-    ) -> i128;
+        to: Option<Address>,
+    ) -> Result<i128, PoolError>;
+
+    // Buys exactly `ask_amount` of `ask_asset` for the other token in the pool, spending at most
+    // `max_offer_amount` of it. The bought tokens are sent to `to`, or back to `sender` if it is
+    // not set.
+    // Returns the amount of the token being sold.
+    fn swap_exact_out(
+        env: Env,
+        sender: Address,
+        ask_asset: Address,
+        ask_amount: i128,
+        max_offer_amount: i128,
+        to: Option<Address>,
+    ) -> Result<i128, PoolError>;
 
     // transfers share_amount of pool share tokens to this contract, burns all pools share tokens in this contracts, and sends the
     // corresponding amount of token_a and token_b to "to".
@@ -76,7 +104,54 @@ pub trait LiquidityPoolTrait {
         share_amount: i128,
         min_a: i128,
         min_b: i128,
-    ) -> (i128, i128);
+    ) -> Result<(i128, i128), PoolError>;
+
+    // Unbonds the stake of `stake_amount` shares made at `stake_timestamp` from the stake contract and
+    // withdraws the liquidity in one call.
+    // Returns the amounts of token_a and token_b sent back to the sender.
+    fn unstake_and_withdraw(
+        env: Env,
+        sender: Address,
+        stake_amount: i128,
+        stake_timestamp: u64,
+        min_a: i128,
+        min_b: i128,
+    ) -> Result<(i128, i128), PoolError>;
+
+    // Withdraws the liquidity of `share_amount` shares into `out_asset` only. The share of the other
+    // token is swapped into `out_asset` against the pool after the withdrawal.
+    // Returns the amount of `out_asset` sent to the sender.
+    fn withdraw_liquidity_single(
+        env: Env,
+        sender: Address,
+        share_amount: i128,
+        out_asset: Address,
+        min_out: i128,
+    ) -> Result<i128, PoolError>;
+
+    // Lends `amount_a` of token_a and `amount_b` of token_b to the `receiver` contract and calls its
+    // `on_flash_loan(initiator: Address, pool: Address, loan: FlashLoan, data: Bytes)`. Before that
+    // call returns, the receiver has to transfer the lent amounts plus the fees of the `FlashLoan`
+    // back to the pool. The `initiator` authorizes the loan and is passed on to the receiver, so
+    // that it can reject the loans it did not ask for.
+    // Returns the `FlashLoan` with the lent amounts and the fees paid.
+    fn flash_loan(
+        env: Env,
+        initiator: Address,
+        receiver: Address,
+        amount_a: i128,
+        amount_b: i128,
+        data: Bytes,
+    ) -> Result<FlashLoan, PoolError>;
+
+    // Sets the stored reserves to the actual token balances of the pool, so that the tokens sent
+    // directly to the pool go to the liquidity providers. Can only be called by the admin.
+    fn sync(env: Env, sender: Address) -> Result<(), PoolError>;
+
+    // Sends the token balances of the pool above the stored reserves to `to`. Can only be called by
+    // the admin.
+    // Returns the amounts of token_a and token_b sent.
+    fn skim(env: Env, sender: Address, to: Address) -> Result<(i128, i128), PoolError>;
 
     // Allows admin address set during initialization to change some parameters of the
     // configuration
@@ -89,7 +164,20 @@ pub trait LiquidityPoolTrait {
         max_allowed_slippage_bps: Option<i64>,
         max_allowed_spread_bps: Option<i64>,
         max_referral_bps: Option<i64>,
-    );
+        protocol_fee_share_bps: Option<i64>,
+        distribute_lp_fees_to_stakers: Option<bool>,
+        flash_fee_bps: Option<i64>,
+    ) -> Result<(), PoolError>;
+
+    // Pauses or resumes the swaps, deposits and withdrawals of the pool. Can be called by the admin
+    // or by the factory of the pool. The flags that are not set keep their value.
+    fn update_pause_status(
+        env: Env,
+        sender: Address,
+        swaps_paused: Option<bool>,
+        deposits_paused: Option<bool>,
+        withdrawals_paused: Option<bool>,
+    ) -> Result<(), PoolError>;
 
     // Migration entrypoint
     fn upgrade(e: Env, new_wasm_hash: BytesN<32>);
@@ -105,6 +193,13 @@ pub trait LiquidityPoolTrait {
     // Returns the address for the pool stake contract
     fn query_stake_contract_address(env: Env) -> Address;
 
+    // Returns which of the pool operations are paused
+    fn query_status(env: Env) -> PoolStatus;
+
+    // Returns the differences between the token balances of the pool and the stored reserves of
+    // token_a and token_b. Positive differences can be skimmed, negative ones are removed by sync.
+    fn query_reserve_discrepancy(env: Env) -> (i128, i128);
+
     // Returns  the total amount of LP tokens and assets in a specific pool
     fn query_pool_info(env: Env) -> PoolResponse;
 
@@ -119,6 +214,30 @@ pub trait LiquidityPoolTrait {
         ask_asset: Address,
         ask_amount: i128,
     ) -> SimulateReverseSwapResponse;
+
+    // Simulate providing liquidity, returning the shares that would be minted and the amounts of
+    // token_a and token_b taken from the depositor
+    fn simulate_provide_liquidity(
+        env: Env,
+        desired_a: Option<i128>,
+        desired_b: Option<i128>,
+    ) -> Result<ProvideLiquidityResponse, PoolError>;
+
+    // Simulate withdrawing the liquidity of `share_amount` shares, returning the amounts of token_a
+    // and token_b that would be sent back
+    fn simulate_withdraw_liquidity(env: Env, share_amount: i128) -> (i128, i128);
+
+    // Simulate withdrawing the liquidity of `share_amount` shares into `out_asset` only
+    fn simulate_withdraw_single(
+        env: Env,
+        share_amount: i128,
+        out_asset: Address,
+    ) -> Result<SimulateWithdrawSingleResponse, PoolError>;
+
+    // Returns the time weighted average price of the given asset, expressed in the other pool
+    // token, over at least the last `window_seconds`
+    fn query_twap(env: Env, asset: Address, window_seconds: u64)
+        -> Result<TwapResponse, PoolError>;
 }
 
 #[contractimpl]
@@ -129,9 +248,14 @@ impl LiquidityPoolTrait for LiquidityPool {
         stake_wasm_hash: BytesN<32>,
         token_wasm_hash: BytesN<32>,
         lp_init_info: LiquidityPoolInitInfo,
-    ) {
+        factory_addr: Address,
+    ) -> Result<(), PoolError> {
         if is_initialized(&env) {
-            panic!("Liquidity Pool: Initialize: initializing contract twice is not allowed");
+            log!(
+                &env,
+                "Pool: Initialize: initializing contract twice is not allowed"
+            );
+            return Err(PoolError::AlreadyInitialized);
         }
 
         let admin = lp_init_info.admin;
@@ -149,7 +273,7 @@ impl LiquidityPoolTrait for LiquidityPool {
             max_allowed_slippage_bps,
             max_allowed_spread_bps,
             max_referral_bps
-        );
+        )?;
 
         set_initialized(&env);
 
@@ -163,15 +287,16 @@ impl LiquidityPoolTrait for LiquidityPool {
 
         // Token order validation to make sure only one instance of a pool can exist
         if token_a >= token_b {
-            log!(&env, "token_a must be less than token_b");
-            panic!(
+            log!(
+                &env,
                 "Pool: Initialize: First token must be alphabetically smaller than second token"
             );
+            return Err(PoolError::TokensNotSorted);
         }
 
         if !(0..=10_000).contains(&swap_fee_bps) {
-            log!(&env, "Fees must be between 0 and 100%");
-            panic!("Pool: Initialize: Fees must be between 0 and 100%");
+            log!(&env, "Pool: Initialize: Fees must be between 0 and 100%");
+            return Err(PoolError::InvalidBps);
         }
 
         // deploy token contract
@@ -203,15 +328,21 @@ impl LiquidityPoolTrait for LiquidityPool {
             share_token: share_token_address,
             stake_contract: stake_contract_address,
             pool_type: PairType::Xyk,
-            total_fee_bps: validate_fee_bps(&env, swap_fee_bps),
+            total_fee_bps: validate_fee_bps(&env, swap_fee_bps)?,
             fee_recipient,
             max_allowed_slippage_bps,
             max_allowed_spread_bps,
             max_referral_bps,
+            // the whole commission goes to the fee recipient until the admin changes it
+            protocol_fee_share_bps: 10_000,
+            distribute_lp_fees_to_stakers: false,
+            // flash loans pay the swap fee until the admin changes it
+            flash_fee_bps: swap_fee_bps,
         };
 
         save_config(&env, config);
         utils::save_admin(&env, admin);
+        utils::save_factory(&env, factory_addr);
         utils::save_total_shares(&env, 0);
         utils::save_pool_balance_a(&env, 0);
         utils::save_pool_balance_b(&env, 0);
@@ -220,8 +351,11 @@ impl LiquidityPoolTrait for LiquidityPool {
             .publish(("initialize", "XYK LP token_a"), token_a);
         env.events()
             .publish(("initialize", "XYK LP token_b"), token_b);
+
+        Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn provide_liquidity(
         env: Env,
         sender: Address,
@@ -230,12 +364,19 @@ impl LiquidityPoolTrait for LiquidityPool {
         desired_b: Option<i128>,
         min_b: Option<i128>,
         custom_slippage_bps: Option<i64>,
-    ) {
-        validate_int_parameters!(desired_a, min_a, desired_b, min_b);
+        auto_stake: bool,
+        min_shares_out: Option<i128>,
+    ) -> Result<ProvideLiquidityResponse, PoolError> {
+        validate_int_parameters!(desired_a, min_a, desired_b, min_b, min_shares_out)?;
 
         // sender needs to authorize the deposit
         sender.require_auth();
 
+        if utils::get_status(&env).deposits_paused {
+            log!(&env, "Pool: ProvideLiquidity: Deposits are paused");
+            return Err(PoolError::DepositsPaused);
+        }
+
         let config = get_config(&env);
         let pool_balance_a = utils::get_pool_balance_a(&env);
         let pool_balance_b = utils::get_pool_balance_b(&env);
@@ -243,80 +384,54 @@ impl LiquidityPoolTrait for LiquidityPool {
         // Check if custom_slippage_bps is more than max_allowed_slippage
         if let Some(custom_slippage) = custom_slippage_bps {
             if custom_slippage > config.max_allowed_slippage_bps {
-                panic!("Pool: ProvideLiquidity: Custom slippage tolerance is more than max allowed slippage tolerance");
+                log!(
+                    &env,
+                    "Pool: ProvideLiquidity: Custom slippage tolerance is more than max allowed slippage tolerance"
+                );
+                return Err(PoolError::SlippageToleranceExceedsMax);
             }
         }
 
-        // Check if both tokens are provided, one token is provided, or none are provided
-        let amounts = match (desired_a, desired_b) {
-            // Both tokens are provided
-            (Some(a), Some(b)) if a > 0 && b > 0 => {
-                // Calculate deposit amounts
-                utils::get_deposit_amounts(
-                    &env,
-                    a,
-                    min_a,
-                    b,
-                    min_b,
-                    pool_balance_a,
-                    pool_balance_b,
-                    Decimal::bps(custom_slippage_bps.unwrap_or(100)),
-                )
-            }
-            // Only token A is provided
-            (Some(a), None) if a > 0 => {
-                let (a_for_swap, b_from_swap) = split_deposit_based_on_pool_ratio(
-                    &env,
-                    &config,
-                    pool_balance_a,
-                    pool_balance_b,
-                    a,
-                    &config.token_a,
-                );
-                do_swap(
+        let (amounts, swap) = compute_deposit_amounts(
+            &env,
+            &config,
+            (desired_a, min_a),
+            (desired_b, min_b),
+            custom_slippage_bps,
+            pool_balance_a,
+            pool_balance_b,
+        )?;
+
+        // A deposit of one token swaps a part of it for the other one first. The amounts taken
+        // from the sender are the swapped amount plus the deposit, minus what the swap returned.
+        let (amount_a, amount_b) = match swap {
+            Some((offer_asset, offer_amount)) => {
+                if utils::get_status(&env).swaps_paused {
+                    log!(
+                        &env,
+                        "Pool: ProvideLiquidity: Swaps are paused, single-sided deposits are not available"
+                    );
+                    return Err(PoolError::SwapsPaused);
+                }
+                // the swap is bound by the slippage tolerance of the depositor, if given
+                let max_spread_bps = custom_slippage_bps.unwrap_or(config.max_allowed_spread_bps);
+                let return_amount = do_swap(
                     env.clone(),
                     sender.clone(),
-                    // FIXM: Disable Referral struct
-                    // None,
-                    config.clone().token_a,
-                    a_for_swap,
-                    None,
-                    None,
-                );
-                // return: rest of Token A amount, simulated result of swap of portion A
-                (a - a_for_swap, b_from_swap)
-            }
-            // Only token B is provided
-            (None, Some(b)) if b > 0 => {
-                let (b_for_swap, a_from_swap) = split_deposit_based_on_pool_ratio(
-                    &env,
-                    &config,
-                    pool_balance_a,
-                    pool_balance_b,
-                    b,
-                    &config.token_b,
-                );
-                do_swap(
-                    env.clone(),
                     sender.clone(),
-                    // FIXM: Disable Referral struct
-                    // None,
-                    config.clone().token_b,
-                    b_for_swap,
                     None,
+                    offer_asset.clone(),
+                    offer_amount,
                     None,
-                );
-                // return: simulated result of swap of portion B, rest of Token B amount
-                (a_from_swap, b - b_for_swap)
-            }
-            // None or invalid amounts are provided
-            _ => {
-                log!(
-                    &env,
-                    "At least one token must be provided and must be bigger then 0!"
-                );
-                panic!("Pool: ProvideLiquidity: At least one token must be provided and must be bigger then 0!");
+                    Some(max_spread_bps),
+                )?;
+                if offer_asset == config.token_a {
+                    (amounts.0 + offer_amount, amounts.1 - return_amount)
+                } else {
+                    (amounts.0 - return_amount, amounts.1 + offer_amount)
+                }
             }
+            None => amounts,
         };
 
         let token_a_client = token_contract::Client::new(&env, &config.token_a);
@@ -334,24 +449,48 @@ impl LiquidityPoolTrait for LiquidityPool {
         let balance_b = utils::get_balance(&env, &config.token_b);
         let total_shares = utils::get_total_shares(&env);
 
-        let new_total_shares = if pool_balance_a > 0 && pool_balance_b > 0 {
-            let shares_a = (balance_a * total_shares) / pool_balance_a;
-            let shares_b = (balance_b * total_shares) / pool_balance_b;
-            shares_a.min(shares_b)
-        } else {
-            // In case of empty pool, just produce X*Y shares
-            (balance_a * balance_b).sqrt()
-        };
-
-        utils::mint_shares(
-            &env,
-            &config.share_token,
-            &sender,
-            new_total_shares - total_shares,
+        let minted_shares = compute_minted_shares(
+            balance_a,
+            balance_b,
+            pool_balance_a,
+            pool_balance_b,
+            total_shares,
         );
+        if minted_shares <= 0 {
+            log!(&env, "Pool: ProvideLiquidity: Liquidity amount is too low");
+            return Err(PoolError::LiquidityAmountTooLow);
+        }
+        if let Some(min_shares_out) = min_shares_out {
+            if minted_shares < min_shares_out {
+                log!(
+                    &env,
+                    "Pool: ProvideLiquidity: Minimum amount of shares is not satisfied! min_shares_out: {}, minted_shares: {}",
+                    min_shares_out,
+                    minted_shares
+                );
+                return Err(PoolError::MinSharesNotSatisfied);
+            }
+        }
+
+        if total_shares == 0 {
+            // The minimum liquidity is minted to the pool itself, so that the pool can never be
+            // emptied and the price of a share can not be inflated by the first depositor
+            utils::mint_shares(
+                &env,
+                &config.share_token,
+                &env.current_contract_address(),
+                MINIMUM_LIQUIDITY_AMOUNT,
+            );
+        }
+        utils::mint_shares(&env, &config.share_token, &sender, minted_shares);
+        oracle::update(&env, pool_balance_a, pool_balance_b);
         utils::save_pool_balance_a(&env, balance_a);
         utils::save_pool_balance_b(&env, balance_b);
 
+        if auto_stake {
+            stake_contract::Client::new(&env, &config.stake_contract).bond(&sender, &minted_shares);
+        }
+
         env.events()
             .publish(("provide_liquidity", "sender"), sender);
         env.events()
@@ -362,26 +501,42 @@ impl LiquidityPoolTrait for LiquidityPool {
             .publish(("provide_liquidity", "token_b"), &config.token_b);
         env.events()
             .publish(("provide_liquidity", "token_b-amount"), amounts.1);
+        env.events()
+            .publish(("provide_liquidity", "shares_amount"), minted_shares);
+
+        Ok(ProvideLiquidityResponse {
+            shares: minted_shares,
+            amount_a,
+            amount_b,
+        })
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn swap(
         env: Env,
         sender: Address,
-        // FIXM: Disable Referral struct
-        // referral: Option<Referral>,
+        referral: Option<Referral>,
         offer_asset: Address,
         offer_amount: i128,
         belief_price: Option<i64>,
         max_spread_bps: Option<i64>,
-    ) -> i128 {
-        validate_int_parameters!(offer_amount);
+        to: Option<Address>,
+    ) -> Result<i128, PoolError> {
+        validate_int_parameters!(offer_amount)?;
 
         sender.require_auth();
 
+        if utils::get_status(&env).swaps_paused {
+            log!(&env, "Pool: Swap: Swaps are paused");
+            return Err(PoolError::SwapsPaused);
+        }
+
+        let receiver = to.unwrap_or_else(|| sender.clone());
         do_swap(
             env,
             sender,
-            // referral,
+            receiver,
+            referral,
             offer_asset,
             offer_amount,
             belief_price,
@@ -389,75 +544,260 @@ impl LiquidityPoolTrait for LiquidityPool {
         )
     }
 
+    fn swap_exact_out(
+        env: Env,
+        sender: Address,
+        ask_asset: Address,
+        ask_amount: i128,
+        max_offer_amount: i128,
+        to: Option<Address>,
+    ) -> Result<i128, PoolError> {
+        validate_int_parameters!(ask_amount, max_offer_amount)?;
+
+        sender.require_auth();
+
+        if utils::get_status(&env).swaps_paused {
+            log!(&env, "Pool: SwapExactOut: Swaps are paused");
+            return Err(PoolError::SwapsPaused);
+        }
+
+        let receiver = to.unwrap_or_else(|| sender.clone());
+        do_swap_exact_out(
+            env,
+            sender,
+            receiver,
+            ask_asset,
+            ask_amount,
+            max_offer_amount,
+        )
+    }
+
     fn withdraw_liquidity(
         env: Env,
         sender: Address,
         share_amount: i128,
         min_a: i128,
         min_b: i128,
-    ) -> (i128, i128) {
-        validate_int_parameters!(share_amount, min_a, min_b);
+    ) -> Result<(i128, i128), PoolError> {
+        validate_int_parameters!(share_amount, min_a, min_b)?;
+
+        sender.require_auth();
+
+        if utils::get_status(&env).withdrawals_paused {
+            log!(&env, "Pool: WithdrawLiquidity: Withdrawals are paused");
+            return Err(PoolError::WithdrawalsPaused);
+        }
+
+        do_withdraw_liquidity(env, sender, share_amount, min_a, min_b)
+    }
+
+    fn unstake_and_withdraw(
+        env: Env,
+        sender: Address,
+        stake_amount: i128,
+        stake_timestamp: u64,
+        min_a: i128,
+        min_b: i128,
+    ) -> Result<(i128, i128), PoolError> {
+        validate_int_parameters!(stake_amount, min_a, min_b)?;
 
         sender.require_auth();
 
+        if utils::get_status(&env).withdrawals_paused {
+            log!(&env, "Pool: UnstakeAndWithdraw: Withdrawals are paused");
+            return Err(PoolError::WithdrawalsPaused);
+        }
+
         let config = get_config(&env);
+        stake_contract::Client::new(&env, &config.stake_contract).unbond(
+            &sender,
+            &stake_amount,
+            &stake_timestamp,
+        );
+
+        do_withdraw_liquidity(env, sender, stake_amount, min_a, min_b)
+    }
+
+    fn withdraw_liquidity_single(
+        env: Env,
+        sender: Address,
+        share_amount: i128,
+        out_asset: Address,
+        min_out: i128,
+    ) -> Result<i128, PoolError> {
+        validate_int_parameters!(share_amount, min_out)?;
+
+        sender.require_auth();
+
+        let status = utils::get_status(&env);
+        if status.withdrawals_paused {
+            log!(
+                &env,
+                "Pool: WithdrawLiquiditySingle: Withdrawals are paused"
+            );
+            return Err(PoolError::WithdrawalsPaused);
+        }
+        // the share of the other token is swapped, so the swaps have to be open as well
+        if status.swaps_paused {
+            log!(&env, "Pool: WithdrawLiquiditySingle: Swaps are paused");
+            return Err(PoolError::SwapsPaused);
+        }
+
+        do_withdraw_liquidity_single(env, sender, share_amount, out_asset, min_out)
+    }
+
+    fn flash_loan(
+        env: Env,
+        initiator: Address,
+        receiver: Address,
+        amount_a: i128,
+        amount_b: i128,
+        data: Bytes,
+    ) -> Result<FlashLoan, PoolError> {
+        initiator.require_auth();
+
+        if amount_a < 0 || amount_b < 0 || amount_a + amount_b == 0 {
+            log!(
+                &env,
+                "Pool: FlashLoan: Amounts cannot be negative and at least one has to be positive"
+            );
+            return Err(PoolError::InvalidAmount);
+        }
 
-        let share_token_client = token_contract::Client::new(&env, &config.share_token);
-        share_token_client.transfer(&sender, &env.current_contract_address(), &share_amount);
+        // flash loans are halted together with the swaps
+        if utils::get_status(&env).swaps_paused {
+            log!(&env, "Pool: FlashLoan: Swaps are paused");
+            return Err(PoolError::SwapsPaused);
+        }
 
+        let config = get_config(&env);
         let pool_balance_a = utils::get_pool_balance_a(&env);
         let pool_balance_b = utils::get_pool_balance_b(&env);
+        if amount_a > pool_balance_a || amount_b > pool_balance_b {
+            log!(&env, "Pool: FlashLoan: Not enough liquidity in the pool");
+            return Err(PoolError::NotEnoughLiquidity);
+        }
 
-        let mut share_ratio = Decimal::zero();
-        let total_shares = utils::get_total_shares(&env);
-        if total_shares != 0i128 {
-            share_ratio = Decimal::from_ratio(share_amount, total_shares);
+        let loan = FlashLoan {
+            token_a: config.token_a.clone(),
+            token_b: config.token_b.clone(),
+            amount_a,
+            amount_b,
+            fee_a: compute_flash_fee(amount_a, config.flash_fee_bps),
+            fee_b: compute_flash_fee(amount_b, config.flash_fee_bps),
+        };
+
+        let token_a_client = token_contract::Client::new(&env, &config.token_a);
+        let token_b_client = token_contract::Client::new(&env, &config.token_b);
+        let balance_a_before = token_a_client.balance(&env.current_contract_address());
+        let balance_b_before = token_b_client.balance(&env.current_contract_address());
+
+        if amount_a > 0 {
+            token_a_client.transfer(&env.current_contract_address(), &receiver, &amount_a);
+        }
+        if amount_b > 0 {
+            token_b_client.transfer(&env.current_contract_address(), &receiver, &amount_b);
         }
 
-        let return_amount_a = pool_balance_a * share_ratio;
-        let return_amount_b = pool_balance_b * share_ratio;
+        let callback_args: Vec<Val> = (
+            initiator.clone(),
+            env.current_contract_address(),
+            loan.clone(),
+            data,
+        )
+            .into_val(&env);
+        env.invoke_contract::<Val>(
+            &receiver,
+            &Symbol::new(&env, "on_flash_loan"),
+            callback_args,
+        );
 
-        if return_amount_a < min_a || return_amount_b < min_b {
+        if token_a_client.balance(&env.current_contract_address()) < balance_a_before + loan.fee_a
+            || token_b_client.balance(&env.current_contract_address())
+                < balance_b_before + loan.fee_b
+        {
             log!(
                 &env,
-                "Minimum amount of token_a or token_b is not satisfied! min_a: {}, min_b: {}, return_amount_a: {}, return_amount_b: {}",
-                min_a,
-                min_b,
-                return_amount_a,
-                return_amount_b
+                "Pool: FlashLoan: The loan plus the fees was not repaid"
             );
-            panic!(
-                "Pool: WithdrawLiquidity: Minimum amount of token_a or token_b is not satisfied!"
-            )
+            return Err(PoolError::FlashLoanNotRepaid);
         }
 
-        // burn shares
-        utils::burn_shares(&env, &config.share_token, share_amount);
-        // transfer tokens from sender to contract
-        token_contract::Client::new(&env, &config.token_a).transfer(
-            &env.current_contract_address(),
-            &sender,
-            &return_amount_a,
-        );
-        token_contract::Client::new(&env, &config.token_b).transfer(
-            &env.current_contract_address(),
-            &sender,
-            &return_amount_b,
-        );
-        // update pool balances
-        utils::save_pool_balance_a(&env, pool_balance_a - return_amount_a);
-        utils::save_pool_balance_b(&env, pool_balance_b - return_amount_b);
+        let fee_removed_a = distribute_flash_fee(&env, &config, &config.token_a, loan.fee_a);
+        let fee_removed_b = distribute_flash_fee(&env, &config, &config.token_b, loan.fee_b);
 
-        env.events()
-            .publish(("withdraw_liquidity", "sender"), sender);
-        env.events()
-            .publish(("withdraw_liquidity", "shares_amount"), share_amount);
-        env.events()
-            .publish(("withdraw_liquidity", "return_amount_a"), return_amount_a);
-        env.events()
-            .publish(("withdraw_liquidity", "return_amount_b"), return_amount_b);
+        oracle::update(&env, pool_balance_a, pool_balance_b);
+        utils::save_pool_balance_a(&env, pool_balance_a + loan.fee_a - fee_removed_a);
+        utils::save_pool_balance_b(&env, pool_balance_b + loan.fee_b - fee_removed_b);
+
+        env.events().publish(("flash_loan", "initiator"), initiator);
+        env.events().publish(("flash_loan", "receiver"), receiver);
+        env.events().publish(("flash_loan", "amount_a"), amount_a);
+        env.events().publish(("flash_loan", "amount_b"), amount_b);
+        env.events().publish(("flash_loan", "fee_a"), loan.fee_a);
+        env.events().publish(("flash_loan", "fee_b"), loan.fee_b);
+
+        Ok(loan)
+    }
+
+    fn sync(env: Env, sender: Address) -> Result<(), PoolError> {
+        sender.require_auth();
+
+        if sender != utils::get_admin(&env) {
+            log!(&env, "Pool: Sync: Unauthorized");
+            return Err(PoolError::Unauthorized);
+        }
+
+        let config = get_config(&env);
+        let pool_balance_a = utils::get_pool_balance_a(&env);
+        let pool_balance_b = utils::get_pool_balance_b(&env);
+        let balance_a = utils::get_balance(&env, &config.token_a);
+        let balance_b = utils::get_balance(&env, &config.token_b);
+
+        oracle::update(&env, pool_balance_a, pool_balance_b);
+        utils::save_pool_balance_a(&env, balance_a);
+        utils::save_pool_balance_b(&env, balance_b);
+
+        env.events().publish(("sync", "reserve_a"), balance_a);
+        env.events().publish(("sync", "reserve_b"), balance_b);
+
+        Ok(())
+    }
+
+    fn skim(env: Env, sender: Address, to: Address) -> Result<(i128, i128), PoolError> {
+        sender.require_auth();
+
+        if sender != utils::get_admin(&env) {
+            log!(&env, "Pool: Skim: Unauthorized");
+            return Err(PoolError::Unauthorized);
+        }
+
+        let config = get_config(&env);
+        let excess_a =
+            (utils::get_balance(&env, &config.token_a) - utils::get_pool_balance_a(&env)).max(0);
+        let excess_b =
+            (utils::get_balance(&env, &config.token_b) - utils::get_pool_balance_b(&env)).max(0);
+
+        if excess_a > 0 {
+            token_contract::Client::new(&env, &config.token_a).transfer(
+                &env.current_contract_address(),
+                &to,
+                &excess_a,
+            );
+        }
+        if excess_b > 0 {
+            token_contract::Client::new(&env, &config.token_b).transfer(
+                &env.current_contract_address(),
+                &to,
+                &excess_b,
+            );
+        }
+
+        env.events().publish(("skim", "to"), to);
+        env.events().publish(("skim", "amount_a"), excess_a);
+        env.events().publish(("skim", "amount_b"), excess_b);
 
-        (return_amount_a, return_amount_b)
+        Ok((excess_a, excess_b))
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -469,7 +809,10 @@ impl LiquidityPoolTrait for LiquidityPool {
         max_allowed_slippage_bps: Option<i64>,
         max_allowed_spread_bps: Option<i64>,
         max_referral_bps: Option<i64>,
-    ) {
+        protocol_fee_share_bps: Option<i64>,
+        distribute_lp_fees_to_stakers: Option<bool>,
+        flash_fee_bps: Option<i64>,
+    ) -> Result<(), PoolError> {
         let admin: Address = utils::get_admin(&env);
         admin.require_auth();
 
@@ -480,7 +823,8 @@ impl LiquidityPoolTrait for LiquidityPool {
         }
         if let Some(total_fee_bps) = total_fee_bps {
             if !(0..=10_000).contains(&total_fee_bps) {
-                panic!("Pool: UpdateConfig: Invalid total_fee_bps");
+                log!(&env, "Pool: UpdateConfig: Invalid total_fee_bps");
+                return Err(PoolError::InvalidBps);
             }
             config.total_fee_bps = total_fee_bps;
         }
@@ -496,8 +840,63 @@ impl LiquidityPoolTrait for LiquidityPool {
         if let Some(max_referral_bps) = max_referral_bps {
             config.max_referral_bps = max_referral_bps;
         }
+        if let Some(protocol_fee_share_bps) = protocol_fee_share_bps {
+            if !(0..=10_000).contains(&protocol_fee_share_bps) {
+                log!(&env, "Pool: UpdateConfig: Invalid protocol_fee_share_bps");
+                return Err(PoolError::InvalidBps);
+            }
+            config.protocol_fee_share_bps = protocol_fee_share_bps;
+        }
+        if let Some(distribute_lp_fees_to_stakers) = distribute_lp_fees_to_stakers {
+            config.distribute_lp_fees_to_stakers = distribute_lp_fees_to_stakers;
+        }
+        if let Some(flash_fee_bps) = flash_fee_bps {
+            if !(0..=10_000).contains(&flash_fee_bps) {
+                log!(&env, "Pool: UpdateConfig: Invalid flash_fee_bps");
+                return Err(PoolError::InvalidBps);
+            }
+            config.flash_fee_bps = flash_fee_bps;
+        }
 
         save_config(&env, config);
+
+        Ok(())
+    }
+
+    fn update_pause_status(
+        env: Env,
+        sender: Address,
+        swaps_paused: Option<bool>,
+        deposits_paused: Option<bool>,
+        withdrawals_paused: Option<bool>,
+    ) -> Result<(), PoolError> {
+        sender.require_auth();
+
+        if sender != utils::get_admin(&env) && sender != utils::get_factory(&env) {
+            log!(
+                &env,
+                "Pool: UpdatePauseStatus: Only the admin or the factory can pause the pool"
+            );
+            return Err(PoolError::Unauthorized);
+        }
+
+        let mut status = utils::get_status(&env);
+        if let Some(swaps_paused) = swaps_paused {
+            status.swaps_paused = swaps_paused;
+            env.events().publish(("pause", "swaps"), swaps_paused);
+        }
+        if let Some(deposits_paused) = deposits_paused {
+            status.deposits_paused = deposits_paused;
+            env.events().publish(("pause", "deposits"), deposits_paused);
+        }
+        if let Some(withdrawals_paused) = withdrawals_paused {
+            status.withdrawals_paused = withdrawals_paused;
+            env.events()
+                .publish(("pause", "withdrawals"), withdrawals_paused);
+        }
+        utils::save_status(&env, &status);
+
+        Ok(())
     }
 
     fn upgrade(env: Env, new_wasm_hash: BytesN<32>) {
@@ -521,6 +920,19 @@ impl LiquidityPoolTrait for LiquidityPool {
         get_config(&env).stake_contract
     }
 
+    fn query_status(env: Env) -> PoolStatus {
+        utils::get_status(&env)
+    }
+
+    fn query_reserve_discrepancy(env: Env) -> (i128, i128) {
+        let config = get_config(&env);
+
+        (
+            utils::get_balance(&env, &config.token_a) - utils::get_pool_balance_a(&env),
+            utils::get_balance(&env, &config.token_b) - utils::get_pool_balance_b(&env),
+        )
+    }
+
     fn query_pool_info(env: Env) -> PoolResponse {
         let config = get_config(&env);
 
@@ -624,25 +1036,444 @@ impl LiquidityPoolTrait for LiquidityPool {
             commission_amount,
         }
     }
+
+    fn simulate_provide_liquidity(
+        env: Env,
+        desired_a: Option<i128>,
+        desired_b: Option<i128>,
+    ) -> Result<ProvideLiquidityResponse, PoolError> {
+        let config = get_config(&env);
+        let mut pool_balance_a = utils::get_pool_balance_a(&env);
+        let mut pool_balance_b = utils::get_pool_balance_b(&env);
+
+        let (amounts, swap) = compute_deposit_amounts(
+            &env,
+            &config,
+            (desired_a, None),
+            (desired_b, None),
+            None,
+            pool_balance_a,
+            pool_balance_b,
+        )?;
+
+        // the swap of a one token deposit changes the pool balances the deposit is added to
+        let (amount_a, amount_b) = match swap {
+            Some((offer_asset, offer_amount)) => {
+                let (pool_balance_offer, pool_balance_ask) = if offer_asset == config.token_a {
+                    (pool_balance_a, pool_balance_b)
+                } else {
+                    (pool_balance_b, pool_balance_a)
+                };
+                let compute_swap = compute_swap(
+                    pool_balance_offer,
+                    pool_balance_ask,
+                    offer_amount,
+                    config.protocol_fee_rate(),
+                    0i64,
+                );
+                let protocol_fee_amount =
+                    compute_swap.commission_amount * config.protocol_fee_share();
+                let lp_fee_removed_from_pool = if config.distribute_lp_fees_to_stakers {
+                    compute_swap.commission_amount - protocol_fee_amount
+                } else {
+                    0
+                };
+                let new_pool_balance_ask = pool_balance_ask
+                    - compute_swap.return_amount
+                    - protocol_fee_amount
+                    - lp_fee_removed_from_pool;
+
+                if offer_asset == config.token_a {
+                    pool_balance_a += offer_amount;
+                    pool_balance_b = new_pool_balance_ask;
+                    (
+                        amounts.0 + offer_amount,
+                        amounts.1 - compute_swap.return_amount,
+                    )
+                } else {
+                    pool_balance_b += offer_amount;
+                    pool_balance_a = new_pool_balance_ask;
+                    (
+                        amounts.0 - compute_swap.return_amount,
+                        amounts.1 + offer_amount,
+                    )
+                }
+            }
+            None => amounts,
+        };
+
+        let shares = compute_minted_shares(
+            pool_balance_a + amounts.0,
+            pool_balance_b + amounts.1,
+            pool_balance_a,
+            pool_balance_b,
+            utils::get_total_shares(&env),
+        );
+        if shares <= 0 {
+            log!(
+                &env,
+                "Pool: SimulateProvideLiquidity: Liquidity amount is too low"
+            );
+            return Err(PoolError::LiquidityAmountTooLow);
+        }
+
+        Ok(ProvideLiquidityResponse {
+            shares,
+            amount_a,
+            amount_b,
+        })
+    }
+
+    fn simulate_withdraw_liquidity(env: Env, share_amount: i128) -> (i128, i128) {
+        let share_ratio = get_share_ratio(&env, share_amount);
+
+        (
+            utils::get_pool_balance_a(&env) * share_ratio,
+            utils::get_pool_balance_b(&env) * share_ratio,
+        )
+    }
+
+    fn simulate_withdraw_single(
+        env: Env,
+        share_amount: i128,
+        out_asset: Address,
+    ) -> Result<SimulateWithdrawSingleResponse, PoolError> {
+        let config = get_config(&env);
+        let (pool_balance_out, pool_balance_other) =
+            get_withdraw_single_balances(&env, &config, &out_asset)?;
+
+        let (return_amount, compute_swap) = compute_withdraw_single(
+            pool_balance_out,
+            pool_balance_other,
+            get_share_ratio(&env, share_amount),
+            config.protocol_fee_rate(),
+        );
+
+        Ok(SimulateWithdrawSingleResponse {
+            return_amount,
+            commission_amount: compute_swap.commission_amount,
+            spread_amount: compute_swap.spread_amount,
+        })
+    }
+
+    fn query_twap(
+        env: Env,
+        asset: Address,
+        window_seconds: u64,
+    ) -> Result<TwapResponse, PoolError> {
+        let config = get_config(&env);
+        if asset != config.token_a && asset != config.token_b {
+            log!(&env, "Pool: QueryTwap: Asset is not part of the pool");
+            return Err(PoolError::AssetNotInPool);
+        }
+
+        let (price_a, price_b, window_seconds) = oracle::twap(
+            &env,
+            utils::get_pool_balance_a(&env),
+            utils::get_pool_balance_b(&env),
+            window_seconds,
+        )?;
+
+        Ok(TwapResponse {
+            price: if asset == config.token_a {
+                price_a
+            } else {
+                price_b
+            },
+            window_seconds,
+        })
+    }
+}
+
+fn do_withdraw_liquidity(
+    env: Env,
+    sender: Address,
+    share_amount: i128,
+    min_a: i128,
+    min_b: i128,
+) -> Result<(i128, i128), PoolError> {
+    let config = get_config(&env);
+
+    let share_token_client = token_contract::Client::new(&env, &config.share_token);
+    share_token_client.transfer(&sender, &env.current_contract_address(), &share_amount);
+
+    let pool_balance_a = utils::get_pool_balance_a(&env);
+    let pool_balance_b = utils::get_pool_balance_b(&env);
+
+    let share_ratio = get_share_ratio(&env, share_amount);
+    let return_amount_a = pool_balance_a * share_ratio;
+    let return_amount_b = pool_balance_b * share_ratio;
+
+    if return_amount_a < min_a || return_amount_b < min_b {
+        log!(
+            &env,
+            "Minimum amount of token_a or token_b is not satisfied! min_a: {}, min_b: {}, return_amount_a: {}, return_amount_b: {}",
+            min_a,
+            min_b,
+            return_amount_a,
+            return_amount_b
+        );
+        return Err(PoolError::WithdrawMinNotSatisfied);
+    }
+
+    // burn shares
+    utils::burn_shares(&env, &config.share_token, share_amount);
+    // transfer tokens from sender to contract
+    token_contract::Client::new(&env, &config.token_a).transfer(
+        &env.current_contract_address(),
+        &sender,
+        &return_amount_a,
+    );
+    token_contract::Client::new(&env, &config.token_b).transfer(
+        &env.current_contract_address(),
+        &sender,
+        &return_amount_b,
+    );
+    // update pool balances
+    oracle::update(&env, pool_balance_a, pool_balance_b);
+    utils::save_pool_balance_a(&env, pool_balance_a - return_amount_a);
+    utils::save_pool_balance_b(&env, pool_balance_b - return_amount_b);
+
+    env.events()
+        .publish(("withdraw_liquidity", "sender"), sender);
+    env.events()
+        .publish(("withdraw_liquidity", "shares_amount"), share_amount);
+    env.events()
+        .publish(("withdraw_liquidity", "return_amount_a"), return_amount_a);
+    env.events()
+        .publish(("withdraw_liquidity", "return_amount_b"), return_amount_b);
+
+    Ok((return_amount_a, return_amount_b))
+}
+
+fn do_withdraw_liquidity_single(
+    env: Env,
+    sender: Address,
+    share_amount: i128,
+    out_asset: Address,
+    min_out: i128,
+) -> Result<i128, PoolError> {
+    let config = get_config(&env);
+    let (pool_balance_out, pool_balance_other) =
+        get_withdraw_single_balances(&env, &config, &out_asset)?;
+
+    let (return_amount, compute_swap) = compute_withdraw_single(
+        pool_balance_out,
+        pool_balance_other,
+        get_share_ratio(&env, share_amount),
+        config.protocol_fee_rate(),
+    );
+
+    if return_amount < min_out {
+        log!(
+            &env,
+            "Pool: WithdrawLiquiditySingle: Minimum amount is not satisfied! min_out: {}, return_amount: {}",
+            min_out,
+            return_amount
+        );
+        return Err(PoolError::WithdrawMinNotSatisfied);
+    }
+
+    let share_token_client = token_contract::Client::new(&env, &config.share_token);
+    share_token_client.transfer(&sender, &env.current_contract_address(), &share_amount);
+    utils::burn_shares(&env, &config.share_token, share_amount);
+
+    let out_token_client = token_contract::Client::new(&env, &out_asset);
+    out_token_client.transfer(&env.current_contract_address(), &sender, &return_amount);
+
+    // the commission of the internal swap is split like the one of a regular swap
+    let protocol_fee_amount = compute_swap.commission_amount * config.protocol_fee_share();
+    let lp_fee_amount = compute_swap.commission_amount - protocol_fee_amount;
+    out_token_client.transfer(
+        &env.current_contract_address(),
+        &config.fee_recipient,
+        &protocol_fee_amount,
+    );
+    let lp_fee_removed_from_pool = if config.distribute_lp_fees_to_stakers && lp_fee_amount > 0 {
+        out_token_client.transfer(
+            &env.current_contract_address(),
+            &config.stake_contract,
+            &lp_fee_amount,
+        );
+        lp_fee_amount
+    } else {
+        0
+    };
+
+    // the share of the other token is swapped back into the pool, so only the out asset leaves it
+    let new_pool_balance_out =
+        pool_balance_out - return_amount - protocol_fee_amount - lp_fee_removed_from_pool;
+    let pool_balance_a = utils::get_pool_balance_a(&env);
+    let pool_balance_b = utils::get_pool_balance_b(&env);
+    oracle::update(&env, pool_balance_a, pool_balance_b);
+    if out_asset == config.token_a {
+        utils::save_pool_balance_a(&env, new_pool_balance_out);
+    } else {
+        utils::save_pool_balance_b(&env, new_pool_balance_out);
+    }
+
+    env.events()
+        .publish(("withdraw_liquidity_single", "sender"), sender);
+    env.events()
+        .publish(("withdraw_liquidity_single", "shares_amount"), share_amount);
+    env.events()
+        .publish(("withdraw_liquidity_single", "out_asset"), out_asset);
+    env.events().publish(
+        ("withdraw_liquidity_single", "return_amount"),
+        return_amount,
+    );
+
+    Ok(return_amount)
+}
+
+/// Returns the amounts of token_a and token_b deposited as liquidity for the desired amounts and
+/// their minimums. When only one token is provided, a part of it has to be swapped for the other
+/// token first; the offer asset and amount of that swap are returned together with the amounts.
+#[allow(clippy::type_complexity)]
+fn compute_deposit_amounts(
+    env: &Env,
+    config: &Config,
+    (desired_a, min_a): (Option<i128>, Option<i128>),
+    (desired_b, min_b): (Option<i128>, Option<i128>),
+    custom_slippage_bps: Option<i64>,
+    pool_balance_a: i128,
+    pool_balance_b: i128,
+) -> Result<((i128, i128), Option<(Address, i128)>), PoolError> {
+    // Check if both tokens are provided, one token is provided, or none are provided
+    match (desired_a, desired_b) {
+        // Both tokens are provided
+        (Some(a), Some(b)) if a > 0 && b > 0 => {
+            // Calculate deposit amounts
+            let amounts = utils::get_deposit_amounts(
+                env,
+                a,
+                min_a,
+                b,
+                min_b,
+                pool_balance_a,
+                pool_balance_b,
+                Decimal::bps(custom_slippage_bps.unwrap_or(100)),
+            )?;
+            Ok((amounts, None))
+        }
+        // Only token A is provided
+        (Some(a), None) if a > 0 => {
+            let (a_for_swap, b_from_swap) = split_deposit_based_on_pool_ratio(
+                env,
+                config,
+                pool_balance_a,
+                pool_balance_b,
+                a,
+                &config.token_a,
+            )?;
+            // return: rest of Token A amount, simulated result of swap of portion A
+            Ok((
+                (a - a_for_swap, b_from_swap),
+                Some((config.token_a.clone(), a_for_swap)),
+            ))
+        }
+        // Only token B is provided
+        (None, Some(b)) if b > 0 => {
+            let (b_for_swap, a_from_swap) = split_deposit_based_on_pool_ratio(
+                env,
+                config,
+                pool_balance_a,
+                pool_balance_b,
+                b,
+                &config.token_b,
+            )?;
+            // return: simulated result of swap of portion B, rest of Token B amount
+            Ok((
+                (a_from_swap, b - b_for_swap),
+                Some((config.token_b.clone(), b_for_swap)),
+            ))
+        }
+        // None or invalid amounts are provided
+        _ => {
+            log!(
+                env,
+                "Pool: ProvideLiquidity: At least one token must be provided and must be bigger then 0!"
+            );
+            Err(PoolError::EmptyDeposit)
+        }
+    }
+}
+
+/// Computes the shares minted for a deposit that brings the token balances of the pool from
+/// `pool_balance_a` and `pool_balance_b` to `balance_a` and `balance_b`.
+/// In case of an empty pool, produces sqrt(X*Y) shares minus the minimum liquidity, which is
+/// minted to the pool itself.
+fn compute_minted_shares(
+    balance_a: i128,
+    balance_b: i128,
+    pool_balance_a: i128,
+    pool_balance_b: i128,
+    total_shares: i128,
+) -> i128 {
+    if total_shares == 0 {
+        return (balance_a * balance_b).sqrt() - MINIMUM_LIQUIDITY_AMOUNT;
+    }
+    let shares_a = (balance_a * total_shares) / pool_balance_a;
+    let shares_b = (balance_b * total_shares) / pool_balance_b;
+    shares_a.min(shares_b) - total_shares
+}
+
+/// Returns the pool balances of `out_asset` and of the other token of the pool.
+fn get_withdraw_single_balances(
+    env: &Env,
+    config: &Config,
+    out_asset: &Address,
+) -> Result<(i128, i128), PoolError> {
+    let pool_balance_a = utils::get_pool_balance_a(env);
+    let pool_balance_b = utils::get_pool_balance_b(env);
+
+    if out_asset == &config.token_a {
+        Ok((pool_balance_a, pool_balance_b))
+    } else if out_asset == &config.token_b {
+        Ok((pool_balance_b, pool_balance_a))
+    } else {
+        log!(
+            env,
+            "Pool: WithdrawLiquiditySingle: Asset is not part of the pool"
+        );
+        Err(PoolError::AssetNotInPool)
+    }
+}
+
+/// Returns the part of the pool owned by `share_amount` shares.
+fn get_share_ratio(env: &Env, share_amount: i128) -> Decimal {
+    let total_shares = utils::get_total_shares(env);
+    if total_shares == 0i128 {
+        return Decimal::zero();
+    }
+    Decimal::from_ratio(share_amount, total_shares)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn do_swap(
     env: Env,
     sender: Address,
-    // FIXM: Disable Referral struct
-    // referral: Option<Referral>,
+    receiver: Address,
+    referral: Option<Referral>,
     offer_asset: Address,
     offer_amount: i128,
     belief_price: Option<i64>,
     max_spread: Option<i64>,
-) -> i128 {
+) -> Result<i128, PoolError> {
     let config = get_config(&env);
-    // FIXM: Disable Referral struct
-    // if let Some(referral) = &referral {
-    //     if referral.fee > config.max_referral_bps {
-    //         panic!("Pool: Swap: Trying to swap with more than the allowed referral fee");
-    //     }
-    // }
+    if let Some(referral) = &referral {
+        if referral.fee > config.max_referral_bps {
+            log!(
+                &env,
+                "Pool: Swap: Trying to swap with more than the allowed referral fee"
+            );
+            return Err(PoolError::ReferralFeeTooHigh);
+        }
+        if referral.fee < 0 {
+            log!(&env, "Pool: Swap: Referral fee cannot be negative");
+            return Err(PoolError::NegativeReferralFee);
+        }
+    }
 
     let belief_price = belief_price.map(Decimal::percent);
     let max_spread = Decimal::bps(max_spread.map_or_else(|| config.max_allowed_spread_bps, |x| x));
@@ -656,12 +1487,10 @@ fn do_swap(
         (pool_balance_b, pool_balance_a)
     };
 
-    // FIXM: Disable Referral struct
-    // let referral_fee_bps = match referral {
-    //     Some(ref referral) => referral.clone().fee,
-    //     None => 0,
-    // };
-    let referral_fee_bps = 0;
+    let referral_fee_bps = match referral {
+        Some(ref referral) => referral.fee,
+        None => 0,
+    };
 
     // 1. We calculate the referral_fee below. If none referral fee will be 0
     let compute_swap: ComputeSwap = compute_swap(
@@ -679,8 +1508,115 @@ fn do_swap(
         offer_amount,
         compute_swap.return_amount + compute_swap.commission_amount,
         compute_swap.spread_amount,
+    )?;
+
+    execute_swap(
+        &env,
+        &config,
+        sender,
+        receiver,
+        referral,
+        offer_asset,
+        offer_amount,
+        &compute_swap,
     );
 
+    Ok(compute_swap.return_amount)
+}
+
+fn do_swap_exact_out(
+    env: Env,
+    sender: Address,
+    receiver: Address,
+    ask_asset: Address,
+    ask_amount: i128,
+    max_offer_amount: i128,
+) -> Result<i128, PoolError> {
+    let config = get_config(&env);
+    if ask_asset != config.token_a && ask_asset != config.token_b {
+        log!(&env, "Pool: SwapExactOut: Asset is not part of the pool");
+        return Err(PoolError::AssetNotInPool);
+    }
+
+    let pool_balance_a = utils::get_pool_balance_a(&env);
+    let pool_balance_b = utils::get_pool_balance_b(&env);
+    let (offer_asset, pool_balance_offer, pool_balance_ask) = if ask_asset == config.token_b {
+        (config.token_a.clone(), pool_balance_a, pool_balance_b)
+    } else {
+        (config.token_b.clone(), pool_balance_b, pool_balance_a)
+    };
+
+    let commission_rate = config.protocol_fee_rate();
+    if compute_ask_before_commission(ask_amount, commission_rate) >= pool_balance_ask {
+        log!(
+            &env,
+            "Pool: SwapExactOut: Not enough liquidity to receive {} of the ask asset",
+            ask_amount
+        );
+        return Err(PoolError::NotEnoughLiquidity);
+    }
+
+    let (offer_amount, spread_amount, commission_amount) = compute_offer_amount(
+        pool_balance_offer,
+        pool_balance_ask,
+        ask_amount,
+        commission_rate,
+    );
+
+    if offer_amount <= 0 {
+        log!(
+            &env,
+            "Pool: SwapExactOut: {} of the ask asset can not be bought for a positive amount",
+            ask_amount
+        );
+        return Err(PoolError::InvalidAmount);
+    }
+
+    if offer_amount > max_offer_amount {
+        log!(
+            &env,
+            "Pool: SwapExactOut: Offer amount {} exceeds the maximum of {}",
+            offer_amount,
+            max_offer_amount
+        );
+        return Err(PoolError::OfferAmountExceedsMax);
+    }
+
+    execute_swap(
+        &env,
+        &config,
+        sender,
+        receiver,
+        None,
+        offer_asset,
+        offer_amount,
+        &ComputeSwap {
+            return_amount: ask_amount,
+            spread_amount,
+            commission_amount,
+            referral_fee_amount: 0,
+        },
+    );
+
+    Ok(offer_amount)
+}
+
+/// Transfers the tokens of an already computed swap and updates the pool balances.
+/// The bought tokens are sent to `receiver`, which can differ from the `sender` paying for them.
+#[allow(clippy::too_many_arguments)]
+fn execute_swap(
+    env: &Env,
+    config: &Config,
+    sender: Address,
+    receiver: Address,
+    referral: Option<Referral>,
+    offer_asset: Address,
+    offer_amount: i128,
+    compute_swap: &ComputeSwap,
+) {
+    let pool_balance_a = utils::get_pool_balance_a(env);
+    let pool_balance_b = utils::get_pool_balance_b(env);
+
     // Transfer the amount being sold to the contract
     let (sell_token, buy_token) = if offer_asset == config.clone().token_a {
         (config.clone().token_a, config.clone().token_b)
@@ -689,38 +1625,54 @@ fn do_swap(
     };
 
     // transfer tokens to swap
-    token_contract::Client::new(&env, &sell_token).transfer(
+    token_contract::Client::new(env, &sell_token).transfer(
         &sender,
         &env.current_contract_address(),
         &offer_amount,
     );
 
-    // return swapped tokens to user
-    token_contract::Client::new(&env, &buy_token).transfer(
+    // send swapped tokens to the receiver
+    token_contract::Client::new(env, &buy_token).transfer(
         &env.current_contract_address(),
-        &sender,
+        &receiver,
         &compute_swap.return_amount,
     );
 
-    // send commission to fee recipient
-    token_contract::Client::new(&env, &buy_token).transfer(
+    // split the commission between the protocol and the liquidity providers
+    let protocol_fee_amount = compute_swap.commission_amount * config.protocol_fee_share();
+    let lp_fee_amount = compute_swap.commission_amount - protocol_fee_amount;
+
+    // send protocol's part of the commission to fee recipient
+    token_contract::Client::new(env, &buy_token).transfer(
         &env.current_contract_address(),
         &config.fee_recipient,
-        &compute_swap.commission_amount,
+        &protocol_fee_amount,
     );
 
-    // 2. If referral is present and return amount is larger than 0 we send referral fee commision
-    //    to fee recipient
-    // FIXM: Disable Referral struct
-    // if let Some(Referral { address, fee }) = referral {
-    //     if fee > 0 {
-    //         token_contract::Client::new(&env, &buy_token).transfer(
-    //             &env.current_contract_address(),
-    //             &address,
-    //             &compute_swap.referral_fee_amount,
-    //         );
-    //     }
-    // }
+    // liquidity providers' part of the commission either stays in the pool or is sent to the
+    // stake contract to be distributed as rewards
+    let lp_fee_removed_from_pool = if config.distribute_lp_fees_to_stakers && lp_fee_amount > 0 {
+        token_contract::Client::new(env, &buy_token).transfer(
+            &env.current_contract_address(),
+            &config.stake_contract,
+            &lp_fee_amount,
+        );
+        lp_fee_amount
+    } else {
+        0
+    };
+
+    // 2. If referral is present and referral fee is larger than 0 we send referral fee commision
+    //    to the referral address
+    if let Some(Referral { address, .. }) = referral {
+        if compute_swap.referral_fee_amount > 0 {
+            token_contract::Client::new(env, &buy_token).transfer(
+                &env.current_contract_address(),
+                &address,
+                &compute_swap.referral_fee_amount,
+            );
+        }
+    }
 
     // user is offering to sell A, so they will receive B
     // A balance is bigger, B balance is smaller
@@ -728,23 +1680,27 @@ fn do_swap(
         (
             pool_balance_a + offer_amount,
             pool_balance_b
-                - compute_swap.commission_amount
+                - protocol_fee_amount
+                - lp_fee_removed_from_pool
                 - compute_swap.referral_fee_amount
                 - compute_swap.return_amount,
         )
     } else {
         (
             pool_balance_a
-                - compute_swap.commission_amount
+                - protocol_fee_amount
+                - lp_fee_removed_from_pool
                 - compute_swap.referral_fee_amount
                 - compute_swap.return_amount,
             pool_balance_b + offer_amount,
         )
     };
-    utils::save_pool_balance_a(&env, balance_a);
-    utils::save_pool_balance_b(&env, balance_b);
+    oracle::update(env, pool_balance_a, pool_balance_b);
+    utils::save_pool_balance_a(env, balance_a);
+    utils::save_pool_balance_b(env, balance_b);
 
     env.events().publish(("swap", "sender"), sender);
+    env.events().publish(("swap", "receiver"), receiver);
     env.events().publish(("swap", "sell_token"), sell_token);
     env.events().publish(("swap", "offer_amount"), offer_amount);
     env.events().publish(("swap", "buy_token"), buy_token);
@@ -756,7 +1712,35 @@ fn do_swap(
         ("swap", "referral_fee_amount"),
         compute_swap.referral_fee_amount,
     );
-    compute_swap.return_amount
+}
+
+/// Splits the fee of a flash loan in `token` between the protocol and the liquidity providers,
+/// the same way as the swap commission. Returns the part of the fee that leaves the pool reserves.
+fn distribute_flash_fee(env: &Env, config: &Config, token: &Address, fee: i128) -> i128 {
+    let protocol_fee_amount = fee * config.protocol_fee_share();
+    let lp_fee_amount = fee - protocol_fee_amount;
+    let token_client = token_contract::Client::new(env, token);
+
+    if protocol_fee_amount > 0 {
+        token_client.transfer(
+            &env.current_contract_address(),
+            &config.fee_recipient,
+            &protocol_fee_amount,
+        );
+    }
+
+    let lp_fee_removed_from_pool = if config.distribute_lp_fees_to_stakers && lp_fee_amount > 0 {
+        token_client.transfer(
+            &env.current_contract_address(),
+            &config.stake_contract,
+            &lp_fee_amount,
+        );
+        lp_fee_amount
+    } else {
+        0
+    };
+
+    protocol_fee_amount + lp_fee_removed_from_pool
 }
 
 /// This function divides the deposit in such a way that when swapping it for the other token,
@@ -775,13 +1759,14 @@ fn split_deposit_based_on_pool_ratio(
     b_pool: i128,
     deposit: i128,
     offer_asset: &Address,
-) -> (i128, i128) {
+) -> Result<(i128, i128), PoolError> {
     // Validate the inputs
     if a_pool <= 0 || b_pool <= 0 || deposit <= 0 {
-        log!(env, "Both pools and deposit must be a positive!");
-        panic!(
+        log!(
+            env,
             "Pool: split_deposit_based_on_pool_ratio: Both pools and deposit must be a positive!"
         );
+        return Err(PoolError::EmptyPool);
     }
 
     // Calculate the current ratio in the pool
@@ -835,7 +1820,7 @@ fn split_deposit_based_on_pool_ratio(
             high = mid;
         };
     }
-    (final_offer_amount, final_ask_amount)
+    Ok((final_offer_amount, final_ask_amount))
 }
 
 /// This function asserts that the slippage does not exceed the provided tolerance.
@@ -854,7 +1839,7 @@ fn assert_slippage_tolerance(
     deposits: &[i128; 2],
     pools: &[i128; 2],
     max_allowed_slippage: Decimal,
-) {
+) -> Result<(), PoolError> {
     let default_slippage = Decimal::percent(1); // Representing 1% as the default slippage tolerance
 
     // If user provided a slippage tolerance, convert it from basis points to a decimal
@@ -865,10 +1850,11 @@ fn assert_slippage_tolerance(
         default_slippage
     };
     if slippage_tolerance > max_allowed_slippage {
-        log!(env, "Slippage tolerance exceeds the maximum allowed value");
-        panic!(
+        log!(
+            env,
             "Pool: Assert slippage tolerance: slippage tolerance exceeds the maximum allowed value"
         );
+        return Err(PoolError::SlippageToleranceExceedsMax);
     }
 
     // Calculate the limit below which the deposit-to-pool ratio must not fall for each token
@@ -890,8 +1876,10 @@ fn assert_slippage_tolerance(
             pools[0],
             pools[1]
         );
-        panic!("Pool: Assert slippage tolerance: slippage tolerance violated");
+        return Err(PoolError::SlippageToleranceViolated);
     }
+
+    Ok(())
 }
 
 /// This function asserts that the spread (slippage) does not exceed a given maximum.
@@ -909,7 +1897,7 @@ pub fn assert_max_spread(
     offer_amount: i128,
     return_amount: i128,
     spread_amount: i128,
-) {
+) -> Result<(), PoolError> {
     // Calculate the expected return if a belief price is provided
     let expected_return = belief_price.map(|price| offer_amount * price);
 
@@ -927,8 +1915,10 @@ pub fn assert_max_spread(
 
     if spread_ratio > max_spread {
         log!(env, "Spread exceeds maximum allowed");
-        panic_with_error!(env, ContractError::SpreadExceedsLimit);
+        return Err(PoolError::SpreadExceedsLimit);
     }
+
+    Ok(())
 }
 
 /// Computes the result of a swap operation.
@@ -984,6 +1974,13 @@ pub fn compute_swap(
 /// * **ask_pool** total amount of ask assets in the pool.
 /// * **ask_amount** amount of ask assets to swap to.
 /// * **commission_rate** total amount of fees charged for the swap.
+/// Computes the amount of the ask asset from which the commission is taken so that `ask_amount` is
+/// left, rounded up.
+pub fn compute_ask_before_commission(ask_amount: i128, commission_rate: Decimal) -> i128 {
+    let one_minus_commission = (Decimal::one() - commission_rate).atomics();
+    (ask_amount * Decimal::one().atomics() + one_minus_commission - 1) / one_minus_commission
+}
+
 pub fn compute_offer_amount(
     offer_pool: i128,
     ask_pool: i128,
@@ -993,16 +1990,13 @@ pub fn compute_offer_amount(
     // Calculate the cross product of offer_pool and ask_pool
     let cp: i128 = offer_pool * ask_pool;
 
-    // Calculate one minus the commission rate
-    let one_minus_commission = Decimal::one() - commission_rate;
+    // Calculate the amount of ask assets leaving the pool, including the commission
+    let ask_before_commission = compute_ask_before_commission(ask_amount, commission_rate);
 
-    // Calculate the inverse of one minus the commission rate
-    let inv_one_minus_commission = Decimal::one() / one_minus_commission;
-
-    // Calculate the resulting amount of ask assets after the swap
-    let offer_amount: i128 = cp / (ask_pool - (ask_amount * inv_one_minus_commission)) - offer_pool;
-
-    let ask_before_commission = ask_amount * inv_one_minus_commission;
+    // Calculate the offer amount keeping the cross product, rounded up so that the pool is never
+    // paid less than the invariant requires
+    let new_ask_pool = ask_pool - ask_before_commission;
+    let offer_amount: i128 = (cp + new_ask_pool - 1) / new_ask_pool - offer_pool;
 
     // Calculate the spread amount, representing the difference between the expected and actual swap amounts
     let spread_amount: i128 = (offer_amount * ask_pool / offer_pool) - ask_before_commission;
@@ -1013,6 +2007,42 @@ pub fn compute_offer_amount(
     (offer_amount, spread_amount, commission_amount)
 }
 
+/// Computes the result of withdrawing liquidity into a single asset.
+///
+/// Arguments:
+/// - `pool_balance_out`: Total amount of the out asset in the pool.
+/// - `pool_balance_other`: Total amount of the other asset in the pool.
+/// - `share_ratio`: Part of the pool owned by the withdrawn shares.
+/// - `commission_rate`: Total amount of fees charged for the swap.
+///
+/// The share of the other asset is swapped into the out asset against the pool balances left after
+/// the withdrawal. Returns the total amount of the out asset together with the computed swap.
+pub fn compute_withdraw_single(
+    pool_balance_out: i128,
+    pool_balance_other: i128,
+    share_ratio: Decimal,
+    commission_rate: Decimal,
+) -> (i128, ComputeSwap) {
+    let withdrawn_out = pool_balance_out * share_ratio;
+    let withdrawn_other = pool_balance_other * share_ratio;
+
+    let compute_swap = compute_swap(
+        pool_balance_other - withdrawn_other,
+        pool_balance_out - withdrawn_out,
+        withdrawn_other,
+        commission_rate,
+        0i64,
+    );
+
+    (withdrawn_out + compute_swap.return_amount, compute_swap)
+}
+
+/// Computes the fee owed on top of a flash loan of `amount`, rounded up so that the pool is never
+/// paid less than `flash_fee_bps` of the loan.
+pub fn compute_flash_fee(amount: i128, flash_fee_bps: i64) -> i128 {
+    (amount * flash_fee_bps as i128 + 9_999) / 10_000
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1024,42 +2054,49 @@ mod tests {
         // slippage tolerance of 5000 (0.5 or 50%), deposits of 10 and 20, pools of 30 and 60
         // The price changes fall within the slippage tolerance
         let max_allowed_slippage = 5_000i64;
-        assert_slippage_tolerance(
-            &env,
-            Some(max_allowed_slippage),
-            &[10, 20],
-            &[30, 60],
-            Decimal::bps(max_allowed_slippage),
-        )
+        assert_eq!(
+            assert_slippage_tolerance(
+                &env,
+                Some(max_allowed_slippage),
+                &[10, 20],
+                &[30, 60],
+                Decimal::bps(max_allowed_slippage),
+            ),
+            Ok(())
+        );
     }
 
     #[test]
-    #[should_panic(expected = "slippage tolerance exceeds the maximum allowed value")]
     fn test_assert_slippage_tolerance_fail_tolerance_too_high() {
         let env = Env::default();
         // Test case that should fail due to slippage tolerance being too high
         let max_allowed_slippage = Decimal::bps(5_000i64);
-        assert_slippage_tolerance(
-            &env,
-            Some(60_000),
-            &[10, 20],
-            &[30, 60],
-            max_allowed_slippage,
+        assert_eq!(
+            assert_slippage_tolerance(
+                &env,
+                Some(60_000),
+                &[10, 20],
+                &[30, 60],
+                max_allowed_slippage,
+            ),
+            Err(PoolError::SlippageToleranceExceedsMax)
         );
     }
 
     #[test]
-    #[should_panic(expected = "slippage tolerance violated")]
     fn test_assert_slippage_tolerance_fail_slippage_violated() {
         let env = Env::default();
         let max_allowed_slippage = Decimal::bps(5_000i64);
         // The price changes from 10/15 (0.67) to 40/40 (1.00), violating the 10% slippage tolerance
-        assert_slippage_tolerance(
-            &env,
-            Some(1_000),
-            &[10, 15],
-            &[40, 40],
-            max_allowed_slippage,
+        assert_eq!(
+            assert_slippage_tolerance(
+                &env,
+                Some(1_000),
+                &[10, 15],
+                &[40, 40],
+                max_allowed_slippage,
+            ),
+            Err(PoolError::SlippageToleranceViolated)
         );
     }
 
@@ -1069,18 +2106,20 @@ mod tests {
         // Test case that should pass:
         // belief price of 2.0, max spread of 10%, offer amount of 100k, return amount of 100k and 1 unit, spread amount of 1
         // The spread ratio is 10% which is equal to the max spread
-        assert_max_spread(
-            &env,
-            Some(Decimal::percent(200)),
-            Decimal::percent(10),
-            100_000,
-            100_001,
-            1,
+        assert_eq!(
+            assert_max_spread(
+                &env,
+                Some(Decimal::percent(200)),
+                Decimal::percent(10),
+                100_000,
+                100_001,
+                1,
+            ),
+            Ok(())
         );
     }
 
     #[test]
-    #[should_panic(expected = "HostError: Error(Contract, #1)")]
     fn test_assert_max_spread_fail_max_spread_exceeded() {
         let env = Env::default();
 
@@ -1090,13 +2129,16 @@ mod tests {
         let return_amount = 100; // These values are chosen such that the spread ratio will be more than 10%
         let spread_amount = 35;
 
-        assert_max_spread(
-            &env,
-            belief_price,
-            max_spread,
-            offer_amount,
-            return_amount,
-            spread_amount,
+        assert_eq!(
+            assert_max_spread(
+                &env,
+                belief_price,
+                max_spread,
+                offer_amount,
+                return_amount,
+                spread_amount,
+            ),
+            Err(PoolError::SpreadExceedsLimit)
         );
     }
 
@@ -1105,16 +2147,21 @@ mod tests {
         let env = Env::default();
         // no belief price, max spread of 100 (0.1 or 10%), offer amount of 10, return amount of 10, spread amount of 1
         // The spread ratio is 10% which is equal to the max spread
-        assert_max_spread(&env, None, Decimal::percent(10), 10, 10, 1);
+        assert_eq!(
+            assert_max_spread(&env, None, Decimal::percent(10), 10, 10, 1),
+            Ok(())
+        );
     }
 
     #[test]
-    #[should_panic(expected = "HostError: Error(Contract, #1)")]
     fn test_assert_max_spread_fail_no_belief_price_max_spread_exceeded() {
         let env = Env::default();
         // no belief price, max spread of 10%, offer amount of 10, return amount of 10, spread amount of 2
         // The spread ratio is 20% which is greater than the max spread
-        assert_max_spread(&env, None, Decimal::percent(10), 10, 10, 2);
+        assert_eq!(
+            assert_max_spread(&env, None, Decimal::percent(10), 10, 10, 2),
+            Err(PoolError::SpreadExceedsLimit)
+        );
     }
 
     #[test]
@@ -1177,4 +2224,36 @@ mod tests {
         // Test that the commission amount is exactly 10% of the offer amount
         assert_eq!(result.2, result.0 * Decimal::percent(10));
     }
+
+    #[test]
+    fn test_compute_withdraw_single() {
+        // 10% of the pool: 100 of the out asset, and 100 of the other asset swapped against 900/900
+        let (return_amount, compute_swap) =
+            compute_withdraw_single(1000, 1000, Decimal::percent(10), Decimal::zero());
+        assert_eq!(return_amount, 190);
+        assert_eq!(
+            compute_swap,
+            ComputeSwap {
+                return_amount: 90,
+                spread_amount: 10,
+                commission_amount: 0,
+                referral_fee_amount: 0,
+            }
+        );
+
+        let (return_amount, compute_swap) =
+            compute_withdraw_single(1000, 1000, Decimal::percent(10), Decimal::percent(10));
+        assert_eq!(compute_swap.commission_amount, 9);
+        assert_eq!(return_amount, 181);
+    }
+
+    #[test]
+    fn test_compute_flash_fee() {
+        assert_eq!(compute_flash_fee(1_000_000, 30), 3_000);
+        // rounded up in favour of the pool
+        assert_eq!(compute_flash_fee(1_001, 30), 4);
+        assert_eq!(compute_flash_fee(1, 30), 1);
+        assert_eq!(compute_flash_fee(1_000, 0), 0);
+        assert_eq!(compute_flash_fee(0, 30), 0);
+    }
 }
@@ -0,0 +1,207 @@
+use phoenix::error::PoolError;
+use soroban_sdk::{contracttype, log, Env, U256};
+
+use crate::storage::DataKey;
+
+/// Minimum time (in seconds) between two observations kept in the ring buffer
+pub const OBSERVATION_INTERVAL: u64 = 60;
+/// Capacity of the ring buffer - with one observation per minute it covers at least one day
+pub const MAX_OBSERVATIONS: u32 = 1_440;
+/// Prices are stored with 18 decimal places
+const PRICE_PRECISION: u128 = 1_000_000_000_000_000_000;
+
+/// Running sums of the pool prices multiplied by the number of seconds they were in effect.
+/// The sums are allowed to wrap around; differences between two snapshots stay correct.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PriceAccumulator {
+    /// Timestamp of the last update
+    pub last_update: u64,
+    /// Sum of the prices of token A expressed in token B
+    pub price_a_cumulative: i128,
+    /// Sum of the prices of token B expressed in token A
+    pub price_b_cumulative: i128,
+    /// Position of the newest observation in the ring buffer
+    pub latest_observation: u32,
+    /// Number of observations currently stored in the ring buffer
+    pub observations_count: u32,
+}
+
+/// Snapshot of the price accumulators at a given time
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Observation {
+    pub timestamp: u64,
+    pub price_a_cumulative: i128,
+    pub price_b_cumulative: i128,
+}
+
+pub fn get_accumulator(env: &Env) -> PriceAccumulator {
+    env.storage()
+        .instance()
+        .get(&DataKey::PriceAccumulator)
+        .unwrap_or(PriceAccumulator {
+            last_update: env.ledger().timestamp(),
+            price_a_cumulative: 0,
+            price_b_cumulative: 0,
+            latest_observation: 0,
+            observations_count: 0,
+        })
+}
+
+fn save_accumulator(env: &Env, accumulator: &PriceAccumulator) {
+    env.storage()
+        .instance()
+        .set(&DataKey::PriceAccumulator, accumulator);
+}
+
+fn get_observation(env: &Env, index: u32) -> Observation {
+    env.storage()
+        .persistent()
+        .get(&(DataKey::Observation, index))
+        .unwrap()
+}
+
+fn save_observation(env: &Env, index: u32, observation: &Observation) {
+    env.storage()
+        .persistent()
+        .set(&(DataKey::Observation, index), observation);
+}
+
+/// Price of one unit of the token with `reserve_base` expressed in the other token,
+/// with 18 decimal places. Saturates instead of overflowing for extremely unbalanced pools.
+fn price(env: &Env, reserve_base: i128, reserve_quote: i128) -> i128 {
+    let price = U256::from_u128(env, reserve_quote as u128)
+        .mul(&U256::from_u128(env, PRICE_PRECISION))
+        .div(&U256::from_u128(env, reserve_base as u128));
+    price
+        .to_u128()
+        .map_or(i128::MAX, |price| price.min(i128::MAX as u128) as i128)
+}
+
+/// Accumulated prices at `now`, assuming the given reserves were in effect since the last update
+fn cumulative_prices(
+    env: &Env,
+    accumulator: &PriceAccumulator,
+    reserve_a: i128,
+    reserve_b: i128,
+    now: u64,
+) -> (i128, i128) {
+    let elapsed = (now - accumulator.last_update) as i128;
+    (
+        accumulator
+            .price_a_cumulative
+            .wrapping_add(price(env, reserve_a, reserve_b).wrapping_mul(elapsed)),
+        accumulator
+            .price_b_cumulative
+            .wrapping_add(price(env, reserve_b, reserve_a).wrapping_mul(elapsed)),
+    )
+}
+
+/// Adds the time elapsed since the last update, weighted with the prices given by the reserves
+/// that were in effect during that time, to the accumulators.
+/// Must be called with the reserves from before every change of the pool reserves.
+pub fn update(env: &Env, reserve_a: i128, reserve_b: i128) {
+    let mut accumulator = get_accumulator(env);
+    let now = env.ledger().timestamp();
+
+    if reserve_a == 0 || reserve_b == 0 {
+        // the price is undefined while the pool is empty, so the history starts over
+        accumulator.observations_count = 0;
+    } else {
+        (
+            accumulator.price_a_cumulative,
+            accumulator.price_b_cumulative,
+        ) = cumulative_prices(env, &accumulator, reserve_a, reserve_b, now);
+    }
+    accumulator.last_update = now;
+
+    let record_observation = accumulator.observations_count == 0
+        || now
+            >= get_observation(env, accumulator.latest_observation).timestamp
+                + OBSERVATION_INTERVAL;
+    if record_observation {
+        let index = if accumulator.observations_count == 0 {
+            0
+        } else {
+            (accumulator.latest_observation + 1) % MAX_OBSERVATIONS
+        };
+        save_observation(
+            env,
+            index,
+            &Observation {
+                timestamp: now,
+                price_a_cumulative: accumulator.price_a_cumulative,
+                price_b_cumulative: accumulator.price_b_cumulative,
+            },
+        );
+        accumulator.latest_observation = index;
+        accumulator.observations_count = (accumulator.observations_count + 1).min(MAX_OBSERVATIONS);
+    }
+
+    save_accumulator(env, &accumulator);
+}
+
+/// Returns the time weighted average prices of token A and token B over at least
+/// `window_seconds`, together with the length of the window that was actually used.
+/// The window starts at the newest observation that is at least `window_seconds` old.
+pub fn twap(
+    env: &Env,
+    reserve_a: i128,
+    reserve_b: i128,
+    window_seconds: u64,
+) -> Result<(i128, i128, u64), PoolError> {
+    if window_seconds == 0 {
+        log!(env, "Pool: QueryTwap: Window must be greater than zero");
+        return Err(PoolError::EmptyTwapWindow);
+    }
+    if reserve_a == 0 || reserve_b == 0 {
+        log!(env, "Pool: QueryTwap: Pool is empty");
+        return Err(PoolError::EmptyPool);
+    }
+
+    let accumulator = get_accumulator(env);
+    let now = env.ledger().timestamp();
+    let target = now.checked_sub(window_seconds);
+
+    // observations are sorted by time, starting from the oldest one
+    let oldest = (accumulator.latest_observation + MAX_OBSERVATIONS
+        - (accumulator.observations_count.max(1) - 1))
+        % MAX_OBSERVATIONS;
+    let at = |position: u32| get_observation(env, (oldest + position) % MAX_OBSERVATIONS);
+
+    let observation = match target {
+        Some(target) if accumulator.observations_count > 0 && at(0).timestamp <= target => {
+            // binary search for the newest observation that is not newer than the target
+            let (mut low, mut high) = (0u32, accumulator.observations_count - 1);
+            while low < high {
+                let mid = (low + high + 1) / 2;
+                if at(mid).timestamp <= target {
+                    low = mid;
+                } else {
+                    high = mid - 1;
+                }
+            }
+            at(low)
+        }
+        _ => {
+            log!(
+                env,
+                "Pool: QueryTwap: Not enough price history for a window of {} seconds",
+                window_seconds
+            );
+            return Err(PoolError::NotEnoughPriceHistory);
+        }
+    };
+
+    // bring the accumulators up to date with the current reserves
+    let (price_a_cumulative, price_b_cumulative) =
+        cumulative_prices(env, &accumulator, reserve_a, reserve_b, now);
+
+    let window = now - observation.timestamp;
+    Ok((
+        price_a_cumulative.wrapping_sub(observation.price_a_cumulative) / window as i128,
+        price_b_cumulative.wrapping_sub(observation.price_b_cumulative) / window as i128,
+        window,
+    ))
+}
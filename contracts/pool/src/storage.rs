@@ -5,6 +5,7 @@ use soroban_sdk::{
 
 use crate::token_contract;
 use decimal::Decimal;
+use phoenix::error::PoolError;
 
 #[derive(Clone, Copy)]
 #[repr(u32)]
@@ -14,6 +15,10 @@ pub enum DataKey {
     ReserveB = 2,
     Admin = 3,
     Initialized = 4,
+    PriceAccumulator = 5,
+    Observation = 6,
+    Factory = 7,
+    Status = 8,
 }
 
 impl TryFromVal<Env, DataKey> for Val {
@@ -49,18 +54,27 @@ pub struct Config {
     pub max_allowed_spread_bps: i64,
     /// The maximum allowed percentage (in bps) for referral fee
     pub max_referral_bps: i64,
+    /// The share of the swap commission (in bps) that is sent to the fee recipient.
+    /// The rest of the commission belongs to the liquidity providers
+    pub protocol_fee_share_bps: i64,
+    /// If set, the liquidity providers' part of the commission is sent to the stake contract
+    /// to be distributed as staking rewards, instead of staying in the pool reserves
+    pub distribute_lp_fees_to_stakers: bool,
+    /// The fee (in bps) charged on top of the amounts lent by a flash loan. It is shared between
+    /// the protocol and the liquidity providers in the same way as the swap commission
+    pub flash_fee_bps: i64,
 }
 const CONFIG: Symbol = symbol_short!("CONFIG");
 
 const MAX_TOTAL_FEE_BPS: i64 = 10_000;
 
 /// This method is used to check fee bps.
-pub fn validate_fee_bps(env: &Env, total_fee_bps: i64) -> i64 {
+pub fn validate_fee_bps(env: &Env, total_fee_bps: i64) -> Result<i64, PoolError> {
     if total_fee_bps > MAX_TOTAL_FEE_BPS {
         log!(env, "Total fees cannot be greater than 100%");
-        panic!("Pool: Validate fee bps: total fees cannot be greater than 100%")
+        return Err(PoolError::InvalidBps);
     }
-    total_fee_bps
+    Ok(total_fee_bps)
 }
 
 impl Config {
@@ -68,6 +82,10 @@ impl Config {
         Decimal::bps(self.total_fee_bps)
     }
 
+    pub fn protocol_fee_share(&self) -> Decimal {
+        Decimal::bps(self.protocol_fee_share_bps)
+    }
+
     pub fn max_allowed_slippage(&self) -> Decimal {
         Decimal::bps(self.max_allowed_slippage_bps)
     }
@@ -151,6 +169,61 @@ pub struct SimulateReverseSwapResponse {
     pub spread_amount: i128,
 }
 
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProvideLiquidityResponse {
+    /// Amount of shares minted to the depositor
+    pub shares: i128,
+    /// Amount of token_a taken from the depositor
+    pub amount_a: i128,
+    /// Amount of token_b taken from the depositor
+    pub amount_b: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulateWithdrawSingleResponse {
+    /// Total amount of the out asset sent to the liquidity provider
+    pub return_amount: i128,
+    /// Commission charged on the share of the other asset swapped into the out asset
+    pub commission_amount: i128,
+    /// Spread of the swap of the other asset into the out asset
+    pub spread_amount: i128,
+}
+
+/// The amounts lent by a flash loan and the fees owed on top of them
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlashLoan {
+    pub token_a: Address,
+    pub token_b: Address,
+    pub amount_a: i128,
+    pub amount_b: i128,
+    pub fee_a: i128,
+    pub fee_b: i128,
+}
+
+/// Time weighted average price of a pool asset
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TwapResponse {
+    /// Average price of the asset expressed in the other pool token, with 18 decimal places
+    pub price: i128,
+    /// Length (in seconds) of the window the average was taken over. It can be slightly longer
+    /// than the requested window, depending on when the price observations were recorded
+    pub window_seconds: u64,
+}
+
+/// The pool operations that are halted. Nothing is paused by default, and withdrawals stay
+/// open when swaps and deposits are paused unless they are paused explicitly
+#[contracttype]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoolStatus {
+    pub swaps_paused: bool,
+    pub deposits_paused: bool,
+    pub withdrawals_paused: bool,
+}
+
 pub mod utils {
     use super::*;
 
@@ -182,6 +255,14 @@ pub mod utils {
         e.storage().instance().set(&DataKey::Admin, &address)
     }
 
+    pub fn save_factory(e: &Env, address: Address) {
+        e.storage().instance().set(&DataKey::Factory, &address)
+    }
+
+    pub fn save_status(e: &Env, status: &PoolStatus) {
+        e.storage().instance().set(&DataKey::Status, status)
+    }
+
     pub fn save_total_shares(e: &Env, amount: i128) {
         e.storage().instance().set(&DataKey::TotalShares, &amount)
     }
@@ -215,6 +296,17 @@ pub mod utils {
         e.storage().instance().get(&DataKey::Admin).unwrap()
     }
 
+    pub fn get_factory(e: &Env) -> Address {
+        e.storage().instance().get(&DataKey::Factory).unwrap()
+    }
+
+    pub fn get_status(e: &Env) -> PoolStatus {
+        e.storage()
+            .instance()
+            .get(&DataKey::Status)
+            .unwrap_or_default()
+    }
+
     pub fn get_total_shares(e: &Env) -> i128 {
         e.storage().instance().get(&DataKey::TotalShares).unwrap()
     }
@@ -240,19 +332,21 @@ pub mod utils {
         pool_balance_a: i128,
         pool_balance_b: i128,
         allowed_slippage: Decimal,
-    ) -> (i128, i128) {
+    ) -> Result<(i128, i128), PoolError> {
         if pool_balance_a == 0 && pool_balance_b == 0 {
-            return (desired_a, desired_b);
+            return Ok((desired_a, desired_b));
         }
 
         if let Some(min_a) = min_a {
             if min_a > desired_a {
-                panic!("Pool: Get deposit amounts: min_a > desired_a");
+                log!(env, "Pool: Get deposit amounts: min_a > desired_a");
+                return Err(PoolError::MinAmountExceedsDesired);
             }
         }
         if let Some(min_b) = min_b {
             if min_b > desired_b {
-                panic!("Pool: Get deposit amounts: min_b > desired_b");
+                log!(env, "Pool: Get deposit amounts: min_b > desired_b");
+                return Err(PoolError::MinAmountExceedsDesired);
             }
         }
 
@@ -269,7 +363,7 @@ pub mod utils {
                         amount_a,
                         desired_a,
                     );
-                    panic!("Pool: Get deposit amounts: amount_a > desired_a");
+                    return Err(PoolError::DepositAmountExceedsDesired);
                 }
             };
             if let Some(min_a) = min_a {
@@ -280,7 +374,7 @@ pub mod utils {
                         amount_a,
                         min_a
                     );
-                    panic!("Pool: Get deposit amounts: amount_a < min_a");
+                    return Err(PoolError::DepositAmountBelowMinimum);
                 }
             }
             amount_a
@@ -299,7 +393,7 @@ pub mod utils {
                 amount_b,
                 desired_b,
             );
-                    panic!("Pool: Get deposit amounts: amount_b > desired_b");
+                    return Err(PoolError::DepositAmountExceedsDesired);
                 }
             };
             if let Some(min_b) = min_b {
@@ -310,13 +404,13 @@ pub mod utils {
                 amount_b,
                 min_b
             );
-                    panic!("Pool: Get deposit amounts: amount_b < min_b");
+                    return Err(PoolError::DepositAmountBelowMinimum);
                 }
             }
             amount_b
         };
 
-        (amount_a, amount_b)
+        Ok((amount_a, amount_b))
     }
 
     pub fn is_initialized(e: &Env) -> bool {
@@ -368,21 +462,25 @@ mod tests {
         let env = Env::default();
         let result =
             utils::get_deposit_amounts(&env, 100, Some(50), 200, Some(50), 0, 0, Decimal::bps(100));
-        assert_eq!(result, (100, 200));
+        assert_eq!(result, Ok((100, 200)));
     }
 
     #[test]
-    #[should_panic(expected = "Pool: Get deposit amounts: amount_b < min_b")]
     fn test_get_deposit_amounts_amount_b_less_than_desired() {
         let env = Env::default();
-        utils::get_deposit_amounts(&env, 1000, None, 1005, Some(1001), 1, 1, Decimal::bps(100));
+        assert_eq!(
+            utils::get_deposit_amounts(&env, 1000, None, 1005, Some(1001), 1, 1, Decimal::bps(100)),
+            Err(PoolError::DepositAmountBelowMinimum)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Pool: Get deposit amounts: amount_b < min_b")]
     fn test_get_deposit_amounts_amount_b_less_than_min_b() {
         let env = Env::default();
-        utils::get_deposit_amounts(&env, 1000, None, 1005, Some(1001), 1, 1, Decimal::bps(100));
+        assert_eq!(
+            utils::get_deposit_amounts(&env, 1000, None, 1005, Some(1001), 1, 1, Decimal::bps(100)),
+            Err(PoolError::DepositAmountBelowMinimum)
+        );
     }
 
     #[test]
@@ -398,37 +496,52 @@ mod tests {
             200,
             Decimal::bps(100),
         );
-        assert_eq!(result, (100, 200));
+        assert_eq!(result, Ok((100, 200)));
     }
 
     #[test]
-    #[should_panic(expected = "Pool: Get deposit amounts: min_a > desired_a")]
     fn test_get_deposit_amounts_amount_a_greater_than_desired_and_less_than_min_a() {
         let env = Env::default();
-        utils::get_deposit_amounts(&env, 50, Some(100), 200, None, 100, 200, Decimal::bps(100));
+        assert_eq!(
+            utils::get_deposit_amounts(&env, 50, Some(100), 200, None, 100, 200, Decimal::bps(100)),
+            Err(PoolError::MinAmountExceedsDesired)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Pool: Get deposit amounts: min_b > desired_b")]
     fn test_get_deposit_amounts_amount_b_greater_than_desired_and_less_than_min_b() {
         let env = Env::default();
-        utils::get_deposit_amounts(
-            &env,
-            150,
-            Some(100),
-            200,
-            Some(300),
-            100,
-            200,
-            Decimal::bps(100),
+        assert_eq!(
+            utils::get_deposit_amounts(
+                &env,
+                150,
+                Some(100),
+                200,
+                Some(300),
+                100,
+                200,
+                Decimal::bps(100),
+            ),
+            Err(PoolError::MinAmountExceedsDesired)
         );
     }
 
     #[test]
-    #[should_panic(expected = "Pool: Get deposit amounts: min_a > desired_a")]
     fn test_get_deposit_amounts_amount_a_less_than_min_a() {
         let env = Env::default();
-        utils::get_deposit_amounts(&env, 100, Some(200), 200, None, 100, 200, Decimal::bps(100));
+        assert_eq!(
+            utils::get_deposit_amounts(
+                &env,
+                100,
+                Some(200),
+                200,
+                None,
+                100,
+                200,
+                Decimal::bps(100)
+            ),
+            Err(PoolError::MinAmountExceedsDesired)
+        );
     }
 
     #[test]
@@ -443,51 +556,67 @@ mod tests {
             5000,
             10000,
             Decimal::bps(100),
-        );
+        )
+        .unwrap();
         // The desired ratio is within 1% of the current pool ratio, so the desired amounts are returned
         assert_eq!(amount_a, 1000);
         assert_eq!(amount_b, 2000);
     }
 
     #[test]
-    #[should_panic(expected = "Pool: Get deposit amounts: amount_a > desired_a")]
     fn test_get_deposit_amounts_exceeds_desired() {
         let env = Env::default();
         // The calculated deposit for asset A exceeds the desired amount and is not within 1% tolerance
-        utils::get_deposit_amounts(&env, 1000, None, 2000, None, 10000, 5000, Decimal::bps(100));
+        assert_eq!(
+            utils::get_deposit_amounts(
+                &env,
+                1000,
+                None,
+                2000,
+                None,
+                10000,
+                5000,
+                Decimal::bps(100)
+            ),
+            Err(PoolError::DepositAmountExceedsDesired)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Pool: Get deposit amounts: amount_a < min_a")]
     fn test_get_deposit_amounts_below_min_a() {
         let env = Env::default();
         // The calculated deposit for asset A is below the minimum requirement
-        utils::get_deposit_amounts(
-            &env,
-            5000,
-            Some(2000),
-            200,
-            None,
-            1000,
-            500,
-            Decimal::bps(1000),
+        assert_eq!(
+            utils::get_deposit_amounts(
+                &env,
+                5000,
+                Some(2000),
+                200,
+                None,
+                1000,
+                500,
+                Decimal::bps(1000),
+            ),
+            Err(PoolError::DepositAmountBelowMinimum)
         );
     }
 
     #[test]
-    #[should_panic(expected = "Pool: Get deposit amounts: amount_b < min_b")]
     fn test_get_deposit_amounts_below_min_b() {
         let env = Env::default();
         // The calculated deposit for asset B is below the minimum requirement
-        utils::get_deposit_amounts(
-            &env,
-            200,
-            None,
-            5000,
-            Some(2000),
-            500,
-            1000,
-            Decimal::bps(120000),
+        assert_eq!(
+            utils::get_deposit_amounts(
+                &env,
+                200,
+                None,
+                5000,
+                Some(2000),
+                500,
+                1000,
+                Decimal::bps(120000),
+            ),
+            Err(PoolError::DepositAmountBelowMinimum)
         );
     }
 
@@ -498,7 +627,7 @@ mod tests {
         // but the ratio is exactly 1.01, which is within the 1% tolerance
         let result =
             utils::get_deposit_amounts(&env, 1000, None, 1010, None, 1000, 1000, Decimal::bps(100));
-        assert_eq!(result, (1000, 1000));
+        assert_eq!(result, Ok((1000, 1000)));
     }
 
     #[test]
@@ -506,24 +635,23 @@ mod tests {
         let env = Env::default();
         let result =
             utils::get_deposit_amounts(&env, 1010, None, 1000, None, 1000, 1000, Decimal::bps(100));
-        assert_eq!(result, (1000, 1000));
+        assert_eq!(result, Ok((1000, 1000)));
     }
 
     #[test]
     fn test_validate_fee_bps() {
         let env = Env::default();
         let result = validate_fee_bps(&env, 0);
-        assert_eq!(result, 0);
+        assert_eq!(result, Ok(0));
         let result = validate_fee_bps(&env, 9999);
-        assert_eq!(result, 9999);
+        assert_eq!(result, Ok(9999));
         let result = validate_fee_bps(&env, 10_000);
-        assert_eq!(result, 10_000);
+        assert_eq!(result, Ok(10_000));
     }
 
     #[test]
-    #[should_panic(expected = "total fees cannot be greater than 100%")]
     fn test_invalidate_fee_bps() {
         let env = Env::default();
-        validate_fee_bps(&env, 10_001);
+        assert_eq!(validate_fee_bps(&env, 10_001), Err(PoolError::InvalidBps));
     }
 }
@@ -1,5 +1,7 @@
 mod config;
+mod flash_loan;
 mod liquidity;
+mod oracle;
 mod setup;
 mod stake_deployment;
 mod swap;
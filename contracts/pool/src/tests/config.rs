@@ -1,8 +1,9 @@
 extern crate std;
-use soroban_sdk::{testutils::Address as _, Address, Env};
+use soroban_sdk::{testutils::Address as _, Address, Env, InvokeError};
 
 use super::setup::{deploy_liquidity_pool_contract, deploy_token_contract};
-use crate::storage::{Config, PairType};
+use crate::storage::{Config, PairType, PoolStatus};
+use phoenix::error::PoolError;
 
 #[test]
 fn update_config() {
@@ -47,6 +48,9 @@ fn update_config() {
             max_allowed_slippage_bps: 500,
             max_allowed_spread_bps: 200,
             max_referral_bps: 5_000,
+            protocol_fee_share_bps: 10_000,
+            distribute_lp_fees_to_stakers: false,
+            flash_fee_bps: 0,
         }
     );
 
@@ -58,6 +62,9 @@ fn update_config() {
         &None,
         &None,
         &Some(1_000i64),
+        &None,
+        &None,
+        &None,
     );
     assert_eq!(
         pool.query_config(),
@@ -72,11 +79,24 @@ fn update_config() {
             max_allowed_slippage_bps: 500,
             max_allowed_spread_bps: 200,
             max_referral_bps: 1_000,
+            protocol_fee_share_bps: 10_000,
+            distribute_lp_fees_to_stakers: false,
+            flash_fee_bps: 0,
         }
     );
 
     // update slippage and spread
-    pool.update_config(&None, &None, &None, &None, &Some(5_000i64), &Some(500));
+    pool.update_config(
+        &None,
+        &None,
+        &None,
+        &None,
+        &Some(5_000i64),
+        &Some(500),
+        &None,
+        &None,
+        &None,
+    );
     assert_eq!(
         pool.query_config(),
         Config {
@@ -90,12 +110,30 @@ fn update_config() {
             max_allowed_slippage_bps: 500,
             max_allowed_spread_bps: 5_000,
             max_referral_bps: 500,
+            protocol_fee_share_bps: 10_000,
+            distribute_lp_fees_to_stakers: false,
+            flash_fee_bps: 0,
         }
     );
+
+    // leave half of the commission to the liquidity providers and send it to the stakers
+    pool.update_config(
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &Some(5_000),
+        &Some(true),
+        &None,
+    );
+    let config = pool.query_config();
+    assert_eq!(config.protocol_fee_share_bps, 5_000);
+    assert!(config.distribute_lp_fees_to_stakers);
 }
 
 #[test]
-#[should_panic(expected = "Error(Auth, InvalidAction)")]
 fn update_config_unauthorized() {
     let env = Env::default();
 
@@ -120,14 +158,22 @@ fn update_config_unauthorized() {
         200,
     );
 
-    pool.update_config(
-        &None,
-        &Some(500i64), // 5% fees
-        &Some(admin2.clone()),
-        &None,
-        &None,
-        &None,
+    // no authorization of the admin is mocked, so the call fails its `require_auth`
+    assert_eq!(
+        pool.try_update_config(
+            &None,
+            &Some(500i64), // 5% fees
+            &Some(admin2.clone()),
+            &None,
+            &None,
+            &None,
+            &None,
+            &None,
+            &None,
+        ),
+        Err(Err(InvokeError::Abort))
     );
+    assert_eq!(pool.query_config().total_fee_bps, 0);
 }
 
 #[test]
@@ -158,13 +204,33 @@ fn update_config_update_admin() {
     );
 
     // update admin to new admin
-    pool.update_config(&Some(admin2.clone()), &None, &None, &None, &None, &None);
+    pool.update_config(
+        &Some(admin2.clone()),
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+    );
 
     let share_token_address = pool.query_share_token_address();
     let stake_token_address = pool.query_stake_contract_address();
 
     // now update succeeds
-    pool.update_config(&Some(admin2.clone()), &None, &None, &None, &None, &None);
+    pool.update_config(
+        &Some(admin2.clone()),
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+    );
     assert_eq!(
         pool.query_config(),
         Config {
@@ -178,12 +244,14 @@ fn update_config_update_admin() {
             max_allowed_slippage_bps: 500,
             max_allowed_spread_bps: 200,
             max_referral_bps: 5_000,
+            protocol_fee_share_bps: 10_000,
+            distribute_lp_fees_to_stakers: false,
+            flash_fee_bps: 0,
         }
     );
 }
 
 #[test]
-#[should_panic(expected = "Pool: UpdateConfig: Invalid total_fee_bps")]
 fn update_config_too_high_fees() {
     let env = Env::default();
     env.mock_all_auths();
@@ -210,12 +278,221 @@ fn update_config_too_high_fees() {
     );
 
     // update fees and recipient
-    pool.update_config(
+    assert_eq!(
+        pool.try_update_config(
+            &None,
+            &Some(10_100i64), // 101% fees
+            &Some(admin2.clone()),
+            &None,
+            &None,
+            &None,
+            &None,
+            &None,
+            &None,
+        ),
+        Err(Ok(PoolError::InvalidBps))
+    );
+}
+
+#[test]
+fn update_config_too_high_protocol_fee_share() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        Some(admin1.clone()),
+        (&token1.address, &token2.address),
+        0i64,
+        Address::generate(&env),
+        500,
+        200,
+    );
+
+    assert_eq!(
+        pool.try_update_config(
+            &None,
+            &None,
+            &None,
+            &None,
+            &None,
+            &None,
+            &Some(10_001),
+            &None,
+            &None,
+        ),
+        Err(Ok(PoolError::InvalidBps))
+    );
+}
+
+#[test]
+fn pause_swaps_and_deposits_keeps_withdrawals_open() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        Some(admin1.clone()),
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &20_000);
+    token2.mint(&user1, &10_000);
+    pool.provide_liquidity(
+        &user1,
+        &Some(10_000),
         &None,
-        &Some(10_100i64), // 101% fees
-        &Some(admin2.clone()),
+        &Some(10_000),
         &None,
         &None,
+        &false,
         &None,
     );
+
+    assert_eq!(pool.query_status(), PoolStatus::default());
+
+    pool.update_pause_status(&admin1, &Some(true), &Some(true), &None);
+    assert_eq!(
+        pool.query_status(),
+        PoolStatus {
+            swaps_paused: true,
+            deposits_paused: true,
+            withdrawals_paused: false,
+        }
+    );
+
+    assert_eq!(
+        pool.try_swap(&user1, &None, &token1.address, &100, &None, &None, &None),
+        Err(Ok(PoolError::SwapsPaused))
+    );
+    assert_eq!(
+        pool.try_swap_exact_out(&user1, &token2.address, &100, &200, &None),
+        Err(Ok(PoolError::SwapsPaused))
+    );
+    assert_eq!(
+        pool.try_provide_liquidity(
+            &user1,
+            &Some(500),
+            &None,
+            &None,
+            &None,
+            &None,
+            &false,
+            &None
+        ),
+        Err(Ok(PoolError::DepositsPaused))
+    );
+
+    pool.withdraw_liquidity(&user1, &500, &500, &500);
+    assert_eq!(token1.balance(&user1), 10_500);
+    assert_eq!(token2.balance(&user1), 500);
+
+    pool.update_pause_status(&admin1, &Some(false), &None, &None);
+    pool.swap(&user1, &None, &token1.address, &10, &None, &None, &None);
+    assert!(pool.query_status().deposits_paused);
+}
+
+#[test]
+fn pause_withdrawals() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        Some(admin1.clone()),
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &10_000);
+    token2.mint(&user1, &10_000);
+    pool.provide_liquidity(
+        &user1,
+        &Some(10_000),
+        &None,
+        &Some(10_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+
+    pool.update_pause_status(&admin1, &None, &None, &Some(true));
+
+    assert_eq!(
+        pool.try_withdraw_liquidity(&user1, &500, &500, &500),
+        Err(Ok(PoolError::WithdrawalsPaused))
+    );
+    assert_eq!(
+        pool.try_unstake_and_withdraw(&user1, &500, &0, &500, &500),
+        Err(Ok(PoolError::WithdrawalsPaused))
+    );
+}
+
+#[test]
+fn update_pause_status_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        Some(admin1),
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    assert_eq!(
+        pool.try_update_pause_status(&admin2, &Some(true), &None, &None),
+        Err(Ok(PoolError::Unauthorized))
+    );
 }
@@ -0,0 +1,313 @@
+extern crate std;
+use pretty_assertions::assert_eq;
+use soroban_sdk::{
+    contract, contractimpl,
+    testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation},
+    Address, Bytes, Env, IntoVal, Symbol,
+};
+
+use super::setup::{deploy_liquidity_pool_contract, deploy_token_contract};
+use crate::{
+    contract::LiquidityPoolClient,
+    storage::{Asset, FlashLoan, PoolResponse},
+    token_contract,
+};
+use phoenix::error::PoolError;
+
+/// Receiver of the flash loans in the tests. It pays back the loan plus the fees, or only the
+/// lent amounts when the first byte of `data` is 0. The loans taken by anyone but its owner are
+/// not paid back at all.
+#[contract]
+pub struct FlashLoanReceiver;
+
+#[contractimpl]
+impl FlashLoanReceiver {
+    pub fn initialize(env: Env, owner: Address) {
+        env.storage()
+            .instance()
+            .set(&Symbol::new(&env, "owner"), &owner);
+    }
+
+    pub fn on_flash_loan(
+        env: Env,
+        initiator: Address,
+        pool: Address,
+        loan: FlashLoan,
+        data: Bytes,
+    ) {
+        let owner: Address = env
+            .storage()
+            .instance()
+            .get(&Symbol::new(&env, "owner"))
+            .unwrap();
+        if initiator != owner {
+            return;
+        }
+
+        let pay_fees = data.get(0) != Some(0);
+        let (repay_a, repay_b) = if pay_fees {
+            (loan.amount_a + loan.fee_a, loan.amount_b + loan.fee_b)
+        } else {
+            (loan.amount_a, loan.amount_b)
+        };
+
+        token_contract::Client::new(&env, &loan.token_a).transfer(
+            &env.current_contract_address(),
+            &pool,
+            &repay_a,
+        );
+        token_contract::Client::new(&env, &loan.token_b).transfer(
+            &env.current_contract_address(),
+            &pool,
+            &repay_b,
+        );
+    }
+}
+
+fn setup<'a>(
+    env: &Env,
+    admin: &Address,
+    fee_recipient: &Address,
+) -> (
+    LiquidityPoolClient<'a>,
+    token_contract::Client<'a>,
+    token_contract::Client<'a>,
+) {
+    let mut token1 = deploy_token_contract(env, admin);
+    let mut token2 = deploy_token_contract(env, admin);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+    }
+    let pool = deploy_liquidity_pool_contract(
+        env,
+        admin.clone(),
+        (&token1.address, &token2.address),
+        0i64,
+        fee_recipient.clone(),
+        None,
+        None,
+    );
+
+    let user = Address::generate(env);
+    token1.mint(&user, &1_000_000);
+    token2.mint(&user, &1_000_000);
+    pool.provide_liquidity(
+        &user,
+        &Some(1_000_000),
+        &None,
+        &Some(1_000_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+
+    (pool, token1, token2)
+}
+
+fn deploy_flash_loan_receiver(env: &Env, owner: &Address) -> Address {
+    let receiver = env.register_contract(None, FlashLoanReceiver);
+    FlashLoanReceiverClient::new(env, &receiver).initialize(owner);
+
+    receiver
+}
+
+#[test]
+fn flash_loan_is_repaid_with_fees() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let fee_recipient = Address::generate(&env);
+    let (pool, token1, token2) = setup(&env, &admin, &fee_recipient);
+
+    // 0.3% flash fee, half of it goes to the protocol
+    pool.update_config(
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &Some(5_000),
+        &None,
+        &Some(30),
+    );
+
+    let owner = Address::generate(&env);
+    let receiver = deploy_flash_loan_receiver(&env, &owner);
+    // the receiver only has to come up with the fees
+    token1.mint(&receiver, &300);
+    token2.mint(&receiver, &150);
+
+    let loan = pool.flash_loan(&owner, &receiver, &100_000, &50_000, &Bytes::new(&env));
+    assert_eq!(
+        loan,
+        FlashLoan {
+            token_a: token1.address.clone(),
+            token_b: token2.address.clone(),
+            amount_a: 100_000,
+            amount_b: 50_000,
+            fee_a: 300,
+            fee_b: 150,
+        }
+    );
+
+    assert_eq!(token1.balance(&receiver), 0);
+    assert_eq!(token2.balance(&receiver), 0);
+    assert_eq!(token1.balance(&fee_recipient), 150);
+    assert_eq!(token2.balance(&fee_recipient), 75);
+
+    // the liquidity providers' part of the fees is added to the reserves
+    assert_eq!(
+        pool.query_pool_info(),
+        PoolResponse {
+            asset_a: Asset {
+                address: token1.address.clone(),
+                amount: 1_000_150,
+            },
+            asset_b: Asset {
+                address: token2.address.clone(),
+                amount: 1_000_075,
+            },
+            asset_lp_share: Asset {
+                address: pool.query_share_token_address(),
+                amount: 1_000_000,
+            },
+        }
+    );
+    assert_eq!(token1.balance(&pool.address), 1_000_150);
+    assert_eq!(token2.balance(&pool.address), 1_000_075);
+}
+
+#[test]
+fn flash_loan_without_fees_paid_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let (pool, token1, _) = setup(&env, &admin, &Address::generate(&env));
+    pool.update_config(
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &Some(30),
+    );
+
+    let owner = Address::generate(&env);
+    let receiver = deploy_flash_loan_receiver(&env, &owner);
+    token1.mint(&receiver, &300);
+
+    assert_eq!(
+        pool.try_flash_loan(
+            &owner,
+            &receiver,
+            &100_000,
+            &0,
+            &Bytes::from_array(&env, &[0])
+        ),
+        Err(Ok(PoolError::FlashLoanNotRepaid))
+    );
+    assert_eq!(token1.balance(&receiver), 300);
+    assert_eq!(token1.balance(&pool.address), 1_000_000);
+}
+
+#[test]
+fn flash_loan_invalid_amounts() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let (pool, _, _) = setup(&env, &admin, &Address::generate(&env));
+    let owner = Address::generate(&env);
+    let receiver = deploy_flash_loan_receiver(&env, &owner);
+
+    assert_eq!(
+        pool.try_flash_loan(&owner, &receiver, &0, &0, &Bytes::new(&env)),
+        Err(Ok(PoolError::InvalidAmount))
+    );
+    assert_eq!(
+        pool.try_flash_loan(&owner, &receiver, &-1, &100, &Bytes::new(&env)),
+        Err(Ok(PoolError::InvalidAmount))
+    );
+    assert_eq!(
+        pool.try_flash_loan(&owner, &receiver, &1_000_001, &0, &Bytes::new(&env)),
+        Err(Ok(PoolError::NotEnoughLiquidity))
+    );
+
+    pool.update_pause_status(&admin, &Some(true), &None, &None);
+    assert_eq!(
+        pool.try_flash_loan(&owner, &receiver, &100, &0, &Bytes::new(&env)),
+        Err(Ok(PoolError::SwapsPaused))
+    );
+}
+
+#[test]
+fn flash_loan_taken_by_third_party_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let fee_recipient = Address::generate(&env);
+    let (pool, token1, _) = setup(&env, &admin, &fee_recipient);
+    pool.update_config(
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &Some(30),
+    );
+
+    let owner = Address::generate(&env);
+    let receiver = deploy_flash_loan_receiver(&env, &owner);
+    // the fees the receiver set aside for its own loans
+    token1.mint(&receiver, &300);
+
+    // anyone else taking a loan on behalf of the receiver is turned down by it
+    let third_party = Address::generate(&env);
+    assert_eq!(
+        pool.try_flash_loan(&third_party, &receiver, &100_000, &0, &Bytes::new(&env)),
+        Err(Ok(PoolError::FlashLoanNotRepaid))
+    );
+    assert_eq!(token1.balance(&receiver), 300);
+    assert_eq!(token1.balance(&pool.address), 1_000_000);
+
+    pool.flash_loan(&owner, &receiver, &100_000, &0, &Bytes::new(&env));
+    // the initiator has to authorize the loan
+    assert_eq!(
+        env.auths()[0],
+        (
+            owner.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    pool.address.clone(),
+                    Symbol::new(&env, "flash_loan"),
+                    (
+                        owner.clone(),
+                        receiver.clone(),
+                        100_000i128,
+                        0i128,
+                        Bytes::new(&env)
+                    )
+                        .into_val(&env),
+                )),
+                sub_invocations: std::vec![],
+            }
+        )
+    );
+    assert_eq!(token1.balance(&receiver), 0);
+    assert_eq!(token1.balance(&fee_recipient), 300);
+}
@@ -4,16 +4,18 @@ use pretty_assertions::assert_eq;
 
 use soroban_sdk::{
     symbol_short,
-    testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation},
-    Address, Env, IntoVal, Symbol,
+    testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation, Ledger},
+    vec, Address, Env, IntoVal, Symbol,
 };
 
 use super::setup::{deploy_liquidity_pool_contract, deploy_token_contract};
 use crate::{
-    storage::{Asset, PoolResponse},
+    stake_contract,
+    storage::{Asset, PoolResponse, ProvideLiquidityResponse, SimulateWithdrawSingleResponse},
     token_contract,
 };
 use decimal::Decimal;
+use phoenix::error::PoolError;
 
 #[test]
 fn provide_liqudity() {
@@ -45,18 +47,20 @@ fn provide_liqudity() {
     let share_token_address = pool.query_share_token_address();
     let token_share = token_contract::Client::new(&env, &share_token_address);
 
-    token1.mint(&user1, &1000);
-    assert_eq!(token1.balance(&user1), 1000);
+    token1.mint(&user1, &10_000);
+    assert_eq!(token1.balance(&user1), 10_000);
 
-    token2.mint(&user1, &1000);
-    assert_eq!(token2.balance(&user1), 1000);
+    token2.mint(&user1, &10_000);
+    assert_eq!(token2.balance(&user1), 10_000);
 
     pool.provide_liquidity(
         &user1,
-        &Some(100),
-        &Some(100),
-        &Some(100),
-        &Some(100),
+        &Some(2_000),
+        &Some(2_000),
+        &Some(2_000),
+        &Some(2_000),
+        &None,
+        &false,
         &None,
     );
 
@@ -70,11 +74,13 @@ fn provide_liqudity() {
                     Symbol::new(&env, "provide_liquidity"),
                     (
                         &user1,
-                        Some(100i128),
-                        Some(100i128),
-                        Some(100i128),
-                        Some(100i128),
-                        None::<i64>
+                        Some(2_000i128),
+                        Some(2_000i128),
+                        Some(2_000i128),
+                        Some(2_000i128),
+                        None::<i64>,
+                        false,
+                        None::<i128>
                     )
                         .into_val(&env),
                 )),
@@ -83,7 +89,7 @@ fn provide_liqudity() {
                         function: AuthorizedFunction::Contract((
                             token1.address.clone(),
                             symbol_short!("transfer"),
-                            (&user1, &pool.address, 100_i128).into_val(&env)
+                            (&user1, &pool.address, 2_000_i128).into_val(&env)
                         )),
                         sub_invocations: std::vec![],
                     },
@@ -91,7 +97,7 @@ fn provide_liqudity() {
                         function: AuthorizedFunction::Contract((
                             token2.address.clone(),
                             symbol_short!("transfer"),
-                            (&user1, &pool.address, 100_i128).into_val(&env)
+                            (&user1, &pool.address, 2_000_i128).into_val(&env)
                         )),
                         sub_invocations: std::vec![],
                     },
@@ -100,12 +106,13 @@ fn provide_liqudity() {
         ),]
     );
 
-    assert_eq!(token_share.balance(&user1), 100);
-    assert_eq!(token_share.balance(&pool.address), 0);
-    assert_eq!(token1.balance(&user1), 900);
-    assert_eq!(token1.balance(&pool.address), 100);
-    assert_eq!(token2.balance(&user1), 900);
-    assert_eq!(token2.balance(&pool.address), 100);
+    // the minimum liquidity is locked in the pool
+    assert_eq!(token_share.balance(&user1), 1_000);
+    assert_eq!(token_share.balance(&pool.address), 1_000);
+    assert_eq!(token1.balance(&user1), 8_000);
+    assert_eq!(token1.balance(&pool.address), 2_000);
+    assert_eq!(token2.balance(&user1), 8_000);
+    assert_eq!(token2.balance(&pool.address), 2_000);
 
     let result = pool.query_pool_info();
     assert_eq!(
@@ -113,15 +120,15 @@ fn provide_liqudity() {
         PoolResponse {
             asset_a: Asset {
                 address: token1.address,
-                amount: 100i128
+                amount: 2_000i128
             },
             asset_b: Asset {
                 address: token2.address,
-                amount: 100i128
+                amount: 2_000i128
             },
             asset_lp_share: Asset {
                 address: share_token_address,
-                amount: 100i128
+                amount: 2_000i128
             }
         }
     );
@@ -157,50 +164,33 @@ fn withdraw_liquidity() {
     let share_token_address = pool.query_share_token_address();
     let token_share = token_contract::Client::new(&env, &share_token_address);
 
-    token1.mint(&user1, &100);
-    token2.mint(&user1, &100);
+    token1.mint(&user1, &4_000);
+    token2.mint(&user1, &4_000);
     pool.provide_liquidity(
         &user1,
-        &Some(100),
-        &Some(100),
-        &Some(100),
-        &Some(100),
+        &Some(4_000),
+        &Some(4_000),
+        &Some(4_000),
+        &Some(4_000),
+        &None,
+        &false,
         &None,
     );
 
     assert_eq!(token1.balance(&user1), 0);
-    assert_eq!(token1.balance(&pool.address), 100);
+    assert_eq!(token1.balance(&pool.address), 4_000);
     assert_eq!(token2.balance(&user1), 0);
-    assert_eq!(token2.balance(&pool.address), 100);
+    assert_eq!(token2.balance(&pool.address), 4_000);
 
-    let share_amount = 50;
-    let min_a = 50;
-    let min_b = 50;
-    pool.withdraw_liquidity(&user1, &share_amount, &min_a, &min_b);
-    // assert_eq!(
-    //     env.auths(),
-    //     [
-    //         (
-    //             user1.clone(),
-    //             pool.address.clone(),
-    //             Symbol::new(&env, "withdraw_liquidity"),
-    //             (&user1, 50_i128, 50_i128, 50_i128).into_val(&env)
-    //         ),
-    //         (
-    //             user1.clone(),
-    //             share_token_address.clone(),
-    //             Symbol::short("transfer"),
-    //             (&user1, &pool.address, 50_i128).into_val(&env)
-    //         )
-    //     ]
-    // );
-
-    assert_eq!(token_share.balance(&user1), 50);
-    assert_eq!(token_share.balance(&pool.address), 0); // sanity check
-    assert_eq!(token1.balance(&user1), 50);
-    assert_eq!(token1.balance(&pool.address), 50);
-    assert_eq!(token2.balance(&user1), 50);
-    assert_eq!(token2.balance(&pool.address), 50);
+    // withdraw half of the pool
+    pool.withdraw_liquidity(&user1, &2_000, &2_000, &2_000);
+
+    assert_eq!(token_share.balance(&user1), 1_000);
+    assert_eq!(token_share.balance(&pool.address), 1_000); // locked minimum liquidity
+    assert_eq!(token1.balance(&user1), 2_000);
+    assert_eq!(token1.balance(&pool.address), 2_000);
+    assert_eq!(token2.balance(&user1), 2_000);
+    assert_eq!(token2.balance(&pool.address), 2_000);
 
     let result = pool.query_pool_info();
     assert_eq!(
@@ -208,34 +198,34 @@ fn withdraw_liquidity() {
         PoolResponse {
             asset_a: Asset {
                 address: token1.address.clone(),
-                amount: 50i128,
+                amount: 2_000i128,
             },
             asset_b: Asset {
                 address: token2.address.clone(),
-                amount: 50i128,
+                amount: 2_000i128,
             },
             asset_lp_share: Asset {
                 address: share_token_address,
-                amount: 50i128,
+                amount: 2_000i128,
             },
         }
     );
 
-    // clear the pool
-    pool.withdraw_liquidity(&user1, &share_amount, &min_a, &min_b);
+    // withdraw the rest, the liquidity backing the locked shares stays in the pool
+    pool.withdraw_liquidity(&user1, &1_000, &1_000, &1_000);
     assert_eq!(token_share.balance(&user1), 0);
-    assert_eq!(token_share.balance(&pool.address), 0); // sanity check
-    assert_eq!(token1.balance(&user1), 100);
-    assert_eq!(token1.balance(&pool.address), 0);
-    assert_eq!(token2.balance(&user1), 100);
-    assert_eq!(token2.balance(&pool.address), 0);
+    assert_eq!(token_share.balance(&pool.address), 1_000);
+    assert_eq!(token1.balance(&user1), 3_000);
+    assert_eq!(token1.balance(&pool.address), 1_000);
+    assert_eq!(token2.balance(&user1), 3_000);
+    assert_eq!(token2.balance(&pool.address), 1_000);
 }
 
 #[test]
-#[should_panic = "Pool: split_deposit_based_on_pool_ratio: Both pools and deposit must be a positive!"]
-fn provide_liqudity_single_asset_on_empty_pool() {
+fn provide_liqudity_with_auto_stake() {
     let env = Env::default();
     env.mock_all_auths();
+    env.budget().reset_unlimited();
 
     let mut admin1 = Address::generate(&env);
     let mut admin2 = Address::generate(&env);
@@ -247,28 +237,149 @@ fn provide_liqudity_single_asset_on_empty_pool() {
         std::mem::swap(&mut admin1, &mut admin2);
     }
     let user1 = Address::generate(&env);
-    let swap_fees = 0i64;
     let pool = deploy_liquidity_pool_contract(
         &env,
         None,
         (&token1.address, &token2.address),
-        swap_fees,
+        0i64,
         None,
         None,
         None,
     );
 
-    token1.mint(&user1, &1_000_000);
+    let token_share = token_contract::Client::new(&env, &pool.query_share_token_address());
+    let stake_address = pool.query_stake_contract_address();
+    let stake = stake_contract::Client::new(&env, &stake_address);
 
-    // providing liquidity with single asset is not allowed on an empty pool
+    token1.mint(&user1, &2_000);
+    token2.mint(&user1, &2_000);
     pool.provide_liquidity(
         &user1,
-        &Some(1_000_000),
-        &Some(1_000_000),
+        &Some(2_000),
+        &None,
+        &Some(2_000),
+        &None,
+        &None,
+        &true,
+        &None,
+    );
+
+    // all minted shares are bonded on behalf of the user, apart from the locked minimum liquidity
+    assert_eq!(token_share.balance(&user1), 0);
+    assert_eq!(token_share.balance(&stake_address), 1_000);
+    assert_eq!(
+        stake.query_staked(&user1).stakes,
+        vec![
+            &env,
+            stake_contract::Stake {
+                stake: 1_000,
+                stake_timestamp: 0,
+            }
+        ]
+    );
+    assert_eq!(stake.query_total_staked(), 1_000);
+}
+
+#[test]
+fn unstake_and_withdraw() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    let token_share = token_contract::Client::new(&env, &pool.query_share_token_address());
+    let stake = stake_contract::Client::new(&env, &pool.query_stake_contract_address());
+
+    token1.mint(&user1, &2_000);
+    token2.mint(&user1, &2_000);
+    env.ledger().with_mut(|li| {
+        li.timestamp = 100;
+    });
+    pool.provide_liquidity(
+        &user1,
+        &Some(2_000),
+        &None,
+        &Some(2_000),
         &None,
         &None,
+        &true,
         &None,
     );
+
+    env.ledger().with_mut(|li| {
+        li.timestamp = 200;
+    });
+    let result = pool.unstake_and_withdraw(&user1, &1_000, &100, &1_000, &1_000);
+    assert_eq!(result, (1_000, 1_000));
+
+    assert_eq!(stake.query_staked(&user1).stakes, vec![&env]);
+    assert_eq!(token_share.balance(&user1), 0);
+    assert_eq!(token1.balance(&user1), 1_000);
+    assert_eq!(token2.balance(&user1), 1_000);
+    assert_eq!(pool.query_pool_info().asset_lp_share.amount, 1_000);
+}
+
+#[test]
+fn provide_liqudity_single_asset_on_empty_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+    let swap_fees = 0i64;
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        swap_fees,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &1_000_000);
+
+    // providing liquidity with single asset is not allowed on an empty pool
+    assert_eq!(
+        pool.try_provide_liquidity(
+            &user1,
+            &Some(1_000_000),
+            &Some(1_000_000),
+            &None,
+            &None,
+            &None,
+            &false,
+            &None,
+        ),
+        Err(Ok(PoolError::EmptyPool))
+    );
 }
 
 #[test]
@@ -309,6 +420,8 @@ fn provide_liqudity_single_asset_equal() {
         &Some(10_000_000),
         &Some(10_000_000),
         &None,
+        &false,
+        &None,
     );
     assert_eq!(token1.balance(&pool.address), 10_000_000);
     assert_eq!(token2.balance(&pool.address), 10_000_000);
@@ -323,6 +436,8 @@ fn provide_liqudity_single_asset_equal() {
         &None,
         &Some(49_000),
         &None,
+        &false,
+        &None,
     );
     // before swap : A(10_000_000), B(10_000_000)
     // since pool is equal divides 50/50 sum for swap
@@ -378,6 +493,8 @@ fn provide_liqudity_single_asset_equal_with_fees() {
         &Some(initial_pool_liquidity),
         &Some(initial_pool_liquidity),
         &None,
+        &false,
+        &None,
     );
     assert_eq!(token1.balance(&pool.address), initial_pool_liquidity);
     assert_eq!(token2.balance(&pool.address), initial_pool_liquidity);
@@ -392,6 +509,8 @@ fn provide_liqudity_single_asset_equal_with_fees() {
         &None,
         &Some(49_000),
         &None,
+        &false,
+        &None,
     );
     // before swap : A(10_000_000), B(10_000_000)
     // algorithm splits 100k in such way, so that after swapping (with 10% fee)
@@ -454,6 +573,8 @@ fn provide_liqudity_single_asset_one_third() {
         &Some(30_000_000),
         &Some(30_000_000),
         &None,
+        &false,
+        &None,
     );
     assert_eq!(token1.balance(&pool.address), 10_000_000);
     assert_eq!(token2.balance(&pool.address), 30_000_000);
@@ -468,6 +589,8 @@ fn provide_liqudity_single_asset_one_third() {
         &Some(100_000),
         &None,
         &Some(slippage_tolerance_bps),
+        &false,
+        &None,
     );
     // before swap : A(10_000_000), B(30_000_000)
     // since pool is 1/3 divides 75k/25k sum for swap
@@ -518,12 +641,23 @@ fn provide_liqudity_single_asset_one_third_with_fees() {
         &Some(30_000_000),
         &Some(30_000_000),
         &None,
+        &false,
+        &None,
     );
     assert_eq!(token1.balance(&pool.address), 10_000_000);
     assert_eq!(token2.balance(&pool.address), 30_000_000);
 
     token2.mint(&user1, &100_000);
-    pool.provide_liquidity(&user1, &None, &None, &Some(100_000), &None, &None);
+    pool.provide_liquidity(
+        &user1,
+        &None,
+        &None,
+        &Some(100_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
     // before swap : A(10_000_000), B(30_000_000)
     // since pool is 1/3 algorithm will split it around 15794/52734
     // swap 47_226k B for A = 17_548 (-10% fee = 15_793)
@@ -542,7 +676,7 @@ fn provide_liqudity_single_asset_one_third_with_fees() {
 }
 
 #[test]
-#[should_panic(expected = "The value 10001 is out of range. Must be between 0 and 10000 bps.")]
+#[should_panic(expected = "HostError: Error(Contract, #3)")]
 fn provide_liqudity_too_high_fees() {
     let env = Env::default();
     env.mock_all_auths();
@@ -569,9 +703,6 @@ fn provide_liqudity_too_high_fees() {
 }
 
 #[test]
-#[should_panic(
-    expected = "Pool: ProvideLiquidity: At least one token must be provided and must be bigger then 0!"
-)]
 fn swap_with_no_amounts() {
     let env = Env::default();
     env.mock_all_auths();
@@ -600,13 +731,13 @@ fn swap_with_no_amounts() {
     token1.mint(&user1, &1_001_000);
     token2.mint(&user1, &1_001_000);
     // providing all amounts as None
-    pool.provide_liquidity(&user1, &None, &None, &None, &None, &None);
+    assert_eq!(
+        pool.try_provide_liquidity(&user1, &None, &None, &None, &None, &None, &false, &None),
+        Err(Ok(PoolError::EmptyDeposit))
+    );
 }
 
 #[test]
-#[should_panic(
-    expected = "Pool: WithdrawLiquidity: Minimum amount of token_a or token_b is not satisfied!"
-)]
 fn withdraw_liqudity_below_min() {
     let env = Env::default();
     env.mock_all_auths();
@@ -632,18 +763,870 @@ fn withdraw_liqudity_below_min() {
         None,
     );
 
-    token1.mint(&user1, &100);
-    token2.mint(&user1, &100);
+    token1.mint(&user1, &2_000);
+    token2.mint(&user1, &2_000);
     pool.provide_liquidity(
         &user1,
-        &Some(100),
-        &Some(100),
-        &Some(100),
-        &Some(100),
+        &Some(2_000),
+        &Some(2_000),
+        &Some(2_000),
+        &Some(2_000),
+        &None,
+        &false,
         &None,
     );
 
     let share_amount = 50;
     // Expecting min_a and/or min_b as huge bigger then available
-    pool.withdraw_liquidity(&user1, &share_amount, &3000, &3000);
+    assert_eq!(
+        pool.try_withdraw_liquidity(&user1, &share_amount, &3000, &3000),
+        Err(Ok(PoolError::WithdrawMinNotSatisfied))
+    );
+}
+
+#[test]
+fn provide_liquidity_first_deposit_below_minimum_liquidity() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut token1 = deploy_token_contract(&env, &Address::generate(&env));
+    let mut token2 = deploy_token_contract(&env, &Address::generate(&env));
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+    }
+    let user1 = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &1_000);
+    token2.mint(&user1, &1_000);
+    // sqrt(1_000 * 1_000) shares are all needed for the locked minimum liquidity
+    assert_eq!(
+        pool.try_provide_liquidity(
+            &user1,
+            &Some(1_000),
+            &None,
+            &Some(1_000),
+            &None,
+            &None,
+            &false,
+            &None
+        ),
+        Err(Ok(PoolError::LiquidityAmountTooLow))
+    );
+}
+
+#[test]
+fn share_inflation_attack_on_fresh_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut token1 = deploy_token_contract(&env, &Address::generate(&env));
+    let mut token2 = deploy_token_contract(&env, &Address::generate(&env));
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+    }
+    let attacker = Address::generate(&env);
+    let victim = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+    let token_share = token_contract::Client::new(&env, &pool.query_share_token_address());
+
+    // the attacker creates the pool with the smallest possible deposit...
+    token1.mint(&attacker, &1_001_001);
+    token2.mint(&attacker, &1_001_001);
+    pool.provide_liquidity(
+        &attacker,
+        &Some(1_001),
+        &None,
+        &Some(1_001),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+    assert_eq!(token_share.balance(&attacker), 1);
+    assert_eq!(token_share.balance(&pool.address), 1_000);
+
+    // ...and donates a lot of tokens to inflate the value of their single share
+    token1.transfer(&attacker, &pool.address, &1_000_000);
+    token2.transfer(&attacker, &pool.address, &1_000_000);
+
+    token1.mint(&victim, &100_000);
+    token2.mint(&victim, &100_000);
+    pool.provide_liquidity(
+        &victim,
+        &Some(100_000),
+        &None,
+        &Some(100_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+
+    // the victim's deposit is not rounded away and can be withdrawn in full
+    let victim_shares = token_share.balance(&victim);
+    assert!(victim_shares > 0);
+    pool.withdraw_liquidity(&victim, &victim_shares, &100_000, &100_000);
+    assert!(token1.balance(&victim) >= 100_000);
+    assert!(token2.balance(&victim) >= 100_000);
+}
+
+#[test]
+fn provide_liquidity_minting_no_shares_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut token1 = deploy_token_contract(&env, &Address::generate(&env));
+    let mut token2 = deploy_token_contract(&env, &Address::generate(&env));
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+    }
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
+    pool.provide_liquidity(
+        &user1,
+        &Some(1_000_000),
+        &None,
+        &Some(1_000_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+
+    // a single token deposit this small is worth less than a share
+    token1.mint(&user2, &1);
+    assert_eq!(
+        pool.try_provide_liquidity(&user2, &Some(1), &None, &None, &None, &None, &false, &None),
+        Err(Ok(PoolError::LiquidityAmountTooLow))
+    );
+    assert_eq!(token1.balance(&user2), 1);
+}
+
+#[test]
+fn skim_sends_donated_tokens_to_recipient() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let admin = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let donor = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        admin.clone(),
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &2_000);
+    token2.mint(&user1, &2_000);
+    pool.provide_liquidity(
+        &user1,
+        &Some(2_000),
+        &None,
+        &Some(2_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+    assert_eq!(pool.query_reserve_discrepancy(), (0, 0));
+
+    // tokens sent directly to the pool are not part of the reserves
+    token1.mint(&donor, &500);
+    token2.mint(&donor, &300);
+    token1.transfer(&donor, &pool.address, &500);
+    token2.transfer(&donor, &pool.address, &300);
+    assert_eq!(pool.query_reserve_discrepancy(), (500, 300));
+
+    assert_eq!(
+        pool.try_skim(&user1, &user1),
+        Err(Ok(PoolError::Unauthorized))
+    );
+
+    assert_eq!(pool.skim(&admin, &recipient), (500, 300));
+    assert_eq!(
+        env.auths(),
+        [(
+            admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    pool.address.clone(),
+                    Symbol::new(&env, "skim"),
+                    (&admin, &recipient).into_val(&env)
+                )),
+                sub_invocations: std::vec![],
+            }
+        ),]
+    );
+
+    assert_eq!(token1.balance(&recipient), 500);
+    assert_eq!(token2.balance(&recipient), 300);
+    assert_eq!(token1.balance(&pool.address), 2_000);
+    assert_eq!(token2.balance(&pool.address), 2_000);
+    assert_eq!(pool.query_reserve_discrepancy(), (0, 0));
+
+    // nothing left to skim
+    assert_eq!(pool.skim(&admin, &recipient), (0, 0));
+}
+
+#[test]
+fn sync_adds_donated_tokens_to_reserves() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let admin = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let donor = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        admin.clone(),
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+    let share_token_address = pool.query_share_token_address();
+
+    token1.mint(&user1, &2_000);
+    token2.mint(&user1, &2_000);
+    pool.provide_liquidity(
+        &user1,
+        &Some(2_000),
+        &None,
+        &Some(2_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+
+    token1.mint(&donor, &1_000);
+    token1.transfer(&donor, &pool.address, &1_000);
+    assert_eq!(pool.query_reserve_discrepancy(), (1_000, 0));
+
+    assert_eq!(pool.try_sync(&user1), Err(Ok(PoolError::Unauthorized)));
+    assert_eq!(pool.query_reserve_discrepancy(), (1_000, 0));
+
+    pool.sync(&admin);
+    assert_eq!(
+        env.auths(),
+        [(
+            admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    pool.address.clone(),
+                    Symbol::new(&env, "sync"),
+                    (&admin,).into_val(&env)
+                )),
+                sub_invocations: std::vec![],
+            }
+        ),]
+    );
+    assert_eq!(pool.query_reserve_discrepancy(), (0, 0));
+
+    let result = pool.query_pool_info();
+    assert_eq!(
+        result,
+        PoolResponse {
+            asset_a: Asset {
+                address: token1.address,
+                amount: 3_000i128
+            },
+            asset_b: Asset {
+                address: token2.address,
+                amount: 2_000i128
+            },
+            asset_lp_share: Asset {
+                address: share_token_address,
+                amount: 2_000i128
+            },
+        }
+    );
+}
+
+#[test]
+fn withdraw_liquidity_single() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+    let share_token_address = pool.query_share_token_address();
+    let token_share = token_contract::Client::new(&env, &share_token_address);
+
+    token1.mint(&user1, &100_000);
+    token2.mint(&user1, &100_000);
+    pool.provide_liquidity(
+        &user1,
+        &Some(100_000),
+        &None,
+        &Some(100_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+    assert_eq!(token_share.balance(&user1), 99_000);
+
+    // 10% of the pool is 10_000 of each token; the 10_000 of token2 are swapped against the
+    // remaining 90_000/90_000 into 9_000 of token1
+    assert_eq!(
+        pool.simulate_withdraw_single(&10_000, &token1.address),
+        SimulateWithdrawSingleResponse {
+            return_amount: 19_000,
+            commission_amount: 0,
+            spread_amount: 1_000,
+        }
+    );
+
+    assert_eq!(
+        pool.withdraw_liquidity_single(&user1, &10_000, &token1.address, &19_000),
+        19_000
+    );
+    assert_eq!(
+        env.auths(),
+        [(
+            user1.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    pool.address.clone(),
+                    Symbol::new(&env, "withdraw_liquidity_single"),
+                    (&user1, 10_000i128, token1.address.clone(), 19_000i128).into_val(&env)
+                )),
+                sub_invocations: std::vec![AuthorizedInvocation {
+                    function: AuthorizedFunction::Contract((
+                        share_token_address.clone(),
+                        symbol_short!("transfer"),
+                        (&user1, &pool.address, 10_000_i128).into_val(&env)
+                    )),
+                    sub_invocations: std::vec![],
+                }],
+            }
+        ),]
+    );
+
+    assert_eq!(token_share.balance(&user1), 89_000);
+    assert_eq!(token1.balance(&user1), 19_000);
+    assert_eq!(token2.balance(&user1), 0);
+
+    let result = pool.query_pool_info();
+    assert_eq!(
+        result,
+        PoolResponse {
+            asset_a: Asset {
+                address: token1.address,
+                amount: 81_000i128
+            },
+            asset_b: Asset {
+                address: token2.address,
+                amount: 100_000i128
+            },
+            asset_lp_share: Asset {
+                address: share_token_address,
+                amount: 90_000i128
+            },
+        }
+    );
+    assert_eq!(pool.query_reserve_discrepancy(), (0, 0));
+}
+
+#[test]
+fn withdraw_liquidity_single_with_fees() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+    let fee_recipient = Address::generate(&env);
+    // 10% swap fee
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        1_000i64,
+        fee_recipient.clone(),
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &100_000);
+    token2.mint(&user1, &100_000);
+    pool.provide_liquidity(
+        &user1,
+        &Some(100_000),
+        &None,
+        &Some(100_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+
+    // the internal swap returns 9_000 of token2, 10% of which is the commission
+    assert_eq!(
+        pool.simulate_withdraw_single(&10_000, &token2.address),
+        SimulateWithdrawSingleResponse {
+            return_amount: 18_100,
+            commission_amount: 900,
+            spread_amount: 1_000,
+        }
+    );
+    assert_eq!(
+        pool.withdraw_liquidity_single(&user1, &10_000, &token2.address, &18_000),
+        18_100
+    );
+
+    assert_eq!(token1.balance(&user1), 0);
+    assert_eq!(token2.balance(&user1), 18_100);
+    // the whole commission goes to the fee recipient by default
+    assert_eq!(token2.balance(&fee_recipient), 900);
+
+    let result = pool.query_pool_info();
+    assert_eq!(result.asset_a.amount, 100_000);
+    assert_eq!(result.asset_b.amount, 81_000);
+    assert_eq!(pool.query_reserve_discrepancy(), (0, 0));
+}
+
+#[test]
+fn withdraw_liquidity_single_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let admin = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        admin.clone(),
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+    let token_share = token_contract::Client::new(&env, &pool.query_share_token_address());
+
+    token1.mint(&user1, &100_000);
+    token2.mint(&user1, &100_000);
+    pool.provide_liquidity(
+        &user1,
+        &Some(100_000),
+        &None,
+        &Some(100_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+
+    assert_eq!(
+        pool.try_withdraw_liquidity_single(&user1, &10_000, &token1.address, &19_001),
+        Err(Ok(PoolError::WithdrawMinNotSatisfied))
+    );
+
+    let not_in_pool = Address::generate(&env);
+    assert_eq!(
+        pool.try_withdraw_liquidity_single(&user1, &10_000, &not_in_pool, &1),
+        Err(Ok(PoolError::AssetNotInPool))
+    );
+    assert_eq!(
+        pool.try_simulate_withdraw_single(&10_000, &not_in_pool),
+        Err(Ok(PoolError::AssetNotInPool))
+    );
+
+    // the share of the other token is swapped, so it is halted with the swaps
+    pool.update_pause_status(&admin, &Some(true), &None, &None);
+    assert_eq!(
+        pool.try_withdraw_liquidity_single(&user1, &10_000, &token1.address, &1),
+        Err(Ok(PoolError::SwapsPaused))
+    );
+    pool.update_pause_status(&admin, &Some(false), &None, &Some(true));
+    assert_eq!(
+        pool.try_withdraw_liquidity_single(&user1, &10_000, &token1.address, &1),
+        Err(Ok(PoolError::WithdrawalsPaused))
+    );
+
+    assert_eq!(token_share.balance(&user1), 99_000);
+}
+
+#[test]
+fn provide_liquidity_returns_shares_and_amounts() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+    let token_share = token_contract::Client::new(&env, &pool.query_share_token_address());
+
+    token1.mint(&user1, &10_000_000);
+    token2.mint(&user1, &10_000_000);
+
+    let expected = ProvideLiquidityResponse {
+        shares: 1_999_000,
+        amount_a: 2_000_000,
+        amount_b: 2_000_000,
+    };
+    assert_eq!(
+        pool.simulate_provide_liquidity(&Some(2_000_000), &Some(2_000_000)),
+        expected
+    );
+    assert_eq!(
+        pool.provide_liquidity(
+            &user1,
+            &Some(2_000_000),
+            &None,
+            &Some(2_000_000),
+            &None,
+            &None,
+            &false,
+            &Some(1_999_000),
+        ),
+        expected
+    );
+
+    // the amounts follow the ratio of the pool
+    let expected = ProvideLiquidityResponse {
+        shares: 1_000_000,
+        amount_a: 1_000_000,
+        amount_b: 1_000_000,
+    };
+    assert_eq!(
+        pool.simulate_provide_liquidity(&Some(1_000_000), &Some(1_005_000)),
+        expected
+    );
+    assert_eq!(
+        pool.provide_liquidity(
+            &user1,
+            &Some(1_000_000),
+            &None,
+            &Some(1_005_000),
+            &None,
+            &None,
+            &false,
+            &None,
+        ),
+        expected
+    );
+    assert_eq!(token_share.balance(&user1), 2_999_000);
+
+    // a deposit of one token swaps a part of it, so nothing is taken of the other token
+    let simulation = pool.simulate_provide_liquidity(&Some(100_000), &None);
+    assert_eq!(simulation.amount_a, 100_000);
+    assert_eq!(simulation.amount_b, 0);
+    assert_eq!(
+        pool.provide_liquidity(
+            &user1,
+            &Some(100_000),
+            &None,
+            &None,
+            &None,
+            &None,
+            &false,
+            &Some(simulation.shares),
+        ),
+        simulation
+    );
+    assert_eq!(token_share.balance(&user1), 2_999_000 + simulation.shares);
+    assert_eq!(token1.balance(&user1), 10_000_000 - 3_100_000);
+    assert_eq!(token2.balance(&user1), 10_000_000 - 3_000_000);
+
+    let (amount_a, amount_b) = pool.simulate_withdraw_liquidity(&1_000_000);
+    assert_eq!(
+        pool.withdraw_liquidity(&user1, &1_000_000, &amount_a, &amount_b),
+        (amount_a, amount_b)
+    );
+}
+
+#[test]
+fn provide_liquidity_below_min_shares_out() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &2_100_000);
+    token2.mint(&user1, &2_000_000);
+
+    assert_eq!(
+        pool.try_provide_liquidity(
+            &user1,
+            &Some(2_000_000),
+            &None,
+            &Some(2_000_000),
+            &None,
+            &None,
+            &false,
+            &Some(1_999_001),
+        ),
+        Err(Ok(PoolError::MinSharesNotSatisfied))
+    );
+    pool.provide_liquidity(
+        &user1,
+        &Some(2_000_000),
+        &None,
+        &Some(2_000_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+
+    // the swap of a one token deposit moves the price, so it mints less than half of its value
+    let simulation = pool.simulate_provide_liquidity(&Some(100_000), &None);
+    assert!(simulation.shares < 50_000);
+    assert_eq!(
+        pool.try_provide_liquidity(
+            &user1,
+            &Some(100_000),
+            &None,
+            &None,
+            &None,
+            &None,
+            &false,
+            &Some(50_000),
+        ),
+        Err(Ok(PoolError::MinSharesNotSatisfied))
+    );
+    assert_eq!(token1.balance(&user1), 100_000);
+    assert_eq!(token2.balance(&user1), 0);
+
+    assert_eq!(
+        pool.try_provide_liquidity(
+            &user1,
+            &Some(100_000),
+            &None,
+            &None,
+            &None,
+            &None,
+            &false,
+            &Some(0),
+        ),
+        Err(Ok(PoolError::InvalidAmount))
+    );
+}
+
+#[test]
+fn provide_liquidity_single_asset_bound_by_pause_and_slippage() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        Some(admin1.clone()),
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &2_200_000);
+    token2.mint(&user1, &2_100_000);
+    pool.provide_liquidity(
+        &user1,
+        &Some(2_000_000),
+        &None,
+        &Some(2_000_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+
+    // a one token deposit swaps half of it, which is not possible while swaps are paused
+    pool.update_pause_status(&admin1, &Some(true), &None, &None);
+    assert_eq!(
+        pool.try_provide_liquidity(
+            &user1,
+            &Some(100_000),
+            &None,
+            &None,
+            &None,
+            &None,
+            &false,
+            &None,
+        ),
+        Err(Ok(PoolError::SwapsPaused))
+    );
+    // deposits of both tokens do not swap
+    pool.provide_liquidity(
+        &user1,
+        &Some(100_000),
+        &None,
+        &Some(100_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+    pool.update_pause_status(&admin1, &Some(false), &None, &None);
+
+    // swapping 50_000 of 2_100_000 has a spread of about 2.3%
+    assert_eq!(
+        pool.try_provide_liquidity(
+            &user1,
+            &Some(100_000),
+            &None,
+            &None,
+            &None,
+            &Some(100),
+            &false,
+            &None,
+        ),
+        Err(Ok(PoolError::SpreadExceedsLimit))
+    );
+    assert_eq!(token1.balance(&user1), 100_000);
+
+    pool.provide_liquidity(
+        &user1,
+        &Some(100_000),
+        &None,
+        &None,
+        &None,
+        &Some(300),
+        &false,
+        &None,
+    );
+    assert_eq!(token1.balance(&user1), 0);
 }
@@ -0,0 +1,326 @@
+extern crate std;
+use pretty_assertions::assert_eq;
+use soroban_sdk::{
+    testutils::{Address as _, Ledger},
+    Address, Env,
+};
+
+use super::setup::{deploy_liquidity_pool_contract, deploy_token_contract};
+use crate::contract::{LiquidityPool, LiquidityPoolClient};
+use crate::oracle::{self, MAX_OBSERVATIONS, OBSERVATION_INTERVAL};
+use crate::storage::TwapResponse;
+use crate::token_contract;
+use phoenix::error::PoolError;
+
+const PRICE_ONE: i128 = 1_000_000_000_000_000_000;
+
+fn set_time(env: &Env, timestamp: u64) {
+    env.ledger().with_mut(|li| {
+        li.timestamp = timestamp;
+    });
+}
+
+fn deploy_pool_with_liquidity<'a>(
+    env: &Env,
+    amount_a: i128,
+    amount_b: i128,
+) -> (
+    LiquidityPoolClient<'a>,
+    token_contract::Client<'a>,
+    token_contract::Client<'a>,
+    Address,
+) {
+    let mut admin1 = Address::generate(env);
+    let mut admin2 = Address::generate(env);
+
+    let mut token1 = deploy_token_contract(env, &admin1);
+    let mut token2 = deploy_token_contract(env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user = Address::generate(env);
+    let pool = deploy_liquidity_pool_contract(
+        env,
+        None,
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user, &(amount_a * 10));
+    token2.mint(&user, &(amount_b * 10));
+    pool.provide_liquidity(
+        &user,
+        &Some(amount_a),
+        &None,
+        &Some(amount_b),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+
+    (pool, token1, token2, user)
+}
+
+#[test]
+fn twap_of_constant_price() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+    set_time(&env, 1_000);
+
+    let (pool, token1, token2, _) = deploy_pool_with_liquidity(&env, 1_000_000, 2_000_000);
+
+    set_time(&env, 4_600);
+    assert_eq!(
+        pool.query_twap(&token1.address, &3_600),
+        TwapResponse {
+            price: 2 * PRICE_ONE,
+            window_seconds: 3_600,
+        }
+    );
+    assert_eq!(
+        pool.query_twap(&token2.address, &3_600),
+        TwapResponse {
+            price: PRICE_ONE / 2,
+            window_seconds: 3_600,
+        }
+    );
+}
+
+#[test]
+fn twap_weights_prices_by_time() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+    set_time(&env, 1_000);
+
+    let (pool, token1, _, user) = deploy_pool_with_liquidity(&env, 1_000_000, 1_000_000);
+
+    set_time(&env, 2_000);
+    pool.swap(
+        &user,
+        &None,
+        &token1.address,
+        &100_000,
+        &None,
+        &Some(5_000),
+        &None,
+    );
+    let pool_info = pool.query_pool_info();
+    let new_price = pool_info.asset_b.amount * PRICE_ONE / pool_info.asset_a.amount;
+    assert!(new_price < PRICE_ONE);
+
+    // first half of the window at the initial price, second half at the new one
+    set_time(&env, 3_000);
+    assert_eq!(
+        pool.query_twap(&token1.address, &2_000),
+        TwapResponse {
+            price: (PRICE_ONE * 1_000 + new_price * 1_000) / 2_000,
+            window_seconds: 2_000,
+        }
+    );
+}
+
+#[test]
+fn twap_is_resistant_to_last_second_swaps() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+    set_time(&env, 1_000);
+
+    let (pool, token1, _, user) = deploy_pool_with_liquidity(&env, 1_000_000, 1_000_000);
+
+    // a huge swap moves the spot price a lot...
+    set_time(&env, 4_599);
+    pool.swap(
+        &user,
+        &None,
+        &token1.address,
+        &1_000_000,
+        &None,
+        &Some(10_000),
+        &None,
+    );
+    let pool_info = pool.query_pool_info();
+    assert!(pool_info.asset_b.amount * 3 < pool_info.asset_a.amount);
+
+    // ...but barely changes the average over the last hour
+    set_time(&env, 4_600);
+    let twap = pool.query_twap(&token1.address, &3_600);
+    assert!(twap.price > PRICE_ONE * 999 / 1_000);
+}
+
+#[test]
+fn twap_window_starts_at_the_closest_observation() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+    set_time(&env, 0);
+
+    let (pool, token1, _, user) = deploy_pool_with_liquidity(&env, 1_000_000, 1_000_000);
+
+    // too close to the previous observation to record a new one
+    set_time(&env, OBSERVATION_INTERVAL / 2);
+    pool.swap(&user, &None, &token1.address, &1_000, &None, &None, &None);
+    // recorded
+    set_time(&env, 2 * OBSERVATION_INTERVAL);
+    pool.swap(&user, &None, &token1.address, &1_000, &None, &None, &None);
+
+    set_time(&env, 3 * OBSERVATION_INTERVAL);
+    let twap = pool.query_twap(&token1.address, &(OBSERVATION_INTERVAL / 2));
+    assert_eq!(twap.window_seconds, OBSERVATION_INTERVAL);
+    let twap = pool.query_twap(&token1.address, &OBSERVATION_INTERVAL);
+    assert_eq!(twap.window_seconds, OBSERVATION_INTERVAL);
+    let twap = pool.query_twap(&token1.address, &(2 * OBSERVATION_INTERVAL));
+    assert_eq!(twap.window_seconds, 3 * OBSERVATION_INTERVAL);
+}
+
+#[test]
+fn twap_window_longer_than_history() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+    set_time(&env, 10_000);
+
+    let (pool, token1, _, _) = deploy_pool_with_liquidity(&env, 1_000_000, 1_000_000);
+
+    set_time(&env, 11_000);
+    assert_eq!(
+        pool.try_query_twap(&token1.address, &1_001),
+        Err(Ok(PoolError::NotEnoughPriceHistory))
+    );
+}
+
+#[test]
+fn twap_empty_window() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let (pool, token1, _, _) = deploy_pool_with_liquidity(&env, 1_000_000, 1_000_000);
+
+    assert_eq!(
+        pool.try_query_twap(&token1.address, &0),
+        Err(Ok(PoolError::EmptyTwapWindow))
+    );
+}
+
+#[test]
+fn twap_asset_not_in_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let (pool, _, _, _) = deploy_pool_with_liquidity(&env, 1_000_000, 1_000_000);
+
+    assert_eq!(
+        pool.try_query_twap(&Address::generate(&env), &100),
+        Err(Ok(PoolError::AssetNotInPool))
+    );
+}
+
+#[test]
+fn twap_empty_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let token1 = deploy_token_contract(&env, &Address::generate(&env));
+    let token2 = deploy_token_contract(&env, &Address::generate(&env));
+    let (token_a, token_b) = if token1.address < token2.address {
+        (token1.address, token2.address)
+    } else {
+        (token2.address, token1.address)
+    };
+    let pool =
+        deploy_liquidity_pool_contract(&env, None, (&token_a, &token_b), 0i64, None, None, None);
+
+    assert_eq!(
+        pool.try_query_twap(&token_a, &100),
+        Err(Ok(PoolError::EmptyPool))
+    );
+}
+
+#[test]
+fn observations_ring_buffer_wraps_around() {
+    let env = Env::default();
+    env.budget().reset_unlimited();
+    let contract = env.register_contract(None, LiquidityPool {});
+
+    env.as_contract(&contract, || {
+        // reserves are empty before the first deposit
+        oracle::update(&env, 0, 0);
+        for i in 1..=(MAX_OBSERVATIONS as u64 + 10) {
+            set_time(&env, i * OBSERVATION_INTERVAL);
+            oracle::update(&env, 1_000, 1_000);
+        }
+        let accumulator = oracle::get_accumulator(&env);
+        assert_eq!(accumulator.observations_count, MAX_OBSERVATIONS);
+        assert_eq!(accumulator.latest_observation, 10);
+
+        // the whole buffer is still available
+        let now = env.ledger().timestamp();
+        let window = (MAX_OBSERVATIONS as u64 - 1) * OBSERVATION_INTERVAL;
+        assert_eq!(
+            oracle::twap(&env, 1_000, 1_000, window),
+            Ok((PRICE_ONE, PRICE_ONE, window))
+        );
+        assert_eq!(
+            oracle::twap(&env, 1_000, 1_000, now - 11 * OBSERVATION_INTERVAL),
+            Ok((PRICE_ONE, PRICE_ONE, window))
+        );
+    });
+}
+
+#[test]
+fn overwritten_observations_are_not_used() {
+    let env = Env::default();
+    env.budget().reset_unlimited();
+    let contract = env.register_contract(None, LiquidityPool {});
+
+    env.as_contract(&contract, || {
+        oracle::update(&env, 0, 0);
+        for i in 1..=(MAX_OBSERVATIONS as u64 + 10) {
+            set_time(&env, i * OBSERVATION_INTERVAL);
+            oracle::update(&env, 1_000, 1_000);
+        }
+
+        assert_eq!(
+            oracle::twap(
+                &env,
+                1_000,
+                1_000,
+                MAX_OBSERVATIONS as u64 * OBSERVATION_INTERVAL,
+            ),
+            Err(PoolError::NotEnoughPriceHistory)
+        );
+    });
+}
+
+#[test]
+fn emptied_pool_starts_a_new_history() {
+    let env = Env::default();
+    env.budget().reset_unlimited();
+    let contract = env.register_contract(None, LiquidityPool {});
+
+    env.as_contract(&contract, || {
+        oracle::update(&env, 0, 0);
+        set_time(&env, 1_000);
+        // the pool is emptied at 1_000 and refilled at 2_000
+        oracle::update(&env, 1_000, 4_000);
+        set_time(&env, 2_000);
+        oracle::update(&env, 0, 0);
+
+        set_time(&env, 2_500);
+        assert_eq!(
+            oracle::twap(&env, 1_000, 1_000, 500),
+            Ok((PRICE_ONE, PRICE_ONE, 500))
+        );
+        assert_eq!(oracle::get_accumulator(&env).observations_count, 1);
+    });
+}
@@ -1,5 +1,8 @@
 extern crate std;
-use phoenix::utils::{LiquidityPoolInitInfo, StakeInitInfo, TokenInitInfo};
+use phoenix::{
+    error::PoolError,
+    utils::{LiquidityPoolInitInfo, StakeInitInfo, TokenInitInfo},
+};
 use soroban_sdk::{testutils::Address as _, Address, Env};
 
 use super::setup::{deploy_liquidity_pool_contract, deploy_token_contract};
@@ -54,6 +57,9 @@ fn confirm_stake_contract_deployment() {
             max_allowed_slippage_bps: 500,
             max_allowed_spread_bps: 200,
             max_referral_bps: 5_000,
+            protocol_fee_share_bps: 10_000,
+            distribute_lp_fees_to_stakers: false,
+            flash_fee_bps: 0,
         }
     );
 
@@ -72,7 +78,6 @@ fn confirm_stake_contract_deployment() {
 }
 
 #[test]
-#[should_panic(expected = "Liquidity Pool: Initialize: initializing contract twice is not allowed")]
 fn second_pool_deployment_should_fail() {
     let env = Env::default();
     env.mock_all_auths();
@@ -119,7 +124,11 @@ fn second_pool_deployment_should_fail() {
         stake_init_info,
     };
 
-    pool.initialize(&stake_wasm_hash, &token_wasm_hash, &lp_init_info);
+    let factory = Address::generate(&env);
+    pool.initialize(&stake_wasm_hash, &token_wasm_hash, &lp_init_info, &factory);
 
-    pool.initialize(&stake_wasm_hash, &token_wasm_hash, &lp_init_info);
+    assert_eq!(
+        pool.try_initialize(&stake_wasm_hash, &token_wasm_hash, &lp_init_info, &factory),
+        Err(Ok(PoolError::AlreadyInitialized))
+    );
 }
@@ -3,13 +3,16 @@ use pretty_assertions::assert_eq;
 use soroban_sdk::{
     symbol_short,
     testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation},
-    Address, Env, IntoVal,
+    Address, Env, IntoVal, Symbol,
 };
 use test_case::test_case;
 
 use super::setup::{deploy_liquidity_pool_contract, deploy_token_contract};
-use crate::storage::{Asset, PoolResponse, SimulateReverseSwapResponse, SimulateSwapResponse};
+use crate::storage::{
+    Asset, PoolResponse, Referral, SimulateReverseSwapResponse, SimulateSwapResponse,
+};
 use decimal::Decimal;
+use phoenix::error::PoolError;
 
 #[test]
 fn simple_swap() {
@@ -47,18 +50,20 @@ fn simple_swap() {
         &Some(1_000_000),
         &Some(1_000_000),
         &None,
+        &false,
+        &None,
     );
 
     // selling just one token with 1% max spread allowed
     let spread = 100i64; // 1% maximum spread allowed
     pool.swap(
         &user1,
-        // FIXM: Disable Referral struct
-        // &None::<Referral>,
+        &None::<Referral>,
         &token1.address,
         &1,
         &None,
         &Some(spread),
+        &None,
     );
     assert_eq!(
         env.auths(),
@@ -70,12 +75,12 @@ fn simple_swap() {
                     symbol_short!("swap"),
                     (
                         &user1,
-                        // FIXM: Disable Referral struct
-                        // None::<Referral>,
+                        None::<Referral>,
                         token1.address.clone(),
                         1_i128,
                         None::<i64>,
-                        spread
+                        spread,
+                        None::<Address>
                     )
                         .into_val(&env)
                 )),
@@ -118,12 +123,12 @@ fn simple_swap() {
     // this time 100 units
     let output_amount = pool.swap(
         &user1,
-        // FIXM: Disable Referral struct
-        // &None::<Referral>,
+        &None::<Referral>,
         &token2.address,
         &1_000,
         &None,
         &Some(spread),
+        &None,
     );
     let result = pool.query_pool_info();
     assert_eq!(
@@ -148,8 +153,6 @@ fn simple_swap() {
     assert_eq!(token2.balance(&user1), 1001 - 1000); // user1 sold 1k of token B on second swap
 }
 
-// FIXM: Disable Referral struct
-#[ignore]
 #[test]
 fn simple_swap_with_referral_fee() {
     let env = Env::default();
@@ -188,25 +191,27 @@ fn simple_swap_with_referral_fee() {
         &Some(1_000_000),
         &Some(1_000_000),
         &None,
+        &false,
+        &None,
     );
 
     // selling just one token with 1% max spread allowed
     let spread = 100i64; // 1% maximum spread allowed
 
     // selling with 10% fee for the big guy
-    // FIXM: Disable Referral struct
-    // let referral = Referral {
-    //     address: referral_addr.clone(),
-    //     fee: 1_000,
-    // };
+    let referral = Referral {
+        address: referral_addr.clone(),
+        fee: 1_000,
+    };
 
     pool.swap(
         &user1,
-        //     &Some(referral.clone()),
+        &Some(referral.clone()),
         &token1.address,
         &1,
         &None,
         &Some(spread),
+        &None,
     );
 
     // zero referral fee because amount is too low
@@ -236,12 +241,12 @@ fn simple_swap_with_referral_fee() {
     assert_eq!(token2.balance(&user1), 1001); // 1 from the swap
     let output_amount = pool.swap(
         &user1,
-        // FIXM: Disable Referral struct
-        // &Some(referral),
+        &Some(referral),
         &token2.address,
         &1_000,
         &None,
         &Some(spread),
+        &None,
     );
     let result = pool.query_pool_info();
     assert_eq!(
@@ -263,15 +268,11 @@ fn simple_swap_with_referral_fee() {
     );
     assert_eq!(output_amount, 900);
     assert_eq!(token1.balance(&user1), 1899); // 999 + 1_000 as a result of swap
-                                              // FIXM: Disable Referral struct
-                                              // assert_eq!(token1.balance(&referral_addr), 100);
+    assert_eq!(token1.balance(&referral_addr), 100);
     assert_eq!(token2.balance(&user1), 1001 - 1000); // user1 sold 1k of token B on second swap
 }
 
-// FIXM: Disable Referral struct
-#[ignore]
 #[test]
-#[should_panic(expected = "Pool: Swap: Trying to swap with more than the allowed referral fee")]
 fn test_swap_should_fail_when_referral_fee_is_larger_than_allowed() {
     let env = Env::default();
     env.mock_all_auths();
@@ -307,31 +308,93 @@ fn test_swap_should_fail_when_referral_fee_is_larger_than_allowed() {
         &Some(1_000_000),
         &Some(1_000_000),
         &None,
+        &false,
+        &None,
     );
 
     let spread = 100i64; // 1% maximum spread allowed
 
-    // FIXM: Disable Referral struct
-    // let referral = Referral {
-    //     address: Address::random(&env),
-    //     // in tests/setup.rs we hardcoded the max referral fee
-    //     // to 5_000 bps (50%), here we try to set it to 10_000 bps (100%)
-    //     fee: 10_000,
-    // };
+    let referral = Referral {
+        address: Address::generate(&env),
+        // in tests/setup.rs we hardcoded the max referral fee
+        // to 5_000 bps (50%), here we try to set it to 10_000 bps (100%)
+        fee: 10_000,
+    };
 
-    pool.swap(
+    assert_eq!(
+        pool.try_swap(
+            &user1,
+            &Some(referral),
+            &token1.address,
+            &1,
+            &None,
+            &Some(spread),
+            &None,
+        ),
+        Err(Ok(PoolError::ReferralFeeTooHigh))
+    );
+}
+
+#[test]
+fn test_swap_should_fail_when_referral_fee_is_negative() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &1_001_000);
+    token2.mint(&user1, &1_001_000);
+    pool.provide_liquidity(
         &user1,
-        //     &Some(referral),
-        &token1.address,
-        &1,
+        &Some(1_000_000),
+        &Some(1_000_000),
+        &Some(1_000_000),
+        &Some(1_000_000),
         &None,
-        &Some(spread),
+        &false,
+        &None,
+    );
+
+    let referral = Referral {
+        address: Address::generate(&env),
+        fee: -1_000,
+    };
+
+    assert_eq!(
+        pool.try_swap(
+            &user1,
+            &Some(referral),
+            &token1.address,
+            &1_000,
+            &None,
+            &Some(100),
+            &None,
+        ),
+        Err(Ok(PoolError::NegativeReferralFee))
     );
 }
 
 #[test]
-#[should_panic(expected = "HostError: Error(Contract, #1)")]
-fn swap_should_panic_with_bad_max_spread() {
+fn swap_should_fail_with_bad_max_spread() {
     let env = Env::default();
     env.mock_all_auths();
     env.budget().reset_unlimited();
@@ -359,11 +422,22 @@ fn swap_should_panic_with_bad_max_spread() {
 
     token1.mint(&user1, &1_001_000);
     token2.mint(&user1, &2_001_000);
-    pool.provide_liquidity(&user1, &Some(5000), &None, &Some(2_000_000), &None, &None);
+    pool.provide_liquidity(
+        &user1,
+        &Some(5000),
+        &None,
+        &Some(2_000_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
 
     // selling just one token with 1% max spread allowed and 50 bps max spread
-    // FIXM: Disable Referral struct
-    pool.swap(&user1, &token1.address, &50, &None, &Some(50));
+    assert_eq!(
+        pool.try_swap(&user1, &None, &token1.address, &50, &None, &Some(50), &None),
+        Err(Ok(PoolError::SpreadExceedsLimit))
+    );
 }
 
 #[test]
@@ -406,6 +480,8 @@ fn swap_with_high_fee() {
         &Some(initial_liquidity),
         &Some(initial_liquidity),
         &None,
+        &false,
+        &None,
     );
 
     let spread = 1_000; // 10% maximum spread allowed
@@ -413,12 +489,12 @@ fn swap_with_high_fee() {
     // let's swap 100_000 units of Token 1 in 1:1 pool with 10% protocol fee
     pool.swap(
         &user1,
-        // FIXM: Disable Referral struct
-        // &None,
+        &None,
         &token1.address,
         &100_000,
         &None,
         &Some(spread),
+        &None,
     );
 
     // This is XYK LP with constant product formula
@@ -450,6 +526,263 @@ fn swap_with_high_fee() {
     assert_eq!(token2.balance(&fee_recipient), fees);
 }
 
+#[test]
+fn swap_with_fee_split_between_protocol_and_lps() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+
+    let swap_fees = 1_000i64; // 10% bps
+    let fee_recipient = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        swap_fees,
+        fee_recipient.clone(),
+        None,
+        None,
+    );
+    // half of the commission goes to the protocol, the other half stays in the pool
+    pool.update_config(
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &Some(5_000),
+        &None,
+        &None,
+    );
+
+    let initial_liquidity = 1_000_000i128;
+
+    token1.mint(&user1, &(initial_liquidity + 100_000));
+    token2.mint(&user1, &initial_liquidity);
+    pool.provide_liquidity(
+        &user1,
+        &Some(initial_liquidity),
+        &Some(initial_liquidity),
+        &Some(initial_liquidity),
+        &Some(initial_liquidity),
+        &None,
+        &false,
+        &None,
+    );
+
+    pool.swap(
+        &user1,
+        &None,
+        &token1.address,
+        &100_000,
+        &None,
+        &Some(1_000),
+        &None,
+    );
+
+    // same swap as in `swap_with_high_fee`
+    let output_amount = 90_910i128;
+    let fees = Decimal::percent(10) * output_amount;
+    let protocol_fees = Decimal::percent(50) * fees;
+    let lp_fees = fees - protocol_fees;
+    assert_eq!(token2.balance(&user1), output_amount - fees);
+    assert_eq!(token2.balance(&fee_recipient), protocol_fees);
+
+    // the liquidity providers' part of the commission stays in the reserves
+    let result = pool.query_pool_info();
+    assert_eq!(
+        result.asset_b.amount,
+        initial_liquidity - output_amount + lp_fees
+    );
+    assert_eq!(token2.balance(&pool.address), result.asset_b.amount);
+}
+
+#[test]
+fn swap_with_lp_fees_sent_to_stakers() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+
+    let swap_fees = 1_000i64; // 10% bps
+    let fee_recipient = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        swap_fees,
+        fee_recipient.clone(),
+        None,
+        None,
+    );
+    pool.update_config(
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &Some(5_000),
+        &Some(true),
+        &None,
+    );
+
+    let initial_liquidity = 1_000_000i128;
+
+    token1.mint(&user1, &(initial_liquidity + 100_000));
+    token2.mint(&user1, &initial_liquidity);
+    pool.provide_liquidity(
+        &user1,
+        &Some(initial_liquidity),
+        &Some(initial_liquidity),
+        &Some(initial_liquidity),
+        &Some(initial_liquidity),
+        &None,
+        &false,
+        &None,
+    );
+
+    pool.swap(
+        &user1,
+        &None,
+        &token1.address,
+        &100_000,
+        &None,
+        &Some(1_000),
+        &None,
+    );
+
+    let output_amount = 90_910i128;
+    let fees = Decimal::percent(10) * output_amount;
+    let protocol_fees = Decimal::percent(50) * fees;
+    let lp_fees = fees - protocol_fees;
+    assert_eq!(token2.balance(&fee_recipient), protocol_fees);
+    // stake contract picks the fees up as rewards on its next distribution
+    assert_eq!(
+        token2.balance(&pool.query_stake_contract_address()),
+        lp_fees
+    );
+
+    let result = pool.query_pool_info();
+    assert_eq!(result.asset_b.amount, initial_liquidity - output_amount);
+    assert_eq!(token2.balance(&pool.address), result.asset_b.amount);
+}
+
+#[test]
+fn lp_share_value_grows_with_swap_volume() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let provider = Address::generate(&env);
+    let trader = Address::generate(&env);
+
+    let swap_fees = 30i64; // 0.3% bps
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        swap_fees,
+        None,
+        None,
+        None,
+    );
+    // whole commission belongs to the liquidity providers
+    pool.update_config(
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &Some(0),
+        &None,
+        &None,
+    );
+
+    let initial_liquidity = 1_000_000_000i128;
+    token1.mint(&provider, &initial_liquidity);
+    token2.mint(&provider, &initial_liquidity);
+    pool.provide_liquidity(
+        &provider,
+        &Some(initial_liquidity),
+        &Some(initial_liquidity),
+        &Some(initial_liquidity),
+        &Some(initial_liquidity),
+        &None,
+        &false,
+        &None,
+    );
+    let shares = pool.query_pool_info().asset_lp_share.amount;
+
+    // trade back and forth, so that the price ends up where it started
+    token1.mint(&trader, &10_000_000);
+    let mut invariant_per_share = initial_liquidity * initial_liquidity / shares;
+    for _ in 0..5 {
+        let bought = pool.swap(
+            &trader,
+            &None,
+            &token1.address,
+            &10_000_000,
+            &None,
+            &Some(1_000),
+            &None,
+        );
+        let sold = pool.swap(
+            &trader,
+            &None,
+            &token2.address,
+            &bought,
+            &None,
+            &Some(1_000),
+            &None,
+        );
+        token1.mint(&trader, &(10_000_000 - sold));
+
+        let pool_info = pool.query_pool_info();
+        let new_invariant_per_share = pool_info.asset_a.amount * pool_info.asset_b.amount / shares;
+        assert!(new_invariant_per_share > invariant_per_share);
+        invariant_per_share = new_invariant_per_share;
+    }
+
+    // provider gets back more than deposited, apart from the part backing the locked minimum
+    // liquidity that stays in the pool
+    pool.withdraw_liquidity(&provider, &(shares - 1_000), &1, &1);
+    assert!(token1.balance(&provider) > initial_liquidity);
+    assert!(token2.balance(&provider) >= initial_liquidity - 1_000);
+}
+
 #[test]
 fn swap_simulation_even_pool() {
     let env = Env::default();
@@ -484,6 +817,8 @@ fn swap_simulation_even_pool() {
         &Some(initial_liquidity),
         &Some(initial_liquidity),
         &None,
+        &false,
+        &None,
     );
 
     // let's simulate swap 100_000 units of Token 1 in 1:1 pool with 10% protocol fee
@@ -514,11 +849,11 @@ fn swap_simulation_even_pool() {
         result,
         SimulateReverseSwapResponse {
             // offer_amount,
-            offer_amount: 99_999i128, // rounding error
+            offer_amount: 100_002i128, // the reverse swap rounds up in favor of the pool
             // spread_amount: Decimal::from_ratio(100_000, 1_000_000) * output_amount, // since it's 10% of the pool
-            spread_amount: 9090, // rounding error, one less then ^
+            spread_amount: 9092, // rounding error, one more then ^
             // commission_amount: fees,
-            commission_amount: 9090,
+            commission_amount: 9091,
         }
     );
 
@@ -541,11 +876,11 @@ fn swap_simulation_even_pool() {
         result,
         SimulateReverseSwapResponse {
             // offer_amount,
-            offer_amount: 99_999i128, // rounding error
+            offer_amount: 100_002i128, // the reverse swap rounds up in favor of the pool
             // spread_amount: Decimal::from_ratio(100_000, 1_000_000) * output_amount, // since it's 10% of the pool
-            spread_amount: 9090, // rounding error, one less then ^
+            spread_amount: 9092, // rounding error, one more then ^
             // commission_amount: fees,
-            commission_amount: 9090,
+            commission_amount: 9091,
         }
     );
 }
@@ -584,6 +919,8 @@ fn swap_simulation_one_third_pool() {
         &Some(3 * initial_liquidity),
         &Some(3 * initial_liquidity),
         &None,
+        &false,
+        &None,
     );
 
     // let's simulate swap 100_000 units of Token 1 in 1:3 pool with 5% protocol fee
@@ -612,8 +949,9 @@ fn swap_simulation_one_third_pool() {
     assert_eq!(
         result,
         SimulateReverseSwapResponse {
-            offer_amount,
-            spread_amount: Decimal::from_ratio(offer_amount, 1_000_000) * output_amount, // since it's 10% of the pool
+            // the reverse swap rounds up in favor of the pool
+            offer_amount: offer_amount + 1,
+            spread_amount: Decimal::from_ratio(offer_amount, 1_000_000) * output_amount + 2,
             commission_amount: fees,
         }
     );
@@ -642,9 +980,9 @@ fn swap_simulation_one_third_pool() {
         result,
         SimulateReverseSwapResponse {
             // offer_amount,
-            offer_amount: 100_002i128, // rounding error
+            offer_amount: 100_007i128, // the reverse swap rounds up in favor of the pool
             spread_amount: Decimal::from_ratio(offer_amount, 3_000_000) * output_amount, // since it's 10% of the pool
-            commission_amount: fees,
+            commission_amount: fees + 1,
         }
     );
 }
@@ -684,6 +1022,8 @@ fn test_swap_fee_variants(swap_fees: i64, commission_fee: i128) {
         &Some(initial_liquidity),
         &Some(initial_liquidity),
         &None,
+        &false,
+        &None,
     );
 
     // simulating a swap with 1_000_000_000 units
@@ -718,9 +1058,316 @@ fn test_swap_fee_variants(swap_fees: i64, commission_fee: i128) {
     assert_eq!(
         result,
         SimulateReverseSwapResponse {
-            offer_amount: 1000000000i128,
-            spread_amount: Decimal::from_ratio(offer_amount, initial_liquidity) * output_amount,
+            // the reverse swap rounds up in favor of the pool
+            offer_amount: 1000000002i128,
+            spread_amount: Decimal::from_ratio(offer_amount, initial_liquidity) * output_amount + 1,
             commission_amount: fees,
         }
     );
 }
+
+#[test]
+fn swap_exact_out() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+
+    let swap_fees = 1_000i64; // 10% bps
+    let fee_recipient = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        swap_fees,
+        fee_recipient.clone(),
+        None,
+        None,
+    );
+
+    let initial_liquidity = 1_000_000i128;
+
+    token1.mint(&user1, &(initial_liquidity + 100_000));
+    token2.mint(&user1, &initial_liquidity);
+    pool.provide_liquidity(
+        &user1,
+        &Some(initial_liquidity),
+        &Some(initial_liquidity),
+        &Some(initial_liquidity),
+        &Some(initial_liquidity),
+        &None,
+        &false,
+        &None,
+    );
+
+    let simulated = pool.simulate_reverse_swap(&token2.address, &10_000);
+
+    // ask amount before commission = 10_000 / (1 - 10%) = 11_111.1, rounded up to 11_112
+    // offer amount = (1_000_000 * 1_000_000) / (1_000_000 - 11_112) - 1_000_000 = 11_236.9,
+    // rounded up to 11_237
+    let offer_amount = pool.swap_exact_out(&user1, &token2.address, &10_000, &11_237, &None);
+    assert_eq!(offer_amount, 11_237);
+    assert_eq!(offer_amount, simulated.offer_amount);
+    assert_eq!(
+        env.auths(),
+        [(
+            user1.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    pool.address.clone(),
+                    Symbol::new(&env, "swap_exact_out"),
+                    (
+                        &user1,
+                        token2.address.clone(),
+                        10_000i128,
+                        11_237i128,
+                        None::<Address>
+                    )
+                        .into_val(&env)
+                )),
+                sub_invocations: std::vec![
+                    (AuthorizedInvocation {
+                        function: AuthorizedFunction::Contract((
+                            token1.address.clone(),
+                            symbol_short!("transfer"),
+                            (&user1, &pool.address, 11_237i128).into_val(&env)
+                        )),
+                        sub_invocations: std::vec![],
+                    }),
+                ],
+            }
+        )]
+    );
+
+    // the user receives exactly the ask amount, the commission goes to the fee recipient
+    assert_eq!(token1.balance(&user1), 100_000 - 11_237);
+    assert_eq!(token2.balance(&user1), 10_000);
+    assert_eq!(token2.balance(&fee_recipient), 1_111);
+    assert_eq!(
+        pool.query_pool_info(),
+        PoolResponse {
+            asset_a: Asset {
+                address: token1.address.clone(),
+                amount: initial_liquidity + 11_237,
+            },
+            asset_b: Asset {
+                address: token2.address.clone(),
+                amount: initial_liquidity - 10_000 - 1_111,
+            },
+            asset_lp_share: Asset {
+                address: pool.query_share_token_address(),
+                amount: 1_000_000i128,
+            },
+        }
+    );
+}
+
+#[test]
+fn swap_exact_out_should_fail_when_offer_exceeds_max() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &1_100_000);
+    token2.mint(&user1, &1_000_000);
+    pool.provide_liquidity(
+        &user1,
+        &Some(1_000_000),
+        &Some(1_000_000),
+        &Some(1_000_000),
+        &Some(1_000_000),
+        &None,
+        &false,
+        &None,
+    );
+
+    // buying 10_000 of token2 costs 10_101.01 of token1, rounded up to 10_102
+    assert_eq!(
+        pool.try_swap_exact_out(&user1, &token2.address, &10_000, &10_101, &None),
+        Err(Ok(PoolError::OfferAmountExceedsMax))
+    );
+    assert_eq!(token1.balance(&user1), 100_000);
+    assert_eq!(token2.balance(&user1), 0);
+
+    assert_eq!(
+        pool.try_swap_exact_out(&user1, &token2.address, &1_000_000, &1_100_000, &None),
+        Err(Ok(PoolError::NotEnoughLiquidity))
+    );
+
+    let other_token = deploy_token_contract(&env, &admin1);
+    assert_eq!(
+        pool.try_swap_exact_out(&user1, &other_token.address, &10_000, &100_000, &None),
+        Err(Ok(PoolError::AssetNotInPool))
+    );
+
+    assert_eq!(
+        pool.swap_exact_out(&user1, &token2.address, &10_000, &10_102, &None),
+        10_102
+    );
+    assert_eq!(token1.balance(&user1), 100_000 - 10_102);
+    assert_eq!(token2.balance(&user1), 10_000);
+}
+
+#[test]
+fn swap_exact_out_on_skewed_pool_is_never_free() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &1_010);
+    token2.mint(&user1, &1_000_000_000);
+    pool.provide_liquidity(
+        &user1,
+        &Some(1_000),
+        &None,
+        &Some(1_000_000_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+
+    // 1_000 of token2 is worth 0.001 of token1, still the pool has to be paid 1
+    // offer amount = (1_000 * 1_000_000_000) / (1_000_000_000 - 1_000) - 1_000 = 0.001
+    assert_eq!(
+        pool.simulate_reverse_swap(&token2.address, &1_000)
+            .offer_amount,
+        1
+    );
+    for _ in 0..10 {
+        let pool_info = pool.query_pool_info();
+        let product_before = pool_info.asset_a.amount * pool_info.asset_b.amount;
+
+        assert_eq!(
+            pool.swap_exact_out(&user1, &token2.address, &1_000, &10, &None),
+            1
+        );
+
+        let pool_info = pool.query_pool_info();
+        assert!(pool_info.asset_a.amount * pool_info.asset_b.amount >= product_before);
+    }
+    assert_eq!(token1.balance(&user1), 0);
+    assert_eq!(token2.balance(&user1), 10_000);
+}
+
+#[test]
+fn swap_sends_the_return_amount_to_another_receiver() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let user1 = Address::generate(&env);
+    let receiver = Address::generate(&env);
+    let pool = deploy_liquidity_pool_contract(
+        &env,
+        None,
+        (&token1.address, &token2.address),
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &1_001_000);
+    token2.mint(&user1, &1_000_000);
+    pool.provide_liquidity(
+        &user1,
+        &Some(1_000_000),
+        &None,
+        &Some(1_000_000),
+        &None,
+        &None,
+        &false,
+        &None,
+    );
+
+    let return_amount = pool.swap(
+        &user1,
+        &None,
+        &token1.address,
+        &1_000,
+        &None,
+        &None,
+        &Some(receiver.clone()),
+    );
+
+    assert_eq!(return_amount, 1_000);
+    // the sender pays, while the receiver gets the bought tokens
+    assert_eq!(token1.balance(&user1), 0);
+    assert_eq!(token2.balance(&user1), 0);
+    assert_eq!(token1.balance(&receiver), 0);
+    assert_eq!(token2.balance(&receiver), return_amount);
+
+    // the same goes for buying an exact amount
+    token1.mint(&user1, &2_000);
+    let offer_amount = pool.swap_exact_out(
+        &user1,
+        &token2.address,
+        &1_000,
+        &2_000,
+        &Some(receiver.clone()),
+    );
+    assert_eq!(token1.balance(&user1), 2_000 - offer_amount);
+    assert_eq!(token2.balance(&user1), 0);
+    assert_eq!(token1.balance(&receiver), 0);
+    assert_eq!(token2.balance(&receiver), return_amount + 1_000);
+}
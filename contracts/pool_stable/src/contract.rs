@@ -1,20 +1,28 @@
-use phoenix::utils::LiquidityPoolInitInfo;
-use soroban_sdk::{contract, contractimpl, contractmeta, log, Address, BytesN, Env, IntoVal};
+use phoenix::utils::StablePoolInitInfo;
+use soroban_sdk::{
+    contract, contractimpl, contractmeta, log, Address, BytesN, Env, IntoVal, Symbol, Vec,
+};
 
 use crate::storage::utils::{is_initialized, set_initialized};
 use crate::storage::StableLiquidityPoolInfo;
 use crate::{
-    math::{calc_y, compute_current_amp, compute_d, AMP_PRECISION},
+    math::{
+        calc_y, calc_y_d, compute_current_amp, compute_d, denormalize, downscale, mul_div,
+        normalize, normalize_balances, upscale, AMP_PRECISION, MAX_AMP, MAX_AMP_CHANGE, MAX_COINS,
+        MIN_AMP_CHANGING_TIME, MIN_COINS, NORMALIZED_PRECISION, RATE_ONE,
+    },
     stake_contract,
     storage::{
-        get_amp, get_config, get_greatest_precision, save_amp, save_config,
-        save_greatest_precision, utils, validate_fee_bps, AmplifierParameters, Asset, Config,
-        PairType, PoolResponse, SimulateReverseSwapResponse, SimulateSwapResponse,
+        get_amp, get_cached_rates, get_config, get_greatest_precision, get_token_decimals,
+        save_amp, save_cached_rates, save_config, save_token_decimals, utils, validate_fee_bps,
+        AmpResponse, AmplifierParameters, Asset, CachedRates, Config, PairType, PoolResponse,
+        PoolStatus, ProvideLiquidityResponse, SimulateReverseSwapResponse, SimulateSwapResponse,
+        SimulateWithdrawSingleResponse,
     },
     token_contract,
 };
 use decimal::Decimal;
-use phoenix::{validate_bps, validate_int_parameters};
+use phoenix::{error::StablePoolError, validate_bps, validate_int_parameters};
 
 // Minimum amount of initial LP shares to mint
 const MINIMUM_LIQUIDITY_AMOUNT: i128 = 1000;
@@ -31,52 +39,111 @@ pub struct StableLiquidityPool;
 pub trait StableLiquidityPoolTrait {
     // Sets the token contract addresses for this pool
     // token_wasm_hash is the WASM hash of the deployed token contract for the pool share token
+    // factory_addr is the factory that deployed the pool, which can pause it next to the admin
     #[allow(clippy::too_many_arguments)]
     fn initialize(
         env: Env,
         stake_wasm_hash: BytesN<32>,
         token_wasm_hash: BytesN<32>,
         amp: u64,
-        lp_init_info: LiquidityPoolInitInfo,
-    );
-
-    // Deposits token_a and token_b. Also mints pool shares for the "to" Identifier. The amount minted
-    // is determined based on the difference between the reserves stored by this contract, and
-    // the actual balance of token_a and token_b for this contract.
+        init_info: StablePoolInitInfo,
+        factory_addr: Address,
+    ) -> Result<(), StablePoolError>;
+
+    // Deposits `desired_amounts` of every token of the pool, in the order of `Config::tokens`.
+    // Also mints pool shares for the "to" Identifier. The amount minted is determined based on
+    // the change of the stableswap invariant caused by the deposit.
+    // Except for the first deposit, some of the amounts can be zero; the imbalanced part of the
+    // deposit is charged a fee. Fails if that fee takes away more than `custom_slippage_bps` of
+    // the shares a fee free deposit would mint.
+    // If `auto_stake` is set, the minted shares are bonded in the stake contract on behalf of the depositor.
+    // Fails if fewer than `min_shares_out` shares would be minted.
+    // Returns the amount of shares minted and the amounts taken from the depositor.
     fn provide_liquidity(
         env: Env,
         depositor: Address,
-        desired_a: i128,
-        desired_b: i128,
+        desired_amounts: Vec<i128>,
         custom_slippage_bps: Option<i64>,
-    );
+        auto_stake: bool,
+        min_shares_out: Option<i128>,
+    ) -> Result<ProvideLiquidityResponse, StablePoolError>;
 
-    // `offer_asset` is the asset that the user would like to swap for the other token in the pool.
+    // `offer_asset` is the asset that the user would like to swap for the `ask_asset` of the pool.
     // `offer_amount` is the amount being sold, with `max_spread_bps` being a safety to make sure you receive at least that amount.
-    // swap will transfer the selling token "to" to this contract, and then the contract will transfer the buying token to `sender`.
+    // swap will transfer the selling token "to" to this contract, and then the contract will transfer the buying token to `to`,
+    // or back to `sender` if it is not set.
     // Returns the amount of the token being bought.
+    #[allow(clippy::too_many_arguments)]
     fn swap(
         env: Env,
         sender: Address,
         offer_asset: Address,
+        ask_asset: Address,
         offer_amount: i128,
         belief_price: Option<i64>,
         max_spread_bps: Option<i64>,
-    ) -> i128;
+        to: Option<Address>,
+    ) -> Result<i128, StablePoolError>;
+
+    // Buys exactly `ask_amount` of `ask_asset` for `offer_asset`, spending at most
+    // `max_offer_amount` of it. The bought tokens are sent to `to`, or back to `sender` if it is
+    // not set.
+    // Returns the amount of the token being sold.
+    #[allow(clippy::too_many_arguments)]
+    fn swap_exact_out(
+        env: Env,
+        sender: Address,
+        offer_asset: Address,
+        ask_asset: Address,
+        ask_amount: i128,
+        max_offer_amount: i128,
+        to: Option<Address>,
+    ) -> Result<i128, StablePoolError>;
 
     // transfers share_amount of pool share tokens to this contract, burns all pools share tokens in this contracts, and sends the
-    // corresponding amount of token_a and token_b to "to".
-    // Returns amount of both tokens withdrawn
+    // corresponding amount of every token of the pool to "to".
+    // Returns amount of every token withdrawn, in the order of `Config::tokens`
     fn withdraw_liquidity(
         env: Env,
         recipient: Address,
         share_amount: i128,
-        min_a: i128,
-        min_b: i128,
-    ) -> (i128, i128);
+        min_amounts: Vec<i128>,
+    ) -> Result<Vec<i128>, StablePoolError>;
+
+    // Unbonds the stake of `stake_amount` shares made at `stake_timestamp` from the stake contract and
+    // withdraws the liquidity in one call.
+    // Returns amount of every token withdrawn, in the order of `Config::tokens`
+    fn unstake_and_withdraw(
+        env: Env,
+        sender: Address,
+        stake_amount: i128,
+        stake_timestamp: u64,
+        min_amounts: Vec<i128>,
+    ) -> Result<Vec<i128>, StablePoolError>;
+
+    // Withdraws the liquidity of `share_amount` shares into `out_asset` only, keeping the
+    // invariant of the pool like `remove_liquidity_one_coin` of Curve.
+    // Returns the amount of `out_asset` sent to the sender.
+    fn withdraw_liquidity_single(
+        env: Env,
+        sender: Address,
+        share_amount: i128,
+        out_asset: Address,
+        min_out: i128,
+    ) -> Result<i128, StablePoolError>;
+
+    // Sets the stored reserves to the actual token balances of the pool, so that the tokens sent
+    // directly to the pool go to the liquidity providers. Can only be called by the admin.
+    fn sync(env: Env, sender: Address) -> Result<(), StablePoolError>;
+
+    // Sends the token balances of the pool above the stored reserves to `to`. Can only be called by
+    // the admin.
+    // Returns the amounts sent, in the order of `Config::tokens`
+    fn skim(env: Env, sender: Address, to: Address) -> Result<Vec<i128>, StablePoolError>;
 
     // Allows admin address set during initialization to change some parameters of the
     // configuration
+    #[allow(clippy::too_many_arguments)]
     fn update_config(
         env: Env,
         sender: Address,
@@ -85,7 +152,30 @@ pub trait StableLiquidityPoolTrait {
         fee_recipient: Option<Address>,
         max_allowed_slippage_bps: Option<i64>,
         max_allowed_spread_bps: Option<i64>,
-    );
+        protocol_fee_share_bps: Option<i64>,
+        distribute_lp_fees_to_stakers: Option<bool>,
+        max_rate_staleness: Option<u64>,
+    ) -> Result<(), StablePoolError>;
+
+    // Allows admin address set during initialization to start ramping the amplification
+    // coefficient towards `next_amp`, reaching it linearly at `next_amp_time`. A ramp can only
+    // start a day after the previous one started, even if that one was stopped
+    fn start_change_amp(env: Env, next_amp: u64, next_amp_time: u64)
+        -> Result<(), StablePoolError>;
+
+    // Allows admin address set during initialization to stop the ongoing amplification
+    // coefficient ramp, freezing it at its current value
+    fn stop_change_amp(env: Env);
+
+    // Pauses or resumes the swaps, deposits and withdrawals of the pool. Can be called by the admin
+    // or by the factory of the pool. The flags that are not set keep their value.
+    fn update_pause_status(
+        env: Env,
+        sender: Address,
+        swaps_paused: Option<bool>,
+        deposits_paused: Option<bool>,
+        withdrawals_paused: Option<bool>,
+    ) -> Result<(), StablePoolError>;
 
     // Migration entrypoint
     fn upgrade(e: Env, new_wasm_hash: BytesN<32>);
@@ -101,20 +191,59 @@ pub trait StableLiquidityPoolTrait {
     // Returns the address for the pool stake contract
     fn query_stake_contract_address(env: Env) -> Address;
 
+    // Returns which of the pool operations are paused
+    fn query_status(env: Env) -> PoolStatus;
+
+    // Returns the differences between the token balances of the pool and the stored reserves, in
+    // the order of `Config::tokens`. Positive differences can be skimmed, negative ones are removed
+    // by sync.
+    fn query_reserve_discrepancy(env: Env) -> Vec<i128>;
+
     // Returns  the total amount of LP tokens and assets in a specific pool
     fn query_pool_info(env: Env) -> PoolResponse;
 
     fn query_pool_info_for_factory(env: Env) -> StableLiquidityPoolInfo;
 
+    // Returns the current amplification coefficient and the ramp target
+    fn query_amp(env: Env) -> AmpResponse;
+
+    // Returns the rate of every token used by the invariant, with 18 decimals, in the order of
+    // `Config::tokens`; fetches them from the rate providers if the cached rates are stale
+    fn query_rates(env: Env) -> Result<Vec<i128>, StablePoolError>;
+
     // Simulate swap transaction
-    fn simulate_swap(env: Env, offer_asset: Address, sell_amount: i128) -> SimulateSwapResponse;
+    fn simulate_swap(
+        env: Env,
+        offer_asset: Address,
+        ask_asset: Address,
+        sell_amount: i128,
+    ) -> Result<SimulateSwapResponse, StablePoolError>;
 
     // Simulate reverse swap transaction
     fn simulate_reverse_swap(
         env: Env,
         offer_asset: Address,
+        ask_asset: Address,
         ask_amount: i128,
-    ) -> SimulateReverseSwapResponse;
+    ) -> Result<SimulateReverseSwapResponse, StablePoolError>;
+
+    // Simulate providing liquidity, returning the shares that would be minted and the amounts taken
+    // from the depositor
+    fn simulate_provide_liquidity(
+        env: Env,
+        desired_amounts: Vec<i128>,
+    ) -> Result<ProvideLiquidityResponse, StablePoolError>;
+
+    // Simulate withdrawing the liquidity of `share_amount` shares, returning the amounts of every
+    // token that would be sent back, in the order of `Config::tokens`
+    fn simulate_withdraw_liquidity(env: Env, share_amount: i128) -> Vec<i128>;
+
+    // Simulate withdrawing the liquidity of `share_amount` shares into `out_asset` only
+    fn simulate_withdraw_single(
+        env: Env,
+        share_amount: i128,
+        out_asset: Address,
+    ) -> Result<SimulateWithdrawSingleResponse, StablePoolError>;
 }
 
 #[contractimpl]
@@ -125,54 +254,88 @@ impl StableLiquidityPoolTrait for StableLiquidityPool {
         stake_wasm_hash: BytesN<32>,
         token_wasm_hash: BytesN<32>,
         amp: u64,
-        lp_init_info: LiquidityPoolInitInfo,
-    ) {
+        init_info: StablePoolInitInfo,
+        factory_addr: Address,
+    ) -> Result<(), StablePoolError> {
         if is_initialized(&env) {
-            panic!("Pool stable: Initialize: initializing contract twice is not allowed");
+            log!(
+                &env,
+                "Pool stable: Initialize: initializing contract twice is not allowed"
+            );
+            return Err(StablePoolError::AlreadyInitialized);
+        }
+
+        if amp == 0 || amp > MAX_AMP {
+            log!(&env, "Amp must be between 1 and {}", MAX_AMP);
+            return Err(StablePoolError::InvalidAmp);
         }
 
-        let admin = lp_init_info.admin;
-        let share_token_decimals = lp_init_info.share_token_decimals;
-        let swap_fee_bps = lp_init_info.swap_fee_bps;
-        let fee_recipient = lp_init_info.fee_recipient;
-        let max_allowed_slippage_bps = lp_init_info.max_allowed_slippage_bps;
-        let max_allowed_spread_bps = lp_init_info.max_allowed_spread_bps;
-        let token_init_info = lp_init_info.token_init_info;
-        let stake_init_info = lp_init_info.stake_init_info;
+        let admin = init_info.admin;
+        let share_token_decimals = init_info.share_token_decimals;
+        let swap_fee_bps = init_info.swap_fee_bps;
+        let fee_recipient = init_info.fee_recipient;
+        let max_allowed_slippage_bps = init_info.max_allowed_slippage_bps;
+        let max_allowed_spread_bps = init_info.max_allowed_spread_bps;
+        let tokens = init_info.tokens;
+        let rate_providers = init_info.rate_providers;
+        let stake_init_info = init_info.stake_init_info;
 
         validate_bps!(
             swap_fee_bps,
             max_allowed_slippage_bps,
             max_allowed_spread_bps
-        );
+        )?;
         set_initialized(&env);
 
-        // Token info
-        let token_a = token_init_info.token_a;
-        let token_b = token_init_info.token_b;
         // Contract info
         let min_bond = stake_init_info.min_bond;
         let max_distributions = stake_init_info.max_distributions;
         let min_reward = stake_init_info.min_reward;
 
+        if tokens.len() < MIN_COINS || tokens.len() > MAX_COINS {
+            log!(
+                &env,
+                "Stable pool must have between {} and {} tokens",
+                MIN_COINS,
+                MAX_COINS
+            );
+            return Err(StablePoolError::InvalidNumberOfTokens);
+        }
+
         // Token order validation to make sure only one instance of a pool can exist
-        if token_a >= token_b {
-            log!(&env, "token_a must be less than token_b");
-            panic!(
-                "Pool: Initialize: First token must be alphabetically smaller than second token"
+        for (previous, next) in tokens.iter().zip(tokens.iter().skip(1)) {
+            if previous >= next {
+                log!(&env, "tokens must be sorted alphabetically and unique");
+                return Err(StablePoolError::TokensNotSorted);
+            }
+        }
+
+        if !rate_providers.is_empty() && rate_providers.len() != tokens.len() {
+            log!(
+                &env,
+                "Expected no rate providers or one per token, got {}",
+                rate_providers.len()
             );
+            return Err(StablePoolError::InvalidRateProviders);
         }
 
-        save_greatest_precision(&env, &token_a, &token_b);
+        save_token_decimals(&env, &tokens);
+        if get_greatest_precision(&env) > NORMALIZED_PRECISION {
+            log!(
+                &env,
+                "Tokens can have at most {} decimals",
+                NORMALIZED_PRECISION
+            );
+            return Err(StablePoolError::TokenPrecisionTooBig);
+        }
 
         if !(0..=10_000).contains(&swap_fee_bps) {
             log!(&env, "Fees must be between 0 and 100%");
-            panic!("Pool: Initialize: Fees must be between 0 and 100%");
+            return Err(StablePoolError::InvalidBps);
         }
 
         // deploy token contract
-        let share_token_address =
-            utils::deploy_token_contract(&env, token_wasm_hash, &token_a, &token_b);
+        let share_token_address = utils::deploy_token_contract(&env, token_wasm_hash, &tokens);
         token_contract::Client::new(&env, &share_token_address).initialize(
             // admin
             &env.current_contract_address(),
@@ -193,16 +356,25 @@ impl StableLiquidityPoolTrait for StableLiquidityPool {
             &min_reward,
         );
 
+        let mut pool_balances = Vec::new(&env);
+        for _ in tokens.iter() {
+            pool_balances.push_back(0i128);
+        }
+
         let config = Config {
-            token_a: token_a.clone(),
-            token_b: token_b.clone(),
+            tokens: tokens.clone(),
             share_token: share_token_address,
             stake_contract: stake_contract_address,
-            pool_type: PairType::Xyk,
-            total_fee_bps: validate_fee_bps(&env, swap_fee_bps),
+            pool_type: PairType::Stable,
+            total_fee_bps: validate_fee_bps(&env, swap_fee_bps)?,
             fee_recipient,
             max_allowed_slippage_bps,
             max_allowed_spread_bps,
+            // the whole commission goes to the fee recipient until the admin changes it
+            protocol_fee_share_bps: 10_000,
+            distribute_lp_fees_to_stakers: false,
+            rate_providers,
+            max_rate_staleness: init_info.max_rate_staleness,
         };
         save_config(&env, config);
         let current_time = env.ledger().timestamp();
@@ -216,197 +388,330 @@ impl StableLiquidityPoolTrait for StableLiquidityPool {
             },
         );
         utils::save_admin(&env, admin);
+        utils::save_factory(&env, factory_addr);
         utils::save_total_shares(&env, 0);
-        utils::save_pool_balance_a(&env, 0);
-        utils::save_pool_balance_b(&env, 0);
+        utils::save_pool_balances(&env, &pool_balances);
 
         env.events()
-            .publish(("initialize", "XYK LP token_a"), token_a);
-        env.events()
-            .publish(("initialize", "XYK LP token_b"), token_b);
+            .publish(("initialize", "Stable LP tokens"), tokens);
+
+        Ok(())
     }
 
     fn provide_liquidity(
         env: Env,
         sender: Address,
-        desired_a: i128,
-        desired_b: i128,
+        desired_amounts: Vec<i128>,
         custom_slippage_bps: Option<i64>,
-    ) {
-        validate_int_parameters!(desired_a, desired_b);
+        auto_stake: bool,
+        min_shares_out: Option<i128>,
+    ) -> Result<ProvideLiquidityResponse, StablePoolError> {
+        validate_int_parameters!(min_shares_out)?;
 
         // sender needs to authorize the deposit
         sender.require_auth();
 
+        if utils::get_status(&env).deposits_paused {
+            log!(&env, "Pool stable: ProvideLiquidity: Deposits are paused");
+            return Err(StablePoolError::DepositsPaused);
+        }
+
         let config = get_config(&env);
-        let greatest_precision = get_greatest_precision(&env);
-        let old_balance_a = utils::get_pool_balance_a(&env);
-        let old_balance_b = utils::get_pool_balance_b(&env);
 
         // Check if custom_slippage_bps is more than max_allowed_slippage
         if let Some(custom_slippage) = custom_slippage_bps {
             if custom_slippage > config.max_allowed_slippage_bps {
-                panic!("Pool: ProvideLiquidity: Custom slippage tolerance is more than max allowed slippage tolerance");
+                log!(
+                    &env,
+                    "Pool: ProvideLiquidity: Custom slippage tolerance is more than max allowed slippage tolerance"
+                );
+                return Err(StablePoolError::SlippageToleranceExceedsMax);
             }
         }
 
-        let amp_parameters = get_amp(&env).unwrap(); // FIXME: This is minor, but add some
-                                                     // validation to AMP parameters
-        let amp = compute_current_amp(&env, &amp_parameters);
+        let (shares, fees) = compute_provide_liquidity(
+            &env,
+            &config,
+            &utils::get_pool_balances(&env),
+            &refresh_rates(&env, &config)?,
+            utils::get_total_shares(&env),
+            &desired_amounts,
+            custom_slippage_bps,
+        )?;
+        if let Some(min_shares_out) = min_shares_out {
+            if shares < min_shares_out {
+                log!(
+                    &env,
+                    "Pool stable: ProvideLiquidity: Minimum amount of shares is not satisfied! min_shares_out: {}, shares: {}",
+                    min_shares_out,
+                    shares
+                );
+                return Err(StablePoolError::MinSharesNotSatisfied);
+            }
+        }
 
-        // Invariant (D) after deposit added
-        let new_balance_a = desired_a + old_balance_a;
-        let new_balance_b = desired_b + old_balance_b;
-        let new_invariant = compute_d(
-            amp as u128,
-            &[
-                Decimal::from_atomics(new_balance_a, 6),
-                Decimal::from_atomics(new_balance_b, 6),
-            ],
-        );
+        // Move tokens from client's wallet to the contract
+        for (token, desired_amount) in config.tokens.iter().zip(desired_amounts.iter()) {
+            token_contract::Client::new(&env, &token).transfer(
+                &sender,
+                &env.current_contract_address(),
+                &desired_amount,
+            );
+        }
 
-        let total_shares = utils::get_total_shares(&env);
-        let shares = if total_shares == 0 {
-            let share =
-                new_invariant.to_i128_with_precision(greatest_precision) - MINIMUM_LIQUIDITY_AMOUNT;
-            if share == 0 {
-                panic!("Pool: ProvideLiquidity: Liquidity amount is too low");
+        // split the fees between the protocol and the liquidity providers like the swap commission
+        for (token, fee) in config.tokens.iter().zip(fees.iter()) {
+            if fee == 0 {
+                continue;
             }
-            share
-        } else {
-            let initial_invariant = compute_d(
-                amp as u128,
-                &[
-                    Decimal::from_atomics(old_balance_a, 6),
-                    Decimal::from_atomics(old_balance_b, 6),
-                ],
+            let protocol_fee_amount = fee * config.protocol_fee_share();
+            let lp_fee_amount = fee - protocol_fee_amount;
+            let token_client = token_contract::Client::new(&env, &token);
+            token_client.transfer(
+                &env.current_contract_address(),
+                &config.fee_recipient,
+                &protocol_fee_amount,
             );
-            // Calculate the proportion of the change in invariant
-            (Decimal::from_ratio((new_invariant - initial_invariant) * total_shares, 1)
-                / initial_invariant)
-                .to_i128_with_precision(greatest_precision)
-        };
-
-        let token_a_client = token_contract::Client::new(&env, &config.token_a);
-        let token_b_client = token_contract::Client::new(&env, &config.token_b);
-
-        // Move tokens from client's wallet to the contract
-        token_a_client.transfer(&sender, &env.current_contract_address(), &(desired_a));
-        token_b_client.transfer(&sender, &env.current_contract_address(), &(desired_b));
+            if config.distribute_lp_fees_to_stakers && lp_fee_amount > 0 {
+                token_client.transfer(
+                    &env.current_contract_address(),
+                    &config.stake_contract,
+                    &lp_fee_amount,
+                );
+            }
+        }
 
         // Now calculate how many new pool shares to mint
-        let balance_a = utils::get_balance(&env, &config.token_a);
-        let balance_b = utils::get_balance(&env, &config.token_b);
+        let mut balances = Vec::new(&env);
+        for token in config.tokens.iter() {
+            balances.push_back(utils::get_balance(&env, &token));
+        }
 
         utils::mint_shares(&env, &config.share_token, &sender, shares);
-        utils::save_pool_balance_a(&env, balance_a);
-        utils::save_pool_balance_b(&env, balance_b);
+        utils::save_pool_balances(&env, &balances);
+
+        if auto_stake {
+            stake_contract::Client::new(&env, &config.stake_contract).bond(&sender, &shares);
+        }
 
         env.events()
             .publish(("provide_liquidity", "sender"), sender);
         env.events()
-            .publish(("provide_liquidity", "token_a"), &config.token_a);
-        env.events()
-            .publish(("provide_liquidity", "token_a-amount"), desired_a);
+            .publish(("provide_liquidity", "tokens"), config.tokens);
         env.events()
-            .publish(("provide_liquidity", "token_b"), &config.token_b);
+            .publish(("provide_liquidity", "amounts"), desired_amounts.clone());
+        env.events().publish(("provide_liquidity", "fees"), fees);
         env.events()
-            .publish(("provide_liquidity", "token_b-amount"), desired_b);
+            .publish(("provide_liquidity", "shares_amount"), shares);
+
+        Ok(ProvideLiquidityResponse {
+            shares,
+            amounts: desired_amounts,
+        })
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn swap(
         env: Env,
         sender: Address,
         offer_asset: Address,
+        ask_asset: Address,
         offer_amount: i128,
         belief_price: Option<i64>,
         max_spread_bps: Option<i64>,
-    ) -> i128 {
-        validate_int_parameters!(offer_amount);
+        to: Option<Address>,
+    ) -> Result<i128, StablePoolError> {
+        validate_int_parameters!(offer_amount)?;
 
         sender.require_auth();
 
+        if utils::get_status(&env).swaps_paused {
+            log!(&env, "Pool stable: Swap: Swaps are paused");
+            return Err(StablePoolError::SwapsPaused);
+        }
+
+        let receiver = to.unwrap_or_else(|| sender.clone());
         do_swap(
             env,
             sender,
+            receiver,
             offer_asset,
+            ask_asset,
             offer_amount,
             belief_price,
             max_spread_bps,
         )
     }
 
+    #[allow(clippy::too_many_arguments)]
+    fn swap_exact_out(
+        env: Env,
+        sender: Address,
+        offer_asset: Address,
+        ask_asset: Address,
+        ask_amount: i128,
+        max_offer_amount: i128,
+        to: Option<Address>,
+    ) -> Result<i128, StablePoolError> {
+        validate_int_parameters!(ask_amount, max_offer_amount)?;
+
+        sender.require_auth();
+
+        if utils::get_status(&env).swaps_paused {
+            log!(&env, "Pool stable: SwapExactOut: Swaps are paused");
+            return Err(StablePoolError::SwapsPaused);
+        }
+
+        let receiver = to.unwrap_or_else(|| sender.clone());
+        do_swap_exact_out(
+            env,
+            sender,
+            receiver,
+            offer_asset,
+            ask_asset,
+            ask_amount,
+            max_offer_amount,
+        )
+    }
+
     fn withdraw_liquidity(
         env: Env,
         sender: Address,
         share_amount: i128,
-        min_a: i128,
-        min_b: i128,
-    ) -> (i128, i128) {
-        validate_int_parameters!(share_amount, min_a, min_b);
+        min_amounts: Vec<i128>,
+    ) -> Result<Vec<i128>, StablePoolError> {
+        validate_int_parameters!(share_amount)?;
+        for min_amount in min_amounts.iter() {
+            validate_int_parameters!(min_amount)?;
+        }
 
         sender.require_auth();
 
-        let config = get_config(&env);
-
-        let share_token_client = token_contract::Client::new(&env, &config.share_token);
-        share_token_client.transfer(&sender, &env.current_contract_address(), &share_amount);
+        if utils::get_status(&env).withdrawals_paused {
+            log!(
+                &env,
+                "Pool stable: WithdrawLiquidity: Withdrawals are paused"
+            );
+            return Err(StablePoolError::WithdrawalsPaused);
+        }
 
-        let pool_balance_a = utils::get_pool_balance_a(&env);
-        let pool_balance_b = utils::get_pool_balance_b(&env);
+        do_withdraw_liquidity(env, sender, share_amount, min_amounts)
+    }
 
-        let mut share_ratio = Decimal::zero();
-        let total_shares = utils::get_total_shares(&env);
-        if total_shares != 0i128 {
-            share_ratio = Decimal::from_ratio(share_amount, total_shares);
+    fn unstake_and_withdraw(
+        env: Env,
+        sender: Address,
+        stake_amount: i128,
+        stake_timestamp: u64,
+        min_amounts: Vec<i128>,
+    ) -> Result<Vec<i128>, StablePoolError> {
+        validate_int_parameters!(stake_amount)?;
+        for min_amount in min_amounts.iter() {
+            validate_int_parameters!(min_amount)?;
         }
 
-        let return_amount_a = pool_balance_a * share_ratio;
-        let return_amount_b = pool_balance_b * share_ratio;
+        sender.require_auth();
 
-        if return_amount_a < min_a || return_amount_b < min_b {
+        if utils::get_status(&env).withdrawals_paused {
             log!(
                 &env,
-                "Minimum amount of token_a or token_b is not satisfied! min_a: {}, min_b: {}, return_amount_a: {}, return_amount_b: {}",
-                min_a,
-                min_b,
-                return_amount_a,
-                return_amount_b
+                "Pool stable: UnstakeAndWithdraw: Withdrawals are paused"
             );
-            panic!(
-                "Pool: WithdrawLiquidity: Minimum amount of token_a or token_b is not satisfied!"
-            )
+            return Err(StablePoolError::WithdrawalsPaused);
         }
 
-        // burn shares
-        utils::burn_shares(&env, &config.share_token, share_amount);
-        // transfer tokens from sender to contract
-        token_contract::Client::new(&env, &config.token_a).transfer(
-            &env.current_contract_address(),
-            &sender,
-            &return_amount_a,
-        );
-        token_contract::Client::new(&env, &config.token_b).transfer(
-            &env.current_contract_address(),
+        let config = get_config(&env);
+        stake_contract::Client::new(&env, &config.stake_contract).unbond(
             &sender,
-            &return_amount_b,
+            &stake_amount,
+            &stake_timestamp,
         );
-        // update pool balances
-        utils::save_pool_balance_a(&env, pool_balance_a - return_amount_a);
-        utils::save_pool_balance_b(&env, pool_balance_b - return_amount_b);
 
+        do_withdraw_liquidity(env, sender, stake_amount, min_amounts)
+    }
+
+    fn withdraw_liquidity_single(
+        env: Env,
+        sender: Address,
+        share_amount: i128,
+        out_asset: Address,
+        min_out: i128,
+    ) -> Result<i128, StablePoolError> {
+        validate_int_parameters!(share_amount, min_out)?;
+
+        sender.require_auth();
+
+        let status = utils::get_status(&env);
+        if status.withdrawals_paused {
+            log!(
+                &env,
+                "Pool stable: WithdrawLiquiditySingle: Withdrawals are paused"
+            );
+            return Err(StablePoolError::WithdrawalsPaused);
+        }
+        // an imbalanced withdrawal moves the price like a swap, so the swaps have to be open as well
+        if status.swaps_paused {
+            log!(
+                &env,
+                "Pool stable: WithdrawLiquiditySingle: Swaps are paused"
+            );
+            return Err(StablePoolError::SwapsPaused);
+        }
+
+        do_withdraw_liquidity_single(env, sender, share_amount, out_asset, min_out)
+    }
+
+    fn sync(env: Env, sender: Address) -> Result<(), StablePoolError> {
+        sender.require_auth();
+
+        if sender != utils::get_admin(&env) {
+            log!(&env, "Pool stable: Sync: Unauthorized");
+            return Err(StablePoolError::Unauthorized);
+        }
+
+        let config = get_config(&env);
+        let mut balances = Vec::new(&env);
+        for token in config.tokens.iter() {
+            balances.push_back(utils::get_balance(&env, &token));
+        }
+        utils::save_pool_balances(&env, &balances);
+
+        env.events().publish(("sync", "reserves"), balances);
+
+        Ok(())
+    }
+
+    fn skim(env: Env, sender: Address, to: Address) -> Result<Vec<i128>, StablePoolError> {
+        sender.require_auth();
+
+        if sender != utils::get_admin(&env) {
+            log!(&env, "Pool stable: Skim: Unauthorized");
+            return Err(StablePoolError::Unauthorized);
+        }
+
+        let config = get_config(&env);
+        let pool_balances = utils::get_pool_balances(&env);
+        let mut excess_amounts = Vec::new(&env);
+        for (token, pool_balance) in config.tokens.iter().zip(pool_balances.iter()) {
+            let excess = (utils::get_balance(&env, &token) - pool_balance).max(0);
+            if excess > 0 {
+                token_contract::Client::new(&env, &token).transfer(
+                    &env.current_contract_address(),
+                    &to,
+                    &excess,
+                );
+            }
+            excess_amounts.push_back(excess);
+        }
+
+        env.events().publish(("skim", "to"), to);
         env.events()
-            .publish(("withdraw_liquidity", "sender"), sender);
-        env.events()
-            .publish(("withdraw_liquidity", "shares_amount"), share_amount);
-        env.events()
-            .publish(("withdraw_liquidity", "return_amount_a"), return_amount_a);
-        env.events()
-            .publish(("withdraw_liquidity", "return_amount_b"), return_amount_b);
+            .publish(("skim", "amounts"), excess_amounts.clone());
 
-        (return_amount_a, return_amount_b)
+        Ok(excess_amounts)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn update_config(
         env: Env,
         sender: Address,
@@ -415,9 +720,15 @@ impl StableLiquidityPoolTrait for StableLiquidityPool {
         fee_recipient: Option<Address>,
         max_allowed_slippage_bps: Option<i64>,
         max_allowed_spread_bps: Option<i64>,
-    ) {
+        protocol_fee_share_bps: Option<i64>,
+        distribute_lp_fees_to_stakers: Option<bool>,
+        max_rate_staleness: Option<u64>,
+    ) -> Result<(), StablePoolError> {
+        sender.require_auth();
+
         if sender != utils::get_admin(&env) {
-            panic!("Pool: UpdateConfig: Unauthorized");
+            log!(&env, "Pool: UpdateConfig: Unauthorized");
+            return Err(StablePoolError::Unauthorized);
         }
 
         let mut config = get_config(&env);
@@ -427,7 +738,8 @@ impl StableLiquidityPoolTrait for StableLiquidityPool {
         }
         if let Some(total_fee_bps) = total_fee_bps {
             if !(0..=10_000).contains(&total_fee_bps) {
-                panic!("Pool: UpdateConfig: Invalid total_fee_bps");
+                log!(&env, "Pool: UpdateConfig: Invalid total_fee_bps");
+                return Err(StablePoolError::InvalidBps);
             }
             config.total_fee_bps = total_fee_bps;
         }
@@ -440,8 +752,159 @@ impl StableLiquidityPoolTrait for StableLiquidityPool {
         if let Some(max_allowed_spread_bps) = max_allowed_spread_bps {
             config.max_allowed_spread_bps = max_allowed_spread_bps;
         }
+        if let Some(protocol_fee_share_bps) = protocol_fee_share_bps {
+            if !(0..=10_000).contains(&protocol_fee_share_bps) {
+                log!(&env, "Pool: UpdateConfig: Invalid protocol_fee_share_bps");
+                return Err(StablePoolError::InvalidBps);
+            }
+            config.protocol_fee_share_bps = protocol_fee_share_bps;
+        }
+        if let Some(distribute_lp_fees_to_stakers) = distribute_lp_fees_to_stakers {
+            config.distribute_lp_fees_to_stakers = distribute_lp_fees_to_stakers;
+        }
+        if let Some(max_rate_staleness) = max_rate_staleness {
+            config.max_rate_staleness = max_rate_staleness;
+        }
 
         save_config(&env, config);
+
+        Ok(())
+    }
+
+    fn start_change_amp(
+        env: Env,
+        next_amp: u64,
+        next_amp_time: u64,
+    ) -> Result<(), StablePoolError> {
+        let admin: Address = utils::get_admin(&env);
+        admin.require_auth();
+
+        if next_amp == 0 || next_amp > MAX_AMP {
+            log!(&env, "Amp must be between 1 and {}", MAX_AMP);
+            return Err(StablePoolError::InvalidAmp);
+        }
+
+        let amp_params = get_amp(&env).unwrap();
+        let current_time = env.ledger().timestamp();
+        // The amp set at initialization has the same init and next time; any ramp, even a
+        // stopped one, keeps its start time in `init_amp_time` and can't be restarted until
+        // `MIN_AMP_CHANGING_TIME` has passed since then
+        if amp_params.init_amp_time != amp_params.next_amp_time
+            && current_time < amp_params.init_amp_time + MIN_AMP_CHANGING_TIME
+        {
+            log!(
+                &env,
+                "Amp ramps must start at least {} seconds apart",
+                MIN_AMP_CHANGING_TIME
+            );
+            return Err(StablePoolError::AmpChangeTooSoon);
+        }
+        if next_amp_time < current_time + MIN_AMP_CHANGING_TIME {
+            log!(
+                &env,
+                "Amp ramp must last at least {} seconds",
+                MIN_AMP_CHANGING_TIME
+            );
+            return Err(StablePoolError::AmpChangeTimeTooShort);
+        }
+
+        let current_amp = compute_current_amp(&env, &amp_params);
+        let next_amp = next_amp * AMP_PRECISION;
+
+        // Amp can be increased or decreased at most by the MAX_AMP_CHANGE factor in one ramp
+        if (next_amp > current_amp && next_amp > current_amp * MAX_AMP_CHANGE)
+            || (next_amp < current_amp && next_amp * MAX_AMP_CHANGE < current_amp)
+        {
+            log!(
+                &env,
+                "Amp can change at most {} times in one ramp",
+                MAX_AMP_CHANGE
+            );
+            return Err(StablePoolError::AmpChangeTooBig);
+        }
+
+        save_amp(
+            &env,
+            AmplifierParameters {
+                init_amp: current_amp,
+                init_amp_time: current_time,
+                next_amp,
+                next_amp_time,
+            },
+        );
+
+        env.events().publish(
+            ("start_change_amp", "init_amp"),
+            current_amp / AMP_PRECISION,
+        );
+        env.events()
+            .publish(("start_change_amp", "next_amp"), next_amp / AMP_PRECISION);
+        env.events()
+            .publish(("start_change_amp", "next_amp_time"), next_amp_time);
+
+        Ok(())
+    }
+
+    fn stop_change_amp(env: Env) {
+        let admin: Address = utils::get_admin(&env);
+        admin.require_auth();
+
+        let amp_params = get_amp(&env).unwrap();
+        let current_amp = compute_current_amp(&env, &amp_params);
+        let current_time = env.ledger().timestamp();
+
+        // the start of the stopped ramp is kept, so that stopping does not allow a new ramp
+        // sooner than `start_change_amp` would
+        save_amp(
+            &env,
+            AmplifierParameters {
+                init_amp: current_amp,
+                init_amp_time: amp_params.init_amp_time,
+                next_amp: current_amp,
+                next_amp_time: current_time,
+            },
+        );
+
+        env.events().publish(
+            ("stop_change_amp", "current_amp"),
+            current_amp / AMP_PRECISION,
+        );
+    }
+
+    fn update_pause_status(
+        env: Env,
+        sender: Address,
+        swaps_paused: Option<bool>,
+        deposits_paused: Option<bool>,
+        withdrawals_paused: Option<bool>,
+    ) -> Result<(), StablePoolError> {
+        sender.require_auth();
+
+        if sender != utils::get_admin(&env) && sender != utils::get_factory(&env) {
+            log!(
+                &env,
+                "Pool stable: UpdatePauseStatus: Only the admin or the factory can pause the pool"
+            );
+            return Err(StablePoolError::Unauthorized);
+        }
+
+        let mut status = utils::get_status(&env);
+        if let Some(swaps_paused) = swaps_paused {
+            status.swaps_paused = swaps_paused;
+            env.events().publish(("pause", "swaps"), swaps_paused);
+        }
+        if let Some(deposits_paused) = deposits_paused {
+            status.deposits_paused = deposits_paused;
+            env.events().publish(("pause", "deposits"), deposits_paused);
+        }
+        if let Some(withdrawals_paused) = withdrawals_paused {
+            status.withdrawals_paused = withdrawals_paused;
+            env.events()
+                .publish(("pause", "withdrawals"), withdrawals_paused);
+        }
+        utils::save_status(&env, &status);
+
+        Ok(())
     }
 
     fn upgrade(env: Env, new_wasm_hash: BytesN<32>) {
@@ -465,18 +928,32 @@ impl StableLiquidityPoolTrait for StableLiquidityPool {
         get_config(&env).stake_contract
     }
 
+    fn query_status(env: Env) -> PoolStatus {
+        utils::get_status(&env)
+    }
+
+    fn query_reserve_discrepancy(env: Env) -> Vec<i128> {
+        let config = get_config(&env);
+        let pool_balances = utils::get_pool_balances(&env);
+
+        let mut discrepancies = Vec::new(&env);
+        for (token, pool_balance) in config.tokens.iter().zip(pool_balances.iter()) {
+            discrepancies.push_back(utils::get_balance(&env, &token) - pool_balance);
+        }
+        discrepancies
+    }
+
     fn query_pool_info(env: Env) -> PoolResponse {
         let config = get_config(&env);
+        let pool_balances = utils::get_pool_balances(&env);
+
+        let mut assets = Vec::new(&env);
+        for (address, amount) in config.tokens.iter().zip(pool_balances.iter()) {
+            assets.push_back(Asset { address, amount });
+        }
 
         PoolResponse {
-            asset_a: Asset {
-                address: config.token_a,
-                amount: utils::get_pool_balance_a(&env),
-            },
-            asset_b: Asset {
-                address: config.token_b,
-                amount: utils::get_pool_balance_b(&env),
-            },
+            assets,
             asset_lp_share: Asset {
                 address: config.share_token,
                 amount: utils::get_total_shares(&env),
@@ -486,182 +963,587 @@ impl StableLiquidityPoolTrait for StableLiquidityPool {
 
     fn query_pool_info_for_factory(env: Env) -> StableLiquidityPoolInfo {
         let config = get_config(&env);
-        let pool_response = PoolResponse {
-            asset_a: Asset {
-                address: config.token_a,
-                amount: utils::get_pool_balance_a(&env),
-            },
-            asset_b: Asset {
-                address: config.token_b,
-                amount: utils::get_pool_balance_b(&env),
-            },
-            asset_lp_share: Asset {
-                address: config.share_token,
-                amount: utils::get_total_shares(&env),
-            },
-        };
-        let total_fee_bps = config.max_allowed_spread_bps;
+        let pool_response = Self::query_pool_info(env.clone());
+        let total_fee_bps = config.total_fee_bps;
+        let amp_parameters = get_amp(&env).unwrap();
 
         StableLiquidityPoolInfo {
             pool_address: env.current_contract_address(),
             pool_response,
             total_fee_bps,
+            amp_parameters,
         }
     }
 
-    fn simulate_swap(env: Env, offer_asset: Address, offer_amount: i128) -> SimulateSwapResponse {
-        let config = get_config(&env);
+    fn query_amp(env: Env) -> AmpResponse {
+        let amp_parameters = get_amp(&env).unwrap();
 
-        let pool_balance_a = utils::get_pool_balance_a(&env);
-        let pool_balance_b = utils::get_pool_balance_b(&env);
-        let (pool_balance_offer, pool_balance_ask) = if offer_asset == config.token_a {
-            (pool_balance_a, pool_balance_b)
-        } else {
-            (pool_balance_b, pool_balance_a)
-        };
+        AmpResponse {
+            current_amp: compute_current_amp(&env, &amp_parameters) / AMP_PRECISION,
+            next_amp: amp_parameters.next_amp / AMP_PRECISION,
+            next_amp_time: amp_parameters.next_amp_time,
+        }
+    }
+
+    fn query_rates(env: Env) -> Result<Vec<i128>, StablePoolError> {
+        get_rates(&env, &get_config(&env))
+    }
+
+    fn simulate_swap(
+        env: Env,
+        offer_asset: Address,
+        ask_asset: Address,
+        offer_amount: i128,
+    ) -> Result<SimulateSwapResponse, StablePoolError> {
+        let config = get_config(&env);
+        let (offer_index, ask_index) = get_swap_indexes(&env, &config, &offer_asset, &ask_asset)?;
 
         let (ask_amount, spread_amount, commission_amount) = compute_swap(
             &env,
-            pool_balance_offer,
-            pool_balance_ask,
+            &utils::get_pool_balances(&env),
+            &get_rates(&env, &config)?,
+            offer_index,
+            ask_index,
             offer_amount,
             config.protocol_fee_rate(),
         );
 
         let total_return = ask_amount + commission_amount + spread_amount;
 
-        SimulateSwapResponse {
+        Ok(SimulateSwapResponse {
             ask_amount,
             spread_amount,
             commission_amount,
             total_return,
-        }
+        })
     }
 
     fn simulate_reverse_swap(
         env: Env,
         offer_asset: Address,
+        ask_asset: Address,
         ask_amount: i128,
-    ) -> SimulateReverseSwapResponse {
+    ) -> Result<SimulateReverseSwapResponse, StablePoolError> {
         let config = get_config(&env);
-
-        let pool_balance_a = utils::get_pool_balance_a(&env);
-        let pool_balance_b = utils::get_pool_balance_b(&env);
-        let (pool_balance_offer, pool_balance_ask) = if offer_asset == config.token_a {
-            (pool_balance_a, pool_balance_b)
-        } else {
-            (pool_balance_b, pool_balance_a)
-        };
+        let (offer_index, ask_index) = get_swap_indexes(&env, &config, &offer_asset, &ask_asset)?;
 
         let (offer_amount, spread_amount, commission_amount) = compute_offer_amount(
             &env,
-            pool_balance_offer,
-            pool_balance_ask,
+            &utils::get_pool_balances(&env),
+            &get_rates(&env, &config)?,
+            offer_index,
+            ask_index,
             ask_amount,
             config.protocol_fee_rate(),
-        );
+        )?;
 
-        SimulateReverseSwapResponse {
+        Ok(SimulateReverseSwapResponse {
             offer_amount,
             spread_amount,
             commission_amount,
+        })
+    }
+
+    fn simulate_provide_liquidity(
+        env: Env,
+        desired_amounts: Vec<i128>,
+    ) -> Result<ProvideLiquidityResponse, StablePoolError> {
+        let config = get_config(&env);
+        let (shares, _) = compute_provide_liquidity(
+            &env,
+            &config,
+            &utils::get_pool_balances(&env),
+            &get_rates(&env, &config)?,
+            utils::get_total_shares(&env),
+            &desired_amounts,
+            None,
+        )?;
+
+        Ok(ProvideLiquidityResponse {
+            shares,
+            amounts: desired_amounts,
+        })
+    }
+
+    fn simulate_withdraw_liquidity(env: Env, share_amount: i128) -> Vec<i128> {
+        get_pro_rata_amounts(&env, &utils::get_pool_balances(&env), share_amount)
+    }
+
+    fn simulate_withdraw_single(
+        env: Env,
+        share_amount: i128,
+        out_asset: Address,
+    ) -> Result<SimulateWithdrawSingleResponse, StablePoolError> {
+        let config = get_config(&env);
+        let out_index = config.token_index(&env, &out_asset)?;
+
+        let (return_amount, spread_amount, commission_amount) = compute_withdraw_single(
+            &env,
+            &utils::get_pool_balances(&env),
+            &get_rates(&env, &config)?,
+            utils::get_total_shares(&env),
+            share_amount,
+            out_index,
+            config.total_fee_bps,
+        )?;
+
+        Ok(SimulateWithdrawSingleResponse {
+            return_amount,
+            commission_amount,
+            spread_amount,
+        })
+    }
+}
+
+fn do_withdraw_liquidity(
+    env: Env,
+    sender: Address,
+    share_amount: i128,
+    min_amounts: Vec<i128>,
+) -> Result<Vec<i128>, StablePoolError> {
+    let config = get_config(&env);
+    if min_amounts.len() != config.tokens.len() {
+        log!(
+            &env,
+            "Expected {} minimum amounts, got {}",
+            config.tokens.len(),
+            min_amounts.len()
+        );
+        return Err(StablePoolError::AmountsLengthMismatch);
+    }
+
+    let share_token_client = token_contract::Client::new(&env, &config.share_token);
+    share_token_client.transfer(&sender, &env.current_contract_address(), &share_amount);
+
+    let pool_balances = utils::get_pool_balances(&env);
+    let pro_rata_amounts = get_pro_rata_amounts(&env, &pool_balances, share_amount);
+
+    let mut return_amounts = Vec::new(&env);
+    let mut new_balances = Vec::new(&env);
+    for ((pool_balance, return_amount), min_amount) in pool_balances
+        .iter()
+        .zip(pro_rata_amounts.iter())
+        .zip(min_amounts.iter())
+    {
+        if return_amount < min_amount {
+            log!(
+                &env,
+                "Minimum amount of a token is not satisfied! min_amount: {}, return_amount: {}",
+                min_amount,
+                return_amount
+            );
+            return Err(StablePoolError::WithdrawMinNotSatisfied);
         }
+        return_amounts.push_back(return_amount);
+        new_balances.push_back(pool_balance - return_amount);
+    }
+
+    // burn shares
+    utils::burn_shares(&env, &config.share_token, share_amount);
+    // transfer tokens from contract to sender
+    for (token, return_amount) in config.tokens.iter().zip(return_amounts.iter()) {
+        token_contract::Client::new(&env, &token).transfer(
+            &env.current_contract_address(),
+            &sender,
+            &return_amount,
+        );
     }
+    // update pool balances
+    utils::save_pool_balances(&env, &new_balances);
+
+    env.events()
+        .publish(("withdraw_liquidity", "sender"), sender);
+    env.events()
+        .publish(("withdraw_liquidity", "shares_amount"), share_amount);
+    env.events().publish(
+        ("withdraw_liquidity", "return_amounts"),
+        return_amounts.clone(),
+    );
+
+    Ok(return_amounts)
 }
 
+fn do_withdraw_liquidity_single(
+    env: Env,
+    sender: Address,
+    share_amount: i128,
+    out_asset: Address,
+    min_out: i128,
+) -> Result<i128, StablePoolError> {
+    let config = get_config(&env);
+    let out_index = config.token_index(&env, &out_asset)?;
+    let mut pool_balances = utils::get_pool_balances(&env);
+
+    let (return_amount, _, commission_amount) = compute_withdraw_single(
+        &env,
+        &pool_balances,
+        &refresh_rates(&env, &config)?,
+        utils::get_total_shares(&env),
+        share_amount,
+        out_index,
+        config.total_fee_bps,
+    )?;
+
+    if return_amount < min_out {
+        log!(
+            &env,
+            "Pool stable: WithdrawLiquiditySingle: Minimum amount is not satisfied! min_out: {}, return_amount: {}",
+            min_out,
+            return_amount
+        );
+        return Err(StablePoolError::WithdrawMinNotSatisfied);
+    }
+
+    let share_token_client = token_contract::Client::new(&env, &config.share_token);
+    share_token_client.transfer(&sender, &env.current_contract_address(), &share_amount);
+    utils::burn_shares(&env, &config.share_token, share_amount);
+
+    let out_token_client = token_contract::Client::new(&env, &out_asset);
+    out_token_client.transfer(&env.current_contract_address(), &sender, &return_amount);
+
+    // the commission is split like the one of a swap
+    let protocol_fee_amount = commission_amount * config.protocol_fee_share();
+    let lp_fee_amount = commission_amount - protocol_fee_amount;
+    out_token_client.transfer(
+        &env.current_contract_address(),
+        &config.fee_recipient,
+        &protocol_fee_amount,
+    );
+    let lp_fee_removed_from_pool = if config.distribute_lp_fees_to_stakers && lp_fee_amount > 0 {
+        out_token_client.transfer(
+            &env.current_contract_address(),
+            &config.stake_contract,
+            &lp_fee_amount,
+        );
+        lp_fee_amount
+    } else {
+        0
+    };
+
+    pool_balances.set(
+        out_index,
+        pool_balances.get(out_index).unwrap()
+            - return_amount
+            - protocol_fee_amount
+            - lp_fee_removed_from_pool,
+    );
+    utils::save_pool_balances(&env, &pool_balances);
+
+    env.events()
+        .publish(("withdraw_liquidity_single", "sender"), sender);
+    env.events()
+        .publish(("withdraw_liquidity_single", "shares_amount"), share_amount);
+    env.events()
+        .publish(("withdraw_liquidity_single", "out_asset"), out_asset);
+    env.events().publish(
+        ("withdraw_liquidity_single", "return_amount"),
+        return_amount,
+    );
+
+    Ok(return_amount)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn do_swap(
     env: Env,
     sender: Address,
+    receiver: Address,
     offer_asset: Address,
+    ask_asset: Address,
     offer_amount: i128,
     belief_price: Option<i64>,
     max_spread: Option<i64>,
-) -> i128 {
+) -> Result<i128, StablePoolError> {
     let config = get_config(&env);
+    let (offer_index, ask_index) = get_swap_indexes(&env, &config, &offer_asset, &ask_asset)?;
 
     let belief_price = belief_price.map(Decimal::percent);
     let max_spread = Decimal::bps(max_spread.map_or_else(|| config.max_allowed_spread_bps, |x| x));
 
-    let pool_balance_a = utils::get_pool_balance_a(&env);
-    let pool_balance_b = utils::get_pool_balance_b(&env);
-
-    let (pool_balance_sell, pool_balance_buy) = if offer_asset == config.token_a {
-        (pool_balance_a, pool_balance_b)
-    } else {
-        (pool_balance_b, pool_balance_a)
-    };
-
     let (return_amount, spread_amount, commission_amount) = compute_swap(
         &env,
-        pool_balance_sell,
-        pool_balance_buy,
+        &utils::get_pool_balances(&env),
+        &refresh_rates(&env, &config)?,
+        offer_index,
+        ask_index,
         offer_amount,
         config.protocol_fee_rate(),
     );
 
+    // the belief price is per whole token, so the offer amount is scaled to the ask decimals
+    let token_decimals = get_token_decimals(&env);
     assert_max_spread(
         &env,
         belief_price,
         max_spread,
-        offer_amount,
+        denormalize(
+            normalize(offer_amount, token_decimals.get(offer_index).unwrap()),
+            token_decimals.get(ask_index).unwrap(),
+        ),
         return_amount + commission_amount,
         spread_amount,
+    )?;
+
+    execute_swap(
+        &env,
+        &config,
+        sender,
+        receiver,
+        offer_index,
+        ask_index,
+        offer_amount,
+        return_amount,
+        spread_amount,
+        commission_amount,
     );
 
-    // Transfer the amount being sold to the contract
-    let (sell_token, buy_token) = if offer_asset == config.clone().token_a {
-        (config.clone().token_a, config.clone().token_b)
-    } else {
-        (config.clone().token_b, config.clone().token_a)
-    };
+    Ok(return_amount)
+}
+
+fn do_swap_exact_out(
+    env: Env,
+    sender: Address,
+    receiver: Address,
+    offer_asset: Address,
+    ask_asset: Address,
+    ask_amount: i128,
+    max_offer_amount: i128,
+) -> Result<i128, StablePoolError> {
+    let config = get_config(&env);
+    let (offer_index, ask_index) = get_swap_indexes(&env, &config, &offer_asset, &ask_asset)?;
+
+    let (offer_amount, spread_amount, commission_amount) = compute_offer_amount(
+        &env,
+        &utils::get_pool_balances(&env),
+        &refresh_rates(&env, &config)?,
+        offer_index,
+        ask_index,
+        ask_amount,
+        config.protocol_fee_rate(),
+    )?;
+
+    if offer_amount > max_offer_amount {
+        log!(
+            &env,
+            "Pool stable: SwapExactOut: Offer amount {} exceeds the maximum of {}",
+            offer_amount,
+            max_offer_amount
+        );
+        return Err(StablePoolError::OfferAmountExceedsMax);
+    }
+
+    execute_swap(
+        &env,
+        &config,
+        sender,
+        receiver,
+        offer_index,
+        ask_index,
+        offer_amount,
+        ask_amount,
+        spread_amount,
+        commission_amount,
+    );
+
+    Ok(offer_amount)
+}
+
+/// Transfers the tokens of an already computed swap and updates the pool balances.
+/// The bought tokens are sent to `receiver`, which can differ from the `sender` paying for them.
+#[allow(clippy::too_many_arguments)]
+fn execute_swap(
+    env: &Env,
+    config: &Config,
+    sender: Address,
+    receiver: Address,
+    offer_index: u32,
+    ask_index: u32,
+    offer_amount: i128,
+    return_amount: i128,
+    spread_amount: i128,
+    commission_amount: i128,
+) {
+    let offer_asset = config.tokens.get(offer_index).unwrap();
+    let ask_asset = config.tokens.get(ask_index).unwrap();
+    let mut pool_balances = utils::get_pool_balances(env);
 
     // transfer tokens to swap
-    token_contract::Client::new(&env, &sell_token).transfer(
+    token_contract::Client::new(env, &offer_asset).transfer(
         &sender,
         &env.current_contract_address(),
         &offer_amount,
     );
 
-    // return swapped tokens to user
-    token_contract::Client::new(&env, &buy_token).transfer(
+    // send swapped tokens to the receiver
+    token_contract::Client::new(env, &ask_asset).transfer(
         &env.current_contract_address(),
-        &sender,
+        &receiver,
         &return_amount,
     );
 
-    // send commission to fee recipient
-    token_contract::Client::new(&env, &buy_token).transfer(
+    // split the commission between the protocol and the liquidity providers
+    let protocol_fee_amount = commission_amount * config.protocol_fee_share();
+    let lp_fee_amount = commission_amount - protocol_fee_amount;
+
+    // send protocol's part of the commission to fee recipient
+    token_contract::Client::new(env, &ask_asset).transfer(
         &env.current_contract_address(),
         &config.fee_recipient,
-        &commission_amount,
+        &protocol_fee_amount,
     );
 
-    // user is offering to sell A, so they will receive B
-    // A balance is bigger, B balance is smaller
-    let (balance_a, balance_b) = if offer_asset == config.token_a {
-        (
-            pool_balance_a + offer_amount,
-            pool_balance_b - commission_amount - return_amount,
-        )
+    // liquidity providers' part of the commission either stays in the pool or is sent to the
+    // stake contract to be distributed as rewards
+    let lp_fee_removed_from_pool = if config.distribute_lp_fees_to_stakers && lp_fee_amount > 0 {
+        token_contract::Client::new(env, &ask_asset).transfer(
+            &env.current_contract_address(),
+            &config.stake_contract,
+            &lp_fee_amount,
+        );
+        lp_fee_amount
     } else {
-        (
-            pool_balance_a - commission_amount - return_amount,
-            pool_balance_b + offer_amount,
-        )
+        0
     };
-    utils::save_pool_balance_a(&env, balance_a);
-    utils::save_pool_balance_b(&env, balance_b);
+
+    // user is offering to sell the offer asset, so they will receive the ask asset
+    // offer balance is bigger, ask balance is smaller
+    pool_balances.set(
+        offer_index,
+        pool_balances.get(offer_index).unwrap() + offer_amount,
+    );
+    pool_balances.set(
+        ask_index,
+        pool_balances.get(ask_index).unwrap()
+            - protocol_fee_amount
+            - lp_fee_removed_from_pool
+            - return_amount,
+    );
+    utils::save_pool_balances(env, &pool_balances);
 
     env.events().publish(("swap", "sender"), sender);
-    env.events().publish(("swap", "sell_token"), sell_token);
+    env.events().publish(("swap", "receiver"), receiver);
+    env.events().publish(("swap", "sell_token"), offer_asset);
     env.events().publish(("swap", "offer_amount"), offer_amount);
-    env.events().publish(("swap", "buy_token"), buy_token);
+    env.events().publish(("swap", "buy_token"), ask_asset);
     env.events()
         .publish(("swap", "return_amount"), return_amount);
     env.events()
         .publish(("swap", "spread_amount"), spread_amount);
+}
+
+/// Returns the rate of every token, in the order of `Config::tokens`. Tokens without a rate
+/// provider have a rate of `RATE_ONE`. The cached rates are used until they are older than
+/// `max_rate_staleness` seconds, the rate providers are asked otherwise. Nothing is stored, so
+/// that the queries and simulations stay read-only.
+fn get_rates(env: &Env, config: &Config) -> Result<Vec<i128>, StablePoolError> {
+    match get_unexpired_rates(env, config) {
+        Some(rates) => Ok(rates),
+        None => fetch_rates(env, config),
+    }
+}
+
+/// Like `get_rates`, but caches the rates fetched from the rate providers. Only used by the
+/// entry points that change the state of the pool.
+fn refresh_rates(env: &Env, config: &Config) -> Result<Vec<i128>, StablePoolError> {
+    if let Some(rates) = get_unexpired_rates(env, config) {
+        return Ok(rates);
+    }
+
+    let rates = fetch_rates(env, config)?;
+    save_cached_rates(
+        env,
+        &CachedRates {
+            rates: rates.clone(),
+            updated_at: env.ledger().timestamp(),
+        },
+    );
+    env.events().publish(("rates", "updated"), rates.clone());
+
+    Ok(rates)
+}
+
+/// Returns the rates that don't have to be asked from the rate providers: `RATE_ONE` for every
+/// token of a pool without rate providers, or the cached rates while they are not stale.
+fn get_unexpired_rates(env: &Env, config: &Config) -> Option<Vec<i128>> {
+    if config.rate_providers.is_empty() {
+        let mut rates = Vec::new(env);
+        for _ in config.tokens.iter() {
+            rates.push_back(RATE_ONE);
+        }
+        return Some(rates);
+    }
+
+    get_cached_rates(env)
+        .filter(|cached_rates| {
+            env.ledger()
+                .timestamp()
+                .saturating_sub(cached_rates.updated_at)
+                <= config.max_rate_staleness
+        })
+        .map(|cached_rates| cached_rates.rates)
+}
+
+/// Asks every rate provider for its rate.
+fn fetch_rates(env: &Env, config: &Config) -> Result<Vec<i128>, StablePoolError> {
+    let mut rates = Vec::new(env);
+    for rate_provider in config.rate_providers.iter() {
+        let rate = match rate_provider {
+            Some(rate_provider) => env.invoke_contract::<i128>(
+                &rate_provider,
+                &Symbol::new(env, "get_rate"),
+                Vec::new(env),
+            ),
+            None => RATE_ONE,
+        };
+        if rate <= 0 {
+            log!(
+                env,
+                "Pool stable: Rate providers must report a positive rate"
+            );
+            return Err(StablePoolError::InvalidRate);
+        }
+        rates.push_back(rate);
+    }
 
-    return_amount
+    Ok(rates)
+}
+
+/// Returns the amounts of every token owned by `share_amount` shares. The product is computed in
+/// 256 bits, as the balances and shares of tokens with 18 decimals overflow a `Decimal`.
+fn get_pro_rata_amounts(env: &Env, pool_balances: &Vec<i128>, share_amount: i128) -> Vec<i128> {
+    let total_shares = utils::get_total_shares(env);
+    let mut amounts = Vec::new(env);
+    for pool_balance in pool_balances.iter() {
+        if total_shares == 0 {
+            amounts.push_back(0);
+        } else {
+            amounts.push_back(mul_div(
+                env,
+                pool_balance as u128,
+                share_amount as u128,
+                total_shares as u128,
+            ) as i128);
+        }
+    }
+    amounts
+}
+
+/// Returns the positions of the offer and the ask asset in the pool.
+fn get_swap_indexes(
+    env: &Env,
+    config: &Config,
+    offer_asset: &Address,
+    ask_asset: &Address,
+) -> Result<(u32, u32), StablePoolError> {
+    let offer_index = config.token_index(env, offer_asset)?;
+    let ask_index = config.token_index(env, ask_asset)?;
+
+    if offer_index == ask_index {
+        log!(env, "Cannot swap {} for itself", offer_asset);
+        return Err(StablePoolError::SameAsset);
+    }
+
+    Ok((offer_index, ask_index))
 }
 
 /// This function asserts that the spread (slippage) does not exceed a given maximum.
@@ -679,7 +1561,7 @@ pub fn assert_max_spread(
     offer_amount: i128,
     return_amount: i128,
     spread_amount: i128,
-) {
+) -> Result<(), StablePoolError> {
     // Calculate the expected return if a belief price is provided
     let expected_return = belief_price.map(|price| offer_amount * price);
 
@@ -697,15 +1579,19 @@ pub fn assert_max_spread(
 
     if spread_ratio > max_spread {
         log!(env, "Spread exceeds maximum allowed");
-        panic!("Pool: Assert max spread: spread exceeds maximum allowed");
+        return Err(StablePoolError::SpreadExceedsLimit);
     }
+
+    Ok(())
 }
 
 /// Computes the result of a swap operation.
 ///
 /// Arguments:
-/// - `offer_pool`: Total amount of offer assets in the pool.
-/// - `ask_pool`: Total amount of ask assets in the pool.
+/// - `pool_balances`: Total amount of every asset in the pool.
+/// - `rates`: Rate of every asset in the pool.
+/// - `offer_index`: Position of the offer asset in the pool.
+/// - `ask_index`: Position of the ask asset in the pool.
 /// - `offer_amount`: Amount of offer assets to swap.
 /// - `commission_rate`: Total amount of fees charged for the swap.
 ///
@@ -715,70 +1601,345 @@ pub fn assert_max_spread(
 /// - The commission amount, representing the fees charged for the swap.
 pub fn compute_swap(
     env: &Env,
-    offer_pool: i128,
-    ask_pool: i128,
+    pool_balances: &Vec<i128>,
+    rates: &Vec<i128>,
+    offer_index: u32,
+    ask_index: u32,
     offer_amount: i128,
     commission_rate: Decimal,
 ) -> (i128, i128, i128) {
     let amp_parameters = get_amp(env).unwrap();
     let amp = compute_current_amp(env, &amp_parameters);
+    let token_decimals = get_token_decimals(env);
+    let offer_decimals = token_decimals.get(offer_index).unwrap();
+    let ask_decimals = token_decimals.get(ask_index).unwrap();
 
+    let offer_rate = rates.get(offer_index).unwrap();
+    let ask_rate = rates.get(ask_index).unwrap();
+
+    let xp = normalize_balances(env, pool_balances, &token_decimals, rates);
+    let new_offer_pool =
+        xp.get(offer_index).unwrap() + upscale(env, offer_amount, offer_decimals, offer_rate);
     let new_ask_pool = calc_y(
+        env,
         amp as u128,
-        Decimal::from_atomics(offer_pool + offer_amount, 6),
-        &[
-            Decimal::from_atomics(offer_pool, 6),
-            Decimal::from_atomics(ask_pool, 6),
-        ],
-        6,
+        offer_index,
+        new_offer_pool,
+        ask_index,
+        &xp,
     );
 
-    let return_amount = ask_pool - new_ask_pool;
-    // We consider swap rate 1:1 in stable swap thus any difference is considered as spread.
-    let spread_amount = offer_amount - return_amount;
-    let commission_amount = return_amount * commission_rate;
+    // One unit is kept in the pool to cover the rounding of the Newton's method
+    let total_return_amount = downscale(
+        env,
+        (xp.get(ask_index).unwrap() - new_ask_pool).saturating_sub(1),
+        ask_decimals,
+        ask_rate,
+    );
+    // We consider the swap rate to be the ratio of the token rates, 1:1 without rate providers,
+    // thus any difference is considered as spread.
+    let offer_value = upscale(env, offer_amount, offer_decimals, offer_rate);
+    let spread_amount =
+        (downscale(env, offer_value, ask_decimals, ask_rate) - total_return_amount).max(0);
+    let commission_amount = total_return_amount * commission_rate;
+    let return_amount = total_return_amount - commission_amount;
 
     (return_amount, spread_amount, commission_amount)
 }
 
+/// Computes the shares minted for depositing `desired_amounts` into a pool with `pool_balances`
+/// valued at `rates` and `total_shares`, together with the imbalance fee charged on every token.
+///
+/// The shares are proportional to the growth of the invariant (D) caused by the deposit. Except
+/// for the first deposit, some of the amounts can be zero. Every balance is then charged the
+/// imbalance fee on its difference from the balance of a proportional deposit, and the shares
+/// are computed from the invariant of the balances after the fees.
+///
+/// Fails if the fees take away more than `slippage_tolerance_bps` of the shares the deposit
+/// would mint without them.
+pub fn compute_provide_liquidity(
+    env: &Env,
+    config: &Config,
+    pool_balances: &Vec<i128>,
+    rates: &Vec<i128>,
+    total_shares: i128,
+    desired_amounts: &Vec<i128>,
+    slippage_tolerance_bps: Option<i64>,
+) -> Result<(i128, Vec<i128>), StablePoolError> {
+    if desired_amounts.len() != config.tokens.len() {
+        log!(
+            env,
+            "Expected {} amounts, got {}",
+            config.tokens.len(),
+            desired_amounts.len()
+        );
+        return Err(StablePoolError::AmountsLengthMismatch);
+    }
+    // zero amounts are allowed, so that the liquidity can be provided in only some of the tokens
+    if desired_amounts.iter().any(|amount| amount < 0)
+        || desired_amounts.iter().all(|amount| amount == 0)
+    {
+        log!(
+            env,
+            "Pool stable: ProvideLiquidity: Amounts have to be non-negative and not all zero"
+        );
+        return Err(StablePoolError::InvalidAmount);
+    }
+    if total_shares == 0 && desired_amounts.iter().any(|amount| amount == 0) {
+        log!(
+            env,
+            "Pool stable: ProvideLiquidity: The first deposit has to contain every token"
+        );
+        return Err(StablePoolError::InvalidAmount);
+    }
+
+    let token_decimals = get_token_decimals(env);
+    let amp_parameters = get_amp(env).unwrap();
+    let amp = compute_current_amp(env, &amp_parameters);
+
+    // Invariant (D) after deposit added
+    let mut new_balances = Vec::new(env);
+    for (old_balance, desired_amount) in pool_balances.iter().zip(desired_amounts.iter()) {
+        new_balances.push_back(old_balance + desired_amount);
+    }
+    let new_invariant = compute_d(
+        env,
+        amp as u128,
+        &normalize_balances(env, &new_balances, &token_decimals, rates),
+    );
+
+    let mut fees = Vec::new(env);
+    let shares = if total_shares == 0 {
+        for _ in desired_amounts.iter() {
+            fees.push_back(0);
+        }
+        denormalize(new_invariant, get_greatest_precision(env)) - MINIMUM_LIQUIDITY_AMOUNT
+    } else {
+        let initial_invariant = compute_d(
+            env,
+            amp as u128,
+            &normalize_balances(env, pool_balances, &token_decimals, rates),
+        );
+
+        // every balance is charged on its difference from the balance of a proportional deposit
+        let mut balances_after_fees = Vec::new(env);
+        for (old_balance, new_balance) in pool_balances.iter().zip(new_balances.iter()) {
+            let ideal_balance =
+                mul_div(env, old_balance as u128, new_invariant, initial_invariant) as i128;
+            let fee = compute_imbalance_fee(
+                env,
+                (new_balance - ideal_balance).unsigned_abs(),
+                config.total_fee_bps,
+                new_balances.len(),
+            ) as i128;
+            fees.push_back(fee);
+            balances_after_fees.push_back(new_balance - fee);
+        }
+        let invariant_after_fees = compute_d(
+            env,
+            amp as u128,
+            &normalize_balances(env, &balances_after_fees, &token_decimals, rates),
+        );
+
+        // Calculate the proportion of the change in invariant
+        let shares = mul_div(
+            env,
+            total_shares as u128,
+            invariant_after_fees.saturating_sub(initial_invariant),
+            initial_invariant,
+        ) as i128;
+
+        // the imbalance fees are the slippage of the deposit, relative to the shares it would
+        // mint without them
+        if let Some(slippage_tolerance_bps) = slippage_tolerance_bps {
+            let shares_without_fees = mul_div(
+                env,
+                total_shares as u128,
+                new_invariant - initial_invariant,
+                initial_invariant,
+            ) as i128;
+            if (shares_without_fees - shares) * 10_000
+                > shares_without_fees * slippage_tolerance_bps as i128
+            {
+                log!(
+                    env,
+                    "Pool stable: ProvideLiquidity: Slippage tolerance violated. Shares: {}, shares without fees: {}",
+                    shares,
+                    shares_without_fees
+                );
+                return Err(StablePoolError::SlippageToleranceViolated);
+            }
+        }
+
+        shares
+    };
+    if shares <= 0 {
+        log!(env, "Pool: ProvideLiquidity: Liquidity amount is too low");
+        return Err(StablePoolError::LiquidityAmountTooLow);
+    }
+
+    Ok((shares, fees))
+}
+
+/// Computes the result of withdrawing liquidity into a single asset.
+///
+/// * **pool_balances** total amount of every asset in the pool.
+/// * **rates** rate of every asset in the pool.
+/// * **total_shares** total amount of pool shares issued.
+/// * **share_amount** amount of pool shares withdrawn.
+/// * **out_index** position of the out asset in the pool.
+/// * **total_fee_bps** swap fee of the pool, charged on the imbalanced part of the withdrawal.
+///
+/// The invariant (D) is reduced by the part owned by the shares and the balance of the out
+/// asset is solved for it. The imbalance fee is charged on the difference between every balance
+/// and its pro-rata value.
+///
+/// Returns a tuple with the amount of the out asset sent, the spread and the commission amount.
+pub fn compute_withdraw_single(
+    env: &Env,
+    pool_balances: &Vec<i128>,
+    rates: &Vec<i128>,
+    total_shares: i128,
+    share_amount: i128,
+    out_index: u32,
+    total_fee_bps: i64,
+) -> Result<(i128, i128, i128), StablePoolError> {
+    if share_amount >= total_shares {
+        log!(
+            env,
+            "Not enough liquidity to withdraw {} shares into one asset",
+            share_amount
+        );
+        return Err(StablePoolError::NotEnoughLiquidity);
+    }
+
+    let amp_parameters = get_amp(env).unwrap();
+    let amp = compute_current_amp(env, &amp_parameters) as u128;
+    let token_decimals = get_token_decimals(env);
+    let out_decimals = token_decimals.get(out_index).unwrap();
+    let out_rate = rates.get(out_index).unwrap();
+
+    let xp = normalize_balances(env, pool_balances, &token_decimals, rates);
+    let initial_invariant = compute_d(env, amp, &xp);
+    let new_invariant = initial_invariant
+        - mul_div(
+            env,
+            initial_invariant,
+            share_amount as u128,
+            total_shares as u128,
+        );
+
+    let new_out_pool = calc_y_d(env, amp, out_index, &xp, new_invariant);
+    let amount_before_commission = xp.get(out_index).unwrap() - new_out_pool;
+
+    // every balance is charged on its difference from the balance of a pro-rata withdrawal
+    let mut xp_reduced = Vec::new(env);
+    for (index, x) in xp.iter().enumerate() {
+        let pro_rata_balance = mul_div(env, x, new_invariant, initial_invariant);
+        let difference = if index as u32 == out_index {
+            pro_rata_balance.saturating_sub(new_out_pool)
+        } else {
+            x - pro_rata_balance
+        };
+        xp_reduced.push_back(x - compute_imbalance_fee(env, difference, total_fee_bps, xp.len()));
+    }
+
+    // One unit is kept in the pool to cover the rounding of the Newton's method
+    let amount_after_commission = (xp_reduced.get(out_index).unwrap()
+        - calc_y_d(env, amp, out_index, &xp_reduced, new_invariant))
+    .saturating_sub(1);
+
+    let return_amount = downscale(env, amount_after_commission, out_decimals, out_rate);
+    let amount_before_commission = downscale(env, amount_before_commission, out_decimals, out_rate);
+    let commission_amount = (amount_before_commission - return_amount).max(0);
+    // We consider the value of the shares at the rate of the out asset, thus any difference is
+    // considered as spread.
+    let spread_amount = (downscale(
+        env,
+        initial_invariant - new_invariant,
+        out_decimals,
+        out_rate,
+    ) - amount_before_commission)
+        .max(0);
+
+    Ok((return_amount, spread_amount, commission_amount))
+}
+
+/// Computes the fee on the imbalanced `amount` of a deposit or withdrawal. Like Curve, the fee
+/// rate is `total_fee_bps * n / (4 * (n - 1))`, so that a balanced pool pays about the swap fee
+/// when a deposit or withdrawal is equivalent to a swap.
+pub fn compute_imbalance_fee(env: &Env, amount: u128, total_fee_bps: i64, n_coins: u32) -> u128 {
+    let n_coins = n_coins as u128;
+    mul_div(
+        env,
+        amount,
+        total_fee_bps as u128 * n_coins,
+        10_000 * 4 * (n_coins - 1),
+    )
+}
+
 /// Returns an amount of offer assets for a specified amount of ask assets.
 ///
-/// * **offer_pool** total amount of offer assets in the pool.
-/// * **ask_pool** total amount of ask assets in the pool.
+/// * **pool_balances** total amount of every asset in the pool.
+/// * **rates** rate of every asset in the pool.
+/// * **offer_index** position of the offer asset in the pool.
+/// * **ask_index** position of the ask asset in the pool.
 /// * **ask_amount** amount of ask assets to swap to.
 /// * **commission_rate** total amount of fees charged for the swap.
 pub fn compute_offer_amount(
     env: &Env,
-    offer_pool: i128,
-    ask_pool: i128,
+    pool_balances: &Vec<i128>,
+    rates: &Vec<i128>,
+    offer_index: u32,
+    ask_index: u32,
     ask_amount: i128,
     commission_rate: Decimal,
-) -> (i128, i128, i128) {
+) -> Result<(i128, i128, i128), StablePoolError> {
     let amp_parameters = get_amp(env).unwrap();
     let amp = compute_current_amp(env, &amp_parameters);
+    let token_decimals = get_token_decimals(env);
+    let offer_decimals = token_decimals.get(offer_index).unwrap();
+    let ask_decimals = token_decimals.get(ask_index).unwrap();
+    let offer_rate = rates.get(offer_index).unwrap();
+    let ask_rate = rates.get(ask_index).unwrap();
+
+    // The amount from which the commission is taken so that `ask_amount` is left, rounded up
+    let one_minus_commission = (Decimal::one() - commission_rate).atomics();
+    let ask_before_commission =
+        (ask_amount * Decimal::one().atomics() + one_minus_commission - 1) / one_minus_commission;
+
+    if ask_before_commission >= pool_balances.get(ask_index).unwrap() {
+        log!(
+            env,
+            "Not enough liquidity to receive {} of the ask asset",
+            ask_amount
+        );
+        return Err(StablePoolError::NotEnoughLiquidity);
+    }
 
-    let new_offer_pool = calc_y(
-        amp as u128,
-        Decimal::from_atomics(ask_pool - ask_amount, 6),
-        &[
-            Decimal::from_atomics(offer_pool, 6),
-            Decimal::from_atomics(ask_pool, 6),
-        ],
-        6,
-    );
-
-    let offer_amount = new_offer_pool - offer_pool;
-
-    let one_minus_commission = Decimal::one() - commission_rate;
-    let inv_one_minus_commission = Decimal::one() / one_minus_commission;
-    let ask_before_commission = ask_amount * inv_one_minus_commission;
-    // Calculate the spread amount, representing the difference between the expected and actual swap amounts
-    let spread_amount: i128 = (offer_amount * ask_pool / offer_pool) - ask_before_commission;
+    let xp = normalize_balances(env, pool_balances, &token_decimals, rates);
+    let new_ask_pool =
+        xp.get(ask_index).unwrap() - upscale(env, ask_before_commission, ask_decimals, ask_rate);
+    let new_offer_pool = calc_y(env, amp as u128, ask_index, new_ask_pool, offer_index, &xp);
+
+    // One unit is added to cover the rounding of the Newton's method
+    let offer_amount = downscale(
+        env,
+        new_offer_pool - xp.get(offer_index).unwrap(),
+        offer_decimals,
+        offer_rate,
+    ) + 1;
+
+    // We consider the swap rate to be the ratio of the token rates, 1:1 without rate providers,
+    // thus any difference is considered as spread.
+    let offer_value = upscale(env, offer_amount, offer_decimals, offer_rate);
+    let spread_amount =
+        (downscale(env, offer_value, ask_decimals, ask_rate) - ask_before_commission).max(0);
 
     // Calculate the commission amount
     let commission_amount: i128 = ask_before_commission * commission_rate;
 
-    (offer_amount, spread_amount, commission_amount)
+    Ok((offer_amount, spread_amount, commission_amount))
 }
 
 #[cfg(test)]
@@ -791,18 +1952,20 @@ mod tests {
         // Test case that should pass:
         // belief price of 2.0, max spread of 10%, offer amount of 100k, return amount of 100k and 1 unit, spread amount of 1
         // The spread ratio is 10% which is equal to the max spread
-        assert_max_spread(
-            &env,
-            Some(Decimal::percent(200)),
-            Decimal::percent(10),
-            100_000,
-            100_001,
-            1,
+        assert_eq!(
+            assert_max_spread(
+                &env,
+                Some(Decimal::percent(200)),
+                Decimal::percent(10),
+                100_000,
+                100_001,
+                1,
+            ),
+            Ok(())
         );
     }
 
     #[test]
-    #[should_panic(expected = "spread exceeds maximum allowed")]
     fn test_assert_max_spread_fail_max_spread_exceeded() {
         let env = Env::default();
 
@@ -812,13 +1975,16 @@ mod tests {
         let return_amount = 100; // These values are chosen such that the spread ratio will be more than 10%
         let spread_amount = 35;
 
-        assert_max_spread(
-            &env,
-            belief_price,
-            max_spread,
-            offer_amount,
-            return_amount,
-            spread_amount,
+        assert_eq!(
+            assert_max_spread(
+                &env,
+                belief_price,
+                max_spread,
+                offer_amount,
+                return_amount,
+                spread_amount,
+            ),
+            Err(StablePoolError::SpreadExceedsLimit)
         );
     }
 
@@ -827,15 +1993,20 @@ mod tests {
         let env = Env::default();
         // no belief price, max spread of 100 (0.1 or 10%), offer amount of 10, return amount of 10, spread amount of 1
         // The spread ratio is 10% which is equal to the max spread
-        assert_max_spread(&env, None, Decimal::percent(10), 10, 10, 1);
+        assert_eq!(
+            assert_max_spread(&env, None, Decimal::percent(10), 10, 10, 1),
+            Ok(())
+        );
     }
 
     #[test]
-    #[should_panic(expected = "spread exceeds maximum allowed")]
     fn test_assert_max_spread_fail_no_belief_price_max_spread_exceeded() {
         let env = Env::default();
         // no belief price, max spread of 10%, offer amount of 10, return amount of 10, spread amount of 2
         // The spread ratio is 20% which is greater than the max spread
-        assert_max_spread(&env, None, Decimal::percent(10), 10, 10, 2);
+        assert_eq!(
+            assert_max_spread(&env, None, Decimal::percent(10), 10, 10, 2),
+            Err(StablePoolError::SpreadExceedsLimit)
+        );
     }
 }
@@ -18,5 +18,5 @@ pub mod stake_contract {
     );
 }
 
-// #[cfg(test)]
-// mod tests;
+#[cfg(test)]
+mod tests;
@@ -1,24 +1,24 @@
-use soroban_sdk::Env;
+use soroban_sdk::{Env, Vec, U256};
 
 use crate::storage::AmplifierParameters;
 
-use decimal::Decimal;
-
-// TODO: Those parameters will be used for updating AMP function later
-#[allow(dead_code)]
 pub const MAX_AMP: u64 = 1_000_000;
-#[allow(dead_code)]
 pub const MAX_AMP_CHANGE: u64 = 10;
-#[allow(dead_code)]
 pub const MIN_AMP_CHANGING_TIME: u64 = 86400;
 pub const AMP_PRECISION: u64 = 100;
 
+/// The minimum and maximum number of tokens in a stable pool.
+pub const MIN_COINS: u32 = 2;
+pub const MAX_COINS: u32 = 4;
+
+/// All token amounts are scaled to this number of decimals before being used in the invariant.
+pub const NORMALIZED_PRECISION: u32 = 18;
+
+/// The rates reported by the rate providers have 18 decimals; tokens without one have a rate of 1.
+pub const RATE_ONE: i128 = 1_000_000_000_000_000_000;
+
 /// The maximum number of calculation steps for Newton's method.
 const ITERATIONS: u8 = 64;
-/// N = 2
-pub const N_COINS: Decimal = Decimal::raw(2000000000000000000);
-/// 1e-6
-pub const TOL: Decimal = Decimal::raw(1000000000000);
 
 /// Compute the current pool amplification coefficient (AMP).
 pub(crate) fn compute_current_amp(env: &Env, amp_params: &AmplifierParameters) -> u64 {
@@ -45,87 +45,294 @@ pub(crate) fn compute_current_amp(env: &Env, amp_params: &AmplifierParameters) -
     }
 }
 
-/// Computes the stableswap invariant (D).
+/// Scales `amount` of a token with `decimals` to `NORMALIZED_PRECISION`.
+pub(crate) fn normalize(amount: i128, decimals: u32) -> u128 {
+    amount as u128 * 10u128.pow(NORMALIZED_PRECISION - decimals)
+}
+
+/// Scales a normalized amount back to a token with `decimals`, rounding down.
+pub(crate) fn denormalize(amount: u128, decimals: u32) -> i128 {
+    (amount / 10u128.pow(NORMALIZED_PRECISION - decimals)) as i128
+}
+
+/// Scales `amount` of a token with `decimals` to `NORMALIZED_PRECISION` and by the `rate` of the
+/// token, giving its value in the invariant.
+pub(crate) fn upscale(env: &Env, amount: i128, decimals: u32, rate: i128) -> u128 {
+    mul_div(
+        env,
+        normalize(amount, decimals),
+        rate as u128,
+        RATE_ONE as u128,
+    )
+}
+
+/// Scales a value in the invariant back to an amount of a token with `decimals` and `rate`,
+/// rounding down.
+pub(crate) fn downscale(env: &Env, amount: u128, decimals: u32, rate: i128) -> i128 {
+    denormalize(
+        mul_div(env, amount, RATE_ONE as u128, rate as u128),
+        decimals,
+    )
+}
+
+/// Scales every pool balance to `NORMALIZED_PRECISION` and by the rate of its token.
+pub(crate) fn normalize_balances(
+    env: &Env,
+    balances: &Vec<i128>,
+    decimals: &Vec<u32>,
+    rates: &Vec<i128>,
+) -> Vec<u128> {
+    let mut xp = Vec::new(env);
+    for ((balance, decimals), rate) in balances.iter().zip(decimals.iter()).zip(rates.iter()) {
+        xp.push_back(upscale(env, balance, decimals, rate));
+    }
+    xp
+}
+
+/// Computes `a * b / c` without overflowing the intermediate product.
+pub(crate) fn mul_div(env: &Env, a: u128, b: u128, c: u128) -> u128 {
+    U256::from_u128(env, a)
+        .mul(&U256::from_u128(env, b))
+        .div(&U256::from_u128(env, c))
+        .to_u128()
+        .expect("Pool stable: Math: result does not fit into u128")
+}
+
+fn abs_diff(a: &U256, b: &U256) -> U256 {
+    if a > b {
+        a.sub(b)
+    } else {
+        b.sub(a)
+    }
+}
+
+/// Computes the stableswap invariant (D) of normalized balances `xp`.
 ///
 /// * **Equation**
 ///
 /// A * sum(x_i) * n**n + D = A * D * n**n + D**(n+1) / (n**n * prod(x_i))
-pub fn compute_d(amp: u128, pools: &[Decimal]) -> Decimal {
-    let leverage = Decimal::from_ratio(amp as i128, AMP_PRECISION) * N_COINS;
-    let amount_a_times_coins = pools[0] * N_COINS;
-    let amount_b_times_coins = pools[1] * N_COINS;
-
-    let sum_x = pools[0] + pools[1]; // sum(x_i), a.k.a S
-    if sum_x.is_zero() {
-        return Decimal::zero();
+pub(crate) fn compute_d(env: &Env, amp: u128, xp: &Vec<u128>) -> u128 {
+    let n_coins = U256::from_u32(env, xp.len());
+    let amp_precision = U256::from_u128(env, AMP_PRECISION as u128);
+    let one = U256::from_u32(env, 1);
+
+    let mut sum_x = U256::from_u32(env, 0);
+    for x in xp.iter() {
+        sum_x = sum_x.add(&U256::from_u128(env, x));
+    }
+    if sum_x == U256::from_u32(env, 0) {
+        return 0;
     }
 
-    let mut d_previous: Decimal;
-    let mut d: Decimal = sum_x;
+    // amp already contains AMP_PRECISION
+    let leverage = U256::from_u128(env, amp).mul(&n_coins);
+    let mut d = sum_x.clone();
 
     // Newton's method to approximate D
     for _ in 0..ITERATIONS {
-        let d_product = d.pow(3) / (amount_a_times_coins * amount_b_times_coins);
-        d_previous = d;
-        d = calculate_step(d, leverage, sum_x, d_product);
-        // Equality with the precision of 1e-6
-        if (d - d_previous).abs() <= TOL {
-            return d;
+        // D**(n+1) / (n**n * prod(x_i)), computed iteratively to keep the numbers small
+        let mut d_product = d.clone();
+        for x in xp.iter() {
+            d_product = d_product
+                .mul(&d)
+                .div(&U256::from_u128(env, x).mul(&n_coins));
+        }
+        let d_previous = d.clone();
+        d = calculate_step(&d, &leverage, &sum_x, &d_product, &n_coins, &amp_precision);
+        if abs_diff(&d, &d_previous) <= one {
+            return d
+                .to_u128()
+                .expect("Pool stable: Math: invariant does not fit into u128");
         }
     }
 
     panic!("Newton method for D failed to converge");
 }
 
-/// Helper function used to calculate the D invariant as a last step in the `compute_d` public function.
+/// Helper function used to calculate the D invariant as a last step in the `compute_d` function.
 ///
 /// * **Equation**:
 ///
 /// d = (leverage * sum_x + d_product * n_coins) * initial_d / ((leverage - 1) * initial_d + (n_coins + 1) * d_product)
 fn calculate_step(
-    initial_d: Decimal,
-    leverage: Decimal,
-    sum_x: Decimal,
-    d_product: Decimal,
-) -> Decimal {
-    let leverage_mul = leverage * sum_x;
-    let d_p_mul = d_product * N_COINS;
+    initial_d: &U256,
+    leverage: &U256,
+    sum_x: &U256,
+    d_product: &U256,
+    n_coins: &U256,
+    amp_precision: &U256,
+) -> U256 {
+    let env = initial_d.env();
+
+    let l_val = leverage
+        .mul(sum_x)
+        .div(amp_precision)
+        .add(&d_product.mul(n_coins))
+        .mul(initial_d);
+    let r_val = leverage
+        .sub(amp_precision)
+        .mul(initial_d)
+        .div(amp_precision)
+        .add(&n_coins.add(&U256::from_u32(env, 1)).mul(d_product));
 
-    let l_val = leverage_mul + d_p_mul * initial_d;
-    let leverage_sub = initial_d * (leverage - Decimal::one());
-    let n_coins_sum = d_product * (N_COINS + Decimal::one());
+    l_val.div(&r_val)
+}
 
-    let r_val = leverage_sub + n_coins_sum;
+/// Computes the new normalized balance of the token at `ask_index`, given that the
+/// normalized balance of the token at `offer_index` changes to `new_amount` and the
+/// invariant stays the same.
+pub(crate) fn calc_y(
+    env: &Env,
+    amp: u128,
+    offer_index: u32,
+    new_amount: u128,
+    ask_index: u32,
+    xp: &Vec<u128>,
+) -> u128 {
+    let d = compute_d(env, amp, xp);
+    let mut new_xp = xp.clone();
+    new_xp.set(offer_index, new_amount);
 
-    l_val / r_val
+    calc_y_d(env, amp, ask_index, &new_xp, d)
 }
 
-/// Compute the swap amount `y` in proportion to `x`.
+/// Computes the normalized balance of the token at `index` for which the invariant of the pool
+/// with the other balances of `xp` equals `d`.
 ///
 /// * **Solve for y**
 ///
 /// y**2 + y * (sum' - (A*n**n - 1) * D / (A * n**n)) = D ** (n + 1) / (n ** (2 * n) * prod' * A)
 ///
 /// y**2 + b*y = c
-pub(crate) fn calc_y(amp: u128, new_amount: Decimal, xp: &[Decimal], target_precision: u8) -> i128 {
-    let d = compute_d(amp, xp);
-    let leverage = Decimal::from_ratio(amp as i128, 1u8) * N_COINS;
-    let amp_prec = Decimal::from_ratio(AMP_PRECISION, 1u8);
+pub(crate) fn calc_y_d(env: &Env, amp: u128, index: u32, xp: &Vec<u128>, d: u128) -> u128 {
+    let d = U256::from_u128(env, d);
+    let n_coins = U256::from_u32(env, xp.len());
+    let amp_precision = U256::from_u128(env, AMP_PRECISION as u128);
+    let leverage = U256::from_u128(env, amp).mul(&n_coins);
+    let one = U256::from_u32(env, 1);
 
-    let c = d.pow(3) * amp_prec / (new_amount * N_COINS * N_COINS * leverage);
-    let b = new_amount + d * amp_prec / leverage;
+    let mut c = d.clone();
+    let mut sum_x = U256::from_u32(env, 0);
+    for (i, x) in xp.iter().enumerate() {
+        if i as u32 == index {
+            continue;
+        }
+        let x = U256::from_u128(env, x);
+        sum_x = sum_x.add(&x);
+        c = c.mul(&d).div(&x.mul(&n_coins));
+    }
+    let c = c.mul(&d).mul(&amp_precision).div(&leverage.mul(&n_coins));
+    let b = sum_x.add(&d.mul(&amp_precision).div(&leverage));
 
     // Solve for y by approximating: y**2 + b*y = c
-    let mut y_prev;
-    let mut y = d;
+    let mut y = d.clone();
     for _ in 0..ITERATIONS {
-        y_prev = y;
-        y = (y.pow(2) + c) / (y * N_COINS + b - d);
-        if (y - y_prev).abs() <= TOL {
-            return y.to_i128_with_precision(target_precision);
+        let y_prev = y.clone();
+        y = y
+            .mul(&y)
+            .add(&c)
+            .div(&y.mul(&U256::from_u32(env, 2)).add(&b).sub(&d));
+        if abs_diff(&y, &y_prev) <= one {
+            return y
+                .to_u128()
+                .expect("Pool stable: Math: balance does not fit into u128");
         }
     }
 
     // Should definitely converge in 64 iterations.
     panic!("y is not converging");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use soroban_sdk::vec;
+
+    const ONE: u128 = 1_000_000_000_000_000_000;
+
+    #[test]
+    fn test_compute_d_balanced_pool_equals_sum() {
+        let env = Env::default();
+        let xp = vec![&env, 1_000 * ONE, 1_000 * ONE, 1_000 * ONE];
+
+        let d = compute_d(&env, 10 * AMP_PRECISION as u128, &xp);
+        assert!(d.abs_diff(3_000 * ONE) <= 1);
+    }
+
+    #[test]
+    fn test_compute_d_empty_pool() {
+        let env = Env::default();
+        let xp = vec![&env, 0, 0];
+
+        assert_eq!(compute_d(&env, 10 * AMP_PRECISION as u128, &xp), 0);
+    }
+
+    #[test]
+    fn test_compute_d_imbalanced_pool_below_sum() {
+        let env = Env::default();
+        let xp = vec![&env, 1_500 * ONE, 500 * ONE];
+
+        let d = compute_d(&env, 10 * AMP_PRECISION as u128, &xp);
+        assert!(d < 2_000 * ONE);
+        // the constant product invariant is the lower bound
+        assert!(d > 2 * 866 * ONE);
+    }
+
+    #[test]
+    fn test_calc_y_keeps_invariant() {
+        let env = Env::default();
+        let amp = 10 * AMP_PRECISION as u128;
+        let xp = vec![&env, 1_000 * ONE, 1_000 * ONE, 1_000 * ONE, 1_000 * ONE];
+
+        let new_offer = 1_100 * ONE;
+        let y = calc_y(&env, amp, 0, new_offer, 3, &xp);
+        // the ask balance drops by a bit less than the offered amount
+        assert!(y > 900 * ONE && y < 901 * ONE);
+
+        let d_before = compute_d(&env, amp, &xp);
+        let d_after = compute_d(
+            &env,
+            amp,
+            &vec![&env, new_offer, 1_000 * ONE, 1_000 * ONE, y],
+        );
+        assert!(d_before.abs_diff(d_after) <= 2);
+    }
+
+    #[test]
+    fn test_calc_y_d_solves_for_invariant() {
+        let env = Env::default();
+        let amp = 10 * AMP_PRECISION as u128;
+        let xp = vec![&env, 1_000 * ONE, 1_000 * ONE];
+
+        // the current invariant gives back the current balance
+        let d = compute_d(&env, amp, &xp);
+        assert!(calc_y_d(&env, amp, 1, &xp, d).abs_diff(1_000 * ONE) <= 1);
+
+        // removing 10% of the invariant through one token leaves the pool imbalanced, so a bit
+        // less than 200 tokens can be taken out
+        let y = calc_y_d(&env, amp, 1, &xp, d - d / 10);
+        assert!(y > 801 * ONE && y < 802 * ONE);
+    }
+
+    #[test]
+    fn test_normalize_and_denormalize() {
+        assert_eq!(normalize(1_234_567, 6), 1_234_567 * 1_000_000_000_000);
+        assert_eq!(
+            denormalize(1_234_567 * 1_000_000_000_000 + 999, 6),
+            1_234_567
+        );
+        assert_eq!(normalize(42, 18), 42);
+        assert_eq!(denormalize(42, 18), 42);
+    }
+
+    #[test]
+    fn test_upscale_and_downscale() {
+        let env = Env::default();
+        // a token of 6 decimals worth 1.1 of the others
+        let rate = RATE_ONE / 10 * 11;
+        assert_eq!(upscale(&env, 1_000_000, 6, rate), 11 * ONE / 10);
+        assert_eq!(downscale(&env, 11 * ONE / 10, 6, rate), 1_000_000);
+        assert_eq!(downscale(&env, ONE, 6, rate), 909_090);
+        assert_eq!(upscale(&env, 42, 18, RATE_ONE), 42);
+    }
+}
@@ -1,21 +1,24 @@
 use soroban_sdk::{
     contracttype, log, symbol_short, xdr::ToXdr, Address, Bytes, BytesN, ConversionError, Env,
-    Symbol, TryFromVal, Val,
+    Symbol, TryFromVal, Val, Vec,
 };
 
 use crate::token_contract;
 use decimal::Decimal;
+use phoenix::error::StablePoolError;
 
 #[derive(Clone, Copy)]
 #[repr(u32)]
 pub enum DataKey {
     TotalShares = 0,
-    ReserveA = 1,
-    ReserveB = 2,
-    Admin = 3,
-    Initialized = 4,
-    Amp = 5,
-    MaxPrecision = 6,
+    Reserves = 1,
+    Admin = 2,
+    Initialized = 3,
+    Amp = 4,
+    TokenDecimals = 5,
+    Factory = 6,
+    Status = 7,
+    Rates = 8,
 }
 
 impl TryFromVal<Env, DataKey> for Val {
@@ -31,13 +34,14 @@ impl TryFromVal<Env, DataKey> for Val {
 #[repr(u32)]
 pub enum PairType {
     Xyk = 0,
+    Stable = 1,
 }
 
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Config {
-    pub token_a: Address,
-    pub token_b: Address,
+    /// Tokens of the pool, sorted alphabetically
+    pub tokens: Vec<Address>,
     pub share_token: Address,
     pub stake_contract: Address,
     pub pool_type: PairType,
@@ -49,18 +53,29 @@ pub struct Config {
     pub max_allowed_slippage_bps: i64,
     /// The maximum amount of spread (in bps) that is tolerated during swap
     pub max_allowed_spread_bps: i64,
+    /// The share of the swap commission (in bps) that is sent to the fee recipient.
+    /// The rest of the commission belongs to the liquidity providers
+    pub protocol_fee_share_bps: i64,
+    /// If set, the liquidity providers' part of the commission is sent to the stake contract
+    /// to be distributed as staking rewards, instead of staying in the pool reserves
+    pub distribute_lp_fees_to_stakers: bool,
+    /// Optional contract per token, in the order of `tokens`, that reports the exchange rate of
+    /// the token with `get_rate()`; empty if no token has one
+    pub rate_providers: Vec<Option<Address>>,
+    /// The time (in seconds) for which the rates reported by the rate providers are reused
+    pub max_rate_staleness: u64,
 }
 const CONFIG: Symbol = symbol_short!("CONFIG");
 
 const MAX_TOTAL_FEE_BPS: i64 = 10_000;
 
 /// This method is used to check fee bps.
-pub fn validate_fee_bps(env: &Env, total_fee_bps: i64) -> i64 {
+pub fn validate_fee_bps(env: &Env, total_fee_bps: i64) -> Result<i64, StablePoolError> {
     if total_fee_bps > MAX_TOTAL_FEE_BPS {
         log!(env, "Total fees cannot be greater than 100%");
-        panic!("Pool: Validate fee bps: total fees cannot be greater than 100%")
+        return Err(StablePoolError::InvalidBps);
     }
-    total_fee_bps
+    Ok(total_fee_bps)
 }
 
 impl Config {
@@ -68,9 +83,24 @@ impl Config {
         Decimal::bps(self.total_fee_bps)
     }
 
+    pub fn protocol_fee_share(&self) -> Decimal {
+        Decimal::bps(self.protocol_fee_share_bps)
+    }
+
     pub fn max_allowed_slippage(&self) -> Decimal {
         Decimal::bps(self.max_allowed_slippage_bps)
     }
+
+    /// Returns the position of the token in the pool
+    pub fn token_index(&self, env: &Env, token: &Address) -> Result<u32, StablePoolError> {
+        match self.tokens.first_index_of(token) {
+            Some(index) => Ok(index),
+            None => {
+                log!(env, "Token {} is not part of the pool", token);
+                Err(StablePoolError::AssetNotInPool)
+            }
+        }
+    }
 }
 
 pub fn get_config(env: &Env) -> Config {
@@ -81,24 +111,44 @@ pub fn save_config(env: &Env, config: Config) {
     env.storage().instance().set(&CONFIG, &config);
 }
 
-pub fn get_greatest_precision(env: &Env) -> i32 {
+/// Returns the number of decimals of each token, in the order of `Config::tokens`
+pub fn get_token_decimals(env: &Env) -> Vec<u32> {
     env.storage()
         .instance()
-        .get(&DataKey::MaxPrecision)
+        .get(&DataKey::TokenDecimals)
         .unwrap()
 }
 
-pub fn save_greatest_precision(env: &Env, token1: &Address, token2: &Address) {
-    let precision1 = token_contract::Client::new(env, token1).decimals();
-    let precision2 = token_contract::Client::new(env, token2).decimals();
-    let max_precision: u32 = if precision1 > precision2 {
-        precision1
-    } else {
-        precision2
-    };
+pub fn save_token_decimals(env: &Env, tokens: &Vec<Address>) {
+    let mut decimals = Vec::new(env);
+    for token in tokens.iter() {
+        decimals.push_back(token_contract::Client::new(env, &token).decimals());
+    }
     env.storage()
         .instance()
-        .set(&DataKey::MaxPrecision, &i32::try_from(max_precision).ok());
+        .set(&DataKey::TokenDecimals, &decimals);
+}
+
+pub fn get_greatest_precision(env: &Env) -> u32 {
+    get_token_decimals(env).iter().max().unwrap()
+}
+
+/// The rates of every token last reported by the rate providers
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedRates {
+    /// Rates with 18 decimals, in the order of `Config::tokens`
+    pub rates: Vec<i128>,
+    /// Timestamp of the ledger in which the rates were fetched
+    pub updated_at: u64,
+}
+
+pub fn get_cached_rates(env: &Env) -> Option<CachedRates> {
+    env.storage().instance().get(&DataKey::Rates)
+}
+
+pub fn save_cached_rates(env: &Env, rates: &CachedRates) {
+    env.storage().instance().set(&DataKey::Rates, rates);
 }
 
 #[contracttype]
@@ -118,6 +168,17 @@ pub fn save_amp(env: &Env, amp: AmplifierParameters) {
     env.storage().instance().set(&DataKey::Amp, &amp);
 }
 
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AmpResponse {
+    /// Amplification coefficient in effect at the current ledger time
+    pub current_amp: u64,
+    /// Amplification coefficient the pool is ramping towards
+    pub next_amp: u64,
+    /// Timestamp at which `next_amp` is reached
+    pub next_amp_time: u64,
+}
+
 #[contracttype]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Asset {
@@ -131,10 +192,8 @@ pub struct Asset {
 #[contracttype]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PoolResponse {
-    /// The asset A in the pool together with asset amounts
-    pub asset_a: Asset,
-    /// The asset B in the pool together with asset amounts
-    pub asset_b: Asset,
+    /// Assets of the pool, in the order of `Config::tokens`
+    pub assets: Vec<Asset>,
     /// The total amount of LP tokens currently issued
     pub asset_lp_share: Asset,
 }
@@ -145,6 +204,7 @@ pub struct StableLiquidityPoolInfo {
     pub pool_address: Address,
     pub pool_response: PoolResponse,
     pub total_fee_bps: i64,
+    pub amp_parameters: AmplifierParameters,
 }
 
 #[contracttype]
@@ -164,18 +224,48 @@ pub struct SimulateReverseSwapResponse {
     pub spread_amount: i128,
 }
 
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProvideLiquidityResponse {
+    /// Amount of shares minted to the depositor
+    pub shares: i128,
+    /// Amounts taken from the depositor, in the order of `Config::tokens`
+    pub amounts: Vec<i128>,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulateWithdrawSingleResponse {
+    /// Amount of the out asset sent to the liquidity provider
+    pub return_amount: i128,
+    /// Commission charged for withdrawing the liquidity imbalanced
+    pub commission_amount: i128,
+    /// Difference between the value of the shares at a 1:1 rate and the amount before commission
+    pub spread_amount: i128,
+}
+
+/// The pool operations that are halted. Nothing is paused by default, and withdrawals stay
+/// open when swaps and deposits are paused unless they are paused explicitly
+#[contracttype]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoolStatus {
+    pub swaps_paused: bool,
+    pub deposits_paused: bool,
+    pub withdrawals_paused: bool,
+}
+
 pub mod utils {
     use super::*;
 
     pub fn deploy_token_contract(
         e: &Env,
         token_wasm_hash: BytesN<32>,
-        token_a: &Address,
-        token_b: &Address,
+        tokens: &Vec<Address>,
     ) -> Address {
         let mut salt = Bytes::new(e);
-        salt.append(&token_a.to_xdr(e));
-        salt.append(&token_b.to_xdr(e));
+        for token in tokens.iter() {
+            salt.append(&token.to_xdr(e));
+        }
         let salt = e.crypto().sha256(&salt);
         e.deployer()
             .with_current_contract(salt)
@@ -195,16 +285,20 @@ pub mod utils {
         e.storage().instance().set(&DataKey::Admin, &address)
     }
 
-    pub fn save_total_shares(e: &Env, amount: i128) {
-        e.storage().instance().set(&DataKey::TotalShares, &amount)
+    pub fn save_factory(e: &Env, address: Address) {
+        e.storage().instance().set(&DataKey::Factory, &address)
     }
 
-    pub fn save_pool_balance_a(e: &Env, amount: i128) {
-        e.storage().instance().set(&DataKey::ReserveA, &amount)
+    pub fn save_status(e: &Env, status: &PoolStatus) {
+        e.storage().instance().set(&DataKey::Status, status)
     }
 
-    pub fn save_pool_balance_b(e: &Env, amount: i128) {
-        e.storage().instance().set(&DataKey::ReserveB, &amount)
+    pub fn save_total_shares(e: &Env, amount: i128) {
+        e.storage().instance().set(&DataKey::TotalShares, &amount)
+    }
+
+    pub fn save_pool_balances(e: &Env, balances: &Vec<i128>) {
+        e.storage().instance().set(&DataKey::Reserves, balances)
     }
 
     pub fn mint_shares(e: &Env, share_token: &Address, to: &Address, amount: i128) {
@@ -228,15 +322,23 @@ pub mod utils {
         e.storage().instance().get(&DataKey::Admin).unwrap()
     }
 
-    pub fn get_total_shares(e: &Env) -> i128 {
-        e.storage().instance().get(&DataKey::TotalShares).unwrap()
+    pub fn get_factory(e: &Env) -> Address {
+        e.storage().instance().get(&DataKey::Factory).unwrap()
     }
-    pub fn get_pool_balance_a(e: &Env) -> i128 {
-        e.storage().instance().get(&DataKey::ReserveA).unwrap()
+
+    pub fn get_status(e: &Env) -> PoolStatus {
+        e.storage()
+            .instance()
+            .get(&DataKey::Status)
+            .unwrap_or_default()
     }
 
-    pub fn get_pool_balance_b(e: &Env) -> i128 {
-        e.storage().instance().get(&DataKey::ReserveB).unwrap()
+    pub fn get_total_shares(e: &Env) -> i128 {
+        e.storage().instance().get(&DataKey::TotalShares).unwrap()
+    }
+    /// Returns the pool balance of each token, in the order of `Config::tokens`
+    pub fn get_pool_balances(e: &Env) -> Vec<i128> {
+        e.storage().instance().get(&DataKey::Reserves).unwrap()
     }
 
     pub fn get_balance(e: &Env, contract: &Address) -> i128 {
@@ -275,33 +377,35 @@ mod tests {
 
     #[test]
     #[should_panic]
-    fn test_get_pool_balance_a_failure() {
+    fn test_get_pool_balances_failure() {
         let env = Env::default();
-        let _ = utils::get_pool_balance_a(&env);
+        let _ = utils::get_pool_balances(&env);
     }
 
     #[test]
     #[should_panic]
-    fn test_get_pool_balance_b_failure() {
+    fn test_get_token_decimals_failure() {
         let env = Env::default();
-        let _ = utils::get_pool_balance_b(&env);
+        let _ = get_token_decimals(&env);
     }
 
     #[test]
     fn test_validate_fee_bps() {
         let env = Env::default();
         let result = validate_fee_bps(&env, 0);
-        assert_eq!(result, 0);
+        assert_eq!(result, Ok(0));
         let result = validate_fee_bps(&env, 9999);
-        assert_eq!(result, 9999);
+        assert_eq!(result, Ok(9999));
         let result = validate_fee_bps(&env, 10_000);
-        assert_eq!(result, 10_000);
+        assert_eq!(result, Ok(10_000));
     }
 
     #[test]
-    #[should_panic(expected = "total fees cannot be greater than 100%")]
     fn test_invalidate_fee_bps() {
         let env = Env::default();
-        validate_fee_bps(&env, 10_001);
+        assert_eq!(
+            validate_fee_bps(&env, 10_001),
+            Err(StablePoolError::InvalidBps)
+        );
     }
 }
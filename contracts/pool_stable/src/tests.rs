@@ -1,5 +1,8 @@
+mod amp;
 mod config;
+mod decimals;
 mod liquidity;
+mod rate_provider;
 mod setup;
 mod stake_deployment;
 mod swap;
@@ -0,0 +1,272 @@
+extern crate std;
+use phoenix::error::StablePoolError;
+use soroban_sdk::{
+    testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation, Ledger},
+    Address, Env, IntoVal, Symbol,
+};
+
+use super::setup::{deploy_stable_liquidity_pool_contract, deploy_token_contract};
+use crate::contract::StableLiquidityPoolClient;
+use crate::math::MIN_AMP_CHANGING_TIME;
+use crate::storage::AmpResponse;
+
+// setup.rs initializes every stable pool with amp of 10
+fn deploy_pool<'a>(env: &Env, admin: &Address) -> StableLiquidityPoolClient<'a> {
+    let mut token1 = deploy_token_contract(env, admin);
+    let mut token2 = deploy_token_contract(env, admin);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+    }
+
+    deploy_stable_liquidity_pool_contract(
+        env,
+        admin.clone(),
+        &[&token1.address, &token2.address],
+        0i64,
+        None,
+        None,
+        None,
+    )
+}
+
+#[test]
+fn start_change_amp_ramps_linearly() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let pool = deploy_pool(&env, &admin);
+
+    assert_eq!(
+        pool.query_amp(),
+        AmpResponse {
+            current_amp: 10,
+            next_amp: 10,
+            next_amp_time: 0,
+        }
+    );
+
+    let next_amp_time = 2 * MIN_AMP_CHANGING_TIME;
+    pool.start_change_amp(&50, &next_amp_time);
+    assert_eq!(
+        env.auths(),
+        [(
+            admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    pool.address.clone(),
+                    Symbol::new(&env, "start_change_amp"),
+                    (50u64, next_amp_time).into_val(&env),
+                )),
+                sub_invocations: std::vec![],
+            }
+        )]
+    );
+
+    // halfway through the ramp
+    env.ledger().with_mut(|li| {
+        li.timestamp = MIN_AMP_CHANGING_TIME;
+    });
+    assert_eq!(
+        pool.query_amp(),
+        AmpResponse {
+            current_amp: 30,
+            next_amp: 50,
+            next_amp_time,
+        }
+    );
+
+    // after the ramp amp stays at the target value
+    env.ledger().with_mut(|li| {
+        li.timestamp = 3 * MIN_AMP_CHANGING_TIME;
+    });
+    assert_eq!(
+        pool.query_amp(),
+        AmpResponse {
+            current_amp: 50,
+            next_amp: 50,
+            next_amp_time,
+        }
+    );
+}
+
+#[test]
+fn start_change_amp_ramps_down() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let pool = deploy_pool(&env, &admin);
+
+    pool.start_change_amp(&1, &MIN_AMP_CHANGING_TIME);
+
+    env.ledger().with_mut(|li| {
+        li.timestamp = MIN_AMP_CHANGING_TIME / 2;
+    });
+    // 10 - (10 - 1) / 2 = 5.5, reported without the amp precision
+    assert_eq!(pool.query_amp().current_amp, 5);
+
+    env.ledger().with_mut(|li| {
+        li.timestamp = MIN_AMP_CHANGING_TIME;
+    });
+    assert_eq!(pool.query_amp().current_amp, 1);
+}
+
+#[test]
+fn stop_change_amp_freezes_current_value() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let pool = deploy_pool(&env, &admin);
+
+    pool.start_change_amp(&50, &(2 * MIN_AMP_CHANGING_TIME));
+
+    env.ledger().with_mut(|li| {
+        li.timestamp = MIN_AMP_CHANGING_TIME;
+    });
+    pool.stop_change_amp();
+
+    let expected = AmpResponse {
+        current_amp: 30,
+        next_amp: 30,
+        next_amp_time: MIN_AMP_CHANGING_TIME,
+    };
+    assert_eq!(pool.query_amp(), expected);
+
+    env.ledger().with_mut(|li| {
+        li.timestamp = 3 * MIN_AMP_CHANGING_TIME;
+    });
+    assert_eq!(pool.query_amp(), expected);
+}
+
+#[test]
+fn start_change_amp_up_to_max_change() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let pool = deploy_pool(&env, &admin);
+
+    // amp of 10 can grow up to 10 times in a single ramp
+    pool.start_change_amp(&100, &MIN_AMP_CHANGING_TIME);
+
+    env.ledger().with_mut(|li| {
+        li.timestamp = MIN_AMP_CHANGING_TIME;
+    });
+    assert_eq!(pool.query_amp().current_amp, 100);
+}
+
+#[test]
+fn start_change_amp_increase_too_big() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let pool = deploy_pool(&env, &admin);
+
+    assert_eq!(
+        pool.try_start_change_amp(&101, &MIN_AMP_CHANGING_TIME),
+        Err(Ok(StablePoolError::AmpChangeTooBig))
+    );
+}
+
+#[test]
+fn start_change_amp_decrease_too_big() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let pool = deploy_pool(&env, &admin);
+
+    pool.start_change_amp(&50, &MIN_AMP_CHANGING_TIME);
+    env.ledger().with_mut(|li| {
+        li.timestamp = MIN_AMP_CHANGING_TIME;
+    });
+
+    // from 50 down to 4 is more than 10 times lower
+    assert_eq!(
+        pool.try_start_change_amp(&4, &(2 * MIN_AMP_CHANGING_TIME)),
+        Err(Ok(StablePoolError::AmpChangeTooBig))
+    );
+}
+
+#[test]
+fn start_change_amp_too_short_ramp() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let pool = deploy_pool(&env, &admin);
+
+    env.ledger().with_mut(|li| {
+        li.timestamp = 1_000;
+    });
+    assert_eq!(
+        pool.try_start_change_amp(&20, &MIN_AMP_CHANGING_TIME),
+        Err(Ok(StablePoolError::AmpChangeTimeTooShort))
+    );
+}
+
+#[test]
+fn start_change_amp_too_soon_after_previous_ramp() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let pool = deploy_pool(&env, &admin);
+
+    pool.start_change_amp(&100, &MIN_AMP_CHANGING_TIME);
+
+    // the first ramp is almost done, a second one would take amp above 10 times in a day
+    env.ledger().with_mut(|li| {
+        li.timestamp = MIN_AMP_CHANGING_TIME - 1;
+    });
+    assert_eq!(
+        pool.try_start_change_amp(&900, &(2 * MIN_AMP_CHANGING_TIME)),
+        Err(Ok(StablePoolError::AmpChangeTooSoon))
+    );
+
+    // stopping the ramp does not allow starting a new one sooner
+    pool.stop_change_amp();
+    assert_eq!(
+        pool.try_start_change_amp(&900, &(2 * MIN_AMP_CHANGING_TIME)),
+        Err(Ok(StablePoolError::AmpChangeTooSoon))
+    );
+
+    env.ledger().with_mut(|li| {
+        li.timestamp = MIN_AMP_CHANGING_TIME;
+    });
+    pool.start_change_amp(&900, &(2 * MIN_AMP_CHANGING_TIME));
+    assert_eq!(
+        pool.query_amp(),
+        AmpResponse {
+            current_amp: 99,
+            next_amp: 900,
+            next_amp_time: 2 * MIN_AMP_CHANGING_TIME,
+        }
+    );
+}
+
+#[test]
+fn start_change_amp_to_zero() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let pool = deploy_pool(&env, &admin);
+
+    assert_eq!(
+        pool.try_start_change_amp(&0, &MIN_AMP_CHANGING_TIME),
+        Err(Ok(StablePoolError::InvalidAmp))
+    );
+}
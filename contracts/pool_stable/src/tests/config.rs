@@ -1,8 +1,11 @@
 extern crate std;
-use soroban_sdk::{testutils::Address as _, Address, Env};
+use phoenix::error::StablePoolError;
+use soroban_sdk::{testutils::Address as _, vec, Address, Env, InvokeError};
 
-use super::setup::{deploy_stable_liquidity_pool_contract, deploy_token_contract};
-use crate::storage::{Config, PairType};
+use super::setup::{
+    deploy_sorted_token_contracts, deploy_stable_liquidity_pool_contract, deploy_token_contract,
+};
+use crate::storage::{Config, PairType, PoolStatus};
 
 #[test]
 fn update_config() {
@@ -24,7 +27,7 @@ fn update_config() {
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         Some(admin1.clone()),
-        (&token1.address, &token2.address),
+        &[&token1.address, &token2.address],
         swap_fees,
         user1.clone(),
         500,
@@ -37,15 +40,18 @@ fn update_config() {
     assert_eq!(
         pool.query_config(),
         Config {
-            token_a: token1.address.clone(),
-            token_b: token2.address.clone(),
+            tokens: vec![&env, token1.address.clone(), token2.address.clone()],
             share_token: share_token_address.clone(),
             stake_contract: stake_token_address.clone(),
-            pool_type: PairType::Xyk,
+            pool_type: PairType::Stable,
             total_fee_bps: 0,
             fee_recipient: user1,
             max_allowed_slippage_bps: 500,
             max_allowed_spread_bps: 200,
+            protocol_fee_share_bps: 10_000,
+            distribute_lp_fees_to_stakers: false,
+            rate_providers: vec![&env],
+            max_rate_staleness: 0,
         }
     );
 
@@ -57,42 +63,60 @@ fn update_config() {
         &Some(admin2.clone()),
         &None,
         &None,
+        &None,
+        &None,
+        &None,
     );
     assert_eq!(
         pool.query_config(),
         Config {
-            token_a: token1.address.clone(),
-            token_b: token2.address.clone(),
+            tokens: vec![&env, token1.address.clone(), token2.address.clone()],
             share_token: share_token_address.clone(),
             stake_contract: stake_token_address.clone(),
-            pool_type: PairType::Xyk,
+            pool_type: PairType::Stable,
             total_fee_bps: 500,
             fee_recipient: admin2.clone(),
             max_allowed_slippage_bps: 500,
             max_allowed_spread_bps: 200,
+            protocol_fee_share_bps: 10_000,
+            distribute_lp_fees_to_stakers: false,
+            rate_providers: vec![&env],
+            max_rate_staleness: 0,
         }
     );
 
     // update slippage and spread
-    pool.update_config(&admin1, &None, &None, &None, &Some(5_000i64), &Some(500));
+    pool.update_config(
+        &admin1,
+        &None,
+        &None,
+        &None,
+        &Some(5_000i64),
+        &Some(500),
+        &None,
+        &None,
+        &None,
+    );
     assert_eq!(
         pool.query_config(),
         Config {
-            token_a: token1.address.clone(),
-            token_b: token2.address,
+            tokens: vec![&env, token1.address.clone(), token2.address],
             share_token: share_token_address,
             stake_contract: stake_token_address,
-            pool_type: PairType::Xyk,
+            pool_type: PairType::Stable,
             total_fee_bps: 500,
             fee_recipient: admin2,
             max_allowed_slippage_bps: 5_000,
             max_allowed_spread_bps: 500,
+            protocol_fee_share_bps: 10_000,
+            distribute_lp_fees_to_stakers: false,
+            rate_providers: vec![&env],
+            max_rate_staleness: 0,
         }
     );
 }
 
 #[test]
-#[should_panic(expected = "Pool: UpdateConfig: Unauthorize")]
 fn update_config_unauthorized() {
     let env = Env::default();
     env.mock_all_auths();
@@ -111,23 +135,72 @@ fn update_config_unauthorized() {
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         Some(admin1.clone()),
-        (&token1.address, &token2.address),
+        &[&token1.address, &token2.address],
         swap_fees,
         user1,
         500,
         200,
     );
 
-    pool.update_config(
-        &Address::generate(&env),
-        &None,
-        &Some(500i64), // 5% fees
-        &Some(admin2.clone()),
-        &None,
-        &None,
+    assert_eq!(
+        pool.try_update_config(
+            &Address::generate(&env),
+            &None,
+            &Some(500i64), // 5% fees
+            &Some(admin2.clone()),
+            &None,
+            &None,
+            &None,
+            &None,
+            &None,
+        ),
+        Err(Ok(StablePoolError::Unauthorized))
     );
 }
 
+#[test]
+fn update_config_without_admin_authorization() {
+    let env = Env::default();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+    let user1 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let swap_fees = 0i64;
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        Some(admin1.clone()),
+        &[&token1.address, &token2.address],
+        swap_fees,
+        user1,
+        500,
+        200,
+    );
+
+    // passing the address of the admin is not enough, the admin has to authorize the call
+    assert_eq!(
+        pool.try_update_config(
+            &admin1,
+            &None,
+            &Some(500i64), // 5% fees
+            &Some(admin2.clone()),
+            &None,
+            &None,
+            &None,
+            &None,
+            &None,
+        ),
+        Err(Err(InvokeError::Abort))
+    );
+    assert_eq!(pool.query_config().total_fee_bps, 0);
+}
+
 #[test]
 fn update_config_update_admin() {
     let env = Env::default();
@@ -148,7 +221,7 @@ fn update_config_update_admin() {
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         Some(admin1.clone()),
-        (&token1.address, &token2.address),
+        &[&token1.address, &token2.address],
         swap_fees,
         user1.clone(),
         500,
@@ -156,31 +229,53 @@ fn update_config_update_admin() {
     );
 
     // update admin to new admin
-    pool.update_config(&admin1, &Some(admin2.clone()), &None, &None, &None, &None);
+    pool.update_config(
+        &admin1,
+        &Some(admin2.clone()),
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+    );
 
     let share_token_address = pool.query_share_token_address();
     let stake_token_address = pool.query_stake_contract_address();
 
     // now update succeeds
-    pool.update_config(&admin2, &None, &None, &None, &None, &Some(3_000_000));
+    pool.update_config(
+        &admin2,
+        &None,
+        &None,
+        &None,
+        &None,
+        &Some(3_000_000),
+        &None,
+        &None,
+        &None,
+    );
     assert_eq!(
         pool.query_config(),
         Config {
-            token_a: token1.address.clone(),
-            token_b: token2.address,
+            tokens: vec![&env, token1.address.clone(), token2.address],
             share_token: share_token_address,
             stake_contract: stake_token_address,
-            pool_type: PairType::Xyk,
+            pool_type: PairType::Stable,
             total_fee_bps: 0,
             fee_recipient: user1,
             max_allowed_slippage_bps: 500,
             max_allowed_spread_bps: 3_000_000,
+            protocol_fee_share_bps: 10_000,
+            distribute_lp_fees_to_stakers: false,
+            rate_providers: vec![&env],
+            max_rate_staleness: 0,
         }
     );
 }
 
 #[test]
-#[should_panic(expected = "Pool: UpdateConfig: Invalid total_fee_bps")]
 fn update_config_too_high_fees() {
     let env = Env::default();
     env.mock_all_auths();
@@ -199,7 +294,7 @@ fn update_config_too_high_fees() {
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         Some(admin1.clone()),
-        (&token1.address, &token2.address),
+        &[&token1.address, &token2.address],
         swap_fees,
         user1,
         500,
@@ -207,12 +302,141 @@ fn update_config_too_high_fees() {
     );
 
     // update fees and recipient
-    pool.update_config(
-        &admin1,
-        &None,
-        &Some(10_100i64), // 101% fees
-        &Some(admin2.clone()),
+    assert_eq!(
+        pool.try_update_config(
+            &admin1,
+            &None,
+            &Some(10_100i64), // 101% fees
+            &Some(admin2.clone()),
+            &None,
+            &None,
+            &None,
+            &None,
+            &None,
+        ),
+        Err(Ok(StablePoolError::InvalidBps))
+    );
+}
+
+#[test]
+fn update_config_too_high_protocol_fee_share() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let mut admin1 = Address::generate(&env);
+    let mut admin2 = Address::generate(&env);
+
+    let mut token1 = deploy_token_contract(&env, &admin1);
+    let mut token2 = deploy_token_contract(&env, &admin2);
+    if token2.address < token1.address {
+        std::mem::swap(&mut token1, &mut token2);
+        std::mem::swap(&mut admin1, &mut admin2);
+    }
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        Some(admin1.clone()),
+        &[&token1.address, &token2.address],
+        0i64,
+        Address::generate(&env),
+        500,
+        200,
+    );
+
+    assert_eq!(
+        pool.try_update_config(
+            &admin1,
+            &None,
+            &None,
+            &None,
+            &None,
+            &None,
+            &Some(10_001),
+            &None,
+            &None,
+        ),
+        Err(Ok(StablePoolError::InvalidBps))
+    );
+}
+
+#[test]
+fn pause_swaps_deposits_and_withdrawals() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        admin.clone(),
+        &[&token1.address, &token2.address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &2_000_000);
+    token2.mint(&user1, &1_000_000);
+    pool.provide_liquidity(
+        &user1,
+        &vec![&env, 1_000_000, 1_000_000],
         &None,
+        &false,
         &None,
     );
+
+    pool.update_pause_status(&admin, &Some(true), &Some(true), &None);
+    assert_eq!(
+        pool.query_status(),
+        PoolStatus {
+            swaps_paused: true,
+            deposits_paused: true,
+            withdrawals_paused: false,
+        }
+    );
+
+    assert_eq!(
+        pool.try_swap(
+            &user1,
+            &token1.address,
+            &token2.address,
+            &1_000,
+            &None,
+            &None,
+            &None
+        ),
+        Err(Ok(StablePoolError::SwapsPaused))
+    );
+    assert_eq!(
+        pool.try_swap_exact_out(
+            &user1,
+            &token1.address,
+            &token2.address,
+            &1_000,
+            &2_000,
+            &None
+        ),
+        Err(Ok(StablePoolError::SwapsPaused))
+    );
+    assert_eq!(
+        pool.try_provide_liquidity(&user1, &vec![&env, 1_000, 1_000], &None, &false, &None),
+        Err(Ok(StablePoolError::DepositsPaused))
+    );
+
+    // withdrawals stay open until they are paused explicitly
+    pool.withdraw_liquidity(&user1, &1_000, &vec![&env, 1, 1]);
+
+    pool.update_pause_status(&admin, &None, &None, &Some(true));
+    assert_eq!(
+        pool.try_withdraw_liquidity(&user1, &1_000, &vec![&env, 1, 1]),
+        Err(Ok(StablePoolError::WithdrawalsPaused))
+    );
+
+    assert_eq!(
+        pool.try_update_pause_status(&user1, &Some(false), &None, &None),
+        Err(Ok(StablePoolError::Unauthorized))
+    );
 }
@@ -0,0 +1,250 @@
+extern crate std;
+
+use pretty_assertions::assert_eq;
+use soroban_sdk::{testutils::Address as _, vec, Address, Env};
+
+use super::setup::{
+    deploy_sorted_token_contracts_with_decimals, deploy_stable_liquidity_pool_contract,
+};
+use crate::token_contract;
+
+/// Amount of one whole token
+fn unit(token: &token_contract::Client) -> i128 {
+    10i128.pow(token.decimals())
+}
+
+#[test]
+fn swap_tokens_with_different_decimals() {
+    for decimals in [[6, 7], [6, 18], [7, 18]] {
+        let env = Env::default();
+        env.mock_all_auths();
+        env.budget().reset_unlimited();
+
+        let admin = Address::generate(&env);
+        let tokens = deploy_sorted_token_contracts_with_decimals(&env, &admin, &decimals);
+        let (token1, token2) = (&tokens[0], &tokens[1]);
+        let user1 = Address::generate(&env);
+        let pool = deploy_stable_liquidity_pool_contract(
+            &env,
+            None,
+            &[&token1.address, &token2.address],
+            0i64,
+            None,
+            None,
+            None,
+        );
+
+        token1.mint(&user1, &(1_010 * unit(token1)));
+        token2.mint(&user1, &(1_010 * unit(token2)));
+        pool.provide_liquidity(
+            &user1,
+            &vec![&env, 1_000 * unit(token1), 1_000 * unit(token2)],
+            &None,
+            &false,
+            &None,
+        );
+
+        // one token buys almost one token of the other asset, whatever their decimals
+        let simulation = pool.simulate_swap(&token1.address, &token2.address, &unit(token1));
+        assert!(simulation.ask_amount > unit(token2) * 999 / 1_000);
+        assert!(simulation.ask_amount < unit(token2));
+
+        // a belief price of 1.0 with a 1% max spread is satisfied
+        assert_eq!(
+            pool.swap(
+                &user1,
+                &token1.address,
+                &token2.address,
+                &unit(token1),
+                &Some(100),
+                &Some(100),
+                &None,
+            ),
+            simulation.ask_amount
+        );
+        assert_eq!(token1.balance(&user1), 9 * unit(token1));
+        assert_eq!(
+            token2.balance(&user1),
+            10 * unit(token2) + simulation.ask_amount
+        );
+
+        // and the other way around
+        let simulation = pool.simulate_swap(&token2.address, &token1.address, &unit(token2));
+        // the pool holds more of the first token now, so it is slightly cheaper
+        assert!(simulation.ask_amount > unit(token1));
+        assert!(simulation.ask_amount < unit(token1) * 1_001 / 1_000);
+        assert_eq!(
+            pool.swap(
+                &user1,
+                &token2.address,
+                &token1.address,
+                &unit(token2),
+                &Some(100),
+                &Some(100),
+                &None,
+            ),
+            simulation.ask_amount
+        );
+
+        let pool_info = pool.query_pool_info();
+        assert_eq!(
+            pool_info.assets.get(0).unwrap().amount,
+            token1.balance(&pool.address)
+        );
+        assert_eq!(
+            pool_info.assets.get(1).unwrap().amount,
+            token2.balance(&pool.address)
+        );
+    }
+}
+
+#[test]
+fn swap_exact_out_tokens_with_different_decimals() {
+    for decimals in [[6, 18], [18, 6]] {
+        let env = Env::default();
+        env.mock_all_auths();
+        env.budget().reset_unlimited();
+
+        let admin = Address::generate(&env);
+        let tokens = deploy_sorted_token_contracts_with_decimals(&env, &admin, &decimals);
+        let (token1, token2) = (&tokens[0], &tokens[1]);
+        let user1 = Address::generate(&env);
+        let pool = deploy_stable_liquidity_pool_contract(
+            &env,
+            None,
+            &[&token1.address, &token2.address],
+            0i64,
+            None,
+            None,
+            None,
+        );
+
+        token1.mint(&user1, &(1_010 * unit(token1)));
+        token2.mint(&user1, &(1_000 * unit(token2)));
+        pool.provide_liquidity(
+            &user1,
+            &vec![&env, 1_000 * unit(token1), 1_000 * unit(token2)],
+            &None,
+            &false,
+            &None,
+        );
+
+        let simulation =
+            pool.simulate_reverse_swap(&token1.address, &token2.address, &unit(token2));
+        assert!(simulation.offer_amount > unit(token1));
+        assert!(simulation.offer_amount < unit(token1) * 1_001 / 1_000);
+
+        assert_eq!(
+            pool.swap_exact_out(
+                &user1,
+                &token1.address,
+                &token2.address,
+                &unit(token2),
+                &(2 * unit(token1)),
+                &None,
+            ),
+            simulation.offer_amount
+        );
+        assert_eq!(
+            token1.balance(&user1),
+            10 * unit(token1) - simulation.offer_amount
+        );
+        assert_eq!(token2.balance(&user1), unit(token2));
+    }
+}
+
+#[test]
+fn provide_and_withdraw_liquidity_with_different_decimals() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts_with_decimals(&env, &admin, &[6, 7, 18]);
+    let (token1, token2, token3) = (&tokens[0], &tokens[1], &tokens[2]);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&token1.address, &token2.address, &token3.address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &(1_000 * unit(token1)));
+    token2.mint(&user1, &(1_000 * unit(token2)));
+    token3.mint(&user1, &(1_000 * unit(token3)));
+    // the shares have the precision of the token with the most decimals
+    let shares = pool
+        .provide_liquidity(
+            &user1,
+            &vec![
+                &env,
+                1_000 * unit(token1),
+                1_000 * unit(token2),
+                1_000 * unit(token3),
+            ],
+            &None,
+            &false,
+            &None,
+        )
+        .shares;
+    assert_eq!(shares, 3_000 * 10i128.pow(18) - 1_000);
+
+    // a single token is worth about a third of the shares of a balanced deposit of three tokens
+    token1.mint(&user2, &unit(token1));
+    let shares = pool
+        .provide_liquidity(
+            &user2,
+            &vec![&env, unit(token1), 0, 0],
+            &None,
+            &false,
+            &None,
+        )
+        .shares;
+    assert!(shares > 10i128.pow(18) * 999 / 1_000);
+    assert!(shares < 10i128.pow(18));
+
+    // and is withdrawn as almost one token of the asset with a different precision
+    let simulation = pool.simulate_withdraw_single(&shares, &token3.address);
+    assert!(simulation.return_amount > unit(token3) * 998 / 1_000);
+    assert!(simulation.return_amount < unit(token3));
+    assert_eq!(
+        pool.withdraw_liquidity_single(&user2, &shares, &token3.address, &1),
+        simulation.return_amount
+    );
+    assert_eq!(token3.balance(&user2), simulation.return_amount);
+
+    // the pro-rata withdrawal returns every token in its own precision
+    let amounts = pool.withdraw_liquidity(&user1, &(1_500 * 10i128.pow(18)), &vec![&env, 1, 1, 1]);
+    assert_eq!(amounts.get(0).unwrap() / unit(token1), 500);
+    assert_eq!(amounts.get(1).unwrap() / unit(token2), 500);
+    assert_eq!(amounts.get(2).unwrap() / unit(token3), 499);
+    assert_eq!(token1.balance(&user1), amounts.get(0).unwrap());
+    assert_eq!(token2.balance(&user1), amounts.get(1).unwrap());
+    assert_eq!(token3.balance(&user1), amounts.get(2).unwrap());
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #303)")]
+fn initialize_with_too_many_decimals() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts_with_decimals(&env, &admin, &[7, 19]);
+
+    deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&tokens[0].address, &tokens[1].address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+}
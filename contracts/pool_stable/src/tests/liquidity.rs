@@ -2,18 +2,19 @@ extern crate std;
 
 use pretty_assertions::assert_eq;
 
+use phoenix::error::StablePoolError;
 use soroban_sdk::{
     symbol_short,
-    testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation},
-    Address, Env, IntoVal, Symbol,
+    testutils::{Address as _, AuthorizedFunction, AuthorizedInvocation, Ledger},
+    vec, Address, Env, IntoVal, Symbol,
 };
 
-use super::setup::{deploy_stable_liquidity_pool_contract, deploy_token_contract};
+use super::setup::{deploy_sorted_token_contracts, deploy_stable_liquidity_pool_contract};
 use crate::{
-    storage::{Asset, PoolResponse},
+    stake_contract,
+    storage::{Asset, PoolResponse, ProvideLiquidityResponse, SimulateWithdrawSingleResponse},
     token_contract,
 };
-use decimal::Decimal;
 
 #[test]
 fn provide_liqudity() {
@@ -21,21 +22,15 @@ fn provide_liqudity() {
     env.mock_all_auths();
     env.budget().reset_unlimited();
 
-    let mut admin1 = Address::generate(&env);
-    let mut admin2 = Address::generate(&env);
-
-    let mut token1 = deploy_token_contract(&env, &admin1);
-    let mut token2 = deploy_token_contract(&env, &admin2);
-    if token2.address < token1.address {
-        std::mem::swap(&mut token1, &mut token2);
-        std::mem::swap(&mut admin1, &mut admin2);
-    }
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
     let user1 = Address::generate(&env);
     let swap_fees = 0i64;
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         None,
-        (&token1.address, &token2.address),
+        &[&token1.address, &token2.address],
         swap_fees,
         None,
         None,
@@ -45,18 +40,14 @@ fn provide_liqudity() {
     let share_token_address = pool.query_share_token_address();
     let token_share = token_contract::Client::new(&env, &share_token_address);
 
-    token1.mint(&user1, &1000);
-    assert_eq!(token1.balance(&user1), 1000);
-
-    token2.mint(&user1, &1000);
-    assert_eq!(token2.balance(&user1), 1000);
+    token1.mint(&user1, &1_001_000);
+    token2.mint(&user1, &1_001_000);
 
     pool.provide_liquidity(
         &user1,
-        &Some(100),
-        &Some(100),
-        &Some(100),
-        &Some(100),
+        &vec![&env, 1_000_000, 1_000_000],
+        &None,
+        &false,
         &None,
     );
 
@@ -70,11 +61,10 @@ fn provide_liqudity() {
                     Symbol::new(&env, "provide_liquidity"),
                     (
                         &user1,
-                        Some(100i128),
-                        Some(100i128),
-                        Some(100i128),
-                        Some(100i128),
-                        None::<i64>
+                        vec![&env, 1_000_000i128, 1_000_000i128],
+                        None::<i64>,
+                        false,
+                        None::<i128>
                     )
                         .into_val(&env),
                 )),
@@ -83,7 +73,7 @@ fn provide_liqudity() {
                         function: AuthorizedFunction::Contract((
                             token1.address.clone(),
                             symbol_short!("transfer"),
-                            (&user1, &pool.address, 100_i128).into_val(&env)
+                            (&user1, &pool.address, 1_000_000_i128).into_val(&env)
                         )),
                         sub_invocations: std::vec![],
                     },
@@ -91,7 +81,7 @@ fn provide_liqudity() {
                         function: AuthorizedFunction::Contract((
                             token2.address.clone(),
                             symbol_short!("transfer"),
-                            (&user1, &pool.address, 100_i128).into_val(&env)
+                            (&user1, &pool.address, 1_000_000_i128).into_val(&env)
                         )),
                         sub_invocations: std::vec![],
                     },
@@ -100,54 +90,155 @@ fn provide_liqudity() {
         ),]
     );
 
-    assert_eq!(token_share.balance(&user1), 100);
+    // invariant of a balanced pool is the sum of its balances, minus the locked minimum liquidity
+    assert_eq!(token_share.balance(&user1), 1_999_000);
     assert_eq!(token_share.balance(&pool.address), 0);
-    assert_eq!(token1.balance(&user1), 900);
-    assert_eq!(token1.balance(&pool.address), 100);
-    assert_eq!(token2.balance(&user1), 900);
-    assert_eq!(token2.balance(&pool.address), 100);
+    assert_eq!(token1.balance(&user1), 1_000);
+    assert_eq!(token1.balance(&pool.address), 1_000_000);
+    assert_eq!(token2.balance(&user1), 1_000);
+    assert_eq!(token2.balance(&pool.address), 1_000_000);
 
     let result = pool.query_pool_info();
     assert_eq!(
         result,
         PoolResponse {
-            asset_a: Asset {
-                address: token1.address,
-                amount: 100i128
-            },
-            asset_b: Asset {
-                address: token2.address,
-                amount: 100i128
-            },
+            assets: vec![
+                &env,
+                Asset {
+                    address: token1.address.clone(),
+                    amount: 1_000_000i128
+                },
+                Asset {
+                    address: token2.address.clone(),
+                    amount: 1_000_000i128
+                },
+            ],
             asset_lp_share: Asset {
                 address: share_token_address,
-                amount: 100i128
+                amount: 1_999_000i128
             }
         }
     );
 }
 
 #[test]
-fn withdraw_liquidity() {
+fn provide_liqudity_twice_proportionally() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&token1.address, &token2.address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    let token_share = token_contract::Client::new(&env, &pool.query_share_token_address());
+
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
+    pool.provide_liquidity(
+        &user1,
+        &vec![&env, 1_000_000, 1_000_000],
+        &None,
+        &false,
+        &None,
+    );
+
+    // second deposit is half of the first one, so it gets half of the shares
+    token1.mint(&user2, &500_000);
+    token2.mint(&user2, &500_000);
+    pool.provide_liquidity(&user2, &vec![&env, 500_000, 500_000], &None, &false, &None);
+
+    assert_eq!(token_share.balance(&user1), 1_999_000);
+    assert_eq!(token_share.balance(&user2), 999_500);
+    assert_eq!(token1.balance(&pool.address), 1_500_000);
+    assert_eq!(token2.balance(&pool.address), 1_500_000);
+}
+
+#[test]
+fn provide_liqudity_three_tokens() {
     let env = Env::default();
     env.mock_all_auths();
     env.budget().reset_unlimited();
 
-    let mut admin1 = Address::generate(&env);
-    let mut admin2 = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 3);
     let user1 = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&tokens[0].address, &tokens[1].address, &tokens[2].address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    let share_token_address = pool.query_share_token_address();
+    let token_share = token_contract::Client::new(&env, &share_token_address);
 
-    let mut token1 = deploy_token_contract(&env, &admin1);
-    let mut token2 = deploy_token_contract(&env, &admin2);
-    if token2.address < token1.address {
-        std::mem::swap(&mut token1, &mut token2);
-        std::mem::swap(&mut admin1, &mut admin2);
+    for token in tokens.iter() {
+        token.mint(&user1, &1_000_000);
     }
+    pool.provide_liquidity(
+        &user1,
+        &vec![&env, 1_000_000, 1_000_000, 1_000_000],
+        &None,
+        &false,
+        &None,
+    );
+
+    assert_eq!(token_share.balance(&user1), 2_999_000);
+    for token in tokens.iter() {
+        assert_eq!(token.balance(&user1), 0);
+        assert_eq!(token.balance(&pool.address), 1_000_000);
+    }
+
+    let result = pool.query_pool_info();
+    assert_eq!(result.assets.len(), 3);
+    for (asset, token) in result.assets.iter().zip(tokens.iter()) {
+        assert_eq!(
+            asset,
+            Asset {
+                address: token.address.clone(),
+                amount: 1_000_000
+            }
+        );
+    }
+    assert_eq!(
+        result.asset_lp_share,
+        Asset {
+            address: share_token_address,
+            amount: 2_999_000
+        }
+    );
+}
+
+#[test]
+fn withdraw_liquidity() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
     let swap_fees = 0i64;
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         None,
-        (&token1.address, &token2.address),
+        &[&token1.address, &token2.address],
         swap_fees,
         None,
         None,
@@ -157,493 +248,1067 @@ fn withdraw_liquidity() {
     let share_token_address = pool.query_share_token_address();
     let token_share = token_contract::Client::new(&env, &share_token_address);
 
-    token1.mint(&user1, &100);
-    token2.mint(&user1, &100);
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
     pool.provide_liquidity(
         &user1,
-        &Some(100),
-        &Some(100),
-        &Some(100),
-        &Some(100),
+        &vec![&env, 1_000_000, 1_000_000],
+        &None,
+        &false,
         &None,
     );
 
     assert_eq!(token1.balance(&user1), 0);
-    assert_eq!(token1.balance(&pool.address), 100);
+    assert_eq!(token1.balance(&pool.address), 1_000_000);
     assert_eq!(token2.balance(&user1), 0);
-    assert_eq!(token2.balance(&pool.address), 100);
-
-    let share_amount = 50;
-    let min_a = 50;
-    let min_b = 50;
-    pool.withdraw_liquidity(&user1, &share_amount, &min_a, &min_b);
-    // assert_eq!(
-    //     env.auths(),
-    //     [
-    //         (
-    //             user1.clone(),
-    //             pool.address.clone(),
-    //             Symbol::new(&env, "withdraw_liquidity"),
-    //             (&user1, 50_i128, 50_i128, 50_i128).into_val(&env)
-    //         ),
-    //         (
-    //             user1.clone(),
-    //             share_token_address.clone(),
-    //             Symbol::short("transfer"),
-    //             (&user1, &pool.address, 50_i128).into_val(&env)
-    //         )
-    //     ]
-    // );
-
-    assert_eq!(token_share.balance(&user1), 50);
+    assert_eq!(token2.balance(&pool.address), 1_000_000);
+
+    // withdrawing half of the shares returns half of the pool
+    let share_amount = 999_500;
+    let min_amounts = vec![&env, 500_000, 500_000];
+    let returned = pool.withdraw_liquidity(&user1, &share_amount, &min_amounts);
+    assert_eq!(returned, vec![&env, 500_000, 500_000]);
+
+    assert_eq!(token_share.balance(&user1), 999_500);
     assert_eq!(token_share.balance(&pool.address), 0); // sanity check
-    assert_eq!(token1.balance(&user1), 50);
-    assert_eq!(token1.balance(&pool.address), 50);
-    assert_eq!(token2.balance(&user1), 50);
-    assert_eq!(token2.balance(&pool.address), 50);
+    assert_eq!(token1.balance(&user1), 500_000);
+    assert_eq!(token1.balance(&pool.address), 500_000);
+    assert_eq!(token2.balance(&user1), 500_000);
+    assert_eq!(token2.balance(&pool.address), 500_000);
 
     let result = pool.query_pool_info();
     assert_eq!(
         result,
         PoolResponse {
-            asset_a: Asset {
-                address: token1.address.clone(),
-                amount: 50i128,
-            },
-            asset_b: Asset {
-                address: token2.address.clone(),
-                amount: 50i128,
-            },
+            assets: vec![
+                &env,
+                Asset {
+                    address: token1.address.clone(),
+                    amount: 500_000i128
+                },
+                Asset {
+                    address: token2.address.clone(),
+                    amount: 500_000i128
+                },
+            ],
             asset_lp_share: Asset {
                 address: share_token_address,
-                amount: 50i128,
-            },
+                amount: 999_500i128
+            }
         }
     );
 
     // clear the pool
-    pool.withdraw_liquidity(&user1, &share_amount, &min_a, &min_b);
+    pool.withdraw_liquidity(&user1, &999_500, &vec![&env, 500_000, 500_000]);
     assert_eq!(token_share.balance(&user1), 0);
-    assert_eq!(token_share.balance(&pool.address), 0); // sanity check
-    assert_eq!(token1.balance(&user1), 100);
-    assert_eq!(token1.balance(&pool.address), 0);
-    assert_eq!(token2.balance(&user1), 100);
-    assert_eq!(token2.balance(&pool.address), 0);
+    assert_eq!(token1.balance(&user1), 1_000_000);
+    assert_eq!(token2.balance(&user1), 1_000_000);
 }
 
 #[test]
-#[should_panic = "Pool: split_deposit_based_on_pool_ratio: Both pools and deposit must be a positive!"]
-fn provide_liqudity_single_asset_on_empty_pool() {
+fn provide_liqudity_with_auto_stake() {
     let env = Env::default();
     env.mock_all_auths();
+    env.budget().reset_unlimited();
 
-    let mut admin1 = Address::generate(&env);
-    let mut admin2 = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
     let user1 = Address::generate(&env);
-
-    let mut token1 = deploy_token_contract(&env, &admin1);
-    let mut token2 = deploy_token_contract(&env, &admin2);
-    if token2.address < token1.address {
-        std::mem::swap(&mut token1, &mut token2);
-        std::mem::swap(&mut admin1, &mut admin2);
-    }
-    let swap_fees = 0i64;
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         None,
-        (&token1.address, &token2.address),
-        swap_fees,
+        &[&token1.address, &token2.address],
+        0i64,
         None,
         None,
         None,
     );
 
-    token1.mint(&user1, &1_000_000);
+    let token_share = token_contract::Client::new(&env, &pool.query_share_token_address());
+    let stake_address = pool.query_stake_contract_address();
+    let stake = stake_contract::Client::new(&env, &stake_address);
 
-    // providing liquidity with single asset is not allowed on an empty pool
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
     pool.provide_liquidity(
         &user1,
-        &Some(1_000_000),
-        &Some(1_000_000),
+        &vec![&env, 1_000_000, 1_000_000],
         &None,
+        &true,
         &None,
+    );
+
+    // all minted shares are bonded on behalf of the user
+    assert_eq!(token_share.balance(&user1), 0);
+    assert_eq!(token_share.balance(&stake_address), 1_999_000);
+    assert_eq!(
+        stake.query_staked(&user1).stakes,
+        vec![
+            &env,
+            stake_contract::Stake {
+                stake: 1_999_000,
+                stake_timestamp: 0,
+            }
+        ]
+    );
+}
+
+#[test]
+fn unstake_and_withdraw() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&token1.address, &token2.address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    let stake = stake_contract::Client::new(&env, &pool.query_stake_contract_address());
+
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
+    env.ledger().with_mut(|li| {
+        li.timestamp = 100;
+    });
+    pool.provide_liquidity(
+        &user1,
+        &vec![&env, 1_000_000, 1_000_000],
+        &None,
+        &true,
         &None,
     );
+
+    env.ledger().with_mut(|li| {
+        li.timestamp = 200;
+    });
+    let returned =
+        pool.unstake_and_withdraw(&user1, &1_999_000, &100, &vec![&env, 999_000, 999_000]);
+    assert_eq!(returned, vec![&env, 1_000_000, 1_000_000]);
+
+    assert_eq!(stake.query_staked(&user1).stakes, vec![&env]);
+    assert_eq!(token1.balance(&user1), 1_000_000);
+    assert_eq!(token2.balance(&user1), 1_000_000);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #3)")]
+fn provide_liqudity_too_high_fees() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let swap_fees = 10_001i64;
+    deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&tokens[0].address, &tokens[1].address],
+        swap_fees,
+        None,
+        None,
+        None,
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #301)")]
+fn initialize_with_one_token() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 1);
+    deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&tokens[0].address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #301)")]
+fn initialize_with_five_tokens() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 5);
+    let addresses: std::vec::Vec<&Address> = tokens.iter().map(|token| &token.address).collect();
+    deploy_stable_liquidity_pool_contract(&env, None, &addresses, 0i64, None, None, None);
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #302)")]
+fn initialize_with_unsorted_tokens() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 3);
+    deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&tokens[0].address, &tokens[2].address, &tokens[1].address],
+        0i64,
+        None,
+        None,
+        None,
+    );
 }
 
 #[test]
-fn provide_liqudity_single_asset_equal() {
+#[should_panic(expected = "HostError: Error(Contract, #302)")]
+fn initialize_with_duplicated_token() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 1);
+    deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&tokens[0].address, &tokens[0].address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+}
+
+#[test]
+fn provide_liqudity_missing_amount() {
     let env = Env::default();
     env.mock_all_auths();
     env.budget().reset_unlimited();
 
-    let mut admin1 = Address::generate(&env);
-    let mut admin2 = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 3);
     let user1 = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&tokens[0].address, &tokens[1].address, &tokens[2].address],
+        0i64,
+        None,
+        None,
+        None,
+    );
 
-    let mut token1 = deploy_token_contract(&env, &admin1);
-    let mut token2 = deploy_token_contract(&env, &admin2);
-    if token2.address < token1.address {
-        std::mem::swap(&mut token1, &mut token2);
-        std::mem::swap(&mut admin1, &mut admin2);
+    for token in tokens.iter() {
+        token.mint(&user1, &1_000_000);
     }
-    let swap_fees = 0i64;
+    assert_eq!(
+        pool.try_provide_liquidity(
+            &user1,
+            &vec![&env, 1_000_000, 1_000_000],
+            &None,
+            &false,
+            &None
+        ),
+        Err(Ok(StablePoolError::AmountsLengthMismatch))
+    );
+}
+
+#[test]
+fn withdraw_liqudity_below_min() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         None,
-        (&token1.address, &token2.address),
-        swap_fees,
+        &[&token1.address, &token2.address],
+        0i64,
         None,
         None,
         None,
     );
 
-    token1.mint(&user1, &10_000_000);
-    token2.mint(&user1, &10_000_000);
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
+    pool.provide_liquidity(
+        &user1,
+        &vec![&env, 1_000_000, 1_000_000],
+        &None,
+        &false,
+        &None,
+    );
+
+    let share_amount = 999_500;
+    // Expecting the minimum of the second token as huge bigger then available
+    assert_eq!(
+        pool.try_withdraw_liquidity(&user1, &share_amount, &vec![&env, 500_000, 3_000_000]),
+        Err(Ok(StablePoolError::WithdrawMinNotSatisfied))
+    );
+}
 
-    // providing liquidity with single asset is not allowed on an empty pool
+#[test]
+fn skim_sends_donated_tokens_to_recipient() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let donor = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        admin.clone(),
+        &[&token1.address, &token2.address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
     pool.provide_liquidity(
         &user1,
-        &Some(10_000_000),
-        &Some(10_000_000),
-        &Some(10_000_000),
-        &Some(10_000_000),
+        &vec![&env, 1_000_000, 1_000_000],
+        &None,
+        &false,
         &None,
     );
-    assert_eq!(token1.balance(&pool.address), 10_000_000);
-    assert_eq!(token2.balance(&pool.address), 10_000_000);
+    assert_eq!(pool.query_reserve_discrepancy(), vec![&env, 0, 0]);
+
+    // tokens sent directly to the pool are not part of the reserves
+    token1.mint(&donor, &5_000);
+    token2.mint(&donor, &3_000);
+    token1.transfer(&donor, &pool.address, &5_000);
+    token2.transfer(&donor, &pool.address, &3_000);
+    assert_eq!(pool.query_reserve_discrepancy(), vec![&env, 5_000, 3_000]);
+
+    assert_eq!(pool.skim(&admin, &recipient), vec![&env, 5_000, 3_000]);
+    assert_eq!(token1.balance(&recipient), 5_000);
+    assert_eq!(token2.balance(&recipient), 3_000);
+    assert_eq!(token1.balance(&pool.address), 1_000_000);
+    assert_eq!(token2.balance(&pool.address), 1_000_000);
+    assert_eq!(pool.query_reserve_discrepancy(), vec![&env, 0, 0]);
+
+    // nothing left to skim
+    assert_eq!(pool.skim(&admin, &recipient), vec![&env, 0, 0]);
+}
 
-    token1.mint(&user1, &100_000);
+#[test]
+fn skim_unauthorized() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        admin,
+        &[&token1.address, &token2.address],
+        0i64,
+        None,
+        None,
+        None,
+    );
 
-    // Providing 100k of token1 to 1:1 pool will perform swap which will create imbalance
+    token1.mint(&user1, &1_005_000);
+    token2.mint(&user1, &1_000_000);
     pool.provide_liquidity(
         &user1,
-        &Some(100_000),
-        &Some(50_000),
+        &vec![&env, 1_000_000, 1_000_000],
         &None,
-        &Some(49_000),
+        &false,
         &None,
     );
-    // before swap : A(10_000_000), B(10_000_000)
-    // since pool is equal divides 50/50 sum for swap
-    // swap 50k A for B = 49752
-    // after swap : A(10_050_000), B(9_950_248)
-    // after providing liquidity
-    // A(1_100_000), B(1_000_000)
+    token1.transfer(&user1, &pool.address, &5_000);
 
-    assert_eq!(token1.balance(&pool.address), 10_100_000);
-    // because of lack of fees, first swap took from pool b exact amount
-    // that was provided to the pool in the next step
-    assert_eq!(token2.balance(&pool.address), 10_000_000);
-    assert_eq!(token1.balance(&user1), 0);
-    assert_eq!(token2.balance(&user1), 0);
+    assert_eq!(
+        pool.try_skim(&user1, &user1),
+        Err(Ok(StablePoolError::Unauthorized))
+    );
+    assert_eq!(token1.balance(&pool.address), 1_005_000);
 }
 
 #[test]
-fn provide_liqudity_single_asset_equal_with_fees() {
+fn sync_adds_donated_tokens_to_reserves() {
     let env = Env::default();
     env.mock_all_auths();
     env.budget().reset_unlimited();
 
-    let mut admin1 = Address::generate(&env);
-    let mut admin2 = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
     let user1 = Address::generate(&env);
-
-    let mut token1 = deploy_token_contract(&env, &admin1);
-    let mut token2 = deploy_token_contract(&env, &admin2);
-    if token2.address < token1.address {
-        std::mem::swap(&mut token1, &mut token2);
-        std::mem::swap(&mut admin1, &mut admin2);
-    }
-    let swap_fees = 1_000i64; // 10% bps
+    let donor = Address::generate(&env);
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
-        None,
-        (&token1.address, &token2.address),
-        swap_fees,
+        admin.clone(),
+        &[&token1.address, &token2.address],
+        0i64,
         None,
         None,
         None,
     );
+    let share_token_address = pool.query_share_token_address();
 
-    let initial_pool_liquidity = 10_000_000;
-    token1.mint(&user1, &initial_pool_liquidity);
-    token2.mint(&user1, &initial_pool_liquidity);
-
-    // providing liquidity with single asset is not allowed on an empty pool
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
     pool.provide_liquidity(
         &user1,
-        &Some(initial_pool_liquidity),
-        &Some(initial_pool_liquidity),
-        &Some(initial_pool_liquidity),
-        &Some(initial_pool_liquidity),
+        &vec![&env, 1_000_000, 1_000_000],
+        &None,
+        &false,
         &None,
     );
-    assert_eq!(token1.balance(&pool.address), initial_pool_liquidity);
-    assert_eq!(token2.balance(&pool.address), initial_pool_liquidity);
 
-    let token_a_amount = 100_000;
-    token1.mint(&user1, &token_a_amount);
-    // Providing 100k of token1 to 1:1 pool will perform swap which will create imbalance
+    token2.mint(&donor, &10_000);
+    token2.transfer(&donor, &pool.address, &10_000);
+    assert_eq!(pool.query_reserve_discrepancy(), vec![&env, 0, 10_000]);
+
+    assert_eq!(
+        pool.try_sync(&user1),
+        Err(Ok(StablePoolError::Unauthorized))
+    );
+    assert_eq!(pool.query_reserve_discrepancy(), vec![&env, 0, 10_000]);
+
+    pool.sync(&admin);
+    assert_eq!(
+        env.auths(),
+        [(
+            admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    pool.address.clone(),
+                    Symbol::new(&env, "sync"),
+                    (&admin,).into_val(&env)
+                )),
+                sub_invocations: std::vec![],
+            }
+        ),]
+    );
+    assert_eq!(pool.query_reserve_discrepancy(), vec![&env, 0, 0]);
+
+    let result = pool.query_pool_info();
+    assert_eq!(
+        result,
+        PoolResponse {
+            assets: vec![
+                &env,
+                Asset {
+                    address: token1.address.clone(),
+                    amount: 1_000_000i128
+                },
+                Asset {
+                    address: token2.address.clone(),
+                    amount: 1_010_000i128
+                },
+            ],
+            asset_lp_share: Asset {
+                address: share_token_address,
+                amount: 1_999_000i128
+            }
+        }
+    );
+}
+
+#[test]
+fn withdraw_liquidity_single() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&token1.address, &token2.address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+    let share_token_address = pool.query_share_token_address();
+    let token_share = token_contract::Client::new(&env, &share_token_address);
+
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
     pool.provide_liquidity(
         &user1,
-        &Some(token_a_amount),
-        &Some(50_000),
+        &vec![&env, 1_000_000, 1_000_000],
         &None,
-        &Some(49_000),
+        &false,
         &None,
     );
-    // before swap : A(10_000_000), B(10_000_000)
-    // algorithm splits 100k in such way, so that after swapping (with 10% fee)
-    // it will provide liquidity maintining 1:1 ratio
-    // split is 47_266 token A and 47213 token B (52_734 of token A was swapped to B)
-    // after swap : A(10_052_734), B(9_947_542)
-    // after providing liquidity
-    // A(1_100_000), B(9_994_755)
+    let shares = token_share.balance(&user1);
 
-    // return_amount: i128 = ask_pool - (cp / (offer_pool + offer_amount))
-    let return_amount = 52_458; // that's how many tokens B would be received from 52_734 tokens A
-    let fees = Decimal::percent(10);
+    // the shares are worth 200_100 at a 1:1 rate; taking them out of one token leaves the pool
+    // imbalanced, which costs a bit of spread
+    let simulation = pool.simulate_withdraw_single(&200_000, &token1.address);
     assert_eq!(
-        token1.balance(&pool.address),
-        initial_pool_liquidity + token_a_amount
+        simulation,
+        SimulateWithdrawSingleResponse {
+            return_amount: 199_088,
+            commission_amount: 0,
+            spread_amount: 1_012,
+        }
     );
+
     assert_eq!(
-        token2.balance(&pool.address),
-        initial_pool_liquidity - return_amount * fees
+        pool.withdraw_liquidity_single(
+            &user1,
+            &200_000,
+            &token1.address,
+            &simulation.return_amount
+        ),
+        simulation.return_amount
     );
-    assert_eq!(token1.balance(&user1), 0);
+    assert_eq!(
+        env.auths(),
+        [(
+            user1.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    pool.address.clone(),
+                    Symbol::new(&env, "withdraw_liquidity_single"),
+                    (
+                        &user1,
+                        200_000i128,
+                        token1.address.clone(),
+                        simulation.return_amount
+                    )
+                        .into_val(&env)
+                )),
+                sub_invocations: std::vec![AuthorizedInvocation {
+                    function: AuthorizedFunction::Contract((
+                        share_token_address.clone(),
+                        symbol_short!("transfer"),
+                        (&user1, &pool.address, 200_000_i128).into_val(&env)
+                    )),
+                    sub_invocations: std::vec![],
+                }],
+            }
+        ),]
+    );
+
+    assert_eq!(token_share.balance(&user1), shares - 200_000);
+    assert_eq!(token1.balance(&user1), simulation.return_amount);
     assert_eq!(token2.balance(&user1), 0);
+
+    let result = pool.query_pool_info();
+    assert_eq!(
+        result.assets,
+        vec![
+            &env,
+            Asset {
+                address: token1.address.clone(),
+                amount: 1_000_000 - simulation.return_amount
+            },
+            Asset {
+                address: token2.address.clone(),
+                amount: 1_000_000i128
+            },
+        ]
+    );
+    assert_eq!(pool.query_reserve_discrepancy(), vec![&env, 0, 0]);
 }
 
 #[test]
-fn provide_liqudity_single_asset_one_third() {
+fn withdraw_liquidity_single_with_fees() {
     let env = Env::default();
     env.mock_all_auths();
     env.budget().reset_unlimited();
 
-    let mut admin1 = Address::generate(&env);
-    let mut admin2 = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
     let user1 = Address::generate(&env);
-
-    let mut token1 = deploy_token_contract(&env, &admin1);
-    let mut token2 = deploy_token_contract(&env, &admin2);
-    if token2.address < token1.address {
-        std::mem::swap(&mut token1, &mut token2);
-        std::mem::swap(&mut admin1, &mut admin2);
-    }
-    let swap_fees = 0i64;
+    let fee_recipient = Address::generate(&env);
+    // 1% swap fee
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         None,
-        (&token1.address, &token2.address),
-        swap_fees,
-        None,
+        &[&token1.address, &token2.address],
+        100i64,
+        fee_recipient.clone(),
         None,
         None,
     );
 
-    token1.mint(&user1, &10_000_000);
-    token2.mint(&user1, &30_000_000);
-
-    // providing liquidity with single asset is not allowed on an empty pool
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
     pool.provide_liquidity(
         &user1,
-        &Some(10_000_000),
-        &Some(10_000_000),
-        &Some(30_000_000),
-        &Some(30_000_000),
+        &vec![&env, 1_000_000, 1_000_000],
+        &None,
+        &false,
         &None,
     );
-    assert_eq!(token1.balance(&pool.address), 10_000_000);
-    assert_eq!(token2.balance(&pool.address), 30_000_000);
 
-    token2.mint(&user1, &100_000);
-    // Providing 100k of token2 to 1:3 pool will perform swap which will create imbalance
-    let slippage_tolerance_bps = 300; // 3%
+    let simulation = pool.simulate_withdraw_single(&200_000, &token2.address);
+    assert_eq!(
+        simulation,
+        SimulateWithdrawSingleResponse {
+            return_amount: 198_103,
+            commission_amount: 985,
+            spread_amount: 1_012,
+        }
+    );
+
+    assert_eq!(
+        pool.withdraw_liquidity_single(&user1, &200_000, &token2.address, &1),
+        simulation.return_amount
+    );
+    assert_eq!(token2.balance(&user1), simulation.return_amount);
+    // the whole commission goes to the fee recipient by default
+    assert_eq!(token2.balance(&fee_recipient), simulation.commission_amount);
+
+    let result = pool.query_pool_info();
+    assert_eq!(result.assets.get(0).unwrap().amount, 1_000_000);
+    assert_eq!(
+        result.assets.get(1).unwrap().amount,
+        1_000_000 - simulation.return_amount - simulation.commission_amount
+    );
+    assert_eq!(pool.query_reserve_discrepancy(), vec![&env, 0, 0]);
+}
+
+#[test]
+fn withdraw_liquidity_single_fails() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        admin.clone(),
+        &[&token1.address, &token2.address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+    let token_share = token_contract::Client::new(&env, &pool.query_share_token_address());
+
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
     pool.provide_liquidity(
         &user1,
+        &vec![&env, 1_000_000, 1_000_000],
         &None,
+        &false,
         &None,
-        &Some(100_000),
-        &None,
-        &Some(slippage_tolerance_bps),
     );
-    // before swap : A(10_000_000), B(30_000_000)
-    // since pool is 1/3 divides 75k/25k sum for swap
-    // swap 25k B for A = 8327
-    // after swap : A(9_991_673), B(30_025_000)
-    // after providing liquidity
-    // A(10_000_000), B(30_100_000)
+    let shares = token_share.balance(&user1);
+
+    let simulation = pool.simulate_withdraw_single(&200_000, &token1.address);
+    assert_eq!(
+        pool.try_withdraw_liquidity_single(
+            &user1,
+            &200_000,
+            &token1.address,
+            &(simulation.return_amount + 1)
+        ),
+        Err(Ok(StablePoolError::WithdrawMinNotSatisfied))
+    );
+
+    let not_in_pool = Address::generate(&env);
+    assert_eq!(
+        pool.try_withdraw_liquidity_single(&user1, &200_000, &not_in_pool, &1),
+        Err(Ok(StablePoolError::AssetNotInPool))
+    );
+    assert_eq!(
+        pool.try_simulate_withdraw_single(&200_000, &not_in_pool),
+        Err(Ok(StablePoolError::AssetNotInPool))
+    );
 
-    assert_eq!(token1.balance(&pool.address), 10_000_000);
-    assert_eq!(token2.balance(&pool.address), 30_100_000);
+    pool.update_pause_status(&admin, &Some(true), &None, &None);
+    assert_eq!(
+        pool.try_withdraw_liquidity_single(&user1, &200_000, &token1.address, &1),
+        Err(Ok(StablePoolError::SwapsPaused))
+    );
+    pool.update_pause_status(&admin, &Some(false), &None, &Some(true));
+    assert_eq!(
+        pool.try_withdraw_liquidity_single(&user1, &200_000, &token1.address, &1),
+        Err(Ok(StablePoolError::WithdrawalsPaused))
+    );
+
+    assert_eq!(token_share.balance(&user1), shares);
 }
 
 #[test]
-fn provide_liqudity_single_asset_one_third_with_fees() {
+fn provide_liquidity_single_sided() {
     let env = Env::default();
     env.mock_all_auths();
     env.budget().reset_unlimited();
 
-    let mut admin1 = Address::generate(&env);
-    let mut admin2 = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
     let user1 = Address::generate(&env);
-
-    let mut token1 = deploy_token_contract(&env, &admin1);
-    let mut token2 = deploy_token_contract(&env, &admin2);
-    if token2.address < token1.address {
-        std::mem::swap(&mut token1, &mut token2);
-        std::mem::swap(&mut admin1, &mut admin2);
-    }
-    let swap_fees = 1_000i64; // 10% bps
+    let user2 = Address::generate(&env);
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         None,
-        (&token1.address, &token2.address),
-        swap_fees,
+        &[&token1.address, &token2.address],
+        0i64,
         None,
         None,
         None,
     );
+    let share_token_address = pool.query_share_token_address();
+    let token_share = token_contract::Client::new(&env, &share_token_address);
 
-    token1.mint(&user1, &10_000_000);
-    token2.mint(&user1, &30_000_000);
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
+    assert_eq!(
+        pool.provide_liquidity(
+            &user1,
+            &vec![&env, 1_000_000, 1_000_000],
+            &None,
+            &false,
+            &None
+        )
+        .shares,
+        1_999_000
+    );
+
+    // a deposit in one token only mints a bit less than its value at a 1:1 rate
+    token1.mint(&user2, &100_000);
+    assert_eq!(
+        pool.provide_liquidity(&user2, &vec![&env, 100_000, 0], &None, &false, &None)
+            .shares,
+        99_733
+    );
+    assert_eq!(token_share.balance(&user2), 99_733);
+    assert_eq!(token1.balance(&user2), 0);
+
+    let result = pool.query_pool_info();
+    assert_eq!(
+        result.assets,
+        vec![
+            &env,
+            Asset {
+                address: token1.address.clone(),
+                amount: 1_100_000i128
+            },
+            Asset {
+                address: token2.address.clone(),
+                amount: 1_000_000i128
+            },
+        ]
+    );
+}
 
-    // providing liquidity with single asset is not allowed on an empty pool
+#[test]
+fn provide_liquidity_imbalanced_pays_fee() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+    let fee_recipient = Address::generate(&env);
+    // 1% swap fee
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&token1.address, &token2.address],
+        100i64,
+        fee_recipient.clone(),
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
     pool.provide_liquidity(
         &user1,
-        &Some(10_000_000),
-        &Some(10_000_000),
-        &Some(30_000_000),
-        &Some(30_000_000),
+        &vec![&env, 1_000_000, 1_000_000],
+        &None,
+        &false,
         &None,
     );
-    assert_eq!(token1.balance(&pool.address), 10_000_000);
-    assert_eq!(token2.balance(&pool.address), 30_000_000);
 
-    token2.mint(&user1, &100_000);
-    pool.provide_liquidity(&user1, &None, &None, &Some(100_000), &None, &None);
-    // before swap : A(10_000_000), B(30_000_000)
-    // since pool is 1/3 algorithm will split it around 15794/52734
-    // swap 47_226k B for A = 17_548 (-10% fee = 15_793)
-    // after swap : A(9_982_452), B(30_052_734)
-    // after providing liquidity
-    // A(10_000_000), B(30_100_000)
+    // a proportional deposit is not charged
+    token1.mint(&user2, &200_000);
+    token2.mint(&user2, &100_000);
+    assert_eq!(
+        pool.provide_liquidity(&user2, &vec![&env, 100_000, 100_000], &None, &false, &None)
+            .shares,
+        199_900
+    );
+    assert_eq!(token1.balance(&fee_recipient), 0);
+    assert_eq!(token2.balance(&fee_recipient), 0);
 
-    // return_amount: i128 = ask_pool - (cp / (offer_pool + offer_amount))
-    let return_amount = 17_548;
-    let fees = Decimal::percent(10);
+    // an imbalanced deposit pays the fee on the difference from a proportional one, in every token
     assert_eq!(
-        token1.balance(&pool.address),
-        10_000_000 - return_amount * fees
+        pool.provide_liquidity(&user2, &vec![&env, 100_000, 0], &None, &false, &None)
+            .shares,
+        99_253
     );
-    assert_eq!(token2.balance(&pool.address), 30_100_000);
+    assert_eq!(token1.balance(&fee_recipient), 250);
+    assert_eq!(token2.balance(&fee_recipient), 249);
+
+    let result = pool.query_pool_info();
+    assert_eq!(
+        result.assets.get(0).unwrap().amount,
+        1_200_000 - token1.balance(&fee_recipient)
+    );
+    assert_eq!(
+        result.assets.get(1).unwrap().amount,
+        1_100_000 - token2.balance(&fee_recipient)
+    );
+    assert_eq!(pool.query_reserve_discrepancy(), vec![&env, 0, 0]);
 }
 
 #[test]
-#[should_panic(expected = "The value 10001 is out of range. Must be between 0 and 10000 bps.")]
-fn provide_liqudity_too_high_fees() {
+fn provide_liquidity_above_slippage_tolerance() {
     let env = Env::default();
     env.mock_all_auths();
+    env.budget().reset_unlimited();
 
-    let mut admin1 = Address::generate(&env);
-    let mut admin2 = Address::generate(&env);
-
-    let mut token1 = deploy_token_contract(&env, &admin1);
-    let mut token2 = deploy_token_contract(&env, &admin2);
-    if token2.address < token1.address {
-        std::mem::swap(&mut token1, &mut token2);
-        std::mem::swap(&mut admin1, &mut admin2);
-    }
-    let swap_fees = 10_001i64;
-    deploy_stable_liquidity_pool_contract(
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+    // 1% swap fee
+    let pool = deploy_stable_liquidity_pool_contract(
         &env,
         None,
-        (&token1.address, &token2.address),
-        swap_fees,
+        &[&token1.address, &token2.address],
+        100i64,
         None,
         None,
         None,
     );
+
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
+    pool.provide_liquidity(
+        &user1,
+        &vec![&env, 1_000_000, 1_000_000],
+        &None,
+        &false,
+        &None,
+    );
+
+    // a proportional deposit pays no fees, so it passes any tolerance
+    token1.mint(&user2, &200_000);
+    token2.mint(&user2, &100_000);
+    pool.provide_liquidity(
+        &user2,
+        &vec![&env, 100_000, 100_000],
+        &Some(0),
+        &false,
+        &None,
+    );
+
+    // the imbalance fees of a single-sided deposit take away about 0.5% of its shares
+    let single_sided = vec![&env, 100_000, 0];
+    assert_eq!(
+        pool.try_provide_liquidity(&user2, &single_sided, &Some(40), &false, &None),
+        Err(Ok(StablePoolError::SlippageToleranceViolated))
+    );
+    assert_eq!(token1.balance(&user2), 100_000);
+
+    let expected_shares = pool.simulate_provide_liquidity(&single_sided).shares;
+    assert_eq!(
+        pool.provide_liquidity(&user2, &single_sided, &Some(60), &false, &None)
+            .shares,
+        expected_shares
+    );
+    assert_eq!(token1.balance(&user2), 0);
 }
 
 #[test]
-#[should_panic(
-    expected = "Pool: ProvideLiquidity: At least one token must be provided and must be bigger then 0!"
-)]
-fn swap_with_no_amounts() {
+fn provide_liquidity_invalid_amounts() {
     let env = Env::default();
     env.mock_all_auths();
+    env.budget().reset_unlimited();
 
-    let mut admin1 = Address::generate(&env);
-    let mut admin2 = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
     let user1 = Address::generate(&env);
-
-    let mut token1 = deploy_token_contract(&env, &admin1);
-    let mut token2 = deploy_token_contract(&env, &admin2);
-    if token2.address < token1.address {
-        std::mem::swap(&mut token1, &mut token2);
-        std::mem::swap(&mut admin1, &mut admin2);
-    }
-    let swap_fees = 0i64;
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         None,
-        (&token1.address, &token2.address),
-        swap_fees,
+        &[&token1.address, &token2.address],
+        0i64,
         None,
         None,
         None,
     );
 
-    token1.mint(&user1, &1_001_000);
-    token2.mint(&user1, &1_001_000);
-    // providing all amounts as None
-    pool.provide_liquidity(&user1, &None, &None, &None, &None, &None);
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
+
+    // the first deposit sets the price, so it needs every token
+    assert_eq!(
+        pool.try_provide_liquidity(&user1, &vec![&env, 1_000_000, 0], &None, &false, &None),
+        Err(Ok(StablePoolError::InvalidAmount))
+    );
+
+    pool.provide_liquidity(&user1, &vec![&env, 500_000, 500_000], &None, &false, &None);
+
+    assert_eq!(
+        pool.try_provide_liquidity(&user1, &vec![&env, 0, 0], &None, &false, &None),
+        Err(Ok(StablePoolError::InvalidAmount))
+    );
+    assert_eq!(
+        pool.try_provide_liquidity(&user1, &vec![&env, 1_000, -1], &None, &false, &None),
+        Err(Ok(StablePoolError::InvalidAmount))
+    );
 }
 
 #[test]
-#[should_panic(
-    expected = "Pool: WithdrawLiquidity: Minimum amount of token_a or token_b is not satisfied!"
-)]
-fn withdraw_liqudity_below_min() {
+fn provide_liquidity_matches_simulation() {
     let env = Env::default();
     env.mock_all_auths();
+    env.budget().reset_unlimited();
 
-    let mut admin1 = Address::generate(&env);
-    let mut admin2 = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
     let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&token1.address, &token2.address],
+        100i64,
+        None,
+        None,
+        None,
+    );
 
-    let mut token1 = deploy_token_contract(&env, &admin1);
-    let mut token2 = deploy_token_contract(&env, &admin2);
-    if token2.address < token1.address {
-        std::mem::swap(&mut token1, &mut token2);
-        std::mem::swap(&mut admin1, &mut admin2);
-    }
-    let swap_fees = 0i64;
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
+    let simulation = pool.simulate_provide_liquidity(&vec![&env, 1_000_000, 1_000_000]);
+    assert_eq!(
+        pool.provide_liquidity(
+            &user1,
+            &vec![&env, 1_000_000, 1_000_000],
+            &None,
+            &false,
+            &None
+        ),
+        simulation
+    );
+
+    token1.mint(&user2, &100_000);
+    let simulation = pool.simulate_provide_liquidity(&vec![&env, 100_000, 0]);
+    assert_eq!(
+        simulation,
+        ProvideLiquidityResponse {
+            shares: 99_234,
+            amounts: vec![&env, 100_000, 0],
+        }
+    );
+    assert_eq!(
+        pool.provide_liquidity(&user2, &vec![&env, 100_000, 0], &None, &false, &None),
+        simulation
+    );
+
+    let simulation = pool.simulate_withdraw_liquidity(&50_000);
+    assert_eq!(
+        pool.withdraw_liquidity(&user2, &50_000, &vec![&env, 1, 1]),
+        simulation
+    );
+    assert_eq!(token1.balance(&user2), simulation.get(0).unwrap());
+    assert_eq!(token2.balance(&user2), simulation.get(1).unwrap());
+}
+
+#[test]
+fn provide_liquidity_below_min_shares_out() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         None,
-        (&token1.address, &token2.address),
-        swap_fees,
+        &[&token1.address, &token2.address],
+        0i64,
         None,
         None,
         None,
     );
 
-    token1.mint(&user1, &100);
-    token2.mint(&user1, &100);
+    token1.mint(&user1, &1_100_000);
+    token2.mint(&user1, &1_000_000);
     pool.provide_liquidity(
         &user1,
-        &Some(100),
-        &Some(100),
-        &Some(100),
-        &Some(100),
+        &vec![&env, 1_000_000, 1_000_000],
+        &None,
+        &false,
         &None,
     );
 
-    let share_amount = 50;
-    // Expecting min_a and/or min_b as huge bigger then available
-    pool.withdraw_liquidity(&user1, &share_amount, &3000, &3000);
+    let simulation = pool.simulate_provide_liquidity(&vec![&env, 100_000, 0]);
+    assert_eq!(
+        pool.try_provide_liquidity(
+            &user1,
+            &vec![&env, 100_000, 0],
+            &None,
+            &false,
+            &Some(simulation.shares + 1)
+        ),
+        Err(Ok(StablePoolError::MinSharesNotSatisfied))
+    );
+    assert_eq!(token1.balance(&user1), 100_000);
+
+    assert_eq!(
+        pool.try_provide_liquidity(&user1, &vec![&env, 100_000, 0], &None, &false, &Some(0)),
+        Err(Ok(StablePoolError::InvalidAmount))
+    );
+
+    assert_eq!(
+        pool.provide_liquidity(
+            &user1,
+            &vec![&env, 100_000, 0],
+            &None,
+            &false,
+            &Some(simulation.shares)
+        ),
+        simulation
+    );
+    assert_eq!(token1.balance(&user1), 0);
 }
@@ -0,0 +1,257 @@
+extern crate std;
+use phoenix::error::StablePoolError;
+use pretty_assertions::assert_eq;
+use soroban_sdk::{
+    contract, contractimpl, symbol_short,
+    testutils::{Address as _, Events, Ledger},
+    vec, Address, Env, Symbol,
+};
+
+use super::setup::{
+    deploy_sorted_token_contracts, deploy_stable_liquidity_pool_contract_with_rate_providers,
+};
+use crate::math::RATE_ONE;
+
+const RATE: Symbol = symbol_short!("RATE");
+
+/// Rate provider in the tests, reporting the rate that was last set.
+#[contract]
+pub struct MockRateProvider;
+
+#[contractimpl]
+impl MockRateProvider {
+    pub fn set_rate(env: Env, rate: i128) {
+        env.storage().instance().set(&RATE, &rate);
+    }
+
+    pub fn get_rate(env: Env) -> i128 {
+        env.storage().instance().get(&RATE).unwrap()
+    }
+}
+
+fn deploy_rate_provider<'a>(env: &Env, rate: i128) -> MockRateProviderClient<'a> {
+    let rate_provider =
+        MockRateProviderClient::new(env, &env.register_contract(None, MockRateProvider));
+    rate_provider.set_rate(&rate);
+    rate_provider
+}
+
+#[test]
+fn swap_at_the_rate_of_the_rate_provider() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    // the first token is worth 1.1 of the second one, like a liquid staking token
+    let rate_provider = deploy_rate_provider(&env, RATE_ONE / 10 * 11);
+    let pool = deploy_stable_liquidity_pool_contract_with_rate_providers(
+        &env,
+        None,
+        &[&token1.address, &token2.address],
+        &[Some(rate_provider.address.clone()), None],
+        3_600,
+    );
+    assert_eq!(pool.query_rates(), vec![&env, RATE_ONE / 10 * 11, RATE_ONE]);
+
+    // a pool balanced by value
+    token1.mint(&user1, &1_001_000);
+    token2.mint(&user1, &1_100_000);
+    let shares = pool
+        .provide_liquidity(
+            &user1,
+            &vec![&env, 1_000_000, 1_100_000],
+            &None,
+            &false,
+            &None,
+        )
+        .shares;
+    assert_eq!(shares, 2_200_000 - 1_000);
+
+    let simulation = pool.simulate_swap(&token1.address, &token2.address, &1_000);
+    assert_eq!(simulation.ask_amount, 1_099);
+    assert_eq!(simulation.spread_amount, 1);
+    assert_eq!(
+        pool.swap(
+            &user1,
+            &token1.address,
+            &token2.address,
+            &1_000,
+            &None,
+            &None,
+            &None
+        ),
+        1_099
+    );
+    assert_eq!(token1.balance(&user1), 0);
+    assert_eq!(token2.balance(&user1), 1_099);
+
+    // and the other way around, in a pool that holds a bit more of the first token now
+    let simulation = pool.simulate_reverse_swap(&token2.address, &token1.address, &1_000);
+    assert_eq!(simulation.offer_amount, 1_100);
+    assert_eq!(simulation.spread_amount, 0);
+}
+
+#[test]
+fn rates_are_cached_until_stale() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let rate_provider = deploy_rate_provider(&env, RATE_ONE);
+    let pool = deploy_stable_liquidity_pool_contract_with_rate_providers(
+        &env,
+        admin.clone(),
+        &[&token1.address, &token2.address],
+        &[None, Some(rate_provider.address.clone())],
+        3_600,
+    );
+
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
+    pool.provide_liquidity(
+        &user1,
+        &vec![&env, 1_000_000, 1_000_000],
+        &None,
+        &false,
+        &None,
+    );
+    let simulation = pool.simulate_swap(&token1.address, &token2.address, &1_000);
+    assert_eq!(simulation.ask_amount, 999);
+
+    // the second token doubles in value, which the pool sees only once the cached rate is stale
+    rate_provider.set_rate(&(2 * RATE_ONE));
+    env.ledger().with_mut(|li| {
+        li.timestamp = 3_600;
+    });
+    assert_eq!(pool.query_rates(), vec![&env, RATE_ONE, RATE_ONE]);
+    assert_eq!(
+        pool.simulate_swap(&token1.address, &token2.address, &1_000),
+        simulation
+    );
+
+    env.ledger().with_mut(|li| {
+        li.timestamp = 3_601;
+    });
+    assert_eq!(pool.query_rates(), vec![&env, RATE_ONE, 2 * RATE_ONE]);
+    assert!(
+        pool.simulate_swap(&token1.address, &token2.address, &1_000)
+            .ask_amount
+            < 600
+    );
+
+    // the admin can make the pool fetch the rates on every ledger
+    rate_provider.set_rate(&RATE_ONE);
+    env.ledger().with_mut(|li| {
+        li.timestamp = 3_602;
+    });
+    pool.update_config(
+        &admin,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &Some(0),
+    );
+    assert_eq!(pool.query_config().max_rate_staleness, 0);
+    assert_eq!(pool.query_rates(), vec![&env, RATE_ONE, RATE_ONE]);
+}
+
+#[test]
+fn queries_do_not_cache_rates() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let rate_provider = deploy_rate_provider(&env, RATE_ONE);
+    let pool = deploy_stable_liquidity_pool_contract_with_rate_providers(
+        &env,
+        None,
+        &[&token1.address, &token2.address],
+        &[None, Some(rate_provider.address.clone())],
+        3_600,
+    );
+
+    // the queries and simulations fetch the rates without storing them or publishing events
+    let event_count = env.events().all().len();
+    assert_eq!(pool.query_rates(), vec![&env, RATE_ONE, RATE_ONE]);
+    pool.simulate_swap(&token1.address, &token2.address, &1_000);
+    assert_eq!(env.events().all().len(), event_count);
+
+    rate_provider.set_rate(&(2 * RATE_ONE));
+    assert_eq!(pool.query_rates(), vec![&env, RATE_ONE, 2 * RATE_ONE]);
+
+    // the state changing entry points cache the rates they fetched
+    token1.mint(&user1, &1_000_000);
+    token2.mint(&user1, &1_000_000);
+    pool.provide_liquidity(
+        &user1,
+        &vec![&env, 1_000_000, 1_000_000],
+        &None,
+        &false,
+        &None,
+    );
+    rate_provider.set_rate(&RATE_ONE);
+    assert_eq!(pool.query_rates(), vec![&env, RATE_ONE, 2 * RATE_ONE]);
+}
+
+#[test]
+fn rate_provider_reports_invalid_rate() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let rate_provider = deploy_rate_provider(&env, 0);
+    let pool = deploy_stable_liquidity_pool_contract_with_rate_providers(
+        &env,
+        None,
+        &[&token1.address, &token2.address],
+        &[Some(rate_provider.address.clone()), None],
+        0,
+    );
+
+    assert_eq!(
+        pool.try_query_rates(),
+        Err(Ok(StablePoolError::InvalidRate))
+    );
+    assert_eq!(
+        pool.try_simulate_swap(&token1.address, &token2.address, &1_000),
+        Err(Ok(StablePoolError::InvalidRate))
+    );
+}
+
+#[test]
+#[should_panic(expected = "HostError: Error(Contract, #319)")]
+fn initialize_with_wrong_number_of_rate_providers() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let rate_provider = deploy_rate_provider(&env, RATE_ONE);
+
+    deploy_stable_liquidity_pool_contract_with_rate_providers(
+        &env,
+        None,
+        &[&tokens[0].address, &tokens[1].address],
+        &[Some(rate_provider.address.clone())],
+        0,
+    );
+}
@@ -1,16 +1,57 @@
-use soroban_sdk::{testutils::Address as _, Address, BytesN, Env};
+extern crate std;
+use soroban_sdk::{testutils::Address as _, Address, BytesN, Env, String, Vec};
 
 use crate::{
     contract::{StableLiquidityPool, StableLiquidityPoolClient},
     token_contract,
 };
 
-use phoenix::utils::{LiquidityPoolInitInfo, StakeInitInfo, TokenInitInfo};
+use phoenix::utils::{StablePoolInitInfo, StakeInitInfo};
 
 pub fn deploy_token_contract<'a>(env: &Env, admin: &Address) -> token_contract::Client<'a> {
     token_contract::Client::new(env, &env.register_stellar_asset_contract(admin.clone()))
 }
 
+/// Deploys `count` tokens, sorted alphabetically by their address as the pool expects them
+pub fn deploy_sorted_token_contracts<'a>(
+    env: &Env,
+    admin: &Address,
+    count: usize,
+) -> std::vec::Vec<token_contract::Client<'a>> {
+    let mut tokens: std::vec::Vec<_> = (0..count)
+        .map(|_| deploy_token_contract(env, admin))
+        .collect();
+    tokens.sort_by(|a, b| a.address.cmp(&b.address));
+    tokens
+}
+
+/// Deploys a token with every number of `decimals`, sorted alphabetically by their address as the
+/// pool expects them
+pub fn deploy_sorted_token_contracts_with_decimals<'a>(
+    env: &Env,
+    admin: &Address,
+    decimals: &[u32],
+) -> std::vec::Vec<token_contract::Client<'a>> {
+    let mut tokens: std::vec::Vec<_> = decimals
+        .iter()
+        .map(|decimals| {
+            let token = token_contract::Client::new(
+                env,
+                &env.register_contract_wasm(None, token_contract::WASM),
+            );
+            token.initialize(
+                admin,
+                decimals,
+                &String::from_str(env, "Token"),
+                &String::from_str(env, "TKN"),
+            );
+            token
+        })
+        .collect();
+    tokens.sort_by(|a, b| a.address.cmp(&b.address));
+    tokens
+}
+
 pub fn install_token_wasm(env: &Env) -> BytesN<32> {
     soroban_sdk::contractimport!(
         file = "../../target/wasm32-unknown-unknown/release/soroban_token_contract.wasm"
@@ -29,48 +70,95 @@ pub fn install_stake_wasm(env: &Env) -> BytesN<32> {
 pub fn deploy_stable_liquidity_pool_contract<'a>(
     env: &Env,
     admin: impl Into<Option<Address>>,
-    token_a_b: (&Address, &Address),
+    tokens: &[&Address],
     swap_fees: i64,
     fee_recipient: impl Into<Option<Address>>,
     max_allowed_slippage_bps: impl Into<Option<i64>>,
     max_allowed_spread_bps: impl Into<Option<i64>>,
 ) -> StableLiquidityPoolClient<'a> {
+    let init_info = stable_pool_init_info(
+        env,
+        admin,
+        tokens,
+        swap_fees,
+        fee_recipient,
+        max_allowed_slippage_bps,
+        max_allowed_spread_bps,
+    );
+    initialize_stable_liquidity_pool(env, &init_info)
+}
+
+/// Deploys a pool without fees whose tokens are valued by `rate_providers`
+pub fn deploy_stable_liquidity_pool_contract_with_rate_providers<'a>(
+    env: &Env,
+    admin: impl Into<Option<Address>>,
+    tokens: &[&Address],
+    rate_providers: &[Option<Address>],
+    max_rate_staleness: u64,
+) -> StableLiquidityPoolClient<'a> {
+    let mut init_info = stable_pool_init_info(env, admin, tokens, 0, None, None, None);
+    init_info.rate_providers = Vec::new(env);
+    for rate_provider in rate_providers {
+        init_info.rate_providers.push_back(rate_provider.clone());
+    }
+    init_info.max_rate_staleness = max_rate_staleness;
+    initialize_stable_liquidity_pool(env, &init_info)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn stable_pool_init_info(
+    env: &Env,
+    admin: impl Into<Option<Address>>,
+    tokens: &[&Address],
+    swap_fees: i64,
+    fee_recipient: impl Into<Option<Address>>,
+    max_allowed_slippage_bps: impl Into<Option<i64>>,
+    max_allowed_spread_bps: impl Into<Option<i64>>,
+) -> StablePoolInitInfo {
     let admin = admin.into().unwrap_or(Address::generate(env));
-    let pool =
-        StableLiquidityPoolClient::new(env, &env.register_contract(None, StableLiquidityPool {}));
     let fee_recipient = fee_recipient
         .into()
         .unwrap_or_else(|| Address::generate(env));
 
-    let token_init_info = TokenInitInfo {
-        token_a: token_a_b.0.clone(),
-        token_b: token_a_b.1.clone(),
-    };
+    let mut pool_tokens = Vec::new(env);
+    for token in tokens {
+        pool_tokens.push_back((*token).clone());
+    }
     let stake_init_info = StakeInitInfo {
         min_bond: 10i128,
         max_distributions: 10u32,
         min_reward: 5i128,
     };
 
-    let token_wasm_hash = install_token_wasm(env);
-    let stake_wasm_hash = install_stake_wasm(env);
-
-    let lp_init_info = LiquidityPoolInitInfo {
+    StablePoolInitInfo {
         admin,
         share_token_decimals: 7u32,
         swap_fee_bps: swap_fees,
         fee_recipient,
         max_allowed_slippage_bps: max_allowed_slippage_bps.into().unwrap_or(5_000),
         max_allowed_spread_bps: max_allowed_spread_bps.into().unwrap_or(500),
-        max_referral_bps: 5_000,
-        token_init_info,
+        tokens: pool_tokens,
+        rate_providers: Vec::new(env),
+        max_rate_staleness: 0,
         stake_init_info,
-    };
+    }
+}
+
+fn initialize_stable_liquidity_pool<'a>(
+    env: &Env,
+    init_info: &StablePoolInitInfo,
+) -> StableLiquidityPoolClient<'a> {
+    let pool =
+        StableLiquidityPoolClient::new(env, &env.register_contract(None, StableLiquidityPool {}));
+    let token_wasm_hash = install_token_wasm(env);
+    let stake_wasm_hash = install_stake_wasm(env);
 
     pool.initialize(
         &stake_wasm_hash,
         &token_wasm_hash,
-        &lp_init_info,
+        &10u64,
+        init_info,
+        &Address::generate(env),
     );
     pool
 }
@@ -1,6 +1,9 @@
 extern crate std;
-use phoenix::utils::{LiquidityPoolInitInfo, StakeInitInfo, TokenInitInfo};
-use soroban_sdk::{testutils::Address as _, Address, Env};
+use phoenix::{
+    error::StablePoolError,
+    utils::{StablePoolInitInfo, StakeInitInfo},
+};
+use soroban_sdk::{testutils::Address as _, vec, Address, Env};
 
 use super::setup::{deploy_stable_liquidity_pool_contract, deploy_token_contract};
 use crate::contract::{StableLiquidityPool, StableLiquidityPoolClient};
@@ -30,7 +33,7 @@ fn confirm_stake_contract_deployment() {
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         Some(admin1.clone()),
-        (&token1.address, &token2.address),
+        &[&token1.address, &token2.address],
         swap_fees,
         user1.clone(),
         500,
@@ -43,15 +46,18 @@ fn confirm_stake_contract_deployment() {
     assert_eq!(
         pool.query_config(),
         Config {
-            token_a: token1.address.clone(),
-            token_b: token2.address.clone(),
+            tokens: vec![&env, token1.address.clone(), token2.address.clone()],
             share_token: share_token_address.clone(),
             stake_contract: stake_token_address.clone(),
-            pool_type: PairType::Xyk,
+            pool_type: PairType::Stable,
             total_fee_bps: 0,
             fee_recipient: user1,
             max_allowed_slippage_bps: 500,
             max_allowed_spread_bps: 200,
+            protocol_fee_share_bps: 10_000,
+            distribute_lp_fees_to_stakers: false,
+            rate_providers: vec![&env],
+            max_rate_staleness: 0,
         }
     );
 
@@ -70,7 +76,6 @@ fn confirm_stake_contract_deployment() {
 }
 
 #[test]
-#[should_panic(expected = "Pool stable: Initialize: initializing contract twice is not allowed")]
 fn second_pool_stable_deployment_should_fail() {
     let env = Env::default();
     env.mock_all_auths();
@@ -96,28 +101,41 @@ fn second_pool_stable_deployment_should_fail() {
     let max_allowed_slippage = 5_000i64; // 50% if not specified
     let max_allowed_spread = 500i64; // 5% if not specified
 
-    let token_init_info = TokenInitInfo {
-        token_a: token1.address.clone(),
-        token_b: token2.address.clone(),
-    };
     let stake_init_info = StakeInitInfo {
         min_bond: 10i128,
         max_distributions: 10u32,
         min_reward: 5i128,
     };
 
-    let lp_init_info = LiquidityPoolInitInfo {
+    let init_info = StablePoolInitInfo {
         admin: admin1,
         share_token_decimals: 7u32,
         swap_fee_bps: 0i64,
         fee_recipient,
         max_allowed_slippage_bps: max_allowed_slippage,
         max_allowed_spread_bps: max_allowed_spread,
-        max_referral_bps: 500,
-        token_init_info,
+        tokens: vec![&env, token1.address.clone(), token2.address.clone()],
+        rate_providers: vec![&env],
+        max_rate_staleness: 0,
         stake_init_info,
     };
 
-    pool.initialize(&stake_wasm_hash, &token_wasm_hash, &lp_init_info);
-    pool.initialize(&stake_wasm_hash, &token_wasm_hash, &lp_init_info);
+    let factory = Address::generate(&env);
+    pool.initialize(
+        &stake_wasm_hash,
+        &token_wasm_hash,
+        &10u64,
+        &init_info,
+        &factory,
+    );
+    assert_eq!(
+        pool.try_initialize(
+            &stake_wasm_hash,
+            &token_wasm_hash,
+            &10u64,
+            &init_info,
+            &factory
+        ),
+        Err(Ok(StablePoolError::AlreadyInitialized))
+    );
 }
@@ -1,12 +1,14 @@
 extern crate std;
 
+use phoenix::error::StablePoolError;
 use pretty_assertions::assert_eq;
 use soroban_sdk::testutils::{AuthorizedFunction, AuthorizedInvocation};
-use soroban_sdk::{symbol_short, testutils::Address as _, Address, Env, IntoVal};
+use soroban_sdk::{symbol_short, testutils::Address as _, vec, Address, Env, IntoVal, Symbol};
 
-use super::setup::{deploy_stable_liquidity_pool_contract, deploy_token_contract};
+use super::setup::{
+    deploy_sorted_token_contracts, deploy_stable_liquidity_pool_contract, deploy_token_contract,
+};
 use crate::storage::{Asset, PoolResponse, SimulateReverseSwapResponse, SimulateSwapResponse};
-use decimal::Decimal;
 
 #[test]
 fn simple_swap() {
@@ -14,42 +16,42 @@ fn simple_swap() {
     env.mock_all_auths();
     env.budget().reset_unlimited();
 
-    let mut admin1 = Address::generate(&env);
-    let mut admin2 = Address::generate(&env);
-
-    let mut token1 = deploy_token_contract(&env, &admin1);
-    let mut token2 = deploy_token_contract(&env, &admin2);
-    if token2.address < token1.address {
-        std::mem::swap(&mut token1, &mut token2);
-        std::mem::swap(&mut admin1, &mut admin2);
-    }
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
     let user1 = Address::generate(&env);
     let swap_fees = 0i64;
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         None,
-        (&token1.address, &token2.address),
+        &[&token1.address, &token2.address],
         swap_fees,
         None,
         None,
         None,
     );
 
-    token1.mint(&user1, &1_001_000);
-    token2.mint(&user1, &1_001_000);
+    token1.mint(&user1, &1_001_000_000);
+    token2.mint(&user1, &1_001_000_000);
     pool.provide_liquidity(
         &user1,
-        &Some(1_000_000),
-        &Some(1_000_000),
-        &Some(1_000_000),
-        &Some(1_000_000),
+        &vec![&env, 1_000_000_000, 1_000_000_000],
+        &None,
+        &false,
         &None,
     );
 
-    // true means "selling A token"
-    // selling just one token with 1% max spread allowed
+    // selling 1_000 units of the first token with 1% max spread allowed
     let spread = 100i64; // 1% maximum spread allowed
-    pool.swap(&user1, &token1.address, &1, &None, &Some(spread));
+    let output_amount = pool.swap(
+        &user1,
+        &token1.address,
+        &token2.address,
+        &1_000,
+        &None,
+        &Some(spread),
+        &None,
+    );
     assert_eq!(
         env.auths(),
         [(
@@ -58,14 +60,23 @@ fn simple_swap() {
                 function: AuthorizedFunction::Contract((
                     pool.address.clone(),
                     symbol_short!("swap"),
-                    (&user1, token1.address.clone(), 1_i128, None::<i64>, spread).into_val(&env)
+                    (
+                        &user1,
+                        token1.address.clone(),
+                        token2.address.clone(),
+                        1_000_i128,
+                        None::<i64>,
+                        spread,
+                        None::<Address>
+                    )
+                        .into_val(&env)
                 )),
                 sub_invocations: std::vec![
                     (AuthorizedInvocation {
                         function: AuthorizedFunction::Contract((
                             token1.address.clone(),
                             symbol_short!("transfer"),
-                            (&user1, &pool.address, 1_i128).into_val(&env)
+                            (&user1, &pool.address, 1_000_i128).into_val(&env)
                         )),
                         sub_invocations: std::vec![],
                     }),
@@ -73,53 +84,68 @@ fn simple_swap() {
             }
         )]
     );
+    // a balanced stable pool trades almost 1:1, the pool keeps the rounding
+    assert_eq!(output_amount, 999);
 
     let share_token_address = pool.query_share_token_address();
     let result = pool.query_pool_info();
     assert_eq!(
         result,
         PoolResponse {
-            asset_a: Asset {
-                address: token1.address.clone(),
-                amount: 1_000_001i128,
-            },
-            asset_b: Asset {
-                address: token2.address.clone(),
-                amount: 999_999i128,
-            },
+            assets: vec![
+                &env,
+                Asset {
+                    address: token1.address.clone(),
+                    amount: 1_000_001_000i128,
+                },
+                Asset {
+                    address: token2.address.clone(),
+                    amount: 999_999_001i128,
+                },
+            ],
             asset_lp_share: Asset {
                 address: share_token_address.clone(),
-                amount: 1_000_000i128,
+                amount: 1_999_999_000i128,
             },
         }
     );
-    assert_eq!(token1.balance(&user1), 999); // -1 from the swap
-    assert_eq!(token2.balance(&user1), 1001); // 1 from the swap
+    assert_eq!(token1.balance(&user1), 999_000); // -1_000 from the swap
+    assert_eq!(token2.balance(&user1), 1_000_999); // 999 from the swap
 
-    // false means selling B token
-    // this time 100 units
-    let output_amount = pool.swap(&user1, &token2.address, &1_000, &None, &Some(spread));
+    // selling the second token this time, a bigger trade has a noticeable spread with amp of 10
+    let output_amount = pool.swap(
+        &user1,
+        &token2.address,
+        &token1.address,
+        &1_000_000,
+        &None,
+        &Some(spread),
+        &None,
+    );
+    assert_eq!(output_amount, 999_909);
     let result = pool.query_pool_info();
     assert_eq!(
         result,
         PoolResponse {
-            asset_a: Asset {
-                address: token1.address.clone(),
-                amount: 1_000_001 - 1_000, // previous balance minus 1_000
-            },
-            asset_b: Asset {
-                address: token2.address.clone(),
-                amount: 999_999 + 1000,
-            },
+            assets: vec![
+                &env,
+                Asset {
+                    address: token1.address.clone(),
+                    amount: 1_000_001_000 - 999_909,
+                },
+                Asset {
+                    address: token2.address.clone(),
+                    amount: 999_999_001 + 1_000_000,
+                },
+            ],
             asset_lp_share: Asset {
                 address: share_token_address,
-                amount: 1_000_000i128, // this has not changed
+                amount: 1_999_999_000i128, // this has not changed
             },
         }
     );
-    assert_eq!(output_amount, 1000);
-    assert_eq!(token1.balance(&user1), 1999); // 999 + 1_000 as a result of swap
-    assert_eq!(token2.balance(&user1), 1001 - 1000); // user1 sold 1k of token B on second swap
+    assert_eq!(token1.balance(&user1), 999_000 + 999_909);
+    assert_eq!(token2.balance(&user1), 1_000_999 - 1_000_000);
 }
 
 #[test]
@@ -128,74 +154,215 @@ fn swap_with_high_fee() {
     env.mock_all_auths();
     env.budget().reset_unlimited();
 
-    let mut admin1 = Address::generate(&env);
-    let mut admin2 = Address::generate(&env);
-
-    let mut token1 = deploy_token_contract(&env, &admin1);
-    let mut token2 = deploy_token_contract(&env, &admin2);
-    if token2.address < token1.address {
-        std::mem::swap(&mut token1, &mut token2);
-        std::mem::swap(&mut admin1, &mut admin2);
-    }
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
     let user1 = Address::generate(&env);
+    let fee_recipient = Address::generate(&env);
 
     let swap_fees = 1_000i64; // 10% bps
-    let fee_recipient = Address::generate(&env);
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         None,
-        (&token1.address, &token2.address),
+        &[&token1.address, &token2.address],
         swap_fees,
         fee_recipient.clone(),
         None,
         None,
     );
 
-    let initial_liquidity = 1_000_000i128;
-
+    let initial_liquidity = 1_000_000_000i128;
     token1.mint(&user1, &(initial_liquidity + 100_000));
     token2.mint(&user1, &initial_liquidity);
     pool.provide_liquidity(
         &user1,
-        &Some(initial_liquidity),
-        &Some(initial_liquidity),
-        &Some(initial_liquidity),
-        &Some(initial_liquidity),
+        &vec![&env, initial_liquidity, initial_liquidity],
+        &None,
+        &false,
         &None,
     );
 
     let spread = 1_000; // 10% maximum spread allowed
 
     // let's swap 100_000 units of Token 1 in 1:1 pool with 10% protocol fee
-    pool.swap(&user1, &token1.address, &100_000, &None, &Some(spread));
+    let output_amount = pool.swap(
+        &user1,
+        &token1.address,
+        &token2.address,
+        &100_000,
+        &None,
+        &Some(spread),
+        &None,
+    );
+
+    // the stable pool returns almost the same amount as offered
+    let total_return = 99_999i128;
+    let fees = 9_999i128; // 10% of the total return, rounded down
+    assert_eq!(output_amount, total_return - fees);
+    assert_eq!(token2.balance(&user1), total_return - fees);
+    assert_eq!(token2.balance(&fee_recipient), fees);
 
-    // This is XYK LP with constant product formula
-    // Y_new = (X_in * Y_old) / (X_in + X_old)
-    // Y_new = (100_000 * 1_000_000) / (100_000 + 1_000_000)
-    // Y_new = 90_909.0909
-    let output_amount = 90_910i128; // rounding
     let result = pool.query_pool_info();
     assert_eq!(
-        result,
-        PoolResponse {
-            asset_a: Asset {
+        result.assets,
+        vec![
+            &env,
+            Asset {
                 address: token1.address.clone(),
-                amount: initial_liquidity + 100_000i128,
+                amount: initial_liquidity + 100_000,
             },
-            asset_b: Asset {
+            Asset {
                 address: token2.address.clone(),
-                amount: initial_liquidity - output_amount,
-            },
-            asset_lp_share: Asset {
-                address: pool.query_share_token_address(),
-                amount: 1_000_000i128,
+                amount: initial_liquidity - total_return,
             },
-        }
+        ]
+    );
+}
+
+#[test]
+fn swap_with_fee_split_between_protocol_and_lps() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let fee_recipient = Address::generate(&env);
+
+    let swap_fees = 1_000i64; // 10% bps
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        Some(admin.clone()),
+        &[&token1.address, &token2.address],
+        swap_fees,
+        fee_recipient.clone(),
+        None,
+        None,
+    );
+    // half of the commission goes to the protocol, the other half stays in the pool
+    pool.update_config(
+        &admin,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &Some(5_000),
+        &None,
+        &None,
+    );
+
+    let initial_liquidity = 1_000_000_000i128;
+    token1.mint(&user1, &(initial_liquidity + 100_000));
+    token2.mint(&user1, &initial_liquidity);
+    pool.provide_liquidity(
+        &user1,
+        &vec![&env, initial_liquidity, initial_liquidity],
+        &None,
+        &false,
+        &None,
+    );
+
+    // same swap as in `swap_with_high_fee`
+    let output_amount = pool.swap(
+        &user1,
+        &token1.address,
+        &token2.address,
+        &100_000,
+        &None,
+        &Some(1_000),
+        &None,
+    );
+
+    let total_return = 99_999i128;
+    let protocol_fees = 4_999i128;
+    let lp_fees = 5_000i128;
+    assert_eq!(output_amount, total_return - protocol_fees - lp_fees);
+    assert_eq!(token2.balance(&fee_recipient), protocol_fees);
+
+    let result = pool.query_pool_info();
+    assert_eq!(
+        result.assets.get(1).unwrap().amount,
+        initial_liquidity - total_return + lp_fees
+    );
+    assert_eq!(
+        token2.balance(&pool.address),
+        result.assets.get(1).unwrap().amount
+    );
+}
+
+#[test]
+fn swap_with_lp_fees_sent_to_stakers() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let fee_recipient = Address::generate(&env);
+
+    let swap_fees = 1_000i64; // 10% bps
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        Some(admin.clone()),
+        &[&token1.address, &token2.address],
+        swap_fees,
+        fee_recipient.clone(),
+        None,
+        None,
+    );
+    pool.update_config(
+        &admin,
+        &None,
+        &None,
+        &None,
+        &None,
+        &None,
+        &Some(5_000),
+        &Some(true),
+        &None,
+    );
+
+    let initial_liquidity = 1_000_000_000i128;
+    token1.mint(&user1, &(initial_liquidity + 100_000));
+    token2.mint(&user1, &initial_liquidity);
+    pool.provide_liquidity(
+        &user1,
+        &vec![&env, initial_liquidity, initial_liquidity],
+        &None,
+        &false,
+        &None,
+    );
+
+    pool.swap(
+        &user1,
+        &token1.address,
+        &token2.address,
+        &100_000,
+        &None,
+        &Some(1_000),
+        &None,
+    );
+
+    let total_return = 99_999i128;
+    let protocol_fees = 4_999i128;
+    let lp_fees = 5_000i128;
+    assert_eq!(token2.balance(&fee_recipient), protocol_fees);
+    // stake contract picks the fees up as rewards on its next distribution
+    assert_eq!(
+        token2.balance(&pool.query_stake_contract_address()),
+        lp_fees
+    );
+
+    let result = pool.query_pool_info();
+    assert_eq!(
+        result.assets.get(1).unwrap().amount,
+        initial_liquidity - total_return
     );
-    // 10% fees are deducted from the swap result and sent to fee recipient address
-    let fees = Decimal::percent(10) * output_amount;
-    assert_eq!(token2.balance(&user1), output_amount - fees);
-    assert_eq!(token2.balance(&fee_recipient), fees);
 }
 
 #[test]
@@ -204,52 +371,44 @@ fn swap_simulation_even_pool() {
     env.mock_all_auths();
     env.budget().reset_unlimited();
 
-    let mut token1 = deploy_token_contract(&env, &Address::generate(&env));
-    let mut token2 = deploy_token_contract(&env, &Address::generate(&env));
-    if token2.address < token1.address {
-        std::mem::swap(&mut token1, &mut token2);
-    }
+    let tokens = deploy_sorted_token_contracts(&env, &Address::generate(&env), 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
 
     let swap_fees = 1_000i64; // 10% bps
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         None,
-        (&token1.address, &token2.address),
+        &[&token1.address, &token2.address],
         swap_fees,
         Address::generate(&env),
         None,
         None,
     );
 
-    let initial_liquidity = 1_000_000i128;
+    let initial_liquidity = 1_000_000_000i128;
     let user1 = Address::generate(&env);
     token1.mint(&user1, &initial_liquidity);
     token2.mint(&user1, &initial_liquidity);
     pool.provide_liquidity(
         &user1,
-        &Some(initial_liquidity),
-        &Some(initial_liquidity),
-        &Some(initial_liquidity),
-        &Some(initial_liquidity),
+        &vec![&env, initial_liquidity, initial_liquidity],
+        &None,
+        &false,
         &None,
     );
 
     // let's simulate swap 100_000 units of Token 1 in 1:1 pool with 10% protocol fee
     let offer_amount = 100_000i128;
-    let result = pool.simulate_swap(&token1.address, &offer_amount);
-
-    // This is XYK LP with constant product formula
-    // Y_new = (X_in * Y_old) / (X_in + X_old)
-    // Y_new = (100_000 * 1_000_000) / (100_000 + 1_000_000)
-    // Y_new = 90_909.0909
-    let output_amount = 90_910i128; // rounding
-    let fees = Decimal::percent(10) * output_amount;
+    let result = pool.simulate_swap(&token1.address, &token2.address, &offer_amount);
+
+    // Swapping 0.01% of the pool barely moves the price, only rounding is lost
+    let output_amount = 99_999i128;
+    let fees = 9_999i128;
     assert_eq!(
         result,
         SimulateSwapResponse {
             ask_amount: output_amount - fees,
-            // spread_amount: Decimal::from_ratio(100_000, 1_000_000) * output_amount, // since it's 10% of the pool
-            spread_amount: 9090, // rounding error, one less then ^
+            spread_amount: 1,
             commission_amount: fees,
             total_return: offer_amount,
         }
@@ -257,142 +416,432 @@ fn swap_simulation_even_pool() {
 
     // now reverse swap querie should give us similar results
     // User wants to buy output_amount of tokens
-    let result = pool.simulate_reverse_swap(&token1.address, &(output_amount - fees));
+    let result =
+        pool.simulate_reverse_swap(&token1.address, &token2.address, &(output_amount - fees));
     assert_eq!(
         result,
         SimulateReverseSwapResponse {
-            // offer_amount,
-            offer_amount: 99_999i128, // rounding error
-            // spread_amount: Decimal::from_ratio(100_000, 1_000_000) * output_amount, // since it's 10% of the pool
-            spread_amount: 9090, // rounding error, one less then ^
-            // commission_amount: fees,
-            commission_amount: 9090,
+            // 90_000 is exactly 90% of 100_000, which the reverse swap does not round down
+            offer_amount: 100_001,
+            spread_amount: 1,
+            commission_amount: fees + 1,
         }
     );
 
-    // false indicates selling the other asset - transaction goes the same
-    let result = pool.simulate_swap(&token2.address, &offer_amount);
+    // selling the other asset - transaction goes the same
+    let result = pool.simulate_swap(&token2.address, &token1.address, &offer_amount);
     assert_eq!(
         result,
         SimulateSwapResponse {
             ask_amount: output_amount - fees,
-            spread_amount: 9090, // spread amount is basically 10%, since it's basically 10% of the
-            // first token
+            spread_amount: 1,
             commission_amount: fees,
             total_return: offer_amount,
         }
     );
 
     // again reverse swap should show the same values
-    let result = pool.simulate_reverse_swap(&token2.address, &(output_amount - fees));
+    let result =
+        pool.simulate_reverse_swap(&token2.address, &token1.address, &(output_amount - fees));
     assert_eq!(
         result,
         SimulateReverseSwapResponse {
-            // offer_amount,
-            offer_amount: 99_999i128, // rounding error
-            // spread_amount: Decimal::from_ratio(100_000, 1_000_000) * output_amount, // since it's 10% of the pool
-            spread_amount: 9090, // rounding error, one less then ^
-            // commission_amount: fees,
-            commission_amount: 9090,
+            // 90_000 is exactly 90% of 100_000, which the reverse swap does not round down
+            offer_amount: 100_001,
+            spread_amount: 1,
+            commission_amount: fees + 1,
         }
     );
 }
 
 #[test]
-fn swap_simulation_one_third_pool() {
+fn swap_between_all_tokens_of_three_token_pool() {
     let env = Env::default();
     env.mock_all_auths();
     env.budget().reset_unlimited();
 
-    let mut token1 = deploy_token_contract(&env, &Address::generate(&env));
-    let mut token2 = deploy_token_contract(&env, &Address::generate(&env));
-    if token2.address < token1.address {
-        std::mem::swap(&mut token1, &mut token2);
+    let tokens = deploy_sorted_token_contracts(&env, &Address::generate(&env), 3);
+    let user1 = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&tokens[0].address, &tokens[1].address, &tokens[2].address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    let initial_liquidity = 1_000_000_000i128;
+    for token in tokens.iter() {
+        token.mint(&user1, &(initial_liquidity + 1_000_000));
     }
+    pool.provide_liquidity(
+        &user1,
+        &vec![
+            &env,
+            initial_liquidity,
+            initial_liquidity,
+            initial_liquidity,
+        ],
+        &None,
+        &false,
+        &None,
+    );
+
+    let offer_amount = 1_000_000i128;
+    for (offer, ask) in [(0, 1), (1, 2), (2, 0), (0, 2)] {
+        let (offer_token, ask_token) = (&tokens[offer], &tokens[ask]);
+        let simulated = pool.simulate_swap(&offer_token.address, &ask_token.address, &offer_amount);
+
+        let ask_balance_before = ask_token.balance(&user1);
+        let output_amount = pool.swap(
+            &user1,
+            &offer_token.address,
+            &ask_token.address,
+            &offer_amount,
+            &None,
+            &None,
+            &None,
+        );
+
+        assert_eq!(output_amount, simulated.ask_amount);
+        assert_eq!(
+            ask_token.balance(&user1),
+            ask_balance_before + output_amount
+        );
+        // the pool stays balanced enough for a close to 1:1 rate
+        assert!((output_amount - offer_amount).abs() < offer_amount / 1_000);
+    }
+
+    // the contract balances match the stored ones
+    for (asset, token) in pool.query_pool_info().assets.iter().zip(tokens.iter()) {
+        assert_eq!(asset.amount, token.balance(&pool.address));
+    }
+}
 
-    let swap_fees = 500i64; // 5% bps
+#[test]
+fn swap_imbalanced_pool_has_bigger_spread() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let tokens = deploy_sorted_token_contracts(&env, &Address::generate(&env), 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
     let pool = deploy_stable_liquidity_pool_contract(
         &env,
         None,
-        (&token1.address, &token2.address),
-        swap_fees,
-        Address::generate(&env),
+        &[&token1.address, &token2.address],
+        0i64,
+        None,
         None,
         None,
     );
 
-    let initial_liquidity = 1_000_000i128;
+    token1.mint(&user1, &3_000_000_000);
+    token2.mint(&user1, &1_000_000_000);
+    pool.provide_liquidity(
+        &user1,
+        &vec![&env, 3_000_000_000, 1_000_000_000],
+        &None,
+        &false,
+        &None,
+    );
+
+    // selling the abundant token gets less than 1:1
+    let result = pool.simulate_swap(&token1.address, &token2.address, &10_000_000);
+    assert!(result.ask_amount < 10_000_000);
+    assert_eq!(result.spread_amount, 10_000_000 - result.ask_amount);
+
+    // selling the scarce token gets more than 1:1 without any spread
+    let result = pool.simulate_swap(&token2.address, &token1.address, &10_000_000);
+    assert!(result.ask_amount > 10_000_000);
+    assert_eq!(result.spread_amount, 0);
+}
+
+#[test]
+fn swap_same_token() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let tokens = deploy_sorted_token_contracts(&env, &Address::generate(&env), 2);
     let user1 = Address::generate(&env);
-    token1.mint(&user1, &initial_liquidity);
-    token2.mint(&user1, &(3 * initial_liquidity));
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&tokens[0].address, &tokens[1].address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    tokens[0].mint(&user1, &1_000);
+    assert_eq!(
+        pool.try_swap(
+            &user1,
+            &tokens[0].address,
+            &tokens[0].address,
+            &1_000,
+            &None,
+            &None,
+            &None,
+        ),
+        Err(Ok(StablePoolError::SameAsset))
+    );
+}
+
+#[test]
+fn swap_token_not_in_pool() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let other_token = deploy_token_contract(&env, &admin);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&tokens[0].address, &tokens[1].address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    assert_eq!(
+        pool.try_simulate_swap(&other_token.address, &tokens[0].address, &1_000),
+        Err(Ok(StablePoolError::AssetNotInPool))
+    );
+}
+
+#[test]
+fn swap_exact_out() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let fee_recipient = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&token1.address, &token2.address],
+        1_000i64, // 10% fees
+        fee_recipient.clone(),
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &1_001_000_000);
+    token2.mint(&user1, &1_000_000_000);
     pool.provide_liquidity(
         &user1,
-        &Some(initial_liquidity),
-        &Some(initial_liquidity),
-        &Some(3 * initial_liquidity),
-        &Some(3 * initial_liquidity),
+        &vec![&env, 1_000_000_000, 1_000_000_000],
+        &None,
+        &false,
         &None,
     );
 
-    // let's simulate swap 100_000 units of Token 1 in 1:3 pool with 5% protocol fee
-    let offer_amount = 100_000i128;
-    let result = pool.simulate_swap(&token1.address, &offer_amount);
-
-    // This is XYK LP with constant product formula
-    // Y_new = (X_in * Y_old) / (X_in + X_old)
-    // Y_new = (100_000 * 3_000_000) / (100_000 + 1_000_000)
-    // Y_new = 272_727.27
-    let output_amount = 272_728i128; // rounding
-    let fees = Decimal::percent(5) * output_amount;
+    let simulated = pool.simulate_reverse_swap(&token1.address, &token2.address, &100_000);
+    // a balanced stable pool trades almost 1:1, so the offer covers the ask amount and the fees
+    assert!(simulated.offer_amount > 111_111 && simulated.offer_amount < 111_200);
+
+    let offer_amount = pool.swap_exact_out(
+        &user1,
+        &token1.address,
+        &token2.address,
+        &100_000,
+        &simulated.offer_amount,
+        &None,
+    );
+    assert_eq!(offer_amount, simulated.offer_amount);
     assert_eq!(
-        result,
-        SimulateSwapResponse {
-            ask_amount: output_amount - fees,
-            spread_amount: Decimal::from_ratio(offer_amount, 1_000_000) * output_amount, // since it's 10% of the pool
-            commission_amount: fees,
-            total_return: 300_000,
-        }
+        env.auths(),
+        [(
+            user1.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    pool.address.clone(),
+                    Symbol::new(&env, "swap_exact_out"),
+                    (
+                        &user1,
+                        token1.address.clone(),
+                        token2.address.clone(),
+                        100_000i128,
+                        offer_amount,
+                        None::<Address>
+                    )
+                        .into_val(&env)
+                )),
+                sub_invocations: std::vec![
+                    (AuthorizedInvocation {
+                        function: AuthorizedFunction::Contract((
+                            token1.address.clone(),
+                            symbol_short!("transfer"),
+                            (&user1, &pool.address, offer_amount).into_val(&env)
+                        )),
+                        sub_invocations: std::vec![],
+                    }),
+                ],
+            }
+        )]
     );
 
-    // now reverse swap querie should give us similar results
-    // User wants to buy output_amount of tokens
-    let result = pool.simulate_reverse_swap(&token1.address, &(output_amount - fees));
+    // the user receives exactly the ask amount, the commission goes to the fee recipient
+    assert_eq!(token1.balance(&user1), 1_000_000 - offer_amount);
+    assert_eq!(token2.balance(&user1), 100_000);
+    assert_eq!(token2.balance(&fee_recipient), simulated.commission_amount);
     assert_eq!(
-        result,
-        SimulateReverseSwapResponse {
-            offer_amount,
-            spread_amount: Decimal::from_ratio(offer_amount, 1_000_000) * output_amount, // since it's 10% of the pool
-            commission_amount: fees,
-        }
+        pool.query_pool_info().assets,
+        vec![
+            &env,
+            Asset {
+                address: token1.address.clone(),
+                amount: 1_000_000_000 + offer_amount,
+            },
+            Asset {
+                address: token2.address.clone(),
+                amount: 1_000_000_000 - 100_000 - simulated.commission_amount,
+            },
+        ]
+    );
+}
+
+#[test]
+fn swap_exact_out_should_fail_when_offer_exceeds_max() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&token1.address, &token2.address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &1_001_000_000);
+    token2.mint(&user1, &1_000_000_000);
+    pool.provide_liquidity(
+        &user1,
+        &vec![&env, 1_000_000_000, 1_000_000_000],
+        &None,
+        &false,
+        &None,
     );
 
-    // false indicates selling the other asset - transaction goes the same
-    let result = pool.simulate_swap(&token2.address, &100_000);
-    // Y_new = (X_in * Y_old) / (X_in + X_old)
-    // Y_new = (100_000 * 1_000_000) / (100_000 + 3_000_000)
-    // Y_new = 32_258.06
-    let output_amount = 32_259i128; // rounding
-    let fees = Decimal::percent(5) * output_amount;
+    let simulated = pool.simulate_reverse_swap(&token1.address, &token2.address, &100_000);
     assert_eq!(
-        result,
-        SimulateSwapResponse {
-            ask_amount: output_amount - fees,
-            // spread_amount: Decimal::from_ratio(100_000i128, 3_000_000i128) * output_amount,
-            spread_amount: 1074, // rounding error, one less then ^
-            commission_amount: fees,
-            total_return: 33_333,
-        }
+        pool.try_swap_exact_out(
+            &user1,
+            &token1.address,
+            &token2.address,
+            &100_000,
+            &(simulated.offer_amount - 1),
+            &None,
+        ),
+        Err(Ok(StablePoolError::OfferAmountExceedsMax))
     );
+    assert_eq!(token1.balance(&user1), 1_000_000);
+    assert_eq!(token2.balance(&user1), 0);
 
-    // reverse should mirror it
-    let result = pool.simulate_reverse_swap(&token2.address, &(output_amount - fees));
     assert_eq!(
-        result,
-        SimulateReverseSwapResponse {
-            // offer_amount,
-            offer_amount: 100_002i128, // rounding error
-            spread_amount: Decimal::from_ratio(offer_amount, 3_000_000) * output_amount, // since it's 10% of the pool
-            commission_amount: fees,
-        }
+        pool.try_swap_exact_out(
+            &user1,
+            &token1.address,
+            &token2.address,
+            &1_000_000_000,
+            &1_000_000,
+            &None,
+        ),
+        Err(Ok(StablePoolError::NotEnoughLiquidity))
+    );
+    assert_eq!(
+        pool.try_swap_exact_out(
+            &user1,
+            &token1.address,
+            &token1.address,
+            &100_000,
+            &1_000_000,
+            &None,
+        ),
+        Err(Ok(StablePoolError::SameAsset))
+    );
+}
+
+#[test]
+fn swap_sends_the_return_amount_to_another_receiver() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.budget().reset_unlimited();
+
+    let admin = Address::generate(&env);
+    let tokens = deploy_sorted_token_contracts(&env, &admin, 2);
+    let (token1, token2) = (&tokens[0], &tokens[1]);
+    let user1 = Address::generate(&env);
+    let receiver = Address::generate(&env);
+    let pool = deploy_stable_liquidity_pool_contract(
+        &env,
+        None,
+        &[&token1.address, &token2.address],
+        0i64,
+        None,
+        None,
+        None,
+    );
+
+    token1.mint(&user1, &1_000_001_000);
+    token2.mint(&user1, &1_000_000_000);
+    pool.provide_liquidity(
+        &user1,
+        &vec![&env, 1_000_000_000, 1_000_000_000],
+        &None,
+        &false,
+        &None,
+    );
+
+    let return_amount = pool.swap(
+        &user1,
+        &token1.address,
+        &token2.address,
+        &1_000,
+        &None,
+        &None,
+        &Some(receiver.clone()),
+    );
+
+    // the sender pays, while the receiver gets the bought tokens
+    assert_eq!(token1.balance(&user1), 0);
+    assert_eq!(token2.balance(&user1), 0);
+    assert_eq!(token1.balance(&receiver), 0);
+    assert_eq!(token2.balance(&receiver), return_amount);
+
+    // the same goes for buying an exact amount
+    token1.mint(&user1, &2_000);
+    let offer_amount = pool.swap_exact_out(
+        &user1,
+        &token1.address,
+        &token2.address,
+        &1_000,
+        &2_000,
+        &Some(receiver.clone()),
     );
+    assert_eq!(token1.balance(&user1), 2_000 - offer_amount);
+    assert_eq!(token2.balance(&user1), 0);
+    assert_eq!(token1.balance(&receiver), 0);
+    assert_eq!(token2.balance(&receiver), return_amount + 1_000);
 }
@@ -2,13 +2,14 @@ use soroban_sdk::{contract, contractimpl, contractmeta, log, vec, Address, Env,
 
 use crate::{
     distribution::{
-        calculate_annualized_payout, get_distribution, get_reward_curve, get_withdraw_adjustment,
-        save_distribution, save_reward_curve, save_withdraw_adjustment, update_rewards,
-        withdrawable_rewards, Distribution, SHARES_SHIFT,
+        calculate_annualized_payout, calculate_rewards_power, get_distribution, get_reward_curve,
+        get_rewards_power, get_withdraw_adjustment, save_distribution, save_reward_curve,
+        save_withdraw_adjustment, update_rewards_power, withdrawable_rewards, Distribution,
+        BASE_MULTIPLIER_BPS, SHARES_SHIFT,
     },
     msg::{
-        AnnualizedReward, AnnualizedRewardsResponse, ConfigResponse, StakedResponse,
-        WithdrawableReward, WithdrawableRewardsResponse,
+        AnnualizedReward, AnnualizedRewardsResponse, ConfigResponse, RewardMultiplier,
+        RewardMultipliersResponse, StakedResponse, WithdrawableReward, WithdrawableRewardsResponse,
     },
     storage::{
         get_config, get_stakes, save_config, save_stakes,
@@ -21,6 +22,7 @@ use crate::{
     token_contract,
 };
 use curve::Curve;
+use phoenix::{error::StakeError, validate_bps};
 
 // Metadata that is added on to the WASM custom section
 contractmeta!(
@@ -41,13 +43,28 @@ pub trait StakingTrait {
         min_bond: i128,
         max_distributions: u32,
         min_reward: i128,
-    );
+    ) -> Result<(), StakeError>;
 
-    fn bond(env: Env, sender: Address, tokens: i128);
+    fn bond(env: Env, sender: Address, tokens: i128) -> Result<(), StakeError>;
 
-    fn unbond(env: Env, sender: Address, stake_amount: i128, stake_timestamp: u64);
-
-    fn create_distribution_flow(env: Env, sender: Address, manager: Address, asset: Address);
+    fn unbond(
+        env: Env,
+        sender: Address,
+        stake_amount: i128,
+        stake_timestamp: u64,
+    ) -> Result<(), StakeError>;
+
+    // Creates a distribution flow of the given reward asset.
+    // Stakes earn a bonus of `bonus_per_day_bps` for every full day they are bonded,
+    // up to `max_bonus_bps`
+    fn create_distribution_flow(
+        env: Env,
+        sender: Address,
+        manager: Address,
+        asset: Address,
+        max_bonus_bps: u64,
+        bonus_per_day_bps: u64,
+    ) -> Result<(), StakeError>;
 
     fn distribute_rewards(env: Env);
 
@@ -60,7 +77,7 @@ pub trait StakingTrait {
         distribution_duration: u64,
         token_address: Address,
         token_amount: i128,
-    );
+    ) -> Result<(), StakeError>;
 
     // QUERIES
 
@@ -79,6 +96,8 @@ pub trait StakingTrait {
     fn query_distributed_rewards(env: Env, asset: Address) -> u128;
 
     fn query_undistributed_rewards(env: Env, asset: Address) -> u128;
+
+    fn query_reward_multipliers(env: Env, address: Address) -> RewardMultipliersResponse;
 }
 
 #[contractimpl]
@@ -90,9 +109,13 @@ impl StakingTrait for Staking {
         min_bond: i128,
         max_distributions: u32,
         min_reward: i128,
-    ) {
+    ) -> Result<(), StakeError> {
         if is_initialized(&env) {
-            panic!("Stake: Initialize: initializing contract twice is not allowed");
+            log!(
+                &env,
+                "Stake: Initialize: initializing contract twice is not allowed"
+            );
+            return Err(StakeError::AlreadyInitialized);
         }
 
         set_initialized(&env);
@@ -102,11 +125,11 @@ impl StakingTrait for Staking {
                 &env,
                 "Minimum amount of lp share tokens to bond can not be smaller or equal to 0"
             );
-            panic!("Stake: Initialize: Minimum amount of lp share tokens to bond can not be smaller or equal to 0")
+            return Err(StakeError::InvalidMinBond);
         }
         if min_reward <= 0 {
             log!(&env, "min_reward must be bigger then 0!");
-            panic!("Stake: Initialize: min reward must be bigger then 0")
+            return Err(StakeError::InvalidMinReward);
         }
 
         env.events()
@@ -122,9 +145,11 @@ impl StakingTrait for Staking {
 
         utils::save_admin(&env, &admin);
         utils::init_total_staked(&env);
+
+        Ok(())
     }
 
-    fn bond(env: Env, sender: Address, tokens: i128) {
+    fn bond(env: Env, sender: Address, tokens: i128) -> Result<(), StakeError> {
         sender.require_auth();
 
         let ledger = env.ledger();
@@ -137,13 +162,14 @@ impl StakingTrait for Staking {
                 tokens,
                 config.min_bond
             );
-            panic!("Stake: Bond: Trying to stake less then minimum required");
+            return Err(StakeError::MinBondNotReached);
         }
 
         let lp_token_client = token_contract::Client::new(&env, &config.lp_token);
         lp_token_client.transfer(&sender, &env.current_contract_address(), &tokens);
 
         let mut stakes = get_stakes(&env, &sender);
+        let old_stakes = stakes.clone();
         let stake = Stake {
             stake: tokens,
             stake_timestamp: ledger.timestamp(),
@@ -151,38 +177,37 @@ impl StakingTrait for Staking {
         stakes.total_stake += tokens as u128;
         // TODO: Discuss: Add implementation to add stake if another is present in +-24h timestamp to avoid
         // creating multiple stakes the same day
+        stakes.stakes.push_back(stake);
 
-        let total_staked = utils::get_total_staked_counter(&env);
-        for distribution_address in get_distributions(&env) {
-            let mut distribution = get_distribution(&env, &distribution_address);
-            update_rewards(
-                &env,
-                &sender,
-                &distribution_address,
-                &mut distribution,
-                total_staked,
-                total_staked + tokens,
-            )
-        }
+        update_rewards_power(&env, &sender, &old_stakes, &stakes);
 
-        stakes.stakes.push_back(stake);
         save_stakes(&env, &sender, &stakes);
         utils::increase_total_staked(&env, &tokens);
 
         env.events().publish(("bond", "user"), &sender);
         env.events().publish(("bond", "token"), &config.lp_token);
         env.events().publish(("bond", "amount"), tokens);
+
+        Ok(())
     }
 
-    fn unbond(env: Env, sender: Address, stake_amount: i128, stake_timestamp: u64) {
+    fn unbond(
+        env: Env,
+        sender: Address,
+        stake_amount: i128,
+        stake_timestamp: u64,
+    ) -> Result<(), StakeError> {
         sender.require_auth();
 
         let config = get_config(&env);
 
         let mut stakes = get_stakes(&env, &sender);
-        remove_stake(&mut stakes.stakes, stake_amount, stake_timestamp);
+        let old_stakes = stakes.clone();
+        remove_stake(&env, &mut stakes.stakes, stake_amount, stake_timestamp)?;
         stakes.total_stake -= stake_amount as u128;
 
+        update_rewards_power(&env, &sender, &old_stakes, &stakes);
+
         let lp_token_client = token_contract::Client::new(&env, &config.lp_token);
         lp_token_client.transfer(&env.current_contract_address(), &sender, &stake_amount);
 
@@ -192,10 +217,20 @@ impl StakingTrait for Staking {
         env.events().publish(("unbond", "user"), &sender);
         env.events().publish(("bond", "token"), &config.lp_token);
         env.events().publish(("bond", "amount"), stake_amount);
+
+        Ok(())
     }
 
-    fn create_distribution_flow(env: Env, sender: Address, manager: Address, asset: Address) {
+    fn create_distribution_flow(
+        env: Env,
+        sender: Address,
+        manager: Address,
+        asset: Address,
+        max_bonus_bps: u64,
+        bonus_per_day_bps: u64,
+    ) -> Result<(), StakeError> {
         sender.require_auth();
+        validate_bps!(max_bonus_bps as i64, bonus_per_day_bps as i64)?;
 
         let distribution = Distribution {
             shares_per_point: 1u128,
@@ -203,13 +238,15 @@ impl StakingTrait for Staking {
             distributed_total: 0u128,
             withdrawable_total: 0u128,
             manager,
-            max_bonus_bps: 0u64,
-            bonus_per_day_bps: 0u64,
+            max_bonus_bps,
+            bonus_per_day_bps,
+            // stakers participate with their total stake until their rewards power is updated
+            total_rewards_power: get_total_staked_counter(&env) as u128,
         };
 
         let reward_token_client = token_contract::Client::new(&env, &asset);
         // add distribution to the vector of distributions
-        add_distribution(&env, &reward_token_client.address);
+        add_distribution(&env, &reward_token_client.address)?;
         save_distribution(&env, &reward_token_client.address, &distribution);
         // Create the default reward distribution curve which is just a flat 0 const
         save_reward_curve(&env, asset, &Curve::Constant(0));
@@ -218,6 +255,8 @@ impl StakingTrait for Staking {
             ("create_distribution_flow", "asset"),
             &reward_token_client.address,
         );
+
+        Ok(())
     }
 
     fn distribute_rewards(env: Env) {
@@ -228,6 +267,10 @@ impl StakingTrait for Staking {
         }
         for distribution_address in get_distributions(&env) {
             let mut distribution = get_distribution(&env, &distribution_address);
+            let total_rewards_power = distribution.total_rewards_power;
+            if total_rewards_power == 0 {
+                continue;
+            }
             let withdrawable = distribution.withdrawable_total;
 
             let reward_token_client = token_contract::Client::new(&env, &distribution_address);
@@ -274,6 +317,10 @@ impl StakingTrait for Staking {
     fn withdraw_rewards(env: Env, sender: Address) {
         env.events().publish(("withdraw_rewards", "user"), &sender);
 
+        // take the current age of the stakes into account for the following distributions
+        let stakes = get_stakes(&env, &sender);
+        update_rewards_power(&env, &sender, &stakes, &stakes);
+
         for distribution_address in get_distributions(&env) {
             // get distribution data for the given reward
             let mut distribution = get_distribution(&env, &distribution_address);
@@ -282,8 +329,13 @@ impl StakingTrait for Staking {
                 get_withdraw_adjustment(&env, &sender, &distribution_address);
             // calculate current reward amount given the distribution and subtracting withdraw
             // adjustments
-            let reward_amount =
-                withdrawable_rewards(&env, &sender, &distribution, &withdraw_adjustment);
+            let reward_amount = withdrawable_rewards(
+                &env,
+                &sender,
+                &distribution_address,
+                &distribution,
+                &withdraw_adjustment,
+            );
 
             if reward_amount == 0 {
                 continue;
@@ -318,12 +370,18 @@ impl StakingTrait for Staking {
         distribution_duration: u64,
         token_address: Address,
         token_amount: i128,
-    ) {
+    ) -> Result<(), StakeError> {
         sender.require_auth();
 
         // Load previous reward curve; it must exist if the distribution exists
         // In case of first time funding, it will be a constant 0 curve
-        let previous_reward_curve = get_reward_curve(&env, &token_address).expect("Stake: Fund distribution: Not reward curve exists, probably distribution haven't been created");
+        let Some(previous_reward_curve) = get_reward_curve(&env, &token_address) else {
+            log!(
+                &env,
+                "Stake: Fund distribution: Not reward curve exists, probably distribution haven't been created"
+            );
+            return Err(StakeError::DistributionNotFound);
+        };
 
         let current_time = env.ledger().timestamp();
         if start_time < current_time {
@@ -333,7 +391,7 @@ impl StakingTrait for Staking {
                 start_time,
                 current_time
             );
-            panic!("Stake: Fund distribution: Fund distribution start time is too early");
+            return Err(StakeError::StartTimeTooEarly);
         }
 
         let config = get_config(&env);
@@ -343,7 +401,7 @@ impl StakingTrait for Staking {
                 "Trying to create distribution flow with reward not reaching minimum amount: {}",
                 config.min_reward
             );
-            panic!("Stake: Fund distribution: minimum reward amount not reached");
+            return Err(StakeError::MinRewardNotReached);
         }
 
         // transfer tokens to fund distribution
@@ -364,7 +422,7 @@ impl StakingTrait for Staking {
                 &env,
                 "Trying to create reward distribution which either doesn't end with empty balance or exceeds provided amount"
             );
-            panic!("Stake: Fund distribution: Rewards validation failed");
+            return Err(StakeError::RewardsValidationFailed);
         }
 
         // now combine old distribution with the new schedule
@@ -379,6 +437,8 @@ impl StakingTrait for Staking {
             .publish(("fund_reward_distribution", "start_time"), start_time);
         env.events()
             .publish(("fund_reward_distribution", "end_time"), end_time);
+
+        Ok(())
     }
 
     // QUERIES
@@ -406,9 +466,11 @@ impl StakingTrait for Staking {
     fn query_annualized_rewards(env: Env) -> AnnualizedRewardsResponse {
         let now = env.ledger().timestamp();
         let mut aprs = vec![&env];
-        let total_rewards_power = get_total_staked_counter(&env) as u128;
 
         for distribution_address in get_distributions(&env) {
+            // get distribution data for the given reward
+            let distribution = get_distribution(&env, &distribution_address);
+            let total_rewards_power = distribution.total_rewards_power;
             if total_rewards_power == 0 {
                 aprs.push_back(AnnualizedReward {
                     asset: distribution_address.clone(),
@@ -417,8 +479,6 @@ impl StakingTrait for Staking {
                 continue;
             }
 
-            // get distribution data for the given reward
-            let distribution = get_distribution(&env, &distribution_address);
             let curve = get_reward_curve(&env, &distribution_address);
             let annualized_payout = calculate_annualized_payout(curve, now);
             let apr =
@@ -443,8 +503,13 @@ impl StakingTrait for Staking {
             let withdraw_adjustment = get_withdraw_adjustment(&env, &user, &distribution_address);
             // calculate current reward amount given the distribution and subtracting withdraw
             // adjustments
-            let reward_amount =
-                withdrawable_rewards(&env, &user, &distribution, &withdraw_adjustment);
+            let reward_amount = withdrawable_rewards(
+                &env,
+                &user,
+                &distribution_address,
+                &distribution,
+                &withdraw_adjustment,
+            );
             rewards.push_back(WithdrawableReward {
                 reward_address: distribution_address,
                 reward_amount,
@@ -465,10 +530,37 @@ impl StakingTrait for Staking {
         reward_token_client.balance(&env.current_contract_address()) as u128
             - distribution.withdrawable_total
     }
+
+    fn query_reward_multipliers(env: Env, address: Address) -> RewardMultipliersResponse {
+        let stakes = get_stakes(&env, &address);
+        let mut multipliers = vec![&env];
+        for distribution_address in get_distributions(&env) {
+            let distribution = get_distribution(&env, &distribution_address);
+            let multiplier_bps = if stakes.total_stake == 0 {
+                BASE_MULTIPLIER_BPS
+            } else {
+                (calculate_rewards_power(&env, &distribution, &stakes.stakes)
+                    * BASE_MULTIPLIER_BPS as u128
+                    / stakes.total_stake) as u64
+            };
+            multipliers.push_back(RewardMultiplier {
+                asset: distribution_address.clone(),
+                multiplier_bps,
+                rewards_power: get_rewards_power(&env, &address, &distribution_address, &stakes),
+            });
+        }
+
+        RewardMultipliersResponse { multipliers }
+    }
 }
 
 // Function to remove a stake from the vector
-fn remove_stake(stakes: &mut Vec<Stake>, stake: i128, stake_timestamp: u64) {
+fn remove_stake(
+    env: &Env,
+    stakes: &mut Vec<Stake>,
+    stake: i128,
+    stake_timestamp: u64,
+) -> Result<(), StakeError> {
     // Find the index of the stake that matches the given stake and stake_timestamp
     if let Some(index) = stakes
         .iter()
@@ -476,9 +568,11 @@ fn remove_stake(stakes: &mut Vec<Stake>, stake: i128, stake_timestamp: u64) {
     {
         // Remove the stake at the found index
         stakes.remove(index as u32);
+        Ok(())
     } else {
         // Stake not found, return an error
-        panic!("Stake: Remove stake: Stake not found");
+        log!(env, "Stake: Remove stake: Stake not found");
+        Err(StakeError::StakeNotFound)
     }
 }
 
@@ -510,7 +604,15 @@ mod tests {
         let stake_timestamp_to_remove = 2;
 
         // Check that the stake is removed successfully
-        remove_stake(&mut stakes, stake_to_remove, stake_timestamp_to_remove);
+        assert_eq!(
+            remove_stake(
+                &env,
+                &mut stakes,
+                stake_to_remove,
+                stake_timestamp_to_remove
+            ),
+            Ok(())
+        );
 
         // Check that the stake is no longer in the vector
         assert_eq!(
@@ -530,7 +632,6 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Stake: Remove stake: Stake not found")]
     fn test_remove_stake_not_found_case1() {
         let env = Env::default();
         let mut stakes = vec![
@@ -549,11 +650,13 @@ mod tests {
             },
         ];
 
-        remove_stake(&mut stakes, 100, 2);
+        assert_eq!(
+            remove_stake(&env, &mut stakes, 100, 2),
+            Err(StakeError::StakeNotFound)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Stake: Remove stake: Stake not found")]
     fn test_remove_stake_not_found_case2() {
         let env = Env::default();
         let mut stakes = vec![
@@ -572,11 +675,13 @@ mod tests {
             },
         ];
 
-        remove_stake(&mut stakes, 200, 1);
+        assert_eq!(
+            remove_stake(&env, &mut stakes, 200, 1),
+            Err(StakeError::StakeNotFound)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Stake: Remove stake: Stake not found")]
     fn test_remove_stake_not_found_case3() {
         let env = Env::default();
         let mut stakes = vec![
@@ -595,6 +700,9 @@ mod tests {
             },
         ];
 
-        remove_stake(&mut stakes, 150, 1);
+        assert_eq!(
+            remove_stake(&env, &mut stakes, 150, 1),
+            Err(StakeError::StakeNotFound)
+        );
     }
 }
@@ -1,9 +1,9 @@
-use soroban_sdk::{contracttype, Address, Env};
+use soroban_sdk::{contracttype, Address, Env, Vec};
 
 use curve::Curve;
 use decimal::Decimal;
 
-use crate::storage::get_stakes;
+use crate::storage::{get_stakes, utils::get_distributions, BondingInfo, Stake};
 
 /// How much points is the worth of single token in rewards distribution.
 /// The scaling is performed to have better precision of fixed point division.
@@ -16,6 +16,9 @@ use crate::storage::get_stakes;
 pub const SHARES_SHIFT: u8 = 32;
 
 const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+/// Multiplier (in bps) of a stake without any bonus
+pub const BASE_MULTIPLIER_BPS: u64 = 10_000;
 
 #[derive(Clone)]
 #[contracttype]
@@ -30,6 +33,7 @@ pub enum DistributionDataKey {
     Curve(Address),
     Distribution(Address),
     WithdrawAdjustment(WithdrawAdjustmentKey),
+    RewardsPower(WithdrawAdjustmentKey),
 }
 
 // one reward distribution curve over one denom
@@ -57,10 +61,23 @@ pub struct Distribution {
     pub withdrawable_total: u128,
     /// The manager of this distribution
     pub manager: Address,
-    /// Max bonus for staking after 60 days
+    /// Max bonus for long-term staking
     pub max_bonus_bps: u64,
     /// Bonus per staking day
     pub bonus_per_day_bps: u64,
+    /// Sum of the rewards power of all stakers, the rewards are split proportionally to it
+    pub total_rewards_power: u128,
+}
+
+impl Distribution {
+    /// Rewards multiplier (in bps) of a stake that has been bonded for `stake_age` seconds
+    pub fn multiplier_bps(&self, stake_age: u64) -> u64 {
+        let bonus = self
+            .bonus_per_day_bps
+            .saturating_mul(stake_age / SECONDS_PER_DAY)
+            .min(self.max_bonus_bps);
+        BASE_MULTIPLIER_BPS + bonus
+    }
 }
 
 pub fn save_distribution(env: &Env, asset: &Address, distribution: &Distribution) {
@@ -77,22 +94,90 @@ pub fn get_distribution(env: &Env, asset: &Address) -> Distribution {
         .unwrap()
 }
 
+/// Rewards power of the given stakes, each stake weighted with the age bonus of the distribution
+pub fn calculate_rewards_power(
+    env: &Env,
+    distribution: &Distribution,
+    stakes: &Vec<Stake>,
+) -> u128 {
+    let now = env.ledger().timestamp();
+    stakes
+        .iter()
+        .map(|stake| {
+            let multiplier = distribution.multiplier_bps(now - stake.stake_timestamp);
+            stake.stake as u128 * multiplier as u128 / BASE_MULTIPLIER_BPS as u128
+        })
+        .sum()
+}
+
+fn rewards_power_key(user: &Address, asset: &Address) -> DistributionDataKey {
+    DistributionDataKey::RewardsPower(WithdrawAdjustmentKey {
+        user: user.clone(),
+        asset: asset.clone(),
+    })
+}
+
+/// Rewards power the user currently participates in the distribution with.
+/// Users that haven't been updated since the distribution was created participate with their
+/// total stake.
+pub fn get_rewards_power(
+    env: &Env,
+    user: &Address,
+    asset: &Address,
+    bonding_info: &BondingInfo,
+) -> u128 {
+    env.storage()
+        .persistent()
+        .get(&rewards_power_key(user, asset))
+        .unwrap_or(bonding_info.total_stake)
+}
+
 pub fn update_rewards(
     env: &Env,
     user: &Address,
     asset: &Address,
     distribution: &mut Distribution,
-    old_rewards_power: i128,
-    new_rewards_power: i128,
+    old_rewards_power: u128,
+    new_rewards_power: u128,
 ) {
+    env.storage()
+        .persistent()
+        .set(&rewards_power_key(user, asset), &new_rewards_power);
     if old_rewards_power == new_rewards_power {
         return;
     }
+    distribution.total_rewards_power =
+        distribution.total_rewards_power + new_rewards_power - old_rewards_power;
     let ppw = distribution.shares_per_point;
-    let diff = new_rewards_power - old_rewards_power;
+    let diff = new_rewards_power as i128 - old_rewards_power as i128;
     apply_points_correction(env, user, asset, diff, ppw);
 }
 
+/// Recalculates the rewards power of the user in every distribution after the user's stakes
+/// changed from `old_stakes` to `new_stakes`, or just to take the current age of the stakes
+/// into account. Rewards accrued so far are not affected.
+pub fn update_rewards_power(
+    env: &Env,
+    user: &Address,
+    old_stakes: &BondingInfo,
+    new_stakes: &BondingInfo,
+) {
+    for asset in get_distributions(env) {
+        let mut distribution = get_distribution(env, &asset);
+        let old_rewards_power = get_rewards_power(env, user, &asset, old_stakes);
+        let new_rewards_power = calculate_rewards_power(env, &distribution, &new_stakes.stakes);
+        update_rewards(
+            env,
+            user,
+            &asset,
+            &mut distribution,
+            old_rewards_power,
+            new_rewards_power,
+        );
+        save_distribution(env, &asset, &distribution);
+    }
+}
+
 /// Applies points correction for given address.
 /// `shares_per_point` is current value from `SHARES_PER_POINT` - not loaded in function, to
 /// avoid multiple queries on bulk updates.
@@ -159,12 +244,13 @@ pub fn get_withdraw_adjustment(
 pub fn withdrawable_rewards(
     env: &Env,
     owner: &Address,
+    asset: &Address,
     distribution: &Distribution,
     adjustment: &WithdrawAdjustment,
 ) -> u128 {
     let ppw = distribution.shares_per_point;
 
-    let points = get_stakes(env, owner).total_stake;
+    let points = get_rewards_power(env, owner, asset, &get_stakes(env, owner));
     let points = (ppw * points) as i128;
 
     let correction = adjustment.shares_correction;
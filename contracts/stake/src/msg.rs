@@ -39,3 +39,22 @@ pub struct WithdrawableRewardsResponse {
     /// Amount of rewards assigned for withdrawal from the given address.
     pub rewards: Vec<WithdrawableReward>,
 }
+
+#[contracttype]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RewardMultiplier {
+    /// Address of the distributed reward asset
+    pub asset: Address,
+    /// Multiplier (in bps) given by the current age of the stakes, 10_000 meaning no bonus
+    pub multiplier_bps: u64,
+    /// Rewards power used in the distributions. It catches up with the multiplier whenever the
+    /// user bonds, unbonds or withdraws rewards
+    pub rewards_power: u128,
+}
+
+#[contracttype]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RewardMultipliersResponse {
+    /// Reward multipliers of the given address in every distribution
+    pub multipliers: Vec<RewardMultiplier>,
+}
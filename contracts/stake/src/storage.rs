@@ -65,7 +65,8 @@ pub fn save_stakes(env: &Env, key: &Address, bonding_info: &BondingInfo) {
 pub mod utils {
     use super::*;
 
-    use soroban_sdk::{ConversionError, TryFromVal, Val};
+    use phoenix::error::StakeError;
+    use soroban_sdk::{log, ConversionError, TryFromVal, Val};
 
     #[derive(Clone, Copy)]
     #[repr(u32)]
@@ -129,15 +130,18 @@ pub mod utils {
     }
 
     // Keep track of all distributions to be able to iterate over them
-    pub fn add_distribution(e: &Env, asset: &Address) {
+    pub fn add_distribution(e: &Env, asset: &Address) -> Result<(), StakeError> {
         let mut distributions = get_distributions(e);
         if distributions.contains(asset) {
-            panic!("Stake: Add distribution: Distribution already added");
+            log!(e, "Stake: Add distribution: Distribution already added");
+            return Err(StakeError::DistributionAlreadyAdded);
         }
         distributions.push_back(asset.clone());
         e.storage()
             .persistent()
             .set(&DataKey::Distributions, &distributions);
+
+        Ok(())
     }
 
     pub fn get_distributions(e: &Env) -> Vec<Address> {
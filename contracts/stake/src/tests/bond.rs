@@ -1,3 +1,4 @@
+use phoenix::error::StakeError;
 use pretty_assertions::assert_eq;
 use soroban_sdk::{
     testutils::{Address as _, Ledger},
@@ -39,7 +40,6 @@ fn initializa_staking_contract() {
 }
 
 #[test]
-#[should_panic(expected = "Stake: Initialize: initializing contract twice is not allowed")]
 fn test_deploying_stake_twice_should_fail() {
     let env = Env::default();
     env.mock_all_auths();
@@ -49,12 +49,13 @@ fn test_deploying_stake_twice_should_fail() {
 
     let first = deploy_staking_contract(&env, admin.clone(), &lp_token.address);
 
-    first.initialize(&admin, &lp_token.address, &100i128, &100_000u32, &50i128);
-    first.initialize(&admin, &lp_token.address, &100i128, &100_000u32, &50i128);
+    assert_eq!(
+        first.try_initialize(&admin, &lp_token.address, &100i128, &100_000u32, &50i128),
+        Err(Ok(StakeError::AlreadyInitialized))
+    );
 }
 
 #[test]
-#[should_panic = "Stake: Bond: Trying to stake less then minimum required"]
 fn bond_too_few() {
     let env = Env::default();
     env.mock_all_auths();
@@ -67,7 +68,10 @@ fn bond_too_few() {
 
     lp_token.mint(&user, &999);
 
-    staking.bond(&user, &999);
+    assert_eq!(
+        staking.try_bond(&user, &999),
+        Err(Ok(StakeError::MinBondNotReached))
+    );
 }
 
 #[test]
@@ -174,7 +178,6 @@ fn initializing_contract_sets_total_staked_var() {
 }
 
 #[test]
-#[should_panic(expected = "Stake: Remove stake: Stake not found")]
 fn unbond_wrong_user_stake_not_found() {
     let env = Env::default();
     env.mock_all_auths();
@@ -203,5 +206,8 @@ fn unbond_wrong_user_stake_not_found() {
     assert_eq!(lp_token.balance(&user2), 0);
     assert_eq!(lp_token.balance(&staking.address), 30_000);
 
-    staking.unbond(&user2, &10_000, &2_000);
+    assert_eq!(
+        staking.try_unbond(&user2, &10_000, &2_000),
+        Err(Ok(StakeError::StakeNotFound))
+    );
 }
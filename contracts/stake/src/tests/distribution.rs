@@ -1,3 +1,4 @@
+use phoenix::error::StakeError;
 use soroban_sdk::{
     testutils::{Address as _, Ledger},
     vec, Address, Env, String,
@@ -6,7 +7,8 @@ use soroban_sdk::{
 use super::setup::{deploy_staking_contract, deploy_token_contract};
 
 use crate::msg::{
-    AnnualizedReward, AnnualizedRewardsResponse, WithdrawableReward, WithdrawableRewardsResponse,
+    AnnualizedReward, AnnualizedRewardsResponse, RewardMultiplier, RewardMultipliersResponse,
+    WithdrawableReward, WithdrawableRewardsResponse,
 };
 
 #[test]
@@ -21,7 +23,7 @@ fn add_distribution_and_distribute_reward() {
 
     let staking = deploy_staking_contract(&env, admin.clone(), &lp_token.address);
 
-    staking.create_distribution_flow(&admin, &admin, &reward_token.address);
+    staking.create_distribution_flow(&admin, &admin, &reward_token.address, &0, &0);
 
     let reward_amount: u128 = 100_000;
     reward_token.mint(&admin, &(reward_amount as i128));
@@ -92,8 +94,8 @@ fn two_distributions() {
 
     let staking = deploy_staking_contract(&env, admin.clone(), &lp_token.address);
 
-    staking.create_distribution_flow(&admin, &admin, &reward_token.address);
-    staking.create_distribution_flow(&admin, &admin, &reward_token_2.address);
+    staking.create_distribution_flow(&admin, &admin, &reward_token.address, &0, &0);
+    staking.create_distribution_flow(&admin, &admin, &reward_token_2.address, &0, &0);
 
     let reward_amount: u128 = 100_000;
     reward_token.mint(&admin, &(reward_amount as i128));
@@ -211,7 +213,7 @@ fn four_users_with_different_stakes() {
 
     let staking = deploy_staking_contract(&env, admin.clone(), &lp_token.address);
 
-    staking.create_distribution_flow(&admin, &admin, &reward_token.address);
+    staking.create_distribution_flow(&admin, &admin, &reward_token.address, &0, &0);
 
     let reward_amount: u128 = 100_000;
     reward_token.mint(&admin, &(reward_amount as i128));
@@ -319,7 +321,7 @@ fn two_users_one_starts_after_distribution_begins() {
 
     let staking = deploy_staking_contract(&env, admin.clone(), &lp_token.address);
 
-    staking.create_distribution_flow(&admin, &admin, &reward_token.address);
+    staking.create_distribution_flow(&admin, &admin, &reward_token.address, &0, &0);
 
     let reward_amount: u128 = 100_000;
     reward_token.mint(&admin, &(reward_amount as i128));
@@ -415,7 +417,7 @@ fn two_users_both_bonds_after_distribution_starts() {
 
     let staking = deploy_staking_contract(&env, admin.clone(), &lp_token.address);
 
-    staking.create_distribution_flow(&admin, &admin, &reward_token.address);
+    staking.create_distribution_flow(&admin, &admin, &reward_token.address, &0, &0);
 
     let reward_amount: u128 = 100_000;
     reward_token.mint(&admin, &(reward_amount as i128));
@@ -526,7 +528,6 @@ fn two_users_both_bonds_after_distribution_starts() {
 }
 
 #[test]
-#[should_panic(expected = "Stake: Fund distribution: Not reward curve exists")]
 fn fund_rewards_without_establishing_distribution() {
     let env = Env::default();
     env.mock_all_auths();
@@ -540,7 +541,10 @@ fn fund_rewards_without_establishing_distribution() {
 
     reward_token.mint(&admin, &1000);
 
-    staking.fund_distribution(&admin, &2_000, &600, &reward_token.address, &1000);
+    assert_eq!(
+        staking.try_fund_distribution(&admin, &2_000, &600, &reward_token.address, &1000),
+        Err(Ok(StakeError::DistributionNotFound))
+    );
 }
 
 #[test]
@@ -555,7 +559,7 @@ fn try_to_withdraw_rewards_without_bonding() {
 
     let staking = deploy_staking_contract(&env, admin.clone(), &lp_token.address);
 
-    staking.create_distribution_flow(&admin, &admin, &reward_token.address);
+    staking.create_distribution_flow(&admin, &admin, &reward_token.address, &0, &0);
 
     let reward_amount: u128 = 100_000;
     reward_token.mint(&admin, &(reward_amount as i128));
@@ -601,7 +605,6 @@ fn try_to_withdraw_rewards_without_bonding() {
 }
 
 #[test]
-#[should_panic(expected = "Stake: Fund distribution: Fund distribution start time is too early")]
 fn fund_distribution_starting_before_current_timestamp() {
     let env = Env::default();
     env.mock_all_auths();
@@ -612,7 +615,7 @@ fn fund_distribution_starting_before_current_timestamp() {
 
     let staking = deploy_staking_contract(&env, admin.clone(), &lp_token.address);
 
-    staking.create_distribution_flow(&admin, &admin, &reward_token.address);
+    staking.create_distribution_flow(&admin, &admin, &reward_token.address, &0, &0);
 
     let reward_amount: u128 = 100_000;
     reward_token.mint(&admin, &(reward_amount as i128));
@@ -622,17 +625,19 @@ fn fund_distribution_starting_before_current_timestamp() {
     });
 
     let reward_duration = 600;
-    staking.fund_distribution(
-        &admin,
-        &1_999,
-        &reward_duration,
-        &reward_token.address,
-        &(reward_amount as i128),
-    )
+    assert_eq!(
+        staking.try_fund_distribution(
+            &admin,
+            &1_999,
+            &reward_duration,
+            &reward_token.address,
+            &(reward_amount as i128),
+        ),
+        Err(Ok(StakeError::StartTimeTooEarly))
+    );
 }
 
 #[test]
-#[should_panic(expected = "Stake: Fund distribution: minimum reward amount not reached")]
 fn fund_distribution_with_reward_below_required_minimum() {
     let env = Env::default();
     env.mock_all_auths();
@@ -643,7 +648,7 @@ fn fund_distribution_with_reward_below_required_minimum() {
 
     let staking = deploy_staking_contract(&env, admin.clone(), &lp_token.address);
 
-    staking.create_distribution_flow(&admin, &admin, &reward_token.address);
+    staking.create_distribution_flow(&admin, &admin, &reward_token.address, &0, &0);
 
     reward_token.mint(&admin, &10);
 
@@ -652,7 +657,10 @@ fn fund_distribution_with_reward_below_required_minimum() {
     });
 
     let reward_duration = 600;
-    staking.fund_distribution(&admin, &2_000, &reward_duration, &reward_token.address, &10);
+    assert_eq!(
+        staking.try_fund_distribution(&admin, &2_000, &reward_duration, &reward_token.address, &10),
+        Err(Ok(StakeError::MinRewardNotReached))
+    );
 }
 
 #[test]
@@ -667,7 +675,7 @@ fn calculate_apr() {
 
     let staking = deploy_staking_contract(&env, admin.clone(), &lp_token.address);
 
-    staking.create_distribution_flow(&admin, &admin, &reward_token.address);
+    staking.create_distribution_flow(&admin, &admin, &reward_token.address, &0, &0);
 
     let reward_amount: u128 = 100_000;
     reward_token.mint(&admin, &(reward_amount as i128));
@@ -743,3 +751,225 @@ fn calculate_apr() {
         }
     );
 }
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[test]
+fn long_term_staker_earns_bonus() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let user = Address::generate(&env);
+    let user2 = Address::generate(&env);
+    let lp_token = deploy_token_contract(&env, &admin);
+    let reward_token = deploy_token_contract(&env, &admin);
+
+    let staking = deploy_staking_contract(&env, admin.clone(), &lp_token.address);
+
+    // 0.5% per day up to 30%
+    staking.create_distribution_flow(&admin, &admin, &reward_token.address, &3_000, &50);
+
+    lp_token.mint(&user, &1_000);
+    staking.bond(&user, &1_000);
+
+    // after 60 days the first user gets the maximum bonus, second one just joined
+    env.ledger().with_mut(|li| {
+        li.timestamp = 60 * DAY;
+    });
+    lp_token.mint(&user2, &1_000);
+    staking.bond(&user2, &1_000);
+    staking.withdraw_rewards(&user);
+
+    let reward_amount: u128 = 230_000;
+    reward_token.mint(&admin, &(reward_amount as i128));
+    staking.fund_distribution(
+        &admin,
+        &(60 * DAY),
+        &600,
+        &reward_token.address,
+        &(reward_amount as i128),
+    );
+
+    env.ledger().with_mut(|li| {
+        li.timestamp = 60 * DAY + 600;
+    });
+    staking.distribute_rewards();
+
+    // rewards are split 130:100
+    assert_eq!(
+        staking.query_withdrawable_rewards(&user),
+        WithdrawableRewardsResponse {
+            rewards: vec![
+                &env,
+                WithdrawableReward {
+                    reward_address: reward_token.address.clone(),
+                    reward_amount: 130_000
+                }
+            ]
+        }
+    );
+    assert_eq!(
+        staking.query_withdrawable_rewards(&user2),
+        WithdrawableRewardsResponse {
+            rewards: vec![
+                &env,
+                WithdrawableReward {
+                    reward_address: reward_token.address.clone(),
+                    reward_amount: 100_000
+                }
+            ]
+        }
+    );
+
+    staking.withdraw_rewards(&user);
+    staking.withdraw_rewards(&user2);
+    assert_eq!(reward_token.balance(&user), 130_000);
+    assert_eq!(reward_token.balance(&user2), 100_000);
+}
+
+#[test]
+fn query_reward_multipliers() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let user = Address::generate(&env);
+    let lp_token = deploy_token_contract(&env, &admin);
+    let reward_token = deploy_token_contract(&env, &admin);
+    let reward_token_2 = deploy_token_contract(&env, &admin);
+
+    let staking = deploy_staking_contract(&env, admin.clone(), &lp_token.address);
+
+    staking.create_distribution_flow(&admin, &admin, &reward_token.address, &3_000, &50);
+    // no bonus for this one
+    staking.create_distribution_flow(&admin, &admin, &reward_token_2.address, &0, &0);
+
+    lp_token.mint(&user, &10_000);
+    staking.bond(&user, &1_000);
+
+    env.ledger().with_mut(|li| {
+        li.timestamp = 10 * DAY;
+    });
+    // the rewards power catches up with the multiplier only after an update
+    assert_eq!(
+        staking.query_reward_multipliers(&user),
+        RewardMultipliersResponse {
+            multipliers: vec![
+                &env,
+                RewardMultiplier {
+                    asset: reward_token.address.clone(),
+                    multiplier_bps: 10_500,
+                    rewards_power: 1_000,
+                },
+                RewardMultiplier {
+                    asset: reward_token_2.address.clone(),
+                    multiplier_bps: 10_000,
+                    rewards_power: 1_000,
+                }
+            ]
+        }
+    );
+    staking.withdraw_rewards(&user);
+    assert_eq!(
+        staking.query_reward_multipliers(&user).multipliers.get(0),
+        Some(RewardMultiplier {
+            asset: reward_token.address.clone(),
+            multiplier_bps: 10_500,
+            rewards_power: 1_050,
+        })
+    );
+
+    // a new stake has no bonus yet, so the multiplier is averaged between the stakes
+    staking.bond(&user, &1_000);
+    assert_eq!(
+        staking.query_reward_multipliers(&user).multipliers.get(0),
+        Some(RewardMultiplier {
+            asset: reward_token.address.clone(),
+            multiplier_bps: 10_250,
+            rewards_power: 2_050,
+        })
+    );
+
+    // the bonus is capped
+    env.ledger().with_mut(|li| {
+        li.timestamp = 1_000 * DAY;
+    });
+    assert_eq!(
+        staking
+            .query_reward_multipliers(&user)
+            .multipliers
+            .get(0)
+            .unwrap()
+            .multiplier_bps,
+        13_000
+    );
+}
+
+#[test]
+fn unbond_keeps_accrued_rewards() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let user = Address::generate(&env);
+    let lp_token = deploy_token_contract(&env, &admin);
+    let reward_token = deploy_token_contract(&env, &admin);
+
+    let staking = deploy_staking_contract(&env, admin.clone(), &lp_token.address);
+
+    staking.create_distribution_flow(&admin, &admin, &reward_token.address, &3_000, &50);
+
+    lp_token.mint(&user, &2_000);
+    staking.bond(&user, &2_000);
+
+    env.ledger().with_mut(|li| {
+        li.timestamp = 30 * DAY;
+    });
+    let reward_amount: u128 = 100_000;
+    reward_token.mint(&admin, &(reward_amount as i128));
+    staking.fund_distribution(
+        &admin,
+        &(30 * DAY),
+        &600,
+        &reward_token.address,
+        &(reward_amount as i128),
+    );
+    env.ledger().with_mut(|li| {
+        li.timestamp = 30 * DAY + 600;
+    });
+    staking.distribute_rewards();
+
+    staking.unbond(&user, &2_000, &0);
+    assert_eq!(
+        staking.query_withdrawable_rewards(&user),
+        WithdrawableRewardsResponse {
+            rewards: vec![
+                &env,
+                WithdrawableReward {
+                    reward_address: reward_token.address.clone(),
+                    reward_amount
+                }
+            ]
+        }
+    );
+    staking.withdraw_rewards(&user);
+    assert_eq!(reward_token.balance(&user), reward_amount as i128);
+}
+
+#[test]
+fn create_distribution_with_too_high_bonus() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lp_token = deploy_token_contract(&env, &admin);
+    let reward_token = deploy_token_contract(&env, &admin);
+
+    let staking = deploy_staking_contract(&env, admin.clone(), &lp_token.address);
+
+    assert_eq!(
+        staking.try_create_distribution_flow(&admin, &admin, &reward_token.address, &10_001, &50),
+        Err(Ok(StakeError::InvalidBps))
+    );
+}
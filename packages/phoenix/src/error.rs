@@ -0,0 +1,209 @@
+//! Error codes returned by the Phoenix contracts.
+//!
+//! Every contract has its own `#[contracterror]` enum, but all of them follow the same scheme, so
+//! that a code can be traced back to the failure even when it bubbles up through another contract:
+//! * `1..=99` - errors shared by all the contracts, with the same code everywhere
+//! * `100..` - factory
+//! * `200..` - XYK liquidity pool
+//! * `300..` - stable liquidity pool
+//! * `400..` - multihop
+//! * `500..` - stake
+use soroban_sdk::contracterror;
+
+/// Errors that can happen in any of the contracts. Every contract error enum contains them
+/// with the same codes and can be created from them.
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum CommonError {
+    AlreadyInitialized = 1,
+    Unauthorized = 2,
+    /// A value in bps is outside of the 0..=10_000 range
+    InvalidBps = 3,
+    /// An amount that has to be positive is zero or negative
+    InvalidAmount = 4,
+}
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum FactoryError {
+    AlreadyInitialized = 1,
+    Unauthorized = 2,
+    InvalidBps = 3,
+    InvalidAmount = 4,
+    WhitelistEmpty = 100,
+    TokensNotSorted = 101,
+    InvalidMinBond = 102,
+    InvalidMinReward = 103,
+    InvalidAmp = 104,
+    PoolNotFound = 105,
+}
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum PoolError {
+    AlreadyInitialized = 1,
+    Unauthorized = 2,
+    InvalidBps = 3,
+    InvalidAmount = 4,
+    TokensNotSorted = 200,
+    SlippageToleranceExceedsMax = 201,
+    SlippageToleranceViolated = 202,
+    EmptyDeposit = 203,
+    MinAmountExceedsDesired = 204,
+    DepositAmountExceedsDesired = 205,
+    DepositAmountBelowMinimum = 206,
+    WithdrawMinNotSatisfied = 207,
+    ReferralFeeTooHigh = 208,
+    NegativeReferralFee = 209,
+    SpreadExceedsLimit = 210,
+    AssetNotInPool = 211,
+    EmptyPool = 212,
+    EmptyTwapWindow = 213,
+    NotEnoughPriceHistory = 214,
+    NotEnoughLiquidity = 215,
+    /// The offer amount needed for an exact output swap is above the given maximum
+    OfferAmountExceedsMax = 216,
+    SwapsPaused = 217,
+    DepositsPaused = 218,
+    WithdrawalsPaused = 219,
+    /// The receiver of a flash loan did not pay back the lent amounts plus the fees
+    FlashLoanNotRepaid = 220,
+    /// The deposit is too small to mint any shares, or below the locked minimum on an empty pool
+    LiquidityAmountTooLow = 221,
+    /// A deposit would mint fewer shares than the given minimum
+    MinSharesNotSatisfied = 222,
+}
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum StablePoolError {
+    AlreadyInitialized = 1,
+    Unauthorized = 2,
+    InvalidBps = 3,
+    InvalidAmount = 4,
+    InvalidAmp = 300,
+    InvalidNumberOfTokens = 301,
+    TokensNotSorted = 302,
+    TokenPrecisionTooBig = 303,
+    AmountsLengthMismatch = 304,
+    SlippageToleranceExceedsMax = 305,
+    LiquidityAmountTooLow = 306,
+    WithdrawMinNotSatisfied = 307,
+    AmpChangeTimeTooShort = 308,
+    AmpChangeTooBig = 309,
+    SameAsset = 310,
+    AssetNotInPool = 311,
+    SpreadExceedsLimit = 312,
+    NotEnoughLiquidity = 313,
+    /// The offer amount needed for an exact output swap is above the given maximum
+    OfferAmountExceedsMax = 314,
+    SwapsPaused = 315,
+    DepositsPaused = 316,
+    WithdrawalsPaused = 317,
+    /// A deposit would mint fewer shares than the given minimum
+    MinSharesNotSatisfied = 318,
+    /// The rate providers are neither empty nor one per token
+    InvalidRateProviders = 319,
+    /// A rate provider reported a rate that is not positive
+    InvalidRate = 320,
+    /// The imbalance fees of a deposit exceed the given slippage tolerance
+    SlippageToleranceViolated = 321,
+    /// A new amp ramp is started less than `MIN_AMP_CHANGING_TIME` after the previous one
+    AmpChangeTooSoon = 322,
+}
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum MultihopError {
+    AlreadyInitialized = 1,
+    Unauthorized = 2,
+    InvalidBps = 3,
+    InvalidAmount = 4,
+    OperationsEmpty = 400,
+    IncorrectSwapOrder = 401,
+    /// The offer amount needed for an exact output swap is above the given maximum
+    OfferAmountExceedsMax = 402,
+    NoRouteFound = 403,
+    InvalidMaxHops = 404,
+    /// The allocations of a split swap are not positive or do not add up to 10_000 bps
+    InvalidSplitAllocation = 405,
+    /// The routes of a split swap do not share the same offer and ask asset
+    SplitRoutesMismatch = 406,
+    /// The total amount returned by a swap is below the given minimum
+    AskAmountBelowMinimum = 407,
+    /// The ledger timestamp is past the deadline of the swap
+    DeadlineExceeded = 408,
+    /// Two routes of a split swap go through the same pool
+    SplitRoutesSharePool = 409,
+}
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum StakeError {
+    AlreadyInitialized = 1,
+    Unauthorized = 2,
+    InvalidBps = 3,
+    InvalidAmount = 4,
+    InvalidMinBond = 500,
+    InvalidMinReward = 501,
+    MinBondNotReached = 502,
+    StakeNotFound = 503,
+    DistributionAlreadyAdded = 504,
+    DistributionNotFound = 505,
+    StartTimeTooEarly = 506,
+    MinRewardNotReached = 507,
+    RewardsValidationFailed = 508,
+}
+
+macro_rules! impl_from_common_error {
+    ($($error:ident),+) => {
+        $(
+            impl From<CommonError> for $error {
+                fn from(error: CommonError) -> Self {
+                    match error {
+                        CommonError::AlreadyInitialized => $error::AlreadyInitialized,
+                        CommonError::Unauthorized => $error::Unauthorized,
+                        CommonError::InvalidBps => $error::InvalidBps,
+                        CommonError::InvalidAmount => $error::InvalidAmount,
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_from_common_error!(
+    FactoryError,
+    PoolError,
+    StablePoolError,
+    MultihopError,
+    StakeError
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_errors_keep_their_codes() {
+        for (common, code) in [
+            (CommonError::AlreadyInitialized, 1),
+            (CommonError::Unauthorized, 2),
+            (CommonError::InvalidBps, 3),
+            (CommonError::InvalidAmount, 4),
+        ] {
+            assert_eq!(common as u32, code);
+            assert_eq!(FactoryError::from(common) as u32, code);
+            assert_eq!(PoolError::from(common) as u32, code);
+            assert_eq!(StablePoolError::from(common) as u32, code);
+            assert_eq!(MultihopError::from(common) as u32, code);
+            assert_eq!(StakeError::from(common) as u32, code);
+        }
+    }
+}
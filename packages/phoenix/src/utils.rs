@@ -1,36 +1,42 @@
 use decimal::Decimal;
-use soroban_sdk::{contracttype, Address};
+use soroban_sdk::{contracttype, Address, Vec};
 
 // Validate if int value is bigger then 0
+// Returns `Err(CommonError::InvalidAmount)` for the first value that is not
 #[macro_export]
 macro_rules! validate_int_parameters {
     ($($arg:expr),*) => {
         {
             $(
-                let value: Option<i128> = Into::<Option<_>>::into($arg);
-                if let Some(val) = value {
-                    if val <= 0 {
-                        panic!("value cannot be less than or equal zero")
-                    }
-                }
+                if matches!(Into::<Option<i128>>::into($arg), Some(value) if value <= 0) {
+                    Err($crate::error::CommonError::InvalidAmount)
+                } else
             )*
+            {
+                Ok::<(), $crate::error::CommonError>(())
+            }
         }
     };
 }
 
 // Validate all bps to be between the range 0..10_000
+// Returns `Err(CommonError::InvalidBps)` for the first value out of the range
 #[macro_export]
 macro_rules! validate_bps {
     ($($value:expr),+) => {
-        const MIN_BPS: i64 = 0;
-        const MAX_BPS: i64 = 10_000;
-        $(
-            // if $value < MIN_BPS || $value > MAX_BPS {
-            //     panic!("The value {} is out of range. Must be between {} and {} bps.", $value, MIN_BPS, MAX_BPS);
-            // }
-            assert!((MIN_BPS..=MAX_BPS).contains(&$value), "The value {} is out of range. Must be between {} and {} bps.", $value, MIN_BPS, MAX_BPS);
-        )+
-    }
+        {
+            const MIN_BPS: i64 = 0;
+            const MAX_BPS: i64 = 10_000;
+            $(
+                if !(MIN_BPS..=MAX_BPS).contains(&$value) {
+                    Err($crate::error::CommonError::InvalidBps)
+                } else
+            )+
+            {
+                Ok::<(), $crate::error::CommonError>(())
+            }
+        }
+    };
 }
 
 pub fn is_approx_ratio(a: Decimal, b: Decimal, tolerance: Decimal) -> bool {
@@ -38,6 +44,47 @@ pub fn is_approx_ratio(a: Decimal, b: Decimal, tolerance: Decimal) -> bool {
     diff <= tolerance
 }
 
+#[contracttype]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum PoolType {
+    Xyk = 0,
+    Stable = 1,
+}
+
+/// Type of the pool to be deployed by the factory, together with the parameters only that type
+/// of pool takes
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PoolTypeInitInfo {
+    Xyk,
+    Stable(StableInitInfo),
+}
+
+impl PoolTypeInitInfo {
+    pub fn pool_type(&self) -> PoolType {
+        match self {
+            PoolTypeInitInfo::Xyk => PoolType::Xyk,
+            PoolTypeInitInfo::Stable(_) => PoolType::Stable,
+        }
+    }
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StableInitInfo {
+    /// Amplification coefficient, bigger than 0
+    pub amp: u64,
+    /// Between 2 and 4 tokens, sorted alphabetically; used instead of the pair of
+    /// `LiquidityPoolInitInfo::token_init_info`
+    pub tokens: Vec<Address>,
+    /// Optional contract per token, in the order of `tokens`, that reports the exchange rate of
+    /// the token with `get_rate()`; empty if no token has one
+    pub rate_providers: Vec<Option<Address>>,
+    /// The time (in seconds) for which the rates reported by the rate providers are reused
+    pub max_rate_staleness: u64,
+}
+
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct TokenInitInfo {
@@ -67,48 +114,81 @@ pub struct LiquidityPoolInitInfo {
     pub stake_init_info: StakeInitInfo,
 }
 
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StablePoolInitInfo {
+    pub admin: Address,
+    pub share_token_decimals: u32,
+    pub swap_fee_bps: i64,
+    pub fee_recipient: Address,
+    pub max_allowed_slippage_bps: i64,
+    pub max_allowed_spread_bps: i64,
+    /// Between 2 and 4 tokens, sorted alphabetically
+    pub tokens: Vec<Address>,
+    /// Optional contract per token, in the order of `tokens`, that reports the exchange rate of
+    /// the token with `get_rate()`; empty if no token has one
+    pub rate_providers: Vec<Option<Address>>,
+    /// The time (in seconds) for which the rates reported by the rate providers are reused
+    pub max_rate_staleness: u64,
+    pub stake_init_info: StakeInitInfo,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::CommonError;
 
     #[test]
     fn test_validate_int_parameters() {
-        // The macro should not panic for valid parameters.
-        validate_int_parameters!(1, 2, 3);
-        validate_int_parameters!(1, 1, 1);
-        validate_int_parameters!(1i128, 2i128, 3i128, Some(4i128), None::<i128>);
-        validate_int_parameters!(None::<i128>, None::<i128>);
-        validate_int_parameters!(Some(1i128), None::<i128>);
+        // The macro should succeed for valid parameters.
+        assert_eq!(validate_int_parameters!(1, 2, 3), Ok(()));
+        assert_eq!(validate_int_parameters!(1, 1, 1), Ok(()));
+        assert_eq!(
+            validate_int_parameters!(1i128, 2i128, 3i128, Some(4i128), None::<i128>),
+            Ok(())
+        );
+        assert_eq!(validate_int_parameters!(None::<i128>, None::<i128>), Ok(()));
+        assert_eq!(validate_int_parameters!(Some(1i128), None::<i128>), Ok(()));
     }
 
     #[test]
-    #[should_panic]
-    fn should_panic_when_value_less_than_zero() {
-        validate_int_parameters!(1, -2, 3);
+    fn should_fail_when_value_less_than_zero() {
+        assert_eq!(
+            validate_int_parameters!(1, -2, 3),
+            Err(CommonError::InvalidAmount)
+        );
     }
 
     #[test]
-    #[should_panic]
-    fn should_panic_when_first_value_equal_zero() {
-        validate_int_parameters!(0, 1, 3);
+    fn should_fail_when_first_value_equal_zero() {
+        assert_eq!(
+            validate_int_parameters!(0, 1, 3),
+            Err(CommonError::InvalidAmount)
+        );
     }
 
     #[test]
-    #[should_panic]
-    fn should_panic_when_last_value_equal_zero() {
-        validate_int_parameters!(1, 1, 0);
+    fn should_fail_when_last_value_equal_zero() {
+        assert_eq!(
+            validate_int_parameters!(1, 1, 0),
+            Err(CommonError::InvalidAmount)
+        );
     }
 
     #[test]
-    #[should_panic]
-    fn should_panic_when_some_equals_zero() {
-        validate_int_parameters!(Some(0i128), None::<i128>);
+    fn should_fail_when_some_equals_zero() {
+        assert_eq!(
+            validate_int_parameters!(Some(0i128), None::<i128>),
+            Err(CommonError::InvalidAmount)
+        );
     }
 
     #[test]
-    #[should_panic]
-    fn should_panic_when_some_less_than_zero() {
-        validate_int_parameters!(Some(-1i128), None::<i128>);
+    fn should_fail_when_some_less_than_zero() {
+        assert_eq!(
+            validate_int_parameters!(Some(-1i128), None::<i128>),
+            Err(CommonError::InvalidAmount)
+        );
     }
 
     #[test]
@@ -136,19 +216,23 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "The value -1 is out of range. Must be between 0 and 10000 bps.")]
     fn validate_bps_below_min() {
-        validate_bps!(-1, 300, 5_000, 8_534);
+        assert_eq!(
+            validate_bps!(-1, 300, 5_000, 8_534),
+            Err(CommonError::InvalidBps)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "The value 10001 is out of range. Must be between 0 and 10000 bps.")]
     fn validate_bps_above_max() {
-        validate_bps!(100, 10_001, 31_3134, 348);
+        assert_eq!(
+            validate_bps!(100, 10_001, 31_3134, 348),
+            Err(CommonError::InvalidBps)
+        );
     }
 
     #[test]
     fn bps_valid_range() {
-        validate_bps!(0, 5_000, 7_500, 10_000);
+        assert_eq!(validate_bps!(0, 5_000, 7_500, 10_000), Ok(()));
     }
 }